@@ -0,0 +1,55 @@
+//! Sets `WEBML_BUILD_ID` to the hash of what the compiler is built from: its sources,
+//! the versions of its dependencies and the rust compiler. The cache keys include it,
+//! as the outputs of a compiler built from anything else may differ.
+
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+fn hash_dir(dir: &Path, hasher: &mut Sha256) {
+    let mut paths = fs::read_dir(dir)
+        .and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .unwrap_or_else(|e| panic!("failed to read {}: {}", dir.display(), e));
+    // in the same order wherever it is built
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            hash_dir(&path, hasher)
+        } else {
+            hash_file(&path, hasher)
+        }
+    }
+}
+
+fn hash_file(path: &Path, hasher: &mut Sha256) {
+    let bytes =
+        fs::read(path).unwrap_or_else(|e| panic!("failed to read {}: {}", path.display(), e));
+    hasher.update(path.to_string_lossy().as_bytes());
+    hasher.update(&(bytes.len() as u64).to_le_bytes());
+    hasher.update(&bytes);
+}
+
+fn main() {
+    let mut hasher = Sha256::new();
+    hash_dir(Path::new("src"), &mut hasher);
+    println!("cargo:rerun-if-changed=src");
+    // Cargo.lock is not committed, so it may be missing
+    let lock = Path::new("Cargo.lock");
+    if lock.exists() {
+        hash_file(lock, &mut hasher);
+    }
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let version = Command::new(&rustc)
+        .arg("--version")
+        .output()
+        .unwrap_or_else(|e| panic!("failed to run {}: {}", rustc, e));
+    hasher.update(&version.stdout);
+    println!("cargo:rustc-env=WEBML_BUILD_ID={:x}", hasher.finalize());
+}
@@ -2,6 +2,8 @@ use super::util::Transform;
 use crate::ast::*;
 use crate::config::Config;
 use crate::id::Id;
+use crate::warning::Warnings;
+use std::cmp::Reverse;
 use std::collections::{HashMap, HashSet};
 
 #[derive(Debug)]
@@ -13,6 +15,7 @@ pub struct CaseSimplify {
 pub struct CaseSimplifyPass {
     symbol_table: SymbolTable,
     id: Id,
+    warnings: Warnings,
 }
 
 #[derive(Debug)]
@@ -22,19 +25,46 @@ pub struct WildcardToVariable {
 
 type Stack<T> = Vec<T>;
 
+/// the head of a refutable pattern, that is what it tests
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Head {
+    Constant(i64),
+    Char(u32),
+    Constructor(Symbol),
+}
+
+impl Head {
+    /// the head of `pattern` and the type of its arg, if it has
+    fn of(pattern: &TypedPattern) -> Option<(Head, Option<Type>)> {
+        match &pattern.inner {
+            PatternKind::Constant { value } => Some((Head::Constant(*value), None)),
+            PatternKind::Char { value } => Some((Head::Char(*value), None)),
+            PatternKind::Constructor { name, arg } => Some((
+                Head::Constructor(name.clone()),
+                arg.as_ref().map(|arg| arg.ty()),
+            )),
+            _ => None,
+        }
+    }
+}
+
 impl CaseSimplify {
     pub fn new(id: Id) -> Self {
         Self { id }
     }
 
-    fn generate_pass(&mut self, symbol_table: SymbolTable) -> CaseSimplifyPass {
-        CaseSimplifyPass::new(symbol_table, self.id.clone())
+    fn generate_pass(&mut self, symbol_table: SymbolTable, warnings: Warnings) -> CaseSimplifyPass {
+        CaseSimplifyPass::new(symbol_table, self.id.clone(), warnings)
     }
 }
 
 impl CaseSimplifyPass {
-    fn new(symbol_table: SymbolTable, id: Id) -> Self {
-        Self { symbol_table, id }
+    fn new(symbol_table: SymbolTable, id: Id, warnings: Warnings) -> Self {
+        Self {
+            symbol_table,
+            id,
+            warnings,
+        }
     }
     fn symbol_table(&self) -> &SymbolTable {
         &self.symbol_table
@@ -82,13 +112,132 @@ impl CaseSimplifyPass {
         } else if clauses[0].0.iter().all(|p| p.is_variable()) {
             self.match_compile_variable(cond, ty, clauses)
         } else if clauses[0].0.iter().any(|p| p.is_tuple()) {
+            // destructing tuples tests nothing
             self.match_compile_tuple(cond, ty, clauses)
-        } else if clauses[0].0.iter().any(|p| p.is_constant()) {
-            self.match_compile_constant(cond, ty, clauses)
-        } else if clauses[0].0.iter().any(|p| p.is_char()) {
-            self.match_compile_char(cond, ty, clauses)
         } else {
-            self.match_compile_mixture(cond, ty, clauses)
+            let pos = self.select_column(&clauses);
+            let head = &clauses[0].0[pos];
+            if head.is_constant() {
+                self.match_compile_constant(pos, cond, ty, clauses)
+            } else if head.is_char() {
+                self.match_compile_char(pos, cond, ty, clauses)
+            } else {
+                self.match_compile_mixture(pos, cond, ty, clauses)
+            }
+        }
+    }
+
+    /// the column to test first. It is one the first clause tests,
+    /// preferring the one the most leading clauses test and then the one branching to the fewest
+    fn select_column(&self, clauses: &[(Stack<TypedPattern>, TypedCoreExpr)]) -> usize {
+        (0..clauses[0].0.len())
+            .filter(|&i| !clauses[0].0[i].is_variable())
+            .max_by_key(|&i| {
+                let needed = clauses
+                    .iter()
+                    .take_while(|(patterns, _)| !patterns[i].is_variable())
+                    .count();
+                let branches = clauses
+                    .iter()
+                    .filter_map(|(patterns, _)| Head::of(&patterns[i]))
+                    .map(|(head, _)| head)
+                    .collect::<HashSet<_>>()
+                    .len();
+                (needed, Reverse(branches), i)
+            })
+            .expect("internal error: no column to test")
+    }
+
+    /// warns the clauses which no values reach, at `positions`
+    fn check_redundancy(&self, clauses: &[(TypedPattern, TypedCoreExpr)], positions: &[Position]) {
+        let mut rows = Vec::new();
+        for (i, (pattern, _)) in clauses.iter().enumerate() {
+            let row = vec![pattern.clone()];
+            if !self.is_useful(&rows, row.clone()) {
+                self.warnings.push(
+                    positions.get(i).cloned(),
+                    format!("redundant match: the clause {} is never matched", i + 1),
+                );
+            }
+            rows.push(row);
+        }
+    }
+
+    /// whether some values match `q` but none of `rows`, following Maranget's usefulness
+    fn is_useful(&self, rows: &[Stack<TypedPattern>], q: Stack<TypedPattern>) -> bool {
+        if q.is_empty() {
+            return rows.is_empty();
+        }
+        let tuple = rows
+            .iter()
+            .map(|row| &row[0])
+            .chain(Some(&q[0]))
+            .find_map(|p| match &p.inner {
+                PatternKind::Tuple { tuple } => Some(tuple.iter().map(|p| p.ty()).collect()),
+                _ => None,
+            });
+        if let Some(tys) = tuple {
+            let rows = rows
+                .iter()
+                .map(|row| expand_tuple(row, &tys))
+                .collect::<Vec<_>>();
+            return self.is_useful(&rows, expand_tuple(&q, &tys));
+        }
+
+        match Head::of(&q[0]) {
+            Some((head, arg)) => {
+                let rows = rows
+                    .iter()
+                    .filter_map(|row| specialize(row, &head, &arg))
+                    .collect::<Vec<_>>();
+                let q = specialize(&q, &head, &arg).expect("internal error");
+                self.is_useful(&rows, q)
+            }
+            None => {
+                let mut heads = Vec::new();
+                for (head, arg) in rows.iter().filter_map(|row| Head::of(&row[0])) {
+                    if heads.iter().all(|(h, _)| h != &head) {
+                        heads.push((head, arg));
+                    }
+                }
+                if self.is_complete(&q[0].ty(), heads.iter().map(|(head, _)| head)) {
+                    heads.into_iter().any(|(head, arg)| {
+                        let rows = rows
+                            .iter()
+                            .filter_map(|row| specialize(row, &head, &arg))
+                            .collect::<Vec<_>>();
+                        let q = specialize(&q, &head, &arg).expect("internal error");
+                        self.is_useful(&rows, q)
+                    })
+                } else {
+                    let rows = rows
+                        .iter()
+                        .filter(|row| Head::of(&row[0]).is_none())
+                        .map(|row| row[1..].to_vec())
+                        .collect::<Vec<_>>();
+                    self.is_useful(&rows, q[1..].to_vec())
+                }
+            }
+        }
+    }
+
+    /// whether `heads` cover all the values of `ty`
+    fn is_complete<'a>(&self, ty: &Type, heads: impl Iterator<Item = &'a Head>) -> bool {
+        let heads = heads.collect::<HashSet<_>>();
+        let covers = |name: &str| heads.contains(&Head::Constructor(Symbol::new(name)));
+        match ty {
+            // bool is built in, and the constants `true` and `false` are its constructors
+            Type::Datatype(name, _) if *name == Symbol::new("bool") => {
+                covers("true") && covers("false")
+            }
+            Type::Datatype(name, _) => match self.symbol_table().get_type(name) {
+                Some(info) => info
+                    .constructors
+                    .iter()
+                    .all(|(name, _)| heads.contains(&Head::Constructor(name.clone()))),
+                None => false,
+            },
+            _ => false,
         }
     }
 
@@ -210,18 +359,18 @@ impl CaseSimplifyPass {
                     },
                     self.match_compile(cond, ty, clauses),
                 )],
+                positions: vec![],
             },
         }
     }
 
     fn match_compile_constant(
         &mut self,
+        pos: usize,
         mut cond: Stack<(Type, Symbol)>,
         ret_ty: Type,
         clauses: Vec<(Stack<TypedPattern>, TypedCoreExpr)>,
     ) -> TypedCoreExpr {
-        let pos = self.find_constant(&clauses);
-
         let (cty, c) = cond.swap_remove(pos);
         let clause_with_heads = clauses
             .into_iter()
@@ -279,18 +428,18 @@ impl CaseSimplifyPass {
                 }
                 .boxed(),
                 clauses: clauses,
+                positions: vec![],
             },
         }
     }
 
     fn match_compile_char(
         &mut self,
+        pos: usize,
         mut cond: Stack<(Type, Symbol)>,
         ret_ty: Type,
         clauses: Vec<(Stack<TypedPattern>, TypedCoreExpr)>,
     ) -> TypedCoreExpr {
-        let pos = self.find_char(&clauses);
-
         let (cty, c) = cond.swap_remove(pos);
         let clause_with_heads = clauses
             .into_iter()
@@ -348,18 +497,18 @@ impl CaseSimplifyPass {
                 }
                 .boxed(),
                 clauses: clauses,
+                positions: vec![],
             },
         }
     }
 
     fn match_compile_mixture(
         &mut self,
+        pos: usize,
         mut cond: Stack<(Type, Symbol)>,
         ret_ty: Type,
         clauses: Vec<(Stack<TypedPattern>, TypedCoreExpr)>,
     ) -> TypedCoreExpr {
-        let pos = self.find_constructor(&clauses);
-
         let (cty, c) = cond.swap_remove(pos);
         let clause_with_heads = clauses
             .into_iter()
@@ -429,6 +578,7 @@ impl CaseSimplifyPass {
                     }
                     .boxed(),
                     clauses,
+                    positions: vec![],
                 },
             }
         } else {
@@ -452,6 +602,7 @@ impl CaseSimplifyPass {
                     }
                     .boxed(),
                     clauses: clauses,
+                    positions: vec![],
                 },
             }
         }
@@ -461,22 +612,6 @@ impl CaseSimplifyPass {
         clauses[0].0.iter().rposition(|p| p.is_tuple()).unwrap()
     }
 
-    fn find_constant(&mut self, clauses: &[(Stack<TypedPattern>, TypedCoreExpr)]) -> usize {
-        clauses[0].0.iter().rposition(|p| p.is_constant()).unwrap()
-    }
-
-    fn find_char(&mut self, clauses: &[(Stack<TypedPattern>, TypedCoreExpr)]) -> usize {
-        clauses[0].0.iter().rposition(|p| p.is_char()).unwrap()
-    }
-
-    fn find_constructor(&mut self, clauses: &[(Stack<TypedPattern>, TypedCoreExpr)]) -> usize {
-        clauses[0]
-            .0
-            .iter()
-            .rposition(|p| p.is_constructor())
-            .unwrap()
-    }
-
     fn specialized_patterns<'a, 'b>(
         &'a mut self,
         (cty, cond): (Type, Symbol),
@@ -649,13 +784,23 @@ impl CaseSimplifyPass {
     ) -> bool {
         use Type::*;
         match ty {
-            Real | Variable(_) | Fun(_, _) => panic!("no way to pattern match against this type"),
+            Real
+            | String
+            | Int64
+            | Word64
+            | Real32
+            | Array(_)
+            | Vector(_)
+            | Variable(_)
+            | Fun(_, _) => {
+                panic!("no way to pattern match against this type")
+            }
             Char | Int => false,
             Tuple(_) => {
                 // unlikely reachable, but writing incase it reaches.
                 true
             }
-            Datatype(name) => {
+            Datatype(name, _) => {
                 self.symbol_table()
                     .get_type(name)
                     .unwrap()
@@ -669,6 +814,43 @@ impl CaseSimplifyPass {
     }
 }
 
+/// replaces the tuple at the head of `row` with its elements
+fn expand_tuple(row: &[TypedPattern], tys: &[Type]) -> Stack<TypedPattern> {
+    let (first, rest) = row.split_first().expect("internal error");
+    let mut expanded = match &first.inner {
+        PatternKind::Tuple { tuple } => tuple.clone(),
+        _ => tys.iter().cloned().map(wildcard).collect(),
+    };
+    expanded.extend(rest.iter().cloned());
+    expanded
+}
+
+/// the rest of `row` following the arg of `head`, if the head of `row` may be `head`
+fn specialize(
+    row: &[TypedPattern],
+    head: &Head,
+    arg: &Option<Type>,
+) -> Option<Stack<TypedPattern>> {
+    let (first, rest) = row.split_first().expect("internal error");
+    let mut specialized = match (Head::of(first), &first.inner) {
+        (None, _) => arg.iter().cloned().map(wildcard).collect(),
+        (Some((ref head1, _)), PatternKind::Constructor { arg, .. }) if head1 == head => {
+            arg.iter().map(|arg| (**arg).clone()).collect()
+        }
+        (Some((ref head1, _)), _) if head1 == head => Vec::new(),
+        _ => return None,
+    };
+    specialized.extend(rest.iter().cloned());
+    Some(specialized)
+}
+
+fn wildcard(ty: Type) -> TypedPattern {
+    Pattern {
+        ty,
+        inner: PatternKind::Wildcard {},
+    }
+}
+
 impl Transform<Type> for CaseSimplifyPass {
     fn transform_val(
         &mut self,
@@ -724,7 +906,7 @@ impl Transform<Type> for CaseSimplifyPass {
                     pattern: tuple_pat,
                     expr: Expr {
                         ty,
-                        inner: self.transform_case(cond.boxed(), vec![(pattern, tuple)]),
+                        inner: self.transform_case(cond.boxed(), vec![(pattern, tuple)], vec![]),
                     },
                 }
             }
@@ -735,6 +917,7 @@ impl Transform<Type> for CaseSimplifyPass {
         &mut self,
         cond: Box<TypedCoreExpr>,
         clauses: Vec<(TypedPattern, TypedCoreExpr)>,
+        positions: Vec<Position>,
     ) -> TypedCoreExprKind {
         let condsym = self.gensym("cond");
         let condty = cond.ty();
@@ -743,6 +926,7 @@ impl Transform<Type> for CaseSimplifyPass {
             .map(|(_, expr)| expr.ty())
             .next()
             .expect("case should have at least 1 clause");
+        self.check_redundancy(&clauses, &positions);
         let clauses = clauses
             .into_iter()
             .map(|(pat, arm)| (vec![pat], self.transform_expr(arm)))
@@ -791,9 +975,9 @@ impl<'a> Pass<TypedCoreContext, TypeError<'a>> for CaseSimplify {
     fn trans<'b>(
         &'b mut self,
         Context(symbol_table, ast): TypedCoreContext,
-        _: &Config,
+        config: &Config,
     ) -> Result<'a, Self::Target> {
-        let mut pass = self.generate_pass(symbol_table);
+        let mut pass = self.generate_pass(symbol_table, config.warnings.clone());
         let ast = pass.wildcard_to_variable(ast);
         let ast = pass.transform_ast(ast);
         let (symbol_table, _) = pass.into_inner();
@@ -31,19 +31,35 @@ impl Desugar {
     fn transform_statement(&mut self, decl: UntypedDeclaration) -> Option<UntypedCoreDeclaration> {
         use Declaration::*;
         match decl {
-            Datatype { name, constructors } => Some(self.transform_datatype(name, constructors)),
+            Datatype {
+                name,
+                params,
+                constructors,
+            } => Some(self.transform_datatype(name, params, constructors)),
             Val { rec, pattern, expr } => Some(self.transform_val(rec, pattern, expr)),
-            D(DerivedDeclaration::Fun { name, clauses }) => Some(self.transform_fun(name, clauses)),
-            D(DerivedDeclaration::Infix { .. }) => None,
+            Local { binds, body } => Some(self.transform_local(binds, body)),
+            Structure { name, body } => Some(self.transform_structure(name, body)),
+            Exception { name, arg } => Some(Exception { name, arg }),
+            D(DerivedDeclaration::Fun {
+                name,
+                clauses,
+                positions,
+            }) => Some(self.transform_fun(name, clauses, positions)),
+            D(DerivedDeclaration::Infix { .. }) | D(DerivedDeclaration::Infixr { .. }) => None,
         }
     }
 
     fn transform_datatype(
         &mut self,
         name: Symbol,
+        params: Vec<u64>,
         constructors: Vec<(Symbol, Option<Type>)>,
     ) -> UntypedCoreDeclaration {
-        Declaration::Datatype { name, constructors }
+        Declaration::Datatype {
+            name,
+            params,
+            constructors,
+        }
     }
 
     fn transform_val(
@@ -59,10 +75,42 @@ impl Desugar {
         }
     }
 
+    fn transform_local(
+        &mut self,
+        binds: Vec<UntypedDeclaration>,
+        body: Vec<UntypedDeclaration>,
+    ) -> UntypedCoreDeclaration {
+        Declaration::Local {
+            binds: binds
+                .into_iter()
+                .filter_map(|decl| self.transform_statement(decl))
+                .collect(),
+            body: body
+                .into_iter()
+                .filter_map(|decl| self.transform_statement(decl))
+                .collect(),
+        }
+    }
+
+    fn transform_structure(
+        &mut self,
+        name: Symbol,
+        body: Vec<UntypedDeclaration>,
+    ) -> UntypedCoreDeclaration {
+        Declaration::Structure {
+            name,
+            body: body
+                .into_iter()
+                .filter_map(|decl| self.transform_statement(decl))
+                .collect(),
+        }
+    }
+
     fn transform_fun(
         &mut self,
         name: Symbol,
         clauses: Vec<(Vec<UntypedPattern>, UntypedExpr)>,
+        positions: Vec<Position>,
     ) -> UntypedCoreDeclaration {
         let arity = clauses[0].0.len();
 
@@ -99,6 +147,7 @@ impl Desugar {
                 }
                 .boxed(),
                 clauses,
+                positions,
             },
         };
 
@@ -133,12 +182,17 @@ impl Desugar {
             } => self.transform_externcall(module, fun, args, argty, retty),
             Fn { param, body } => self.transform_fn(param, body),
             App { fun, arg } => self.transform_app(fun, arg),
-            Case { cond, clauses } => self.transform_case(cond, clauses),
+            Case {
+                cond,
+                clauses,
+                positions,
+            } => self.transform_case(cond, clauses, positions),
             Tuple { tuple } => self.transform_tuple(tuple),
             Constructor { arg, name } => self.transform_constructor(arg, name),
             Symbol { name } => self.transform_symbol(name),
             Literal { value } => self.transform_literal(value),
             D(DerivedExprKind::If { cond, then, else_ }) => self.transform_if(cond, then, else_),
+            D(DerivedExprKind::Vector { elems }) => self.transform_vector(elems),
         };
         UntypedCoreExpr { ty: expr.ty, inner }
     }
@@ -234,6 +288,64 @@ impl Desugar {
                     self.transform_expr(*else_),
                 ),
             ],
+            positions: vec![],
+        }
+    }
+
+    /// builds the vector with the builtins, so that it doesn't depend on the bindings in scope
+    ///
+    /// ```sml
+    /// let
+    ///     val a = _builtincall "arrayalloc"(n)
+    ///     val _ = _builtincall "arrayupdate"(a, 0, e1)
+    ///     ...
+    /// in _builtincall "arraytovector"(a) end
+    /// ```
+    fn transform_vector(&mut self, elems: Vec<UntypedExpr>) -> UntypedCoreExprKind {
+        let array = self.gensym();
+        let builtin = |fun, args| Expr {
+            ty: Empty {},
+            inner: ExprKind::BuiltinCall { fun, args },
+        };
+        let sym = || Expr {
+            ty: Empty {},
+            inner: ExprKind::Symbol {
+                name: array.clone(),
+            },
+        };
+        let int = |value: usize| Expr {
+            ty: Empty {},
+            inner: ExprKind::Literal {
+                value: Literal::Int(value as i64),
+            },
+        };
+        let alloc = Declaration::Val {
+            rec: false,
+            pattern: Pattern {
+                ty: Empty {},
+                inner: PatternKind::Variable {
+                    name: array.clone(),
+                },
+            },
+            expr: builtin(BIF::ArrayAlloc, vec![int(elems.len())]),
+        };
+        let updates = elems
+            .into_iter()
+            .enumerate()
+            .map(|(i, elem)| Declaration::Val {
+                rec: false,
+                pattern: Pattern {
+                    ty: Empty {},
+                    inner: PatternKind::Wildcard {},
+                },
+                expr: builtin(
+                    BIF::ArrayUpdate,
+                    vec![sym(), int(i), self.transform_expr(elem)],
+                ),
+            });
+        ExprKind::Binds {
+            binds: Some(alloc).into_iter().chain(updates).collect(),
+            ret: builtin(BIF::ArrayToVector, vec![sym()]).boxed(),
         }
     }
 
@@ -241,6 +353,7 @@ impl Desugar {
         &mut self,
         cond: Box<UntypedExpr>,
         clauses: Vec<(UntypedPattern, UntypedExpr)>,
+        positions: Vec<Position>,
     ) -> UntypedCoreExprKind {
         ExprKind::Case {
             cond: self.transform_expr(*cond).boxed(),
@@ -248,6 +361,7 @@ impl Desugar {
                 .into_iter()
                 .map(|(p, e)| (self.transform_pattern(p), self.transform_expr(e)))
                 .collect(),
+            positions,
         }
     }
 
@@ -14,9 +14,11 @@ pub use self::var2constructor::VarToConstructor;
 use crate::ast;
 use crate::prim::*;
 use nom;
+use serde::{Deserialize, Serialize};
 pub use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
+use std::rc::Rc;
 
 pub type UntypedAst = AST<Empty>;
 pub type Core<Ty> = AST<Ty, Nothing, Nothing>;
@@ -47,6 +49,7 @@ pub type TypedCoreDeclaration = CoreDeclaration<Type>;
 pub enum Declaration<Ty, DE = DerivedExprKind<Ty>, DS = DerivedDeclaration<Ty>> {
     Datatype {
         name: Symbol,
+        params: Vec<u64>,
         constructors: Vec<(Symbol, Option<Type>)>,
     },
     Val {
@@ -54,6 +57,18 @@ pub enum Declaration<Ty, DE = DerivedExprKind<Ty>, DS = DerivedDeclaration<Ty>>
         pattern: Pattern<Ty>,
         expr: Expr<Ty, DE, DS>,
     },
+    Local {
+        binds: Vec<Declaration<Ty, DE, DS>>,
+        body: Vec<Declaration<Ty, DE, DS>>,
+    },
+    Structure {
+        name: Symbol,
+        body: Vec<Declaration<Ty, DE, DS>>,
+    },
+    Exception {
+        name: Symbol,
+        arg: Option<Type>,
+    },
     D(DS),
 }
 
@@ -62,11 +77,17 @@ pub enum DerivedDeclaration<Ty> {
     Fun {
         name: Symbol,
         clauses: Vec<(Vec<Pattern<Ty>>, Expr<Ty>)>,
+        /// where the clauses start in the source
+        positions: Vec<Position>,
     },
     Infix {
         priority: Option<u8>,
         names: Vec<Symbol>,
     },
+    Infixr {
+        priority: Option<u8>,
+        names: Vec<Symbol>,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -119,6 +140,8 @@ pub enum ExprKind<Ty, DE = DerivedExprKind<Ty>, DS = DerivedDeclaration<Ty>> {
     Case {
         cond: Box<Expr<Ty, DE, DS>>,
         clauses: Vec<(Pattern<Ty>, Expr<Ty, DE, DS>)>,
+        /// where the clauses start in the source. Empty for the cases the compiler makes
+        positions: Vec<Position>,
     },
     Tuple {
         tuple: Vec<Expr<Ty, DE, DS>>,
@@ -143,6 +166,8 @@ pub enum DerivedExprKind<Ty> {
         then: Box<Expr<Ty>>,
         else_: Box<Expr<Ty>>,
     },
+    /// `#[e1, ..., en]`
+    Vector { elems: Vec<Expr<Ty>> },
 }
 
 pub type UntypedPattern = Pattern<Empty>;
@@ -175,25 +200,42 @@ pub enum PatternKind<Ty> {
     Wildcard {},
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// where a piece of the program starts in its source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Position {
+    /// the name of the source, like its path. Empty for the programs given as strings
+    pub source: Rc<str>,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SymbolTable {
     pub types: HashMap<Symbol, TypeInfo>,
     pub constructors: HashMap<Symbol, Symbol>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Type {
     Variable(u64),
     Char,
     Int,
     Real,
+    String,
+    Int64,
+    Word64,
+    Real32,
+    Array(Box<Type>),
+    Vector(Box<Type>),
     Fun(Box<Type>, Box<Type>),
     Tuple(Vec<Type>),
-    Datatype(Symbol),
+    Datatype(Symbol, Vec<Type>),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TypeInfo {
+    /// the type variables the constructors are parameterized over
+    pub params: Vec<u64>,
     pub constructors: Vec<(Symbol, Option<Type>)>,
 }
 
@@ -219,13 +261,30 @@ impl<Ty> CoreDeclaration<Ty> {
     fn map_ty<Ty2>(self, f: &mut dyn FnMut(Ty) -> Ty2) -> CoreDeclaration<Ty2> {
         use Declaration::*;
         match self {
-            Datatype { name, constructors } => Datatype { name, constructors },
+            Datatype {
+                name,
+                params,
+                constructors,
+            } => Datatype {
+                name,
+                params,
+                constructors,
+            },
 
             Val { pattern, expr, rec } => Val {
                 rec,
                 pattern: pattern.map_ty(&mut *f),
                 expr: expr.map_ty(f),
             },
+            Local { binds, body } => Local {
+                binds: binds.into_iter().map(|decl| decl.map_ty(f)).collect(),
+                body: body.into_iter().map(|decl| decl.map_ty(f)).collect(),
+            },
+            Structure { name, body } => Structure {
+                name,
+                body: body.into_iter().map(|decl| decl.map_ty(f)).collect(),
+            },
+            Exception { name, arg } => Exception { name, arg },
             D(d) => match d {},
         }
     }
@@ -265,12 +324,17 @@ impl<Ty> CoreExpr<Ty> {
                 fun: fun.map_ty(f).boxed(),
                 arg: arg.map_ty(f).boxed(),
             },
-            Case { cond, clauses } => Case {
+            Case {
+                cond,
+                clauses,
+                positions,
+            } => Case {
                 cond: cond.map_ty(&mut *f).boxed(),
                 clauses: clauses
                     .into_iter()
                     .map(move |(pat, expr)| (pat.map_ty(&mut *f), expr.map_ty(f)))
                     .collect(),
+                positions,
             },
             Tuple { tuple } => Tuple {
                 tuple: tuple.into_iter().map(|t| t.map_ty(f)).collect(),
@@ -366,6 +430,23 @@ impl Type {
     pub fn unit() -> Type {
         Type::Tuple(Vec::new())
     }
+
+    /// replace the type variables in `subst`
+    pub fn substitute(&self, subst: &HashMap<u64, Type>) -> Type {
+        use Type::*;
+        match self {
+            Variable(id) => subst.get(id).cloned().unwrap_or(Variable(*id)),
+            Char | Int | Real | String | Int64 | Word64 | Real32 => self.clone(),
+            Array(elem) => Array(Box::new(elem.substitute(subst))),
+            Vector(elem) => Vector(Box::new(elem.substitute(subst))),
+            Fun(param, ret) => Type::fun(param.substitute(subst), ret.substitute(subst)),
+            Tuple(tys) => Tuple(tys.iter().map(|ty| ty.substitute(subst)).collect()),
+            Datatype(name, args) => Datatype(
+                name.clone(),
+                args.iter().map(|ty| ty.substitute(subst)).collect(),
+            ),
+        }
+    }
 }
 
 impl SymbolTable {
@@ -383,6 +464,17 @@ impl SymbolTable {
         self.types.insert(name, info);
     }
 
+    /// add a constructor to the builtin `exn` type
+    pub fn register_exception(&mut self, name: Symbol, arg: Option<Type>) {
+        let exn = Symbol::new("exn");
+        self.constructors.insert(name.clone(), exn.clone());
+        self.types
+            .get_mut(&exn)
+            .expect("internal error: exn is not registered")
+            .constructors
+            .push((name, arg));
+    }
+
     pub fn get_type(&self, name: &Symbol) -> Option<&TypeInfo> {
         self.types.get(&name)
     }
@@ -423,6 +515,7 @@ pub enum TypeError<'a> {
     CannotInfer,
     FreeVar,
     NotFunction(ast::Expr<Type>),
+    IntOutOfRange(i64),
     ParseError(nom::Err<(&'a str, nom::error::ErrorKind)>),
 }
 
@@ -440,6 +533,7 @@ impl<'a> Error for TypeError<'a> {
             &CannotInfer => "cannot infer the type",
             &FreeVar => "free variable is found",
             &NotFunction(_) => "not a function",
+            &IntOutOfRange(_) => "integer constant doesn't fit in int",
             &ParseError(_) => "parse error",
         }
     }
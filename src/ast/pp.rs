@@ -40,8 +40,15 @@ impl<Ty: PP, DE: PP, DS: PP> PP for Declaration<Ty, DE, DS> {
     fn pp<W: io::Write>(&self, w: &mut W, indent: usize) -> io::Result<()> {
         use Declaration::*;
         match self {
-            Datatype { name, constructors } => {
+            Datatype {
+                name,
+                params,
+                constructors,
+            } => {
                 write!(w, "datatype ")?;
+                for param in params {
+                    write!(w, "'{} ", param)?;
+                }
                 name.pp(w, indent)?;
                 write!(w, " =")?;
                 inter_iter!(constructors, write!(w, " |")?, |(name, param)| =>{
@@ -67,6 +74,42 @@ impl<Ty: PP, DE: PP, DS: PP> PP for Declaration<Ty, DE, DS> {
                 expr.pp(w, indent + 4)?;
                 Ok(())
             }
+            Local { binds, body } => {
+                let ind = Self::nspaces(indent);
+                write!(w, "{}local\n", ind)?;
+                for bind in binds {
+                    bind.pp(w, indent + 4)?;
+                    write!(w, "\n")?;
+                }
+                write!(w, "{}in\n", ind)?;
+                for bind in body {
+                    bind.pp(w, indent + 4)?;
+                    write!(w, "\n")?;
+                }
+                write!(w, "{}end", ind)?;
+                Ok(())
+            }
+            Structure { name, body } => {
+                let ind = Self::nspaces(indent);
+                write!(w, "{}structure ", ind)?;
+                name.pp(w, indent)?;
+                write!(w, " = struct\n")?;
+                for decl in body {
+                    decl.pp(w, indent + 4)?;
+                    write!(w, "\n")?;
+                }
+                write!(w, "{}end", ind)?;
+                Ok(())
+            }
+            Exception { name, arg } => {
+                write!(w, "{}exception ", Self::nspaces(indent))?;
+                name.pp(w, indent)?;
+                if let Some(arg) = arg {
+                    write!(w, " of ")?;
+                    arg.pp(w, indent)?;
+                }
+                Ok(())
+            }
             D(d) => d.pp(w, indent),
         }
     }
@@ -81,8 +124,16 @@ impl<Ty: fmt::Display, DE: fmt::Display, DS: fmt::Display> fmt::Display
         let next = indent + 4;
 
         match self {
-            Datatype { name, constructors } => {
-                write!(f, "datatype {:indent$} =", name, indent = indent)?;
+            Datatype {
+                name,
+                params,
+                constructors,
+            } => {
+                write!(f, "datatype ")?;
+                for param in params {
+                    write!(f, "'{} ", param)?;
+                }
+                write!(f, "{:indent$} =", name, indent = indent)?;
                 inter_iter!(constructors, write!(f, " |")?, |(name, param)| =>{
                     write!(f, " {:indent$}", name, indent = indent)?;
                     if let Some(param) = param {
@@ -106,6 +157,35 @@ impl<Ty: fmt::Display, DE: fmt::Display, DS: fmt::Display> fmt::Display
                 )?;
                 Ok(())
             }
+            Local { binds, body } => {
+                let ind = nspaces(indent);
+                write!(f, "{}local\n", ind)?;
+                for bind in binds {
+                    write!(f, "{:next$}\n", bind, next = next)?;
+                }
+                write!(f, "{}in\n", ind)?;
+                for bind in body {
+                    write!(f, "{:next$}\n", bind, next = next)?;
+                }
+                write!(f, "{}end", ind)?;
+                Ok(())
+            }
+            Structure { name, body } => {
+                let ind = nspaces(indent);
+                write!(f, "{}structure {} = struct\n", ind, name)?;
+                for decl in body {
+                    write!(f, "{:next$}\n", decl, next = next)?;
+                }
+                write!(f, "{}end", ind)?;
+                Ok(())
+            }
+            Exception { name, arg } => {
+                write!(f, "{}exception {}", nspaces(indent), name)?;
+                if let Some(arg) = arg {
+                    write!(f, " of {}", arg)?;
+                }
+                Ok(())
+            }
             D(d) => write!(f, "{:indent$}", d, indent = indent),
         }
     }
@@ -135,8 +215,11 @@ impl<Ty: PP> PP for DerivedDeclaration<Ty> {
                 });
                 Ok(())
             }
-            Infix { priority, names } => {
-                write!(w, "infix")?;
+            Infix { priority, names } | Infixr { priority, names } => {
+                match self {
+                    Infixr { .. } => write!(w, "infixr")?,
+                    _ => write!(w, "infix")?,
+                }
                 if let Some(p) = priority {
                     write!(w, " {}", p)?;
                 }
@@ -173,8 +256,11 @@ impl<Ty: fmt::Display> fmt::Display for DerivedDeclaration<Ty> {
                 });
                 Ok(())
             }
-            Infix { priority, names } => {
-                write!(f, "infix")?;
+            Infix { priority, names } | Infixr { priority, names } => {
+                match self {
+                    Infixr { .. } => write!(f, "infixr")?,
+                    _ => write!(f, "infix")?,
+                }
                 if let Some(p) = priority {
                     write!(f, " {}", p)?;
                 }
@@ -254,7 +340,7 @@ impl<Ty: PP, DE: PP, DS: PP> PP for Expr<Ty, DE, DS> {
                 write!(w, ") ")?;
                 arg.pp(w, indent + 4)?;
             }
-            Case { cond, clauses } => {
+            Case { cond, clauses, .. } => {
                 let ind = Self::nspaces(indent);
                 write!(w, "case ")?;
                 cond.pp(w, indent + 4)?;
@@ -373,7 +459,7 @@ impl<Ty: fmt::Display, DE: fmt::Display, DS: fmt::Display> fmt::Display for Expr
                     next = next
                 )?;
             }
-            Case { cond, clauses } => {
+            Case { cond, clauses, .. } => {
                 let ind = nspaces(indent);
                 write!(f, "case {:next$} of", cond, next = next)?;
                 for (pat, arm) in clauses {
@@ -426,6 +512,17 @@ impl<Ty: PP> PP for DerivedExprKind<Ty> {
                 write!(w, "\n{}else ", ind)?;
                 else_.pp(w, indent + 4)?;
             }
+            Vector { elems } => {
+                write!(w, "#[")?;
+                inter_iter! {
+                    elems,
+                    write!(w, ", ")?,
+                    |elem| => {
+                        elem.pp(w, indent)?
+                    }
+                }
+                write!(w, "]")?;
+            }
         }
         Ok(())
     }
@@ -446,6 +543,17 @@ impl<Ty: fmt::Display> fmt::Display for DerivedExprKind<Ty> {
                 write!(f, "{}then {:next$}\n", ind, then, next = next)?;
                 write!(f, "{}else {:next$}", ind, else_, next = next)?;
             }
+            Vector { elems } => {
+                write!(f, "#[")?;
+                inter_iter!(
+                    elems,
+                    write!(f, ", ")?,
+                    |elem| => {
+                        write!(f, "{:next$}", elem, next = next)?
+                    }
+                );
+                write!(f, "]")?;
+            }
         }
         Ok(())
     }
@@ -538,6 +646,18 @@ impl PP for Type {
             Char => write!(w, "char")?,
             Int => write!(w, "int")?,
             Real => write!(w, "float")?,
+            String => write!(w, "string")?,
+            Int64 => write!(w, "int64")?,
+            Word64 => write!(w, "word64")?,
+            Real32 => write!(w, "float32")?,
+            Array(elem) => {
+                elem.pp(w, indent)?;
+                write!(w, " array")?;
+            }
+            Vector(elem) => {
+                elem.pp(w, indent)?;
+                write!(w, " vector")?;
+            }
             Fun(t1, t2) => {
                 t1.pp(w, indent)?;
                 write!(w, " -> ")?;
@@ -551,7 +671,20 @@ impl PP for Type {
                 }
                 write!(w, ")")?;
             }
-            Datatype(name) => name.pp(w, indent)?,
+            Datatype(name, args) => {
+                if !args.is_empty() {
+                    write!(w, "(")?;
+                    inter_iter! {
+                        args.iter(),
+                        write!(w, ", ")?,
+                        |ty| => {
+                            ty.pp(w, indent)?
+                        }
+                    }
+                    write!(w, ") ")?;
+                }
+                name.pp(w, indent)?
+            }
         }
         Ok(())
     }
@@ -565,6 +698,12 @@ impl fmt::Display for Type {
             Char => write!(f, "char")?,
             Int => write!(f, "int")?,
             Real => write!(f, "float")?,
+            String => write!(f, "string")?,
+            Int64 => write!(f, "int64")?,
+            Word64 => write!(f, "word64")?,
+            Real32 => write!(f, "float32")?,
+            Array(elem) => write!(f, "{} array", elem)?,
+            Vector(elem) => write!(f, "{} vector", elem)?,
             Fun(t1, t2) => {
                 write!(f, "{} -> {}", t1, t2)?;
             }
@@ -576,7 +715,20 @@ impl fmt::Display for Type {
                 }
                 write!(f, ")")?;
             }
-            Datatype(name) => write!(f, "{}", name)?,
+            Datatype(name, args) => {
+                if !args.is_empty() {
+                    write!(f, "(")?;
+                    inter_iter! {
+                        args.iter(),
+                        write!(f, ", ")?,
+                        |ty| => {
+                            write!(f, "{}", ty)?;
+                        }
+                    }
+                    write!(f, ") ")?;
+                }
+                write!(f, "{}", name)?
+            }
         }
         Ok(())
     }
@@ -593,3 +745,12 @@ impl fmt::Display for Empty {
         Ok(())
     }
 }
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.source.is_empty() {
+            write!(f, "{}:", self.source)?;
+        }
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
@@ -1,6 +1,7 @@
 use crate::ast::util::{Transform, Traverse};
 use crate::ast::*;
 use crate::config::Config;
+use crate::hir_unit::Interface;
 use crate::id::Id;
 use crate::pass::Pass;
 use crate::prim::*;
@@ -9,15 +10,23 @@ use std::ops::{Deref, DerefMut, Drop};
 
 pub struct Rename {
     symbol_table: Option<SymbolTable>,
-    variable_tables: Vec<HashMap<Symbol, u64>>,
-    type_tables: Vec<HashMap<Symbol, u64>>,
-    constructor_tables: Vec<HashMap<Symbol, u64>>,
+    variable_tables: Vec<HashMap<Symbol, Symbol>>,
+    type_tables: Vec<HashMap<Symbol, Symbol>>,
+    constructor_tables: Vec<HashMap<Symbol, Symbol>>,
+    // qualifiers of the names defined in each scope, like `List.`
+    prefixes: Vec<String>,
     pos: usize,
     id: Id,
 }
 
 struct Scope<'a>(&'a mut Rename);
 
+type Tables = (
+    HashMap<Symbol, Symbol>,
+    HashMap<Symbol, Symbol>,
+    HashMap<Symbol, Symbol>,
+);
+
 impl<'a> Deref for Scope<'a> {
     type Target = Rename;
     fn deref(&self) -> &Self::Target {
@@ -44,10 +53,12 @@ impl<'a> Scope<'a> {
             inner.variable_tables.push(HashMap::new());
             inner.type_tables.push(HashMap::new());
             inner.constructor_tables.push(HashMap::new());
+            inner.prefixes.push(String::new());
         } else {
             inner.variable_tables[pos].clear();
             inner.type_tables[pos].clear();
             inner.constructor_tables[pos].clear();
+            inner.prefixes[pos].clear();
         }
 
         inner.pos += 1;
@@ -58,25 +69,55 @@ impl<'a> Scope<'a> {
         Scope::new(self)
     }
 
-    fn new_variable(&mut self, symbol: &mut Symbol) {
+    fn prefix(&self) -> &str {
+        &self.prefixes[self.pos - 1]
+    }
+
+    fn set_prefix(&mut self, prefix: String) {
         let pos = self.pos - 1;
+        self.prefixes[pos] = prefix;
+    }
+
+    fn fresh_symbol(&mut self, symbol: &Symbol) -> Symbol {
         let new_id = self.id.next();
-        self.variable_tables[pos].insert(symbol.clone(), new_id);
-        symbol.1 = new_id;
+        Symbol(format!("{}{}", self.prefix(), symbol.0), new_id)
+    }
+
+    fn new_variable(&mut self, symbol: &mut Symbol) {
+        let pos = self.pos - 1;
+        let new = self.fresh_symbol(symbol);
+        self.variable_tables[pos].insert(symbol.clone(), new.clone());
+        *symbol = new;
     }
 
     fn new_type(&mut self, symbol: &mut Symbol) {
         let pos = self.pos - 1;
-        let new_id = self.id.next();
-        self.type_tables[pos].insert(symbol.clone(), new_id);
-        symbol.1 = new_id;
+        let new = self.fresh_symbol(symbol);
+        self.type_tables[pos].insert(symbol.clone(), new.clone());
+        *symbol = new;
     }
 
     fn new_constructor(&mut self, symbol: &mut Symbol) {
         let pos = self.pos - 1;
-        let new_id = self.id.next();
-        self.constructor_tables[pos].insert(symbol.clone(), new_id);
-        symbol.1 = new_id;
+        let new = self.fresh_symbol(symbol);
+        self.constructor_tables[pos].insert(symbol.clone(), new.clone());
+        *symbol = new;
+    }
+
+    fn current_tables(&self) -> Tables {
+        let pos = self.pos - 1;
+        (
+            self.variable_tables[pos].clone(),
+            self.type_tables[pos].clone(),
+            self.constructor_tables[pos].clone(),
+        )
+    }
+
+    fn extend_current_tables(&mut self, (variables, types, constructors): Tables) {
+        let pos = self.pos - 1;
+        self.variable_tables[pos].extend(variables);
+        self.type_tables[pos].extend(types);
+        self.constructor_tables[pos].extend(constructors);
     }
 
     fn is_constructor(&mut self, symbol: &Symbol) -> bool {
@@ -91,8 +132,8 @@ impl<'a> Scope<'a> {
         let pos = self.pos;
         for table in self.variable_tables[0..pos].iter_mut().rev() {
             match table.get(symbol) {
-                Some(new_id) => {
-                    symbol.1 = *new_id;
+                Some(new) => {
+                    *symbol = new.clone();
                     return;
                 }
                 None => {}
@@ -104,8 +145,8 @@ impl<'a> Scope<'a> {
         let pos = self.pos;
         for table in self.constructor_tables[0..pos].iter_mut().rev() {
             match table.get(symbol) {
-                Some(new_id) => {
-                    symbol.1 = *new_id;
+                Some(new) => {
+                    *symbol = new.clone();
                     return;
                 }
                 None => {}
@@ -117,10 +158,11 @@ impl<'a> Scope<'a> {
         use Type::*;
 
         match ty {
-            Variable(_) | Char | Int | Real => {
+            Variable(_) | Char | Int | Real | String | Int64 | Word64 | Real32 => {
                 // noop
                 ()
             }
+            Array(elem) | Vector(elem) => self.rename_type(elem),
             Fun(arg, body) => {
                 self.rename_type(arg);
                 self.rename_type(body);
@@ -130,12 +172,15 @@ impl<'a> Scope<'a> {
                     self.rename_type(t)
                 }
             }
-            Datatype(name) => {
+            Datatype(name, args) => {
+                for arg in args {
+                    self.rename_type(arg)
+                }
                 let pos = self.pos;
                 for table in self.type_tables[0..pos].iter_mut().rev() {
                     match table.get(name) {
-                        Some(new_id) => {
-                            name.1 = *new_id;
+                        Some(new) => {
+                            *name = new.clone();
                             return;
                         }
                         None => {}
@@ -150,6 +195,7 @@ impl<'a, Ty: Clone> util::Traverse<Ty> for Scope<'a> {
     fn traverse_datatype<'b, 'c>(
         &'b mut self,
         name: &mut Symbol,
+        params: &mut Vec<u64>,
         constructors: &mut Vec<(Symbol, Option<Type>)>,
     ) {
         let scope = self;
@@ -162,6 +208,7 @@ impl<'a, Ty: Clone> util::Traverse<Ty> for Scope<'a> {
         }
 
         let constructor_info = TypeInfo {
+            params: params.clone(),
             constructors: constructors.clone(),
         };
         scope
@@ -185,6 +232,58 @@ impl<'a, Ty: Clone> util::Traverse<Ty> for Scope<'a> {
         }
     }
 
+    // `local binds in body end` makes `binds` visible only to `body`,
+    // and exports `body` to the enclosing scope
+    fn traverse_local(
+        &mut self,
+        binds: &mut Vec<CoreDeclaration<Ty>>,
+        body: &mut Vec<CoreDeclaration<Ty>>,
+    ) {
+        let prefix = self.prefix().to_string();
+        let exported = {
+            let mut scope = self.new_scope();
+            for bind in binds.iter_mut() {
+                scope.traverse_statement(bind);
+            }
+            let mut scope = scope.new_scope();
+            scope.set_prefix(prefix);
+            for bind in body.iter_mut() {
+                scope.traverse_statement(bind);
+            }
+            scope.current_tables()
+        };
+        self.extend_current_tables(exported);
+    }
+
+    // the names defined in the structure `S` are exported as `S.name`
+    fn traverse_structure(&mut self, name: &mut Symbol, body: &mut Vec<CoreDeclaration<Ty>>) {
+        let prefix = format!("{}{}.", self.prefix(), name.0);
+        let (variables, types, constructors) = {
+            let mut scope = self.new_scope();
+            scope.set_prefix(prefix);
+            for decl in body.iter_mut() {
+                scope.traverse_statement(decl);
+            }
+            scope.current_tables()
+        };
+        let qualify = |table: HashMap<Symbol, Symbol>| {
+            table
+                .into_iter()
+                .map(|(key, symbol)| (Symbol::new(format!("{}.{}", name.0, key.0)), symbol))
+                .collect()
+        };
+        self.extend_current_tables((qualify(variables), qualify(types), qualify(constructors)));
+    }
+
+    fn traverse_exception(&mut self, name: &mut Symbol, arg: &mut Option<Type>) {
+        self.new_constructor(name);
+        if let Some(arg) = arg {
+            self.rename_type(arg);
+        }
+        self.symbol_table()
+            .register_exception(name.clone(), arg.clone());
+    }
+
     fn traverse_binds(
         &mut self,
         binds: &mut Vec<CoreDeclaration<Ty>>,
@@ -216,6 +315,13 @@ impl<'a, Ty: Clone> util::Traverse<Ty> for Scope<'a> {
         }
     }
 
+    fn traverse_constructor(&mut self, arg: &mut Option<Box<CoreExpr<Ty>>>, name: &mut Symbol) {
+        self.rename_constructor(name);
+        if let Some(arg) = arg {
+            self.traverse_expr(arg)
+        }
+    }
+
     fn traverse_sym(&mut self, name: &mut Symbol) {
         if self.is_constructor(name) {
             self.rename_constructor(name);
@@ -264,34 +370,65 @@ static BUILTIN_FUNCTIONS: &[(&str, BIF)] = &[
 impl Rename {
     pub fn new(id: Id) -> Self {
         // leave built in functions as non_renamed
-        let functions = BUILTIN_FUNCTIONS
-            .iter()
-            .map(|(s, _)| (Symbol::new(*s), 0))
-            .collect();
-        let datatypes = ["bool"].iter().map(|s| (Symbol::new(*s), 0)).collect();
-        let constructors = ["false", "true"]
-            .iter()
-            .map(|s| (Symbol::new(*s), 0))
-            .collect();
+        let builtin = |s: &str| (Symbol::new(s), Symbol::new(s));
+        let functions = BUILTIN_FUNCTIONS.iter().map(|(s, _)| builtin(s)).collect();
+        let datatypes = ["bool", "exn"].iter().map(|s| builtin(s)).collect();
+        let constructors = ["false", "true"].iter().map(|s| builtin(s)).collect();
 
         let mut symbol_table = SymbolTable::new();
         symbol_table.register_type(
             Symbol::new("bool"),
             TypeInfo {
+                params: vec![],
                 constructors: vec![(Symbol::new("false"), None), (Symbol::new("true"), None)],
             },
         );
+        // constructors of exn are added by each exception declaration
+        symbol_table.register_type(
+            Symbol::new("exn"),
+            TypeInfo {
+                params: vec![],
+                constructors: vec![],
+            },
+        );
 
         Rename {
             symbol_table: Some(symbol_table),
             variable_tables: vec![functions],
             type_tables: vec![datatypes],
             constructor_tables: vec![constructors],
+            prefixes: vec![String::new()],
             pos: 0,
             id,
         }
     }
 
+    /// rename with the bindings of `interface` visible from the top level
+    pub fn with_interface(id: Id, interface: &Interface) -> Self {
+        let mut rename = Self::new(id);
+        for (name, _) in &interface.values {
+            rename.variable_tables[0].insert(Symbol::new(name.0.as_str()), name.clone());
+        }
+        for (name, info) in &interface.types {
+            rename.type_tables[0].insert(Symbol::new(name.0.as_str()), name.clone());
+            for (cname, _) in &info.constructors {
+                rename.constructor_tables[0].insert(Symbol::new(cname.0.as_str()), cname.clone());
+            }
+            rename
+                .symbol_table()
+                .register_type(name.clone(), info.clone());
+        }
+        for (name, arg) in &interface.exceptions {
+            rename.constructor_tables[0].insert(Symbol::new(name.0.as_str()), name.clone());
+            rename
+                .symbol_table()
+                .register_exception(name.clone(), arg.clone());
+        }
+        // the scope of the unit starts above the interface
+        rename.pos = 1;
+        rename
+    }
+
     fn symbol_table(&mut self) -> &mut SymbolTable {
         self.symbol_table.as_mut().unwrap()
     }
@@ -333,6 +470,13 @@ impl Transform<Empty> for WrapBIF {
             if let Some(bif) = self.bif_table.get(&name.0).cloned() {
                 use BIF::*;
                 return match bif {
+                    Raise | Ord | Chr | ToReal | Floor | Ceil | Round | Trunc | StringSize
+                    | StringSub | StringAlloc | StringUpdate | ArrayAlloc | ArrayLength
+                    | ArraySub | ArrayUpdate | ArrayToVector | VectorToArray | IntToInt64
+                    | Int64ToInt | Int64ToWord64 | Word64ToInt64 | RealToReal32 | Real32ToReal
+                    | Sqrt | RealFloor | RealCeil | RealRound | RealTrunc => {
+                        unreachable!("{} is not an operator", bif)
+                    }
                     Add | Sub | Mul | Div | Divf | Mod | Eq | Neq | Gt | Ge | Lt | Le => {
                         let tuple = self.gensym("tuple");
                         let l = self.gensym("x");
@@ -385,6 +529,7 @@ impl Transform<Empty> for WrapBIF {
                                             },
                                         },
                                     )],
+                                    positions: vec![],
                                 },
                             }
                             .boxed(),
@@ -1,24 +1,36 @@
 use crate::ast::*;
 use crate::config::Config;
+use crate::hir_unit::Interface;
 use crate::id::Id;
 use crate::prim::*;
 use crate::unification_pool::{NodeId, UnificationPool};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug)]
-pub struct Typer;
+pub struct Typer {
+    env: Vec<(Symbol, Type)>,
+}
 
 #[derive(Debug)]
 struct TyEnv {
-    env: HashMap<Symbol, NodeId>,
+    env: HashMap<Symbol, Scheme>,
     symbol_table: SymbolTable,
     pool: TypePool,
 }
 
+/// `ty` quantified over the type variables `vars`
+#[derive(Debug, Clone)]
+struct Scheme {
+    vars: Vec<u64>,
+    ty: NodeId,
+}
+
 #[derive(Debug)]
 struct TypePool {
     cache: HashMap<Typing, NodeId>,
     pool: UnificationPool<Typing>,
+    // type variables quantified by some let-bound value
+    generalized: HashSet<u64>,
     id: Id,
 }
 
@@ -28,11 +40,35 @@ enum Typing {
     Char,
     Int,
     Real,
+    String,
+    Int64,
+    Word64,
+    Real32,
+    Array(NodeId),
+    Vector(NodeId),
     Fun(NodeId, NodeId),
     Tuple(Vec<NodeId>),
-    Datatype(Symbol),
+    Datatype(Symbol, Vec<NodeId>),
     OverloadedNum,
     OverloadedNumText,
+    OverloadedInt,
+    OverloadedReal,
+}
+
+/// the types an overloaded type can be resolved to. Empty if not overloaded.
+fn overloads(ty: &Typing) -> &'static [Typing] {
+    use Typing::*;
+    const NUM: &[Typing] = &[Int, Int64, Word64, Real, Real32];
+    const NUM_TEXT: &[Typing] = &[Int, Int64, Word64, Real, Real32, Char];
+    const INT: &[Typing] = &[Int, Int64, Word64];
+    const REAL: &[Typing] = &[Real, Real32];
+    match ty {
+        OverloadedNum => NUM,
+        OverloadedNumText => NUM_TEXT,
+        OverloadedInt => INT,
+        OverloadedReal => REAL,
+        _ => &[],
+    }
 }
 
 fn resolve(pool: &UnificationPool<Typing>, id: NodeId) -> Type {
@@ -46,14 +82,23 @@ fn conv_ty(pool: &UnificationPool<Typing>, ty: Typing) -> Type {
         Char => Type::Char,
         Int => Type::Int,
         Real => Type::Real,
+        String => Type::String,
+        Int64 => Type::Int64,
+        Word64 => Type::Word64,
+        Real32 => Type::Real32,
+        Array(elem) => Type::Array(Box::new(resolve(pool, elem))),
+        Vector(elem) => Type::Vector(Box::new(resolve(pool, elem))),
         Fun(param, body) => Type::Fun(
             Box::new(resolve(pool, param)),
             Box::new(resolve(pool, body)),
         ),
         Tuple(tys) => Type::Tuple(tys.into_iter().map(|ty| resolve(pool, ty)).collect()),
-        Datatype(type_id) => Type::Datatype(type_id),
-        OverloadedNum => Type::Int,
-        OverloadedNumText => Type::Int,
+        Datatype(type_id, args) => Type::Datatype(
+            type_id,
+            args.into_iter().map(|ty| resolve(pool, ty)).collect(),
+        ),
+        OverloadedNum | OverloadedNumText | OverloadedInt => Type::Int,
+        OverloadedReal => Type::Real,
     }
 }
 
@@ -65,15 +110,32 @@ fn try_unify<'b, 'r>(
     use Typing::*;
     match (t1, t2) {
         (t1, t2) if t1 == t2 => Ok(t1),
-        (Int, OverloadedNum) | (OverloadedNum, Int) => Ok(Int),
-        (Int, OverloadedNumText) | (OverloadedNumText, Int) => Ok(Int),
-        (Char, OverloadedNumText) | (OverloadedNumText, Char) => Ok(Char),
-        (Real, OverloadedNum) | (OverloadedNum, Real) => Ok(Real),
-        (Real, OverloadedNumText) | (OverloadedNumText, Real) => Ok(Real),
-        (OverloadedNumText, OverloadedNum) | (OverloadedNum, OverloadedNumText) => {
-            Ok(OverloadedNumText)
+        // the overloads are either nested or disjoint, so the narrower one is the intersection
+        (t1, t2) if !overloads(&t1).is_empty() && !overloads(&t2).is_empty() => {
+            let (o1, o2) = (overloads(&t1), overloads(&t2));
+            if o1.iter().all(|t| o2.contains(t)) {
+                Ok(t1)
+            } else if o2.iter().all(|t| o1.contains(t)) {
+                Ok(t2)
+            } else {
+                Err(TypeError::MisMatch {
+                    expected: conv_ty(pool, t1),
+                    actual: conv_ty(pool, t2),
+                })
+            }
         }
+        (t, overloaded) | (overloaded, t) if overloads(&overloaded).contains(&t) => Ok(t),
         (Variable(_), ty) | (ty, Variable(_)) => Ok(ty),
+        (Datatype(n1, args1), Datatype(n2, args2)) if n1 == n2 => {
+            let args = args1
+                .into_iter()
+                .zip(args2)
+                .map(|(t1, t2)| pool.try_unify_with(t1, t2, try_unify))
+                .collect::<Result<'_, Vec<_>>>()?;
+            Ok(Datatype(n1, args))
+        }
+        (Array(e1), Array(e2)) => Ok(Array(pool.try_unify_with(e1, e2, try_unify)?)),
+        (Vector(e1), Vector(e2)) => Ok(Vector(pool.try_unify_with(e1, e2, try_unify)?)),
         (Fun(p1, b1), Fun(p2, b2)) => {
             let p = pool.try_unify_with(p1, p2, try_unify)?;
             let b = pool.try_unify_with(b1, b2, try_unify)?;
@@ -103,11 +165,26 @@ fn try_unify<'b, 'r>(
 
 impl Typer {
     pub fn new() -> Self {
-        Typer
+        Typer { env: Vec::new() }
+    }
+
+    /// type with the values of `interface` in the environment
+    pub fn with_interface(interface: &Interface) -> Self {
+        Typer {
+            env: interface.values.clone(),
+        }
     }
 
     fn generate_pass(&mut self, symbol_table: SymbolTable) -> TyEnv {
-        TyEnv::new(symbol_table)
+        let mut tyenv = TyEnv::new(symbol_table);
+        for (name, ty) in self.env.iter().cloned() {
+            // values of other units are polymorphic over all of their type variables
+            let mut vars = HashMap::new();
+            let ty = tyenv.convert(ty, &mut vars);
+            let vars = vars.values().map(|&var| tyenv.pool.var_id(var)).collect();
+            tyenv.insert(name, Scheme { vars, ty });
+        }
+        tyenv
     }
 }
 
@@ -116,6 +193,7 @@ impl TypePool {
         let mut ret = Self {
             cache: HashMap::new(),
             pool: UnificationPool::new(),
+            generalized: HashSet::new(),
             id: Id::new(),
         };
         ret.init();
@@ -126,11 +204,17 @@ impl TypePool {
         self.node_new(Typing::Char);
         self.node_new(Typing::Int);
         self.node_new(Typing::Real);
+        self.node_new(Typing::String);
+        self.node_new(Typing::Int64);
+        self.node_new(Typing::Word64);
+        self.node_new(Typing::Real32);
     }
 
     fn feed_symbol_table(&mut self, symbol_table: &SymbolTable) {
-        for typename in symbol_table.types.keys() {
-            self.node_new(Typing::Datatype(typename.clone()));
+        for (typename, info) in symbol_table.types.iter() {
+            if info.params.is_empty() {
+                self.node_new(Typing::Datatype(typename.clone(), vec![]));
+            }
         }
     }
 
@@ -138,6 +222,71 @@ impl TypePool {
         self.pool.node_new(Typing::Variable(self.id.next()))
     }
 
+    fn var_id(&self, var: NodeId) -> u64 {
+        match self.pool.value_of(var) {
+            Typing::Variable(id) => *id,
+            _ => panic!("internal error: not a type variable"),
+        }
+    }
+
+    fn free_vars(&self, ty: NodeId, acc: &mut HashSet<u64>) {
+        use Typing::*;
+        match self.pool.value_of(ty) {
+            Variable(id) => {
+                acc.insert(*id);
+            }
+            Array(elem) | Vector(elem) => self.free_vars(*elem, acc),
+            Fun(param, body) => {
+                self.free_vars(*param, acc);
+                self.free_vars(*body, acc);
+            }
+            Tuple(tys) | Datatype(_, tys) => {
+                for ty in tys {
+                    self.free_vars(*ty, acc)
+                }
+            }
+            Char | Int | Real | String | Int64 | Word64 | Real32 | OverloadedNum
+            | OverloadedNumText | OverloadedInt | OverloadedReal => (),
+        }
+    }
+
+    /// copy `ty` replacing the type variables in `subst`
+    fn instantiate(&mut self, ty: NodeId, subst: &HashMap<u64, NodeId>) -> NodeId {
+        use Typing::*;
+        match self.pool.value_of(ty).clone() {
+            Variable(id) => subst.get(&id).cloned().unwrap_or(ty),
+            Array(elem) => {
+                let elem = self.instantiate(elem, subst);
+                self.ty(Array(elem))
+            }
+            Vector(elem) => {
+                let elem = self.instantiate(elem, subst);
+                self.ty(Vector(elem))
+            }
+            Fun(param, body) => {
+                let param = self.instantiate(param, subst);
+                let body = self.instantiate(body, subst);
+                self.ty(Fun(param, body))
+            }
+            Tuple(tys) => {
+                let tys = tys
+                    .into_iter()
+                    .map(|ty| self.instantiate(ty, subst))
+                    .collect();
+                self.ty(Tuple(tys))
+            }
+            Datatype(name, args) => {
+                let args = args
+                    .into_iter()
+                    .map(|ty| self.instantiate(ty, subst))
+                    .collect();
+                self.ty(Datatype(name, args))
+            }
+            Char | Int | Real | String | Int64 | Word64 | Real32 | OverloadedNum
+            | OverloadedNumText | OverloadedInt | OverloadedReal => ty,
+        }
+    }
+
     fn ty(&mut self, ty: Typing) -> NodeId {
         self.pool.node_new(ty)
     }
@@ -153,7 +302,14 @@ impl TypePool {
     fn ty_bool(&mut self) -> NodeId {
         *self
             .cache
-            .get(&Typing::Datatype(Symbol::new("bool")))
+            .get(&Typing::Datatype(Symbol::new("bool"), vec![]))
+            .unwrap()
+    }
+
+    fn ty_exn(&mut self) -> NodeId {
+        *self
+            .cache
+            .get(&Typing::Datatype(Symbol::new("exn"), vec![]))
             .unwrap()
     }
 
@@ -161,6 +317,22 @@ impl TypePool {
         *self.cache.get(&Typing::Real).unwrap()
     }
 
+    fn ty_string(&mut self) -> NodeId {
+        *self.cache.get(&Typing::String).unwrap()
+    }
+
+    fn ty_int64(&mut self) -> NodeId {
+        *self.cache.get(&Typing::Int64).unwrap()
+    }
+
+    fn ty_word64(&mut self) -> NodeId {
+        *self.cache.get(&Typing::Word64).unwrap()
+    }
+
+    fn ty_real32(&mut self) -> NodeId {
+        *self.cache.get(&Typing::Real32).unwrap()
+    }
+
     fn ty_overloaded_num(&mut self) -> NodeId {
         self.node_new(Typing::OverloadedNum)
     }
@@ -169,13 +341,29 @@ impl TypePool {
         self.node_new(Typing::OverloadedNumText)
     }
 
+    fn ty_overloaded_int(&mut self) -> NodeId {
+        self.node_new(Typing::OverloadedInt)
+    }
+
+    fn ty_overloaded_real(&mut self) -> NodeId {
+        self.node_new(Typing::OverloadedReal)
+    }
+
     fn node_new(&mut self, t: Typing) -> NodeId {
         let node_id = self.pool.node_new(t.clone());
-        match t {
-            t @ Typing::Char | t @ Typing::Int | t @ Typing::Real | t @ Typing::Datatype(_) => {
-                self.cache.insert(t, node_id);
-            }
-            _ => (), // no cache
+        let cacheable = match &t {
+            Typing::Char
+            | Typing::Int
+            | Typing::Real
+            | Typing::String
+            | Typing::Int64
+            | Typing::Word64
+            | Typing::Real32 => true,
+            Typing::Datatype(_, args) => args.is_empty(),
+            _ => false,
+        };
+        if cacheable {
+            self.cache.insert(t, node_id);
         }
         node_id
     }
@@ -198,7 +386,34 @@ impl TypePool {
 
 impl TypePool {
     fn typed_ast(&self, ast: Core<NodeId>) -> TypedCore {
-        ast.map_ty(&mut |ty| resolve(&self.pool, ty))
+        ast.map_ty(&mut |ty| self.default_unit(resolve(&self.pool, ty)))
+    }
+
+    // type variables nobody quantifies can be anything. Make them unit.
+    fn default_unit(&self, ty: Type) -> Type {
+        match ty {
+            Type::Variable(id) if !self.generalized.contains(&id) => Type::unit(),
+            Type::Variable(_)
+            | Type::Char
+            | Type::Int
+            | Type::Real
+            | Type::String
+            | Type::Int64
+            | Type::Word64
+            | Type::Real32 => ty,
+            Type::Array(elem) => Type::Array(Box::new(self.default_unit(*elem))),
+            Type::Vector(elem) => Type::Vector(Box::new(self.default_unit(*elem))),
+            Type::Fun(param, body) => {
+                Type::fun(self.default_unit(*param), self.default_unit(*body))
+            }
+            Type::Tuple(tys) => {
+                Type::Tuple(tys.into_iter().map(|ty| self.default_unit(ty)).collect())
+            }
+            Type::Datatype(name, args) => Type::Datatype(
+                name,
+                args.into_iter().map(|ty| self.default_unit(ty)).collect(),
+            ),
+        }
     }
 }
 
@@ -216,22 +431,6 @@ impl TyEnv {
 
     fn init(&mut self) {
         self.pool.feed_symbol_table(&self.symbol_table);
-        let cnames = self
-            .symbol_table
-            .constructors
-            .keys()
-            .cloned()
-            .collect::<Vec<_>>();
-        for cname in cnames {
-            let ty = self
-                .symbol_table
-                .get_datatype_of_constructor(&cname)
-                .expect("internal error: typing");
-            let ty = Type::Datatype(ty.clone());
-            let typing = self.convert(ty);
-            let node_id = self.pool.ty(typing);
-            self.insert(cname, node_id);
-        }
     }
 
     pub fn infer<'a, 'b>(&'a mut self, ast: &mut ast::Core<NodeId>) -> Result<'b, ()> {
@@ -239,44 +438,148 @@ impl TyEnv {
         Ok(())
     }
 
-    fn symbol_table(&self) -> &SymbolTable {
-        &self.symbol_table
-    }
-
     pub fn into_symbol_table(self) -> SymbolTable {
         self.symbol_table
     }
 
-    fn get(&self, name: &Symbol) -> Option<NodeId> {
-        self.env.get(name).cloned()
+    /// the type of `name`, instantiated if polymorphic
+    fn get(&mut self, name: &Symbol) -> Option<NodeId> {
+        let Scheme { vars, ty } = self.env.get(name).cloned()?;
+        if vars.is_empty() {
+            return Some(ty);
+        }
+        let subst = vars
+            .into_iter()
+            .map(|var| (var, self.pool.tyvar()))
+            .collect();
+        Some(self.pool.instantiate(ty, &subst))
+    }
+
+    fn insert(&mut self, k: Symbol, ty: Scheme) -> Option<Scheme> {
+        self.env.insert(k, ty)
+    }
+
+    fn insert_mono(&mut self, k: Symbol, ty: NodeId) -> Option<Scheme> {
+        self.insert(k, Scheme { vars: vec![], ty })
     }
 
-    fn insert(&mut self, k: Symbol, v: NodeId) -> Option<NodeId> {
-        self.env.insert(k, v)
+    fn remove(&mut self, k: &Symbol) {
+        self.env.remove(k);
     }
 
-    fn convert(&mut self, ty: Type) -> Typing {
+    /// convert `ty`, mapping its type variables with `vars`, or to fresh ones if not found
+    fn convert(&mut self, ty: Type, vars: &mut HashMap<u64, NodeId>) -> NodeId {
         match ty {
-            Type::Variable(v) => Typing::Variable(v),
-            Type::Char => Typing::Char,
-            Type::Int => Typing::Int,
-            Type::Real => Typing::Real,
+            Type::Variable(v) => {
+                let pool = &mut self.pool;
+                *vars.entry(v).or_insert_with(|| pool.tyvar())
+            }
+            Type::Char => self.pool.ty(Typing::Char),
+            Type::Int => self.pool.ty(Typing::Int),
+            Type::Real => self.pool.ty(Typing::Real),
+            Type::String => self.pool.ty(Typing::String),
+            Type::Int64 => self.pool.ty(Typing::Int64),
+            Type::Word64 => self.pool.ty(Typing::Word64),
+            Type::Real32 => self.pool.ty(Typing::Real32),
+            Type::Array(elem) => {
+                let elem = self.convert(*elem, vars);
+                self.pool.ty(Typing::Array(elem))
+            }
+            Type::Vector(elem) => {
+                let elem = self.convert(*elem, vars);
+                self.pool.ty(Typing::Vector(elem))
+            }
             Type::Fun(arg, ret) => {
-                let arg_typing = self.convert(*arg);
-                let ret_typing = self.convert(*ret);
-                Typing::Fun(self.pool.ty(arg_typing), self.pool.ty(ret_typing))
+                let arg = self.convert(*arg, vars);
+                let ret = self.convert(*ret, vars);
+                self.pool.ty(Typing::Fun(arg, ret))
+            }
+            Type::Tuple(tuple) => {
+                let tuple = tuple.into_iter().map(|ty| self.convert(ty, vars)).collect();
+                self.pool.ty(Typing::Tuple(tuple))
             }
-            Type::Tuple(tuple) => Typing::Tuple(
-                tuple
+            Type::Datatype(name, args) => {
+                let args = args.into_iter().map(|ty| self.convert(ty, vars)).collect();
+                self.pool.ty(Typing::Datatype(name, args))
+            }
+        }
+    }
+
+    /// a fresh instance of the datatype `name` belongs to and the type of its argument
+    fn constructor_type(&mut self, name: &Symbol) -> Option<(NodeId, Option<NodeId>)> {
+        let type_name = self.symbol_table.get_datatype_of_constructor(name)?.clone();
+        let info = self.symbol_table.get_type(&type_name)?;
+        let params = info.params.clone();
+        let arg = info
+            .constructors
+            .iter()
+            .find(|(cname, _)| cname == name)?
+            .1
+            .clone();
+        let mut vars = HashMap::new();
+        let args = params
+            .into_iter()
+            .map(|param| *vars.entry(param).or_insert_with(|| self.pool.tyvar()))
+            .collect();
+        let ty = self.pool.ty(Typing::Datatype(type_name, args));
+        let arg = arg.map(|arg| self.convert(arg, &mut vars));
+        Some((ty, arg))
+    }
+
+    /// quantify the type variables of `ty` that are not free in the environment.
+    /// `names` are the names being defined with `ty`.
+    fn generalize(&mut self, ty: NodeId, names: &[&Symbol]) -> Vec<u64> {
+        let mut vars = HashSet::new();
+        self.pool.free_vars(ty, &mut vars);
+        if vars.is_empty() {
+            return vec![];
+        }
+        let mut env_vars = HashSet::new();
+        for (name, scheme) in self.env.iter() {
+            if names.contains(&name) {
+                continue;
+            }
+            let mut scheme_vars = HashSet::new();
+            self.pool.free_vars(scheme.ty, &mut scheme_vars);
+            env_vars.extend(
+                scheme_vars
                     .into_iter()
-                    .map(|ty| {
-                        let typing = self.convert(ty);
-                        self.pool.ty(typing)
-                    })
-                    .collect(),
-            ),
-            Type::Datatype(name) => Typing::Datatype(name),
+                    .filter(|var| !scheme.vars.contains(var)),
+            );
         }
+        let vars = vars
+            .into_iter()
+            .filter(|var| !env_vars.contains(var))
+            .collect::<Vec<_>>();
+        self.pool.generalized.extend(vars.iter().cloned());
+        vars
+    }
+}
+
+/// the value restriction: only values are generalized
+fn is_value<Ty>(expr: &CoreExpr<Ty>) -> bool {
+    use crate::ast::ExprKind::*;
+    match &expr.inner {
+        Fn { .. } | Symbol { .. } | Literal { .. } => true,
+        Constructor { arg, .. } => arg.iter().all(|arg| is_value(arg)),
+        Tuple { tuple } => tuple.iter().all(is_value),
+        _ => false,
+    }
+}
+
+/// names `decl` binds
+fn bound_names<Ty>(decl: &CoreDeclaration<Ty>) -> Vec<Symbol> {
+    use Declaration::*;
+    match decl {
+        Val { pattern, .. } => pattern
+            .binds()
+            .into_iter()
+            .map(|(name, _)| name.clone())
+            .collect(),
+        Local { binds, body } => binds.iter().chain(body).flat_map(bound_names).collect(),
+        Structure { body, .. } => body.iter().flat_map(bound_names).collect(),
+        Datatype { .. } | Exception { .. } => vec![],
+        D(d) => match *d {},
     }
 }
 
@@ -291,21 +594,43 @@ impl TyEnv {
     fn infer_statement<'b, 'r>(&'b mut self, decl: &CoreDeclaration<NodeId>) -> Result<'r, ()> {
         use Declaration::*;
         match decl {
-            Datatype { .. } => Ok(()),
+            Datatype { .. } | Exception { .. } => Ok(()),
             Val { rec, pattern, expr } => {
                 let names = pattern.binds();
                 if *rec {
                     for &(name, ty) in &names {
-                        self.insert(name.clone(), ty.clone());
+                        self.insert_mono(name.clone(), ty.clone());
                     }
                 }
                 self.infer_expr(expr)?;
                 self.infer_pat(pattern)?;
                 self.unify(expr.ty(), pattern.ty())?;
-                if !rec {
-                    for &(name, ty) in &names {
-                        self.insert(name.clone(), ty.clone());
-                    }
+                let vars = if pattern.is_variable() && is_value(expr) {
+                    let bound = names.iter().map(|&(name, _)| name).collect::<Vec<_>>();
+                    self.generalize(pattern.ty(), &bound)
+                } else {
+                    vec![]
+                };
+                for &(name, ty) in &names {
+                    self.insert(
+                        name.clone(),
+                        Scheme {
+                            vars: vars.clone(),
+                            ty: ty.clone(),
+                        },
+                    );
+                }
+                Ok(())
+            }
+            Local { binds, body } => {
+                for decl in binds.iter().chain(body) {
+                    self.infer_statement(decl)?;
+                }
+                Ok(())
+            }
+            Structure { body, .. } => {
+                for decl in body {
+                    self.infer_statement(decl)?;
                 }
                 Ok(())
             }
@@ -328,6 +653,9 @@ impl TyEnv {
                 }
                 self.unify(ret.ty(), *ty)?;
                 self.infer_expr(ret)?;
+                for name in binds.iter().flat_map(bound_names) {
+                    self.remove(&name);
+                }
                 Ok(())
             }
             BuiltinCall { fun, args } => {
@@ -357,28 +685,99 @@ impl TyEnv {
                         self.unify(*ty, bool)?;
                         Ok(())
                     }
-                    Div | Mod => {
+                    Div | Mod | Divf => {
                         assert!(args.len() == 2);
                         let l = &args[0];
                         let r = &args[1];
+                        let overloaded = match fun {
+                            Divf => self.pool.ty_overloaded_real(),
+                            _ => self.pool.ty_overloaded_int(),
+                        };
 
-                        self.unify(l.ty(), int)?;
-                        self.unify(r.ty(), int)?;
-                        self.unify(*ty, int)?;
                         self.infer_expr(l)?;
                         self.infer_expr(r)?;
+                        self.unify(l.ty(), r.ty())?;
+                        self.unify(l.ty(), overloaded)?;
+                        self.unify(*ty, l.ty())?;
                         Ok(())
                     }
-                    Divf => {
-                        assert!(args.len() == 2);
-                        let l = &args[0];
-                        let r = &args[1];
+                    Raise => {
+                        assert!(args.len() == 1);
+                        let exn = &args[0];
+                        let ty_exn = self.pool.ty_exn();
 
-                        self.unify(l.ty(), real)?;
-                        self.unify(r.ty(), real)?;
-                        self.unify(*ty, real)?;
-                        self.infer_expr(l)?;
-                        self.infer_expr(r)?;
+                        self.infer_expr(exn)?;
+                        self.unify(exn.ty(), ty_exn)?;
+                        Ok(())
+                    }
+                    Ord | Chr | ToReal | Floor | Ceil | Round | Trunc | IntToInt64 | Int64ToInt
+                    | Int64ToWord64 | Word64ToInt64 | RealToReal32 | Real32ToReal | Sqrt
+                    | RealFloor | RealCeil | RealRound | RealTrunc => {
+                        assert!(args.len() == 1);
+                        let arg = &args[0];
+                        let char = self.pool.ty_char();
+                        let int64 = self.pool.ty_int64();
+                        let word64 = self.pool.ty_word64();
+                        let real32 = self.pool.ty_real32();
+                        let (argty, retty) = match fun {
+                            Ord => (char, int),
+                            Chr => (int, char),
+                            ToReal => (int, real),
+                            IntToInt64 => (int, int64),
+                            Int64ToInt => (int64, int),
+                            Int64ToWord64 => (int64, word64),
+                            Word64ToInt64 => (word64, int64),
+                            RealToReal32 => (real, real32),
+                            Real32ToReal => (real32, real),
+                            Sqrt | RealFloor | RealCeil | RealRound | RealTrunc => (real, real),
+                            _ => (real, int),
+                        };
+
+                        self.unify(arg.ty(), argty)?;
+                        self.unify(*ty, retty)?;
+                        self.infer_expr(arg)?;
+                        Ok(())
+                    }
+                    StringSize | StringSub | StringAlloc | StringUpdate => {
+                        let string = self.pool.ty_string();
+                        let char = self.pool.ty_char();
+                        let unit = self.pool.ty(Typing::Tuple(vec![]));
+                        let (argtys, retty) = match fun {
+                            StringSize => (vec![string], int),
+                            StringSub => (vec![string, int], char),
+                            StringAlloc => (vec![int], string),
+                            _ => (vec![string, int, char], unit),
+                        };
+                        assert!(args.len() == argtys.len());
+
+                        for (arg, argty) in args.iter().zip(argtys) {
+                            self.unify(arg.ty(), argty)?;
+                            self.infer_expr(arg)?;
+                        }
+                        self.unify(*ty, retty)?;
+                        Ok(())
+                    }
+                    ArrayAlloc | ArrayLength | ArraySub | ArrayUpdate | ArrayToVector
+                    | VectorToArray => {
+                        let elem = self.pool.tyvar();
+                        let array = self.pool.ty(Typing::Array(elem));
+                        let vector = self.pool.ty(Typing::Vector(elem));
+                        let unit = self.pool.ty(Typing::Tuple(vec![]));
+                        let (argtys, retty) = match fun {
+                            ArrayAlloc => (vec![int], array),
+                            ArrayLength => (vec![array], int),
+                            ArraySub => (vec![array, int], elem),
+                            ArrayUpdate => (vec![array, int, elem], unit),
+                            ArrayToVector => (vec![array], vector),
+                            _ => (vec![vector], array),
+                        };
+                        assert!(args.len() == argtys.len());
+
+                        for (arg, argty) in args.iter().zip(argtys) {
+                            self.unify(arg.ty(), argty)?;
+                            self.infer_expr(arg)?;
+                        }
+                        self.unify(*ty, retty)?;
                         Ok(())
                     }
                 }
@@ -388,17 +787,18 @@ impl TyEnv {
             } => {
                 for (arg, argty) in args.into_iter().zip(argty) {
                     self.infer_expr(arg)?;
-                    let argty = self.convert(argty.clone());
-                    self.give(arg.ty(), argty)?;
+                    let argty = self.convert(argty.clone(), &mut HashMap::new());
+                    self.unify(arg.ty(), argty)?;
                 }
-                let retty = self.convert(retty.clone());
-                self.give(*ty, retty)?;
+                let retty = self.convert(retty.clone(), &mut HashMap::new());
+                self.unify(*ty, retty)?;
                 Ok(())
             }
             Fn { param, body } => {
                 let param_ty = self.pool.tyvar();
-                self.insert(param.clone(), param_ty);
+                self.insert_mono(param.clone(), param_ty);
                 self.infer_expr(body)?;
+                self.remove(param);
                 self.give(*ty, Typing::Fun(param_ty, body.ty()))?;
                 Ok(())
             }
@@ -408,13 +808,16 @@ impl TyEnv {
                 self.give(fun.ty(), Typing::Fun(arg.ty(), *ty))?;
                 Ok(())
             }
-            Case { cond, clauses } => {
+            Case { cond, clauses, .. } => {
                 self.infer_expr(cond)?;
                 for (pat, branch) in clauses {
                     self.infer_pat(pat)?;
                     self.unify(pat.ty(), cond.ty())?;
                     self.infer_expr(branch)?;
                     self.unify(branch.ty(), *ty)?;
+                    for (name, _) in pat.binds() {
+                        self.remove(name);
+                    }
                 }
                 Ok(())
             }
@@ -444,15 +847,12 @@ impl TyEnv {
         arg: &Option<Box<CoreExpr<NodeId>>>,
         given: NodeId,
     ) -> Result<'r, ()> {
-        match self.get(&sym) {
-            Some(ty) => {
+        match self.constructor_type(&sym) {
+            Some((ty, arg_ty)) => {
                 self.unify(ty, given)?;
-                let arg_ty = self.symbol_table().get_argtype_of_constructor(sym);
-                if let (Some(arg), Some(arg_ty)) = (arg.clone(), arg_ty.cloned()) {
+                if let (Some(arg), Some(arg_ty)) = (arg, arg_ty) {
                     self.infer_expr(&arg)?;
-                    let arg_typing = self.convert(arg_ty);
-                    let arg_ty_id = self.pool.ty(arg_typing);
-                    self.unify(arg.ty(), arg_ty_id)?;
+                    self.unify(arg.ty(), arg_ty)?;
                 }
                 Ok(())
             }
@@ -470,15 +870,20 @@ impl TyEnv {
     fn infer_literal<'b, 'r>(&'b mut self, lit: &Literal, given: NodeId) -> Result<'r, ()> {
         use crate::prim::Literal::*;
         let ty = match lit {
-            Int(_) => self.pool.ty_int(),
+            Int(i) => {
+                check_int_range(*i)?;
+                self.pool.ty_int()
+            }
             Real(_) => self.pool.ty_real(),
             Char(_) => self.pool.ty_char(),
+            String(_) => self.pool.ty_string(),
         };
         self.unify(given, ty)?;
         Ok(())
     }
 
-    fn infer_constant<'b, 'r>(&'b mut self, _: &i64, given: NodeId) -> Result<'r, ()> {
+    fn infer_constant<'b, 'r>(&'b mut self, value: &i64, given: NodeId) -> Result<'r, ()> {
+        check_int_range(*value)?;
         let ty = self.pool.ty_int();
         self.unify(given, ty)?;
         Ok(())
@@ -501,27 +906,12 @@ impl TyEnv {
                 self.infer_char(value, *ty)?;
             }
             Constructor { arg, name } => {
-                let type_name = self
-                    .symbol_table()
-                    .get_datatype_of_constructor(name)
-                    .expect("internal error: typing")
-                    .clone();
-                self.give(*ty, Typing::Datatype(type_name.clone()))?;
+                let (cty, arg_ty) = self.constructor_type(name).expect("internal error: typing");
+                self.unify(*ty, cty)?;
                 if let Some(arg) = arg {
                     self.infer_pat(arg)?;
-                    let arg_ty = self
-                        .symbol_table()
-                        .get_type(&type_name)
-                        .expect("internal error: typing")
-                        .constructors
-                        .iter()
-                        .find(|(cname, _)| cname == name)
-                        .map(|(_, arg)| arg.clone())
-                        .expect("internal error: typing")
-                        .expect("internal error: typing");
-                    let arg_typing = self.convert(arg_ty);
-                    let arg_ty_id = self.pool.ty(arg_typing);
-                    self.unify(arg.ty(), arg_ty_id)?;
+                    let arg_ty = arg_ty.expect("internal error: typing");
+                    self.unify(arg.ty(), arg_ty)?;
                 }
             }
             Tuple { tuple } => {
@@ -536,7 +926,7 @@ impl TyEnv {
             Wildcard { .. } | Variable { .. } => (),
         };
         for (name, ty) in pat.binds() {
-            self.insert(name.clone(), *ty);
+            self.insert_mono(name.clone(), *ty);
         }
         Ok(())
     }
@@ -571,6 +961,15 @@ impl TyEnv {
 }
 
 use crate::pass::Pass;
+/// `int` is 32 bits though integer constants are parsed into i64
+fn check_int_range<'r>(i: i64) -> Result<'r, ()> {
+    if i < i32::min_value() as i64 || (i32::max_value() as i64) < i {
+        Err(TypeError::IntOutOfRange(i))
+    } else {
+        Ok(())
+    }
+}
+
 impl<'a> Pass<UntypedCoreContext, TypeError<'a>> for Typer {
     type Target = TypedCoreContext;
 
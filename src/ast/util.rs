@@ -10,8 +10,15 @@ pub trait Traverse<Ty> {
     fn traverse_statement(&mut self, decl: &mut CoreDeclaration<Ty>) {
         use Declaration::*;
         match decl {
-            Datatype { name, constructors } => self.traverse_datatype(name, constructors),
+            Datatype {
+                name,
+                params,
+                constructors,
+            } => self.traverse_datatype(name, params, constructors),
             Val { rec, pattern, expr } => self.traverse_val(rec, pattern, expr),
+            Local { binds, body } => self.traverse_local(binds, body),
+            Structure { name, body } => self.traverse_structure(name, body),
+            Exception { name, arg } => self.traverse_exception(name, arg),
             D(_) => (),
         }
     }
@@ -19,6 +26,7 @@ pub trait Traverse<Ty> {
     fn traverse_datatype(
         &mut self,
         _name: &mut Symbol,
+        _params: &mut Vec<u64>,
         _constructors: &mut Vec<(Symbol, Option<Type>)>,
     ) {
     }
@@ -33,6 +41,27 @@ pub trait Traverse<Ty> {
         self.traverse_pattern(pattern)
     }
 
+    fn traverse_local(
+        &mut self,
+        binds: &mut Vec<CoreDeclaration<Ty>>,
+        body: &mut Vec<CoreDeclaration<Ty>>,
+    ) {
+        for decl in binds.iter_mut() {
+            self.traverse_statement(decl)
+        }
+        for decl in body.iter_mut() {
+            self.traverse_statement(decl)
+        }
+    }
+
+    fn traverse_structure(&mut self, _name: &mut Symbol, body: &mut Vec<CoreDeclaration<Ty>>) {
+        for decl in body.iter_mut() {
+            self.traverse_statement(decl)
+        }
+    }
+
+    fn traverse_exception(&mut self, _name: &mut Symbol, _arg: &mut Option<Type>) {}
+
     fn traverse_expr(&mut self, expr: &mut CoreExpr<Ty>) {
         use crate::ast::ExprKind::*;
         match &mut expr.inner {
@@ -47,7 +76,7 @@ pub trait Traverse<Ty> {
             } => self.traverse_externcall(module, fun, args, argty, retty),
             Fn { param, body } => self.traverse_fn(param, body),
             App { fun, arg } => self.traverse_app(fun, arg),
-            Case { cond, clauses } => self.traverse_case(cond, clauses),
+            Case { cond, clauses, .. } => self.traverse_case(cond, clauses),
             Tuple { tuple } => self.traverse_tuple(tuple),
             Constructor { arg, name } => self.traverse_constructor(arg, name),
             Symbol { name } => self.traverse_sym(name),
@@ -158,8 +187,15 @@ pub trait Transform<Ty> {
     fn transform_statement(&mut self, decl: CoreDeclaration<Ty>) -> CoreDeclaration<Ty> {
         use Declaration::*;
         match decl {
-            Datatype { name, constructors } => self.transform_datatype(name, constructors),
+            Datatype {
+                name,
+                params,
+                constructors,
+            } => self.transform_datatype(name, params, constructors),
             Val { rec, pattern, expr } => self.transform_val(rec, pattern, expr),
+            Local { binds, body } => self.transform_local(binds, body),
+            Structure { name, body } => self.transform_structure(name, body),
+            Exception { name, arg } => self.transform_exception(name, arg),
             D(d) => match d {},
         }
     }
@@ -167,9 +203,14 @@ pub trait Transform<Ty> {
     fn transform_datatype(
         &mut self,
         name: Symbol,
+        params: Vec<u64>,
         constructors: Vec<(Symbol, Option<Type>)>,
     ) -> CoreDeclaration<Ty> {
-        Declaration::Datatype { name, constructors }
+        Declaration::Datatype {
+            name,
+            params,
+            constructors,
+        }
     }
 
     fn transform_val(
@@ -185,6 +226,41 @@ pub trait Transform<Ty> {
         }
     }
 
+    fn transform_local(
+        &mut self,
+        binds: Vec<CoreDeclaration<Ty>>,
+        body: Vec<CoreDeclaration<Ty>>,
+    ) -> CoreDeclaration<Ty> {
+        Declaration::Local {
+            binds: binds
+                .into_iter()
+                .map(|decl| self.transform_statement(decl))
+                .collect(),
+            body: body
+                .into_iter()
+                .map(|decl| self.transform_statement(decl))
+                .collect(),
+        }
+    }
+
+    fn transform_structure(
+        &mut self,
+        name: Symbol,
+        body: Vec<CoreDeclaration<Ty>>,
+    ) -> CoreDeclaration<Ty> {
+        Declaration::Structure {
+            name,
+            body: body
+                .into_iter()
+                .map(|decl| self.transform_statement(decl))
+                .collect(),
+        }
+    }
+
+    fn transform_exception(&mut self, name: Symbol, arg: Option<Type>) -> CoreDeclaration<Ty> {
+        Declaration::Exception { name, arg }
+    }
+
     fn transform_expr(&mut self, mut expr: CoreExpr<Ty>) -> CoreExpr<Ty> {
         use crate::ast::ExprKind::*;
         expr.inner = match expr.inner {
@@ -199,7 +275,11 @@ pub trait Transform<Ty> {
             } => self.transform_externcall(module, fun, args, argty, retty),
             Fn { param, body } => self.transform_fn(param, body),
             App { fun, arg } => self.transform_app(fun, arg),
-            Case { cond, clauses } => self.transform_case(cond, clauses),
+            Case {
+                cond,
+                clauses,
+                positions,
+            } => self.transform_case(cond, clauses, positions),
             Tuple { tuple } => self.transform_tuple(tuple),
             Constructor { arg, name } => self.transform_constructor(arg, name),
             Symbol { name } => self.transform_symbol(name),
@@ -274,6 +354,7 @@ pub trait Transform<Ty> {
         &mut self,
         cond: Box<CoreExpr<Ty>>,
         clauses: Vec<(Pattern<Ty>, CoreExpr<Ty>)>,
+        positions: Vec<Position>,
     ) -> CoreExprKind<Ty> {
         ExprKind::Case {
             cond: self.transform_expr(*cond).boxed(),
@@ -281,6 +362,7 @@ pub trait Transform<Ty> {
                 .into_iter()
                 .map(|(p, e)| (self.transform_pattern(p), self.transform_expr(e)))
                 .collect(),
+            positions,
         }
     }
 
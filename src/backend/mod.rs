@@ -1,6 +1,23 @@
+mod multi_value;
 pub mod wasm;
 pub use self::wasm::LIR2WASM;
 mod pp;
 
+/// the module, and the results of the types of the functions returning more than one value,
+/// by the indices of the types. `::wasm::FuncType` holds at most one result,
+/// so the module has them without results and `dump` fills them in
 #[derive(Debug, Clone)]
-pub struct Output(pub ::wasm::Module);
+pub struct Output(pub ::wasm::Module, pub Vec<(u32, Vec<::wasm::ValueType>)>);
+
+impl Output {
+    pub fn dump(&self) -> Vec<u8> {
+        use ::wasm::Dump;
+        let mut code = Vec::new();
+        self.0.dump(&mut code);
+        if self.1.is_empty() {
+            code
+        } else {
+            multi_value::fill_results(&code, &self.1)
+        }
+    }
+}
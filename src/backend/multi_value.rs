@@ -0,0 +1,64 @@
+//! Results of the function types for the wasm multi-value proposal,
+//! written into the module dumped as `::wasm::FuncType` holds at most one result.
+
+use crate::hir_unit::{read_u32_leb128, write_u32_leb128, WASM_HEADER};
+use wasm::{Dump, ValueType};
+
+const TYPE_SECTION: u8 = 1;
+
+/// `module` with the results of the types at the indices replaced with `results`
+pub fn fill_results(module: &[u8], results: &[(u32, Vec<ValueType>)]) -> Vec<u8> {
+    let read = |pos: &mut usize| {
+        read_u32_leb128(module, pos).expect("internal error: broken module dumped") as usize
+    };
+    let mut bytes = WASM_HEADER.to_vec();
+    let mut pos = WASM_HEADER.len();
+    while pos < module.len() {
+        let id = module[pos];
+        pos += 1;
+        let size = read(&mut pos);
+        let content = &module[pos..pos + size];
+        pos += size;
+        let content = if id == TYPE_SECTION {
+            fill_type_section(content, results)
+        } else {
+            content.to_vec()
+        };
+        bytes.push(id);
+        write_u32_leb128(&mut bytes, content.len() as u32);
+        bytes.extend(content);
+    }
+    bytes
+}
+
+fn fill_type_section(content: &[u8], results: &[(u32, Vec<ValueType>)]) -> Vec<u8> {
+    let read = |pos: &mut usize| {
+        read_u32_leb128(content, pos).expect("internal error: broken type section dumped")
+    };
+    let mut bytes = Vec::new();
+    let mut pos = 0;
+    let count = read(&mut pos);
+    write_u32_leb128(&mut bytes, count);
+    for index in 0..count {
+        // the form of the function types and the params, which take a byte each
+        let start = pos;
+        pos += 1;
+        let nparams = read(&mut pos) as usize;
+        pos += nparams;
+        bytes.extend_from_slice(&content[start..pos]);
+
+        let start = pos;
+        let nresults = read(&mut pos) as usize;
+        pos += nresults;
+        match results.iter().find(|(i, _)| *i == index) {
+            Some((_, tys)) => {
+                write_u32_leb128(&mut bytes, tys.len() as u32);
+                for ty in tys {
+                    ty.dump(&mut bytes);
+                }
+            }
+            None => bytes.extend_from_slice(&content[start..pos]),
+        }
+    }
+    bytes
+}
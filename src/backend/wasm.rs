@@ -35,26 +35,42 @@ fn lty_to_valuetype(t: &lir::LTy) -> ValueType {
     lty_to_valuetype_opt(t).unwrap_or(ValueType::I32)
 }
 
-fn fun_type(f: &lir::Function) -> FuncType {
-    let &lir::Function {
-        ref nparams,
-        ref regs,
-        ref ret_ty,
-        ..
-    } = f;
-    let mut tys = regs
-        .iter()
-        .map(|reg| lty_to_valuetype(reg))
-        .collect::<Vec<_>>();
-    let _ = tys.split_off(*nparams as usize);
-    FuncType {
-        params: tys,
-        ret: match ret_ty {
-            ty => lty_to_valuetype_opt(&ty),
-        },
+/// the object descriptor for the runtime to find the pointers. See webml-rt/src/gc.rs.
+/// Returns the descriptor and the bitmap words to store after the slots, if it needs them
+fn object_descriptor(tys: &[lir::LTy]) -> (u32, Vec<u32>) {
+    // the number of the slots whose bits are in the descriptor
+    const DESC_SLOTS: usize = 30;
+    const EXTENDED: u32 = 1 << 31;
+
+    let is_ptr = |ty: &lir::LTy| *ty == lir::LTy::Ptr;
+    let last = tys.last().map(is_ptr).unwrap_or(false);
+    if tys.len() <= DESC_SLOTS || tys[DESC_SLOTS..].iter().all(|ty| is_ptr(ty) == last) {
+        // bit DESC_SLOTS stands for all the rest
+        let desc = (0..=DESC_SLOTS)
+            .filter(|&i| tys.get(i).map(is_ptr).unwrap_or(last))
+            .fold(0, |desc, i| desc | 1 << i);
+        (desc, vec![])
+    } else {
+        let mut bitmap = vec![0; (tys.len() + 31) / 32];
+        for (i, ty) in tys.iter().enumerate() {
+            if is_ptr(ty) {
+                bitmap[i / 32] |= 1 << (i % 32);
+            }
+        }
+        (EXTENDED | tys.len() as u32, bitmap)
     }
 }
 
+/// the types of the params and the results of the function
+fn signature(f: &lir::Function) -> (Vec<ValueType>, Vec<ValueType>) {
+    let params = f.regs[0..f.nparams as usize]
+        .iter()
+        .map(lty_to_valuetype)
+        .collect();
+    let results = f.ret_tys.iter().filter_map(lty_to_valuetype_opt).collect();
+    (params, results)
+}
+
 pub struct LIR2WASM;
 
 impl LIR2WASM {
@@ -94,9 +110,17 @@ struct LIR2WASMPass {
     md: ModuleBuilder,
     init_fun: FunctionSpaceIndex,
     alloc_fun: FunctionSpaceIndex,
+    enter_frame_fun: FunctionSpaceIndex,
+    leave_frame_fun: FunctionSpaceIndex,
+    init_globals_fun: FunctionSpaceIndex,
+    globals_fun: FunctionSpaceIndex,
     extern_functions: HashMap<(String, String), FunctionSpaceIndex>,
     function_table: HashMap<Symbol, u32>,
     function_type_table: HashMap<FuncType, TypeIndex>,
+    /// the types of the functions returning more than one value, by their params and results.
+    /// `FuncType` holds at most one result, so they are added without results and filled in
+    /// by `Output::dump`
+    multi_value_types: HashMap<(Vec<ValueType>, Vec<ValueType>), TypeIndex>,
     dynamic_function_table: HashMap<Symbol, u32>,
     dynamic_function_elements: Vec<FunctionSpaceIndex>,
 }
@@ -108,17 +132,35 @@ impl LIR2WASMPass {
         mut function_type_table: HashMap<FuncType, TypeIndex>,
     ) -> Self {
         let init_fun_ty = funtype!(());
-        let alloc_fun_ty = funtype!((i32) -> i32);
+        let alloc_fun_ty = funtype!((i32, i32) -> i32);
+        let enter_frame_fun_ty = funtype!((i32) -> i32);
+        let leave_frame_fun_ty = funtype!((i32));
+        let globals_fun_ty = funtype!(() -> i32);
         let init_fun_ty_index = md.add_type(init_fun_ty.clone());
         let alloc_fun_ty_index = md.add_type(alloc_fun_ty.clone());
+        let enter_frame_fun_ty_index = md.add_type(enter_frame_fun_ty.clone());
+        let leave_frame_fun_ty_index = md.add_type(leave_frame_fun_ty.clone());
+        let globals_fun_ty_index = md.add_type(globals_fun_ty.clone());
         let init_fun = md.import("webml-rt", "init", init_fun_ty_index);
         let init_fun = md.function_index_of(init_fun).unwrap();
         let alloc_fun = md.import("webml-rt", "alloc", alloc_fun_ty_index);
         let alloc_fun = md.function_index_of(alloc_fun).unwrap();
+        let enter_frame_fun = md.import("webml-rt", "enter_frame", enter_frame_fun_ty_index);
+        let enter_frame_fun = md.function_index_of(enter_frame_fun).unwrap();
+        let leave_frame_fun = md.import("webml-rt", "leave_frame", leave_frame_fun_ty_index);
+        let leave_frame_fun = md.function_index_of(leave_frame_fun).unwrap();
+        // (i32) as leave_frame
+        let init_globals_fun = md.import("webml-rt", "init_globals", leave_frame_fun_ty_index);
+        let init_globals_fun = md.function_index_of(init_globals_fun).unwrap();
+        let globals_fun = md.import("webml-rt", "globals", globals_fun_ty_index);
+        let globals_fun = md.function_index_of(globals_fun).unwrap();
 
         function_type_table.extend(vec![
             (init_fun_ty, init_fun_ty_index),
             (alloc_fun_ty, alloc_fun_ty_index),
+            (enter_frame_fun_ty, enter_frame_fun_ty_index),
+            (leave_frame_fun_ty, leave_frame_fun_ty_index),
+            (globals_fun_ty, globals_fun_ty_index),
         ]);
 
         md.import(
@@ -133,14 +175,51 @@ impl LIR2WASMPass {
             md,
             init_fun,
             alloc_fun,
+            enter_frame_fun,
+            leave_frame_fun,
+            init_globals_fun,
+            globals_fun,
             extern_functions,
             function_table: HashMap::new(),
             function_type_table,
+            multi_value_types: HashMap::new(),
             dynamic_function_table: HashMap::new(),
             dynamic_function_elements: vec![],
         }
     }
 
+    /// the index of the type of the functions of `params` and `results`, added if not yet
+    fn type_index(&mut self, params: Vec<ValueType>, mut results: Vec<ValueType>) -> TypeIndex {
+        if results.len() <= 1 {
+            let ftype = FuncType {
+                params,
+                ret: results.pop(),
+            };
+            if !self.function_type_table.contains_key(&ftype) {
+                let tyi = self.md.add_type(ftype.clone());
+                self.function_type_table.insert(ftype.clone(), tyi);
+            }
+            return self.function_type_table[&ftype];
+        }
+        let key = (params, results);
+        if !self.multi_value_types.contains_key(&key) {
+            let tyi = self.md.add_type(FuncType {
+                params: key.0.clone(),
+                ret: None,
+            });
+            self.multi_value_types.insert(key.clone(), tyi);
+        }
+        self.multi_value_types[&key]
+    }
+
+    /// the results of the types of `multi_value_types`, by their indices
+    fn multi_value_results(&self) -> Vec<(u32, Vec<ValueType>)> {
+        self.multi_value_types
+            .iter()
+            .map(|((_, results), tyi)| (tyi.0, results.clone()))
+            .collect()
+    }
+
     fn intern_fun(&mut self, fname: &Symbol) -> u32 {
         let index = self.function_index(fname);
         let &mut Self {
@@ -163,17 +242,21 @@ impl LIR2WASMPass {
                 .enumerate()
                 .map(|(i, s)| (s.name.clone(), i as u32))
                 .collect();
-        {
-            for f in l.0.iter() {
-                let ftype = fun_type(f);
-                if !self.function_type_table.contains_key(&ftype) {
-                    let tyi = self.md.add_type(ftype.clone());
-                    self.function_type_table.insert(ftype, tyi);
-                }
-            }
+        for f in l.0.iter() {
+            let (params, results) = signature(f);
+            self.type_index(params, results);
         }
 
         let nfunctions = l.0.len();
+        let nglobals =
+            l.0.iter()
+                .flat_map(|f| f.body.iter().flat_map(|b| b.body.iter()))
+                .filter_map(|op| match op {
+                    lir::Op::StoreGlobal(index, _) => Some(index + 1),
+                    _ => None,
+                })
+                .max()
+                .unwrap_or(0);
         for f in l.0 {
             self.trans_function(f);
         }
@@ -188,6 +271,9 @@ impl LIR2WASMPass {
         let main_function = FunctionBuilder::new(funtype!(()))
             .code(|cb, _params| {
                 cb.call(self.init_fun)
+                    // a global takes 2 slots of 4 bytes
+                    .constant((nglobals * 2) as i32)
+                    .call(self.init_globals_fun)
                     .call(self.function_index(&Symbol::new("sml-main")))
                     .return_()
             })
@@ -208,7 +294,17 @@ impl LIR2WASMPass {
 
     fn trans_function(&mut self, f: lir::Function) {
         use crate::lir::Value::*;
-        let ftype = fun_type(&f);
+        let (params, results) = signature(&f);
+        let type_index = self.type_index(params.clone(), results.clone());
+        // the type to build the function with, which is `type_index` unless it has more results
+        let ftype = FuncType {
+            params,
+            ret: if results.len() == 1 {
+                results.first().cloned()
+            } else {
+                None
+            },
+        };
         let lir::Function {
             nparams,
             regs,
@@ -219,7 +315,29 @@ impl LIR2WASMPass {
             .iter()
             .map(|reg| lty_to_valuetype(reg))
             .collect::<Vec<_>>();
-        let regtys = tys.split_off(nparams as usize);
+        let mut regtys = tys.split_off(nparams as usize);
+        // the registers holding pointers, which are the roots of the garbage collection
+        let roots = regs
+            .iter()
+            .enumerate()
+            .filter(|(_, ty)| **ty == lir::LTy::Ptr)
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        // the objects not escaping the function follow the roots in the frame,
+        // where the collector scans them conservatively
+        let stack_size = body
+            .iter()
+            .flat_map(|block| block.body.iter())
+            .map(|op| match op {
+                lir::Op::StackAlloc(_, size, _) => *size,
+                _ => 0,
+            })
+            .sum::<u32>();
+        let nslots = roots.len() as u32 + stack_size / 4;
+        if nslots != 0 {
+            // pointer to the slots of the shadow stack frame
+            regtys.push(ValueType::I32);
+        }
         let mut fb = FunctionBuilder::new(ftype.clone());
 
         let mut locals = fb.new_locals(regtys);
@@ -228,7 +346,10 @@ impl LIR2WASMPass {
             let body = self.alloc_loop_block_break(&body);
             let mut params = params.to_vec();
             params.append(&mut locals);
+            let frame = params.get(regs.len()).cloned();
             let mut scope = Vec::new();
+            // where the next object on the stack goes, from the slots
+            let mut stack_offset = roots.len() as u32 * 4;
 
             macro_rules! reg {
                 ($reg: expr) => {
@@ -236,6 +357,27 @@ impl LIR2WASMPass {
                 };
             }
 
+            // store the roots to the frame before anything that may collect garbage
+            macro_rules! spill_roots {
+                () => {
+                    if let Some(frame) = frame {
+                        for (i, root) in roots.iter().enumerate() {
+                            cb = cb
+                                .get_local(frame)
+                                .get_local(params[*root])
+                                .i32_store((i * 4) as u32);
+                        }
+                    }
+                };
+            }
+
+            if let Some(frame) = frame {
+                cb = cb
+                    .constant(nslots as i32)
+                    .call(self.enter_frame_fun)
+                    .set_local(frame);
+            }
+
             macro_rules! label {
                 ($label: expr) => {
                     scope
@@ -413,6 +555,18 @@ impl LIR2WASMPass {
                                         .i32_load(addr.1)
                                         .set_local(reg!(reg));
                                 }
+                                StoreU8(addr, value) => {
+                                    cb = cb
+                                        .get_local(reg!(addr.0))
+                                        .get_local(reg!(value))
+                                        .i32_store8(addr.1);
+                                }
+                                LoadU8(reg, addr) => {
+                                    cb = cb
+                                        .get_local(reg!(addr.0))
+                                        .i32_load8_u(addr.1)
+                                        .set_local(reg!(reg));
+                                }
                                 JumpIfI32(reg, label) => {
                                     cb = cb.get_local(reg!(reg)).br_if(label!(&label));
                                 }
@@ -730,24 +884,92 @@ impl LIR2WASMPass {
                                         .f64_load(addr.1)
                                         .set_local(reg!(reg));
                                 }
+                                FloorF64(reg1, reg2) => {
+                                    cb = cb.get_local(reg!(reg2)).f64_floor().set_local(reg!(reg1))
+                                }
+                                CeilF64(reg1, reg2) => {
+                                    cb = cb.get_local(reg!(reg2)).f64_ceil().set_local(reg!(reg1))
+                                }
+                                NearestF64(reg1, reg2) => {
+                                    cb =
+                                        cb.get_local(reg!(reg2)).f64_nearest().set_local(reg!(reg1))
+                                }
+                                TruncF64(reg1, reg2) => {
+                                    cb = cb.get_local(reg!(reg2)).f64_trunc().set_local(reg!(reg1))
+                                }
+                                SqrtF64(reg1, reg2) => {
+                                    cb = cb.get_local(reg!(reg2)).f64_sqrt().set_local(reg!(reg1))
+                                }
+                                ConvertF64I32(reg1, reg2) => {
+                                    cb = cb
+                                        .get_local(reg!(reg2))
+                                        .f64_convert_s_i32()
+                                        .set_local(reg!(reg1))
+                                }
+                                TruncI32F64(reg1, reg2) => {
+                                    cb = cb
+                                        .get_local(reg!(reg2))
+                                        .i32_trunc_s_f64()
+                                        .set_local(reg!(reg1))
+                                }
+                                ExtendI64I32(reg1, reg2) => {
+                                    cb = cb
+                                        .get_local(reg!(reg2))
+                                        .i64_extend_s_i32()
+                                        .set_local(reg!(reg1))
+                                }
+                                WrapI32I64(reg1, reg2) => {
+                                    cb = cb
+                                        .get_local(reg!(reg2))
+                                        .i32_wrap_i64()
+                                        .set_local(reg!(reg1))
+                                }
+                                PromoteF64F32(reg1, reg2) => {
+                                    cb = cb
+                                        .get_local(reg!(reg2))
+                                        .f64_promote_f32()
+                                        .set_local(reg!(reg1))
+                                }
+                                DemoteF32F64(reg1, reg2) => {
+                                    cb = cb
+                                        .get_local(reg!(reg2))
+                                        .f32_demote_f64()
+                                        .set_local(reg!(reg1))
+                                }
 
-                                HeapAlloc(reg, value, _tys) => {
+                                HeapAlloc(reg, value, tys) => {
+                                    spill_roots!();
+                                    let (desc, bitmap) = object_descriptor(tys);
                                     cb = match value {
-                                        I(i) => cb.constant(*i as i32),
-                                        R(r) => cb.get_local(reg!(r)),
+                                        I(i) => cb.constant(*i + 4 * bitmap.len() as i32),
+                                        R(r) => {
+                                            assert!(bitmap.is_empty());
+                                            cb.get_local(reg!(r))
+                                        }
                                     };
 
-                                    cb = cb//.constant(tys_to_ptrbits(tys) as i32)
+                                    cb = cb
+                                        .constant(desc as i32)
                                         .call(self.alloc_fun)
-                                        .set_local(reg!(reg))
+                                        .set_local(reg!(reg));
+                                    // the bitmap follows the slots
+                                    for (i, word) in bitmap.into_iter().enumerate() {
+                                        cb = cb
+                                            .get_local(reg!(reg))
+                                            .constant(word as i32)
+                                            .i32_store((tys.len() * 8 + i * 4) as u32);
+                                    }
                                 }
-                                StackAlloc(reg, size, _tys) => {
-                                    // allocating to heap, not stack
+                                StackAlloc(reg, size, _) => {
+                                    // each allocation has its own place in the frame.
+                                    // The one in a loop is reused as it doesn't live over iterations
+                                    let frame = frame.expect("internal error: no frame");
                                     cb = cb
-                                        .constant(*size as i32)
-                                        //.constant(tys_to_ptrbits(tys) as i32)
-                                        .call(self.alloc_fun)
-                                        .set_local(reg!(reg))
+                                        .get_local(frame)
+                                        .constant(stack_offset as i32)
+                                        .i32_add()
+                                        .set_local(reg!(reg));
+                                    stack_offset += size;
                                 }
                                 StoreFnPtr(addr, value) => {
                                     cb = cb
@@ -756,12 +978,43 @@ impl LIR2WASMPass {
                                         .i32_store(addr.1);
                                 }
 
+                                LoadGlobal(reg, index) => {
+                                    let offset = index * 8;
+                                    cb = cb.call(self.globals_fun);
+                                    cb = match reg.0 {
+                                        lir::LTy::I32
+                                        | lir::LTy::U32
+                                        | lir::LTy::Ptr
+                                        | lir::LTy::FPtr => cb.i32_load(offset),
+                                        lir::LTy::I64 | lir::LTy::U64 => cb.i64_load(offset),
+                                        lir::LTy::F32 => cb.f32_load(offset),
+                                        lir::LTy::F64 => cb.f64_load(offset),
+                                        lir::LTy::Unit => unreachable!(),
+                                    };
+                                    cb = cb.set_local(reg!(reg));
+                                }
+                                StoreGlobal(index, value) => {
+                                    let offset = index * 8;
+                                    cb = cb.call(self.globals_fun).get_local(reg!(value));
+                                    cb = match value.0 {
+                                        lir::LTy::I32
+                                        | lir::LTy::U32
+                                        | lir::LTy::Ptr
+                                        | lir::LTy::FPtr => cb.i32_store(offset),
+                                        lir::LTy::I64 | lir::LTy::U64 => cb.i64_store(offset),
+                                        lir::LTy::F32 => cb.f32_store(offset),
+                                        lir::LTy::F64 => cb.f64_store(offset),
+                                        lir::LTy::Unit => unreachable!(),
+                                    };
+                                }
+
                                 ClosureCall(reg, fun, args) => {
+                                    spill_roots!();
                                     cb = cb
                                         .get_local(reg!(fun))
                                         // load ptr to captured env
-                                        // assuming sizeof(*p) == 4
-                                        .constant(4)
+                                        // the env follows the 8-byte slot of the function
+                                        .constant(8)
                                         .i32_add();
 
                                     // load the rest args
@@ -793,6 +1046,7 @@ impl LIR2WASMPass {
                                     }
                                 }
                                 FunCall(reg, fun, args) => {
+                                    spill_roots!();
                                     for arg in args.iter() {
                                         cb = cb.get_local(reg!(arg))
                                     }
@@ -803,7 +1057,22 @@ impl LIR2WASMPass {
                                         cb = cb.set_local(reg!(reg));
                                     }
                                 }
+                                MultiFunCall(regs, fun, args) => {
+                                    spill_roots!();
+                                    for arg in args.iter() {
+                                        cb = cb.get_local(reg!(arg))
+                                    }
+
+                                    cb = cb.call(self.function_index(&fun));
+                                    // the last result is on the top of the stack
+                                    for reg in regs.iter().rev() {
+                                        if lty_to_valuetype_opt(&reg.0).is_some() {
+                                            cb = cb.set_local(reg!(reg));
+                                        }
+                                    }
+                                }
                                 ExternCall(reg, module, fun, args) => {
+                                    spill_roots!();
                                     for arg in args.iter() {
                                         cb = cb.get_local(reg!(arg))
                                     }
@@ -814,19 +1083,77 @@ impl LIR2WASMPass {
                                         cb = cb.set_local(reg!(reg));
                                     }
                                 }
+                                TailCall(fun, args) => {
+                                    // the callee pushes its own frame
+                                    if let Some(frame) = frame {
+                                        cb = cb.get_local(frame).call(self.leave_frame_fun);
+                                    }
+                                    for arg in args.iter() {
+                                        cb = cb.get_local(reg!(arg))
+                                    }
+                                    cb = cb.return_call(self.function_index(&fun));
+                                }
+                                TailClosureCall(fun, args) => {
+                                    if let Some(frame) = frame {
+                                        cb = cb.get_local(frame).call(self.leave_frame_fun);
+                                    }
+                                    cb = cb.get_local(reg!(fun)).constant(8).i32_add();
+                                    for arg in args.iter() {
+                                        cb = cb.get_local(reg!(arg))
+                                    }
+                                    // the callee returns what this function does
+                                    let ftype = {
+                                        let mut params = vec![ValueType::I32];
+                                        params.extend(args.iter().map(|r| lty_to_valuetype(&r.0)));
+                                        FuncType {
+                                            params,
+                                            ret: ftype.ret.clone(),
+                                        }
+                                    };
+                                    cb = cb.get_local(reg!(fun)).i32_load(0).return_call_indirect(
+                                        self.function_type_table[&ftype],
+                                        false,
+                                    );
+                                }
                                 Jump(label) => {
                                     cb = cb.br(label!(&label));
                                 }
                                 Unreachable => {
                                     cb = cb.unreachable();
                                 }
+                                UnreachableIfI32(reg) => {
+                                    // skip the trap if 0.
+                                    // No jumps go out of this block, so it doesn't need a label
+                                    cb = cb
+                                        .block(BlockType(None))
+                                        .get_local(reg!(reg))
+                                        .constant(0)
+                                        .i32_eq()
+                                        .br_if(0)
+                                        .unreachable()
+                                        .end();
+                                }
                                 Ret(reg) => {
+                                    if let Some(frame) = frame {
+                                        cb = cb.get_local(frame).call(self.leave_frame_fun);
+                                    }
                                     cb = match reg {
                                         Some(r) => cb.get_local(reg!(r)),
                                         None => cb,
                                     };
                                     cb = cb.return_()
                                 }
+                                MultiRet(regs) => {
+                                    if let Some(frame) = frame {
+                                        cb = cb.get_local(frame).call(self.leave_frame_fun);
+                                    }
+                                    for reg in regs.iter() {
+                                        if lty_to_valuetype_opt(&reg.0).is_some() {
+                                            cb = cb.get_local(reg!(reg));
+                                        }
+                                    }
+                                    cb = cb.return_()
+                                }
                             }
                         }
                     }
@@ -836,7 +1163,7 @@ impl LIR2WASMPass {
         });
         let (_, body) = fb.build();
         // use calculated type index,
-        NewFunction::new_function(&mut self.md, self.function_type_table[&ftype], body);
+        NewFunction::new_function(&mut self.md, type_index, body);
     }
 
     /// allocate block and loop scopes for jump -> break transformation.
@@ -1066,6 +1393,7 @@ impl<E> Pass<lir::Context, E> for LIR2WASM {
         _: &Config,
     ) -> ::std::result::Result<Self::Target, E> {
         let mut pass = self.generate_pass(extern_types);
-        Ok(Output(pass.trans_lir(lir)))
+        let module = pass.trans_lir(lir);
+        Ok(Output(module, pass.multi_value_results()))
     }
 }
@@ -0,0 +1,296 @@
+//! Cache of compiled units and programs.
+//!
+//! A unit is a top level declaration. Its HIR is stored under the hash of
+//! the declaration, the environment it is compiled in and the build of the compiler,
+//! which `build.rs` sets `WEBML_BUILD_ID` to. So a unit is compiled again only
+//! when it, the interfaces it can see or the compiler change.
+//! The units can't be cached lowered any further, as monomorphization and inlining
+//! need the whole program. Instead the program lowered from the units is stored
+//! under the hash of their keys, so a build changing no unit skips the backend as well.
+//! The warnings of a unit are reported only when it is compiled, not when it is reused,
+//! and the IR of a program reused is not printed.
+
+use crate::ast::{
+    Declaration, DerivedDeclaration, DerivedExprKind, ExprKind, UntypedDeclaration, UntypedExpr,
+};
+use crate::config::Config;
+use crate::hir_unit::{HirUnit, Interface, WASM_HEADER};
+use log::{debug, warn};
+use sha2::{Digest, Sha256};
+use std::cell::Cell;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// SHA-256, as the keys are stored across builds and must not change between them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key([u8; 32]);
+
+impl Key {
+    pub fn new(decl: &UntypedDeclaration, env: &Interface, config: &Config) -> Self {
+        let mut hasher = Sha256::new();
+        hash_config(config, &mut hasher);
+        // debug format is fully parenthesized, unlike the pretty printer
+        hasher.update(format!("{:?}", without_positions(decl)).as_bytes());
+        hash_serialized(
+            &(env.next_id, &env.values, &env.types, &env.exceptions),
+            &mut hasher,
+        );
+        Key::finish(hasher)
+    }
+
+    /// the key of the program lowered from the units of `units`, in order
+    pub fn program(units: &[Key], config: &Config) -> Self {
+        let mut hasher = Sha256::new();
+        hash_config(config, &mut hasher);
+        for unit in units {
+            hasher.update(&unit.0);
+        }
+        Key::finish(hasher)
+    }
+
+    fn finish(hasher: Sha256) -> Self {
+        let mut key = [0; 32];
+        key.copy_from_slice(&hasher.finalize());
+        Key(key)
+    }
+}
+
+/// `decl` without the positions of its clauses, which only the warnings read,
+/// so that a declaration moved in the source keeps its key
+fn without_positions(decl: &UntypedDeclaration) -> UntypedDeclaration {
+    let mut decl = decl.clone();
+    clear_decl_positions(&mut decl);
+    decl
+}
+
+fn clear_decl_positions(decl: &mut UntypedDeclaration) {
+    use Declaration::*;
+    match decl {
+        Val { expr, .. } => clear_expr_positions(expr),
+        Local { binds, body } => binds
+            .iter_mut()
+            .chain(body.iter_mut())
+            .for_each(clear_decl_positions),
+        Structure { body, .. } => body.iter_mut().for_each(clear_decl_positions),
+        D(DerivedDeclaration::Fun {
+            clauses, positions, ..
+        }) => {
+            positions.clear();
+            for (_, expr) in clauses {
+                clear_expr_positions(expr)
+            }
+        }
+        Datatype { .. }
+        | Exception { .. }
+        | D(DerivedDeclaration::Infix { .. })
+        | D(DerivedDeclaration::Infixr { .. }) => (),
+    }
+}
+
+fn clear_expr_positions(expr: &mut UntypedExpr) {
+    use ExprKind::*;
+    match &mut expr.inner {
+        Binds { binds, ret } => {
+            binds.iter_mut().for_each(clear_decl_positions);
+            clear_expr_positions(ret)
+        }
+        BuiltinCall { args, .. } | ExternCall { args, .. } | Tuple { tuple: args } => {
+            args.iter_mut().for_each(clear_expr_positions)
+        }
+        Fn { body, .. } => clear_expr_positions(body),
+        App { fun, arg } => {
+            clear_expr_positions(fun);
+            clear_expr_positions(arg)
+        }
+        Case {
+            cond,
+            clauses,
+            positions,
+        } => {
+            positions.clear();
+            clear_expr_positions(cond);
+            for (_, expr) in clauses {
+                clear_expr_positions(expr)
+            }
+        }
+        Constructor { arg, .. } => {
+            if let Some(arg) = arg {
+                clear_expr_positions(arg)
+            }
+        }
+        Symbol { .. } | Literal { .. } => (),
+        D(DerivedExprKind::If { cond, then, else_ }) => {
+            clear_expr_positions(cond);
+            clear_expr_positions(then);
+            clear_expr_positions(else_)
+        }
+        D(DerivedExprKind::Vector { elems }) => elems.iter_mut().for_each(clear_expr_positions),
+    }
+}
+
+/// hashes the version of the compiler and the options which may change what it generates.
+/// The frontend reads none of the options now, but the units are keyed by them as well
+/// so that they don't go stale once it does
+fn hash_config(config: &Config, hasher: &mut Sha256) {
+    // listing all the fields so that a new one is not missed
+    let Config {
+        // only what is printed
+        pretty_print_ir: _,
+        // only where the output is stored
+        cache_dir: _,
+        wrapping_int,
+        tail_call,
+        multi_value,
+        // hashed as `effective_inline_threshold`, which the default is resolved to
+        inline_threshold: _,
+        opt_level,
+        // hashed as the optimizations they let run, with `opt_level`
+        enabled_passes: _,
+        disabled_passes: _,
+        // only where the warnings are reported
+        warnings: _,
+    } = config;
+    // outputs of other builds of the compiler are not compatible
+    hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+    hasher.update(env!("WEBML_BUILD_ID").as_bytes());
+    // the passes which run, rather than how they are asked for
+    let passes = Config::OPTIMIZATIONS
+        .iter()
+        .map(|(pass, _)| config.pass_enabled(pass))
+        .collect::<Vec<_>>();
+    hash_serialized(
+        &(
+            wrapping_int,
+            tail_call,
+            multi_value,
+            config.effective_inline_threshold(),
+            opt_level,
+            passes,
+        ),
+        hasher,
+    );
+}
+
+/// bincode encodes the values the same way on any platform
+fn hash_serialized(value: &impl serde::Serialize, hasher: &mut Sha256) {
+    bincode::serialize_into(HashWriter(hasher), value)
+        .expect("internal error: failed to hash a value");
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in &self.0 {
+            write!(f, "{:02x}", byte)?
+        }
+        Ok(())
+    }
+}
+
+struct HashWriter<'a>(&'a mut Sha256);
+
+impl io::Write for HashWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Cache {
+    dir: PathBuf,
+    hits: Cell<usize>,
+    misses: Cell<usize>,
+    program_hits: Cell<usize>,
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Cache {
+            dir: dir.into(),
+            hits: Cell::new(0),
+            misses: Cell::new(0),
+            program_hits: Cell::new(0),
+        }
+    }
+
+    fn path(&self, key: Key) -> PathBuf {
+        self.dir.join(format!("{}.hir.wasm", key))
+    }
+
+    fn program_path(&self, key: Key) -> PathBuf {
+        self.dir.join(format!("{}.wasm", key))
+    }
+
+    /// broken or unreadable entries are treated as missing
+    pub fn get(&self, key: Key) -> Option<HirUnit> {
+        let unit = fs::read(self.path(key))
+            .ok()
+            .and_then(|bytes| HirUnit::from_wasm(&bytes).ok());
+        match unit {
+            Some(_) => {
+                debug!("cache hit: {}", key);
+                self.hits.set(self.hits.get() + 1)
+            }
+            None => {
+                debug!("cache miss: {}", key);
+                self.misses.set(self.misses.get() + 1)
+            }
+        }
+        unit
+    }
+
+    /// failing to store a unit only costs compiling it again next time
+    pub fn put(&self, key: Key, unit: &HirUnit) {
+        self.store(self.path(key), &unit.to_wasm())
+    }
+
+    /// the program lowered before. Entries other than wasm modules are treated as missing
+    pub fn get_program(&self, key: Key) -> Option<Vec<u8>> {
+        let code = fs::read(self.program_path(key))
+            .ok()
+            .filter(|code| code.starts_with(WASM_HEADER));
+        match code {
+            Some(_) => {
+                debug!("cache hit: program {}", key);
+                self.program_hits.set(self.program_hits.get() + 1)
+            }
+            None => debug!("cache miss: program {}", key),
+        }
+        code
+    }
+
+    pub fn put_program(&self, key: Key, code: &[u8]) {
+        self.store(self.program_path(key), code)
+    }
+
+    fn store(&self, path: PathBuf, bytes: &[u8]) {
+        let tmp = path.with_extension("tmp");
+        let result = fs::create_dir_all(&self.dir)
+            .and_then(|()| fs::write(&tmp, bytes))
+            .and_then(|()| fs::rename(&tmp, &path));
+        if let Err(e) = result {
+            warn!("failed to store {} in cache: {}", path.display(), e)
+        }
+    }
+
+    /// number of units found in the cache
+    pub fn hits(&self) -> usize {
+        self.hits.get()
+    }
+
+    /// number of units compiled
+    pub fn misses(&self) -> usize {
+        self.misses.get()
+    }
+
+    /// number of programs found in the cache, not lowered again
+    pub fn program_hits(&self) -> usize {
+        self.program_hits.get()
+    }
+}
@@ -1,6 +1,105 @@
+use crate::warning::Warnings;
 use std::collections::HashSet;
+use std::path::PathBuf;
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug)]
 pub struct Config {
     pub pretty_print_ir: HashSet<String>,
+    pub cache_dir: Option<PathBuf>,
+    /// let `int` arithmetic wrap around instead of raising `Overflow`
+    pub wrapping_int: bool,
+    /// compile the calls in tail position with the wasm tail call proposal, `return_call`.
+    /// The calls of a function to itself become loops regardless of this
+    pub tail_call: bool,
+    /// return the elements of small tuples as separate values with the wasm multi-value proposal,
+    /// instead of allocating the tuples
+    pub multi_value: bool,
+    /// the size in HIR nodes of the largest function to inline at its calls.
+    /// Functions used only once are inlined regardless of their size. 0 disables inlining.
+    /// `None` for the default of `opt_level`
+    pub inline_threshold: Option<usize>,
+    /// 0 to 3. The optimizations of the levels up to this run
+    pub opt_level: u8,
+    /// the optimizations to run regardless of `opt_level`
+    pub enabled_passes: HashSet<String>,
+    /// the optimizations not to run regardless of `opt_level`. Takes precedence over `enabled_passes`
+    pub disabled_passes: HashSet<String>,
+    /// where the passes put the warnings, for the caller to report
+    pub warnings: Warnings,
+}
+
+impl Config {
+    pub const DEFAULT_INLINE_THRESHOLD: usize = 20;
+    /// the inline threshold of `-O3`
+    pub const AGGRESSIVE_INLINE_THRESHOLD: usize = 60;
+    pub const DEFAULT_OPT_LEVEL: u8 = 2;
+    pub const MAX_OPT_LEVEL: u8 = 3;
+    /// the optimizations which can be turned off, and the levels they run from.
+    /// The other passes are needed to compile and always run
+    pub const OPTIMIZATIONS: &'static [(&'static str, u8)] = &[
+        ("simplify", 1),
+        ("unalias", 1),
+        ("constant_propagation", 1),
+        ("peephole", 1),
+        ("inlining", 2),
+        ("known_call", 2),
+        ("uncurrying", 2),
+        ("flattening_arguments", 2),
+        ("unboxing_returns", 2),
+        ("stack_allocation", 2),
+    ];
+    /// the optimizations which run only along with another, as they rely on it
+    pub const DEPENDENCIES: &'static [(&'static str, &'static str)] =
+        &[("uncurrying", "known_call")];
+
+    /// whether the pass `name` runs. Passes other than the optimizations always do.
+    /// An optimization doesn't run without the one of `DEPENDENCIES` it relies on
+    pub fn pass_enabled(&self, name: &str) -> bool {
+        if self.disabled_passes.contains(name) {
+            return false;
+        }
+        let missing_dependency = Self::DEPENDENCIES
+            .iter()
+            .any(|(pass, dependency)| *pass == name && !self.pass_enabled(dependency));
+        if missing_dependency {
+            return false;
+        }
+        if self.enabled_passes.contains(name) {
+            return true;
+        }
+        match Self::OPTIMIZATIONS.iter().find(|(pass, _)| *pass == name) {
+            Some((_, level)) => *level <= self.opt_level,
+            None => true,
+        }
+    }
+
+    /// `inline_threshold`, or the default of `opt_level` if not given
+    pub fn effective_inline_threshold(&self) -> usize {
+        match self.inline_threshold {
+            Some(threshold) => threshold,
+            None if self.opt_level >= Self::MAX_OPT_LEVEL => Self::AGGRESSIVE_INLINE_THRESHOLD,
+            None => Self::DEFAULT_INLINE_THRESHOLD,
+        }
+    }
+
+    pub fn is_optimization(name: &str) -> bool {
+        Self::OPTIMIZATIONS.iter().any(|(pass, _)| *pass == name)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            pretty_print_ir: HashSet::new(),
+            cache_dir: None,
+            wrapping_int: false,
+            tail_call: false,
+            multi_value: false,
+            inline_threshold: None,
+            opt_level: Self::DEFAULT_OPT_LEVEL,
+            enabled_passes: HashSet::new(),
+            disabled_passes: HashSet::new(),
+            warnings: Warnings::default(),
+        }
+    }
 }
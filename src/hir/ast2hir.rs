@@ -29,13 +29,27 @@ fn conv_symbol_table(symbol_table: ast::SymbolTable) -> SymbolTable {
         types: symbol_table
             .types
             .into_iter()
+            .filter(|(k, _)| !is_exn(k))
             .map(|(k, v)| (k, conv_type_info(v)))
             .collect(),
     }
 }
 
+// exceptions are represented by their tags
+fn is_exn(name: &Symbol) -> bool {
+    name == &Symbol::new("exn")
+}
+
+fn is_exn_ty(ty: &ast::Type) -> bool {
+    match ty {
+        ast::Type::Datatype(name, _) => is_exn(name),
+        _ => false,
+    }
+}
+
 fn conv_type_info(type_info: ast::TypeInfo) -> TypeInfo {
     TypeInfo {
+        params: type_info.params,
         constructors: type_info
             .constructors
             .into_iter()
@@ -51,10 +65,18 @@ fn conv_ty(ty: ast::Type) -> HTy {
         Char => HTy::Char,
         Int => HTy::Int,
         Real => HTy::Real,
+        String => HTy::String,
+        Int64 => HTy::Int64,
+        Word64 => HTy::Word64,
+        Real32 => HTy::Real32,
+        Array(elem) | Vector(elem) => HTy::Array(Box::new(conv_ty(*elem))),
         Tuple(tys) => HTy::Tuple(tys.into_iter().map(|ty| conv_ty(ty)).collect()),
         Fun(arg, ret) => HTy::fun(conv_ty(*arg), conv_ty(*ret)),
-        Datatype(name) => HTy::Datatype(name),
-        Variable(_) => panic!("polymorphism is not supported yet"),
+        Datatype(name, _) if is_exn(&name) => HTy::Int,
+        Datatype(name, args) => {
+            HTy::Datatype(name, args.into_iter().map(|ty| conv_ty(ty)).collect())
+        }
+        Variable(id) => HTy::Variable(id),
     }
 }
 
@@ -229,6 +251,16 @@ impl AST2HIRPass {
                     }
                 }
             }
+            ast::Declaration::Local { binds, body } => binds
+                .into_iter()
+                .chain(body)
+                .flat_map(|decl| self.conv_statement(decl))
+                .collect(),
+            ast::Declaration::Structure { body, .. } => body
+                .into_iter()
+                .flat_map(|decl| self.conv_statement(decl))
+                .collect(),
+            ast::Declaration::Exception { .. } => vec![],
             ast::Declaration::D(d) => match d {},
         }
     }
@@ -276,7 +308,7 @@ impl AST2HIRPass {
                 }
             }
             E::App { fun, arg } => self.conv_expr(*fun).app1(conv_ty(ty), self.conv_expr(*arg)),
-            E::Case { cond, clauses } => Expr::Case {
+            E::Case { cond, clauses, .. } => Expr::Case {
                 ty: conv_ty(ty),
                 expr: Box::new(self.conv_expr(*cond)),
                 arms: clauses
@@ -288,10 +320,35 @@ impl AST2HIRPass {
                 tys: self.force_tuple(ty),
                 tuple: tuple.into_iter().map(|e| self.conv_expr(e)).collect(),
             },
-            E::Constructor { arg, name } => Expr::Constructor {
-                ty: conv_ty(ty),
-                arg: arg.map(|a| Box::new(self.conv_expr(*a))),
-                descriminant: self.conv_constructor_name(&name),
+            E::Constructor { arg, name } => match ty {
+                ref ty if is_exn_ty(ty) => {
+                    // the argument is evaluated but not kept yet
+                    let tag = Expr::Lit {
+                        ty: HTy::Int,
+                        value: Literal::Int(name.1 as i64),
+                    };
+                    match arg {
+                        Some(arg) => {
+                            let arg = self.conv_expr(*arg);
+                            Expr::Binds {
+                                ty: HTy::Int,
+                                binds: vec![Val {
+                                    ty: arg.ty(),
+                                    rec: false,
+                                    name: self.gensym(),
+                                    expr: arg,
+                                }],
+                                ret: Box::new(tag),
+                            }
+                        }
+                        None => tag,
+                    }
+                }
+                ty => Expr::Constructor {
+                    ty: conv_ty(ty),
+                    arg: arg.map(|a| Box::new(self.conv_expr(*a))),
+                    descriminant: self.conv_constructor_name(&name),
+                },
             },
             E::Symbol { name } => Expr::Sym {
                 ty: conv_ty(ty),
@@ -315,6 +372,9 @@ impl AST2HIRPass {
                 value,
                 ty: conv_ty(ty),
             },
+            ast::PatternKind::Constructor { .. } if is_exn_ty(&ty) => {
+                panic!("matching exceptions is not supported yet")
+            }
             ast::PatternKind::Constructor { arg, name } => Pattern::Constructor {
                 ty: conv_ty(ty),
                 arg: arg.map(|pat| match *pat {
@@ -12,7 +12,8 @@ pub struct ConstructorToEnumPass {
 fn rewrite_ty(enum_likes: &HashSet<Symbol>, ty: HTy) -> HTy {
     use HTy::*;
     match ty {
-        Datatype(name) if enum_likes.contains(&name) => HTy::Int,
+        Datatype(name, _) if enum_likes.contains(&name) => HTy::Int,
+        Array(elem) => Array(Box::new(rewrite_ty(enum_likes, *elem))),
         Fun(arg, ret) => Fun(
             Box::new(rewrite_ty(enum_likes, *arg)),
             Box::new(rewrite_ty(enum_likes, *ret)),
@@ -203,7 +204,7 @@ impl Transform for ConstructorToEnumPass {
                         ty,
                         arg,
                     } => match ty {
-                        HTy::Datatype(name) if self.is_enum_like(&name) => Constant {
+                        HTy::Datatype(name, _) if self.is_enum_like(&name) => Constant {
                             ty: HTy::Int,
                             value: descriminant as i64,
                         },
@@ -256,7 +257,7 @@ impl Transform for ConstructorToEnumPass {
         descriminant: u32,
     ) -> Expr {
         let name = match &ty {
-            HTy::Datatype(name) => name,
+            HTy::Datatype(name, _) => name,
             _ => unreachable!(),
         };
         if self.is_enum_like(name) {
@@ -0,0 +1,280 @@
+use crate::config::Config;
+use crate::hir::util::{Transform, Traverse};
+use crate::hir::*;
+use crate::id::Id;
+use crate::pass::Pass;
+use crate::prim::*;
+use std::collections::{HashMap, HashSet};
+
+/// inlines the calls to the known functions which are used only once
+/// or whose size is at most `Config::effective_inline_threshold`, then beta-reduces them.
+/// The functions no longer used are removed.
+pub struct Inline {
+    id: Id,
+    threshold: usize,
+    uses: HashMap<Symbol, usize>,
+    /// the functions to inline, by their names
+    funs: HashMap<Symbol, Expr>,
+}
+
+impl Inline {
+    pub fn new(id: Id) -> Self {
+        Inline {
+            id,
+            threshold: 0,
+            uses: HashMap::new(),
+            funs: HashMap::new(),
+        }
+    }
+
+    fn inline_hir(&mut self, mut hir: HIR) -> HIR {
+        let mut uses = Uses(HashMap::new());
+        uses.traverse_hir(&mut hir);
+        self.uses = uses.0;
+
+        let mut hir = self.transform_hir(hir);
+
+        let mut uses = Uses(HashMap::new());
+        uses.traverse_hir(&mut hir);
+        let dead = self
+            .funs
+            .keys()
+            .filter(|name| !uses.0.contains_key(name))
+            .cloned()
+            .collect();
+        RemoveDead(dead).transform_hir(hir)
+    }
+
+    fn is_inlinable(&self, val: &mut Val) -> bool {
+        let body = match &val.expr {
+            Expr::Fun { body, .. } => body,
+            _ => return false,
+        };
+        let once = self.uses.get(&val.name) == Some(&1);
+        if !(once || size(body) <= self.threshold) {
+            return false;
+        }
+        // recursive functions would be inlined forever
+        let mut refs = Uses(HashMap::new());
+        refs.traverse_val(val);
+        !refs.0.contains_key(&val.name)
+    }
+
+    /// a copy of the function with all the names bound in it renamed
+    fn copy_fun(&mut self, name: &Symbol) -> Expr {
+        let mut fun = self.funs[name].clone();
+        Rename {
+            id: &mut self.id,
+            table: HashMap::new(),
+        }
+        .traverse_expr(&mut fun);
+        fun
+    }
+}
+
+/// applies `fun` to `arg`, binding the param to the arg if `fun` is a function literal
+fn beta(ty: HTy, fun: Expr, arg: Expr) -> Expr {
+    use crate::hir::Expr::*;
+    match fun {
+        Fun {
+            param: (param_ty, param),
+            body_ty,
+            body,
+            ..
+        } => Binds {
+            ty: body_ty,
+            binds: vec![Val {
+                ty: param_ty,
+                rec: false,
+                name: param,
+                expr: arg,
+            }],
+            ret: body,
+        },
+        // as of curried functions, `(let ... in fn y => ... end) arg`
+        Binds { binds, ret, .. } => Binds {
+            ty: ty.clone(),
+            binds,
+            ret: Box::new(beta(ty, *ret, arg)),
+        },
+        fun => App {
+            ty,
+            fun: Box::new(fun),
+            arg: Box::new(arg),
+        },
+    }
+}
+
+fn size(expr: &Expr) -> usize {
+    use crate::hir::Expr::*;
+    1 + match expr {
+        Binds { binds, ret, .. } => {
+            binds.iter().map(|val| size(&val.expr)).sum::<usize>() + size(ret)
+        }
+        BuiltinCall { args, .. } | ExternCall { args, .. } => args.iter().map(size).sum(),
+        Fun { body, .. } => size(body),
+        App { fun, arg, .. } => size(fun) + size(arg),
+        Case { expr, arms, .. } => {
+            size(expr) + arms.iter().map(|(_, arm)| size(arm)).sum::<usize>()
+        }
+        Tuple { tuple, .. } => tuple.iter().map(size).sum(),
+        Proj { tuple, .. } => size(tuple),
+        Constructor { arg, .. } => arg.iter().map(|arg| size(arg)).sum(),
+        Closure { .. } | Sym { .. } | Lit { .. } => 0,
+    }
+}
+
+impl Transform for Inline {
+    fn transform_val(&mut self, mut val: Val) -> Val {
+        val.expr = self.transform_expr(val.expr);
+        if self.is_inlinable(&mut val) {
+            self.funs.insert(val.name.clone(), val.expr.clone());
+        }
+        val
+    }
+
+    fn transform_app(&mut self, ty: HTy, fun: Box<Expr>, arg: Box<Expr>) -> Expr {
+        let fun = match *fun {
+            Expr::Sym { ref name, .. } if self.funs.contains_key(name) => self.copy_fun(name),
+            fun => self.transform_expr(fun),
+        };
+        let arg = self.transform_expr(*arg);
+        beta(ty, fun, arg)
+    }
+}
+
+/// counts the references to each name
+struct Uses(HashMap<Symbol, usize>);
+
+impl Traverse for Uses {
+    fn traverse_closure(
+        &mut self,
+        envs: &mut Vec<(HTy, Symbol)>,
+        _param_ty: &mut HTy,
+        _body_ty: &mut HTy,
+        fname: &mut Symbol,
+    ) {
+        for name in envs.iter().map(|(_, var)| var).chain(Some(&*fname)) {
+            *self.0.entry(name.clone()).or_insert(0) += 1;
+        }
+    }
+
+    fn traverse_sym(&mut self, _ty: &mut HTy, name: &mut Symbol) {
+        *self.0.entry(name.clone()).or_insert(0) += 1;
+    }
+}
+
+/// gives fresh names to the names bound in an expression, to copy it
+struct Rename<'a> {
+    id: &'a mut Id,
+    table: HashMap<Symbol, Symbol>,
+}
+
+impl<'a> Rename<'a> {
+    fn bind(&mut self, name: &mut Symbol) {
+        let new = Symbol(name.0.clone(), self.id.next());
+        self.table.insert(name.clone(), new.clone());
+        *name = new;
+    }
+}
+
+impl<'a> Traverse for Rename<'a> {
+    fn traverse_binds(&mut self, _ty: &mut HTy, binds: &mut Vec<Val>, ret: &mut Box<Expr>) {
+        for val in binds.iter_mut() {
+            self.bind(&mut val.name);
+            self.traverse_val(val)
+        }
+        self.traverse_expr(ret)
+    }
+
+    fn traverse_fun(
+        &mut self,
+        param: &mut (HTy, Symbol),
+        _body_ty: &mut HTy,
+        body: &mut Box<Expr>,
+        _captures: &mut Vec<(HTy, Symbol)>,
+    ) {
+        self.bind(&mut param.1);
+        self.traverse_expr(body)
+    }
+
+    fn traverse_case(
+        &mut self,
+        _ty: &mut HTy,
+        expr: &mut Box<Expr>,
+        arms: &mut Vec<(Pattern, Expr)>,
+    ) {
+        self.traverse_expr(expr);
+        for (pat, arm) in arms.iter_mut() {
+            match pat {
+                Pattern::Var { name, .. }
+                | Pattern::Constructor {
+                    arg: Some((_, name)),
+                    ..
+                } => self.bind(name),
+                Pattern::Tuple { tuple, .. } => {
+                    for name in tuple.iter_mut() {
+                        self.bind(name)
+                    }
+                }
+                Pattern::Constant { .. } | Pattern::Char { .. } | Pattern::Constructor { .. } => (),
+            }
+            self.traverse_expr(arm)
+        }
+    }
+
+    fn traverse_sym(&mut self, _ty: &mut HTy, name: &mut Symbol) {
+        if let Some(new) = self.table.get(name) {
+            *name = new.clone();
+        }
+    }
+}
+
+/// removes the bindings of the functions
+struct RemoveDead(HashSet<Symbol>);
+
+impl Transform for RemoveDead {
+    fn transform_hir(&mut self, mut hir: HIR) -> HIR {
+        hir.0.retain(|val| !self.0.contains(&val.name));
+        hir.0 = hir
+            .0
+            .into_iter()
+            .map(|val| self.transform_val(val))
+            .collect();
+        hir
+    }
+
+    fn transform_binds(&mut self, ty: HTy, mut binds: Vec<Val>, ret: Box<Expr>) -> Expr {
+        binds.retain(|val| !self.0.contains(&val.name));
+        let binds = binds
+            .into_iter()
+            .map(|val| self.transform_val(val))
+            .collect::<Vec<_>>();
+        let ret = self.transform_expr(*ret);
+        if binds.is_empty() {
+            ret
+        } else {
+            Expr::Binds {
+                ty,
+                binds,
+                ret: Box::new(ret),
+            }
+        }
+    }
+}
+
+impl<E> Pass<Context, E> for Inline {
+    type Target = Context;
+
+    fn trans(
+        &mut self,
+        Context(symbol_table, hir): Context,
+        config: &Config,
+    ) -> ::std::result::Result<Self::Target, E> {
+        self.threshold = config.effective_inline_threshold();
+        if self.threshold == 0 {
+            return Ok(Context(symbol_table, hir));
+        }
+        Ok(Context(symbol_table, self.inline_hir(hir)))
+    }
+}
@@ -3,6 +3,8 @@ pub mod constructor2enum;
 pub mod flat_expr;
 pub mod flat_let;
 pub mod force_closure;
+pub mod inline;
+pub mod monomorphize;
 pub mod pp;
 pub mod simplify;
 pub mod unnest_func;
@@ -13,19 +15,22 @@ pub use self::constructor2enum::ConstructorToEnum;
 pub use self::flat_expr::FlatExpr;
 pub use self::flat_let::FlatLet;
 pub use self::force_closure::ForceClosure;
+pub use self::inline::Inline;
+pub use self::monomorphize::Monomorphize;
 pub use self::simplify::Simplify;
 pub use self::unnest_func::UnnestFunc;
 use std::collections::HashMap;
 
 use crate::prim::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Context(pub SymbolTable, pub HIR);
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct HIR(pub Vec<Val>);
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Val {
     pub ty: HTy,
     pub rec: bool,
@@ -33,7 +38,7 @@ pub struct Val {
     pub expr: Expr,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Expr {
     Binds {
         ty: HTy,
@@ -98,7 +103,7 @@ pub enum Expr {
     },
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Pattern {
     Constant {
         value: i64,
@@ -123,24 +128,34 @@ pub enum Pattern {
     },
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SymbolTable {
     pub types: HashMap<Symbol, TypeInfo>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum HTy {
     Char,
     Int,
     Real,
+    String,
+    Int64,
+    Word64,
+    Real32,
+    /// arrays and vectors, which share the representation
+    Array(Box<HTy>),
     Fun(Box<HTy>, Box<HTy>),
     Tuple(Vec<HTy>),
-    Datatype(Symbol),
+    Datatype(Symbol, Vec<HTy>),
     // Datatype(Vec<(u32, Option<HTy>)>),
+    /// only appears until `Monomorphize`
+    Variable(u64),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TypeInfo {
+    /// the type variables the constructors are parameterized over
+    pub params: Vec<u64>,
     pub constructors: Vec<(u32, Option<HTy>)>,
 }
 
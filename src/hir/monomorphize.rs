@@ -0,0 +1,507 @@
+use crate::config::Config;
+use crate::hir::util::Traverse;
+use crate::hir::*;
+use crate::id::Id;
+use crate::pass::Pass;
+use crate::prim::*;
+use std::collections::HashMap;
+use std::mem;
+
+/// Makes a copy of each polymorphic value per type it is used at,
+/// and then a copy of each parameterized datatype per type arguments.
+/// After this pass, no `HTy::Variable` nor datatype arguments remain.
+pub struct Monomorphize {
+    id: Id,
+}
+
+impl Monomorphize {
+    pub fn new(id: Id) -> Self {
+        Self { id }
+    }
+}
+
+struct Scope {
+    generics: HashMap<Symbol, Val>,
+    instances: HashMap<(Symbol, HTy), Symbol>,
+    /// (generic, type, instance) waiting to be generated
+    pending: Vec<(Symbol, HTy, Symbol)>,
+}
+
+impl Scope {
+    fn new() -> Self {
+        Self {
+            generics: HashMap::new(),
+            instances: HashMap::new(),
+            pending: Vec::new(),
+        }
+    }
+}
+
+enum Slot {
+    Generic(Symbol),
+    Mono(Val),
+}
+
+struct MonomorphizePass {
+    id: Id,
+    scopes: Vec<Scope>,
+    generic_types: HashMap<Symbol, TypeInfo>,
+    types: HashMap<Symbol, TypeInfo>,
+    datatypes: HashMap<(Symbol, Vec<HTy>), Symbol>,
+}
+
+impl MonomorphizePass {
+    fn new(id: Id, symbol_table: SymbolTable) -> Self {
+        let (types, generic_types) = symbol_table
+            .types
+            .into_iter()
+            .partition::<HashMap<_, _>, _>(|(_, info)| info.params.is_empty());
+        Self {
+            id,
+            scopes: Vec::new(),
+            generic_types,
+            types,
+            datatypes: HashMap::new(),
+        }
+    }
+
+    fn mono_hir(&mut self, hir: HIR) -> HIR {
+        HIR(self.mono_vals(hir.0))
+    }
+
+    fn mono_vals(&mut self, vals: Vec<Val>) -> Vec<Val> {
+        self.scopes.push(Scope::new());
+        // register all the generics first to be found by forward references of `rec`
+        let slots = vals
+            .into_iter()
+            .map(|val| {
+                if has_variable(&val.ty) {
+                    let name = val.name.clone();
+                    self.scope_mut().generics.insert(name.clone(), val);
+                    Slot::Generic(name)
+                } else {
+                    Slot::Mono(val)
+                }
+            })
+            .collect::<Vec<_>>();
+        let slots = slots
+            .into_iter()
+            .map(|slot| match slot {
+                Slot::Mono(mut val) => {
+                    self.traverse_val(&mut val);
+                    Slot::Mono(val)
+                }
+                slot => slot,
+            })
+            .collect::<Vec<_>>();
+
+        let mut instances = HashMap::<Symbol, Vec<Val>>::new();
+        while let Some((generic, ty, instance)) = self.scope_mut().pending.pop() {
+            let mut val = self.instantiate(&generic, ty, instance);
+            self.traverse_val(&mut val);
+            instances.entry(generic).or_insert_with(Vec::new).push(val);
+        }
+        self.scopes.pop();
+
+        // instances are placed where their generic was defined
+        slots
+            .into_iter()
+            .flat_map(|slot| match slot {
+                Slot::Mono(val) => vec![val],
+                Slot::Generic(name) => instances.remove(&name).unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    fn scope_mut(&mut self) -> &mut Scope {
+        self.scopes.last_mut().expect("internal error: no scope")
+    }
+
+    fn instantiate(&mut self, generic: &Symbol, ty: HTy, instance: Symbol) -> Val {
+        let mut val = self.scope_mut().generics[generic].clone();
+        let mut subst = HashMap::new();
+        match_ty(&val.ty, &ty, &mut subst);
+        let mut rewriter =
+            Rewriter::with_fresh_names(|ty: &mut HTy| substitute(ty, &subst), self.id.clone());
+        rewriter.traverse_expr(&mut val.expr);
+        val.ty = ty;
+        val.name = instance;
+        val
+    }
+
+    fn instance_of(&mut self, name: &Symbol, ty: &HTy) -> Option<Symbol> {
+        let id = &mut self.id;
+        let scope = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find(|scope| scope.generics.contains_key(name))?;
+        let key = (name.clone(), ty.clone());
+        if let Some(instance) = scope.instances.get(&key) {
+            return Some(instance.clone());
+        }
+        let instance = Symbol(name.0.clone(), id.next());
+        scope.instances.insert(key, instance.clone());
+        scope
+            .pending
+            .push((name.clone(), ty.clone(), instance.clone()));
+        Some(instance)
+    }
+
+    fn specialize_hir(&mut self, hir: &mut HIR) {
+        let mut rewriter = Rewriter::new(|ty: &mut HTy| self.specialize_ty(ty));
+        rewriter.traverse_hir(hir);
+    }
+
+    fn specialize_types(&mut self) -> SymbolTable {
+        let mut types = mem::replace(&mut self.types, HashMap::new());
+        for info in types.values_mut() {
+            for (_, arg) in info.constructors.iter_mut() {
+                if let Some(ty) = arg {
+                    self.specialize_ty(ty)
+                }
+            }
+        }
+        types.extend(self.types.drain());
+        SymbolTable { types }
+    }
+
+    fn specialize_ty(&mut self, ty: &mut HTy) {
+        use HTy::*;
+        match ty {
+            Char | Int | Real | String | Int64 | Word64 | Real32 | Variable(_) => (),
+            Array(elem) => self.specialize_ty(elem),
+            Fun(arg, ret) => {
+                self.specialize_ty(arg);
+                self.specialize_ty(ret);
+            }
+            Tuple(tys) => {
+                for ty in tys {
+                    self.specialize_ty(ty)
+                }
+            }
+            Datatype(name, args) => {
+                if args.is_empty() {
+                    return;
+                }
+                for arg in args.iter_mut() {
+                    self.specialize_ty(arg)
+                }
+                let args = mem::replace(args, Vec::new());
+                *name = self.specialize_datatype(name.clone(), args);
+            }
+        }
+    }
+
+    fn specialize_datatype(&mut self, name: Symbol, args: Vec<HTy>) -> Symbol {
+        let key = (name, args);
+        if let Some(specialized) = self.datatypes.get(&key) {
+            return specialized.clone();
+        }
+        let (name, args) = key;
+        let specialized = Symbol(name.0.clone(), self.id.next());
+        // register before specializing the constructors to handle recursive types
+        self.datatypes
+            .insert((name.clone(), args.clone()), specialized.clone());
+        let info = self.generic_types[&name].clone();
+        let subst = info.params.into_iter().zip(args).collect();
+        let constructors = info
+            .constructors
+            .into_iter()
+            .map(|(descriminant, arg)| {
+                let arg = arg.map(|mut ty| {
+                    substitute(&mut ty, &subst);
+                    self.specialize_ty(&mut ty);
+                    ty
+                });
+                (descriminant, arg)
+            })
+            .collect();
+        self.types.insert(
+            specialized.clone(),
+            TypeInfo {
+                params: Vec::new(),
+                constructors,
+            },
+        );
+        specialized
+    }
+}
+
+impl Traverse for MonomorphizePass {
+    fn traverse_binds(&mut self, _ty: &mut HTy, binds: &mut Vec<Val>, ret: &mut Box<Expr>) {
+        let vals = mem::replace(binds, Vec::new());
+        *binds = self.mono_vals(vals);
+        self.traverse_expr(ret)
+    }
+
+    fn traverse_sym(&mut self, ty: &mut HTy, name: &mut Symbol) {
+        if let Some(instance) = self.instance_of(name, ty) {
+            *name = instance
+        }
+    }
+}
+
+fn has_variable(ty: &HTy) -> bool {
+    use HTy::*;
+    match ty {
+        Char | Int | Real | String | Int64 | Word64 | Real32 => false,
+        Variable(_) => true,
+        Array(elem) => has_variable(elem),
+        Fun(arg, ret) => has_variable(arg) || has_variable(ret),
+        Tuple(tys) | Datatype(_, tys) => tys.iter().any(has_variable),
+    }
+}
+
+/// collects the substitution which makes `generic` into `ty`
+fn match_ty(generic: &HTy, ty: &HTy, subst: &mut HashMap<u64, HTy>) {
+    use HTy::*;
+    match (generic, ty) {
+        (Variable(id), ty) => {
+            subst.insert(*id, ty.clone());
+        }
+        (Array(elem1), Array(elem2)) => match_ty(elem1, elem2, subst),
+        (Fun(arg1, ret1), Fun(arg2, ret2)) => {
+            match_ty(arg1, arg2, subst);
+            match_ty(ret1, ret2, subst);
+        }
+        (Tuple(tys1), Tuple(tys2)) | (Datatype(_, tys1), Datatype(_, tys2)) => {
+            for (ty1, ty2) in tys1.iter().zip(tys2) {
+                match_ty(ty1, ty2, subst)
+            }
+        }
+        _ => (),
+    }
+}
+
+fn substitute(ty: &mut HTy, subst: &HashMap<u64, HTy>) {
+    use HTy::*;
+    match ty {
+        Char | Int | Real | String | Int64 | Word64 | Real32 => (),
+        Variable(id) => {
+            if let Some(t) = subst.get(id).cloned() {
+                *ty = t
+            }
+        }
+        Array(elem) => substitute(elem, subst),
+        Fun(arg, ret) => {
+            substitute(arg, subst);
+            substitute(ret, subst);
+        }
+        Tuple(tys) | Datatype(_, tys) => {
+            for ty in tys {
+                substitute(ty, subst)
+            }
+        }
+    }
+}
+
+/// Rewrites all the types in the tree,
+/// optionally giving fresh names to the variables bound in it.
+struct Rewriter<F> {
+    rewrite_ty: F,
+    fresh: Option<Id>,
+    renames: HashMap<Symbol, Symbol>,
+}
+
+impl<F: FnMut(&mut HTy)> Rewriter<F> {
+    fn new(rewrite_ty: F) -> Self {
+        Self {
+            rewrite_ty,
+            fresh: None,
+            renames: HashMap::new(),
+        }
+    }
+
+    fn with_fresh_names(rewrite_ty: F, id: Id) -> Self {
+        Self {
+            rewrite_ty,
+            fresh: Some(id),
+            renames: HashMap::new(),
+        }
+    }
+
+    fn ty(&mut self, ty: &mut HTy) {
+        (self.rewrite_ty)(ty)
+    }
+
+    fn bind(&mut self, name: &mut Symbol) {
+        if let Some(id) = self.fresh.as_mut() {
+            let new = Symbol(name.0.clone(), id.next());
+            self.renames.insert(name.clone(), new.clone());
+            *name = new;
+        }
+    }
+
+    fn refer(&mut self, name: &mut Symbol) {
+        if let Some(new) = self.renames.get(name) {
+            *name = new.clone();
+        }
+    }
+
+    fn pattern(&mut self, pat: &mut Pattern) {
+        use crate::hir::Pattern::*;
+        match pat {
+            Constant { ty, .. } | Char { ty, .. } => self.ty(ty),
+            Constructor { arg, ty, .. } => {
+                if let Some((arg_ty, name)) = arg {
+                    self.ty(arg_ty);
+                    self.bind(name);
+                }
+                self.ty(ty)
+            }
+            Var { name, ty } => {
+                self.ty(ty);
+                self.bind(name);
+            }
+            Tuple { tys, tuple } => {
+                for ty in tys {
+                    self.ty(ty)
+                }
+                for name in tuple {
+                    self.bind(name)
+                }
+            }
+        }
+    }
+}
+
+impl<F: FnMut(&mut HTy)> Traverse for Rewriter<F> {
+    fn traverse_val(&mut self, val: &mut Val) {
+        self.ty(&mut val.ty);
+        self.bind(&mut val.name);
+        self.traverse_expr(&mut val.expr)
+    }
+
+    fn traverse_binds(&mut self, ty: &mut HTy, binds: &mut Vec<Val>, ret: &mut Box<Expr>) {
+        self.ty(ty);
+        for val in binds.iter_mut() {
+            self.traverse_val(val)
+        }
+        self.traverse_expr(ret)
+    }
+
+    fn traverse_fun(
+        &mut self,
+        param: &mut (HTy, Symbol),
+        body_ty: &mut HTy,
+        body: &mut Box<Expr>,
+        captures: &mut Vec<(HTy, Symbol)>,
+    ) {
+        self.ty(&mut param.0);
+        self.bind(&mut param.1);
+        self.ty(body_ty);
+        for (ty, name) in captures.iter_mut() {
+            self.ty(ty);
+            self.refer(name);
+        }
+        self.traverse_expr(body)
+    }
+
+    fn traverse_closure(
+        &mut self,
+        envs: &mut Vec<(HTy, Symbol)>,
+        param_ty: &mut HTy,
+        body_ty: &mut HTy,
+        fname: &mut Symbol,
+    ) {
+        for (ty, name) in envs.iter_mut() {
+            self.ty(ty);
+            self.refer(name);
+        }
+        self.ty(param_ty);
+        self.ty(body_ty);
+        self.refer(fname);
+    }
+
+    fn traverse_builtin_call(&mut self, ty: &mut HTy, _fun: &mut BIF, args: &mut Vec<Expr>) {
+        self.ty(ty);
+        for arg in args {
+            self.traverse_expr(arg)
+        }
+    }
+
+    fn traverse_extern_call(
+        &mut self,
+        ty: &mut HTy,
+        _module: &mut String,
+        _fun: &mut String,
+        args: &mut Vec<Expr>,
+    ) {
+        self.ty(ty);
+        for arg in args {
+            self.traverse_expr(arg)
+        }
+    }
+
+    fn traverse_app(&mut self, ty: &mut HTy, fun: &mut Box<Expr>, arg: &mut Box<Expr>) {
+        self.ty(ty);
+        self.traverse_expr(fun);
+        self.traverse_expr(arg);
+    }
+
+    fn traverse_case(
+        &mut self,
+        ty: &mut HTy,
+        expr: &mut Box<Expr>,
+        arms: &mut Vec<(Pattern, Expr)>,
+    ) {
+        self.ty(ty);
+        self.traverse_expr(expr);
+        for (pat, arm) in arms.iter_mut() {
+            self.pattern(pat);
+            self.traverse_expr(arm);
+        }
+    }
+
+    fn traverse_tuple(&mut self, tys: &mut Vec<HTy>, tuple: &mut Vec<Expr>) {
+        for ty in tys.iter_mut() {
+            self.ty(ty)
+        }
+        for t in tuple.iter_mut() {
+            self.traverse_expr(t)
+        }
+    }
+
+    fn traverse_proj(&mut self, ty: &mut HTy, _index: &mut u32, tuple: &mut Box<Expr>) {
+        self.ty(ty);
+        self.traverse_expr(tuple)
+    }
+
+    fn traverse_constructor(
+        &mut self,
+        ty: &mut HTy,
+        arg: &mut Option<Box<Expr>>,
+        _descriminant: &mut u32,
+    ) {
+        self.ty(ty);
+        if let Some(arg) = arg {
+            self.traverse_expr(arg)
+        }
+    }
+
+    fn traverse_sym(&mut self, ty: &mut HTy, name: &mut Symbol) {
+        self.ty(ty);
+        self.refer(name);
+    }
+
+    fn traverse_lit(&mut self, ty: &mut HTy, _value: &mut Literal) {
+        self.ty(ty)
+    }
+}
+
+impl<E> Pass<Context, E> for Monomorphize {
+    type Target = Context;
+
+    fn trans(
+        &mut self,
+        Context(symbol_table, hir): Context,
+        _: &Config,
+    ) -> ::std::result::Result<Self::Target, E> {
+        let mut pass = MonomorphizePass::new(self.id.clone(), symbol_table);
+        let mut hir = pass.mono_hir(hir);
+        pass.specialize_hir(&mut hir);
+        let symbol_table = pass.specialize_types();
+        Ok(Context(symbol_table, hir))
+    }
+}
@@ -382,6 +382,14 @@ impl PP for HTy {
             Char => write!(w, "char")?,
             Int => write!(w, "int")?,
             Real => write!(w, "real")?,
+            String => write!(w, "string")?,
+            Int64 => write!(w, "int64")?,
+            Word64 => write!(w, "word64")?,
+            Real32 => write!(w, "real32")?,
+            Array(elem) => {
+                elem.pp(w, indent)?;
+                write!(w, " array")?;
+            }
             Tuple(tys) => {
                 write!(w, "(")?;
                 inter_iter! {
@@ -398,9 +406,21 @@ impl PP for HTy {
                 write!(w, " -> ")?;
                 t2.pp(w, indent)?;
             }
-            Datatype(name) => {
+            Datatype(name, args) => {
+                if !args.is_empty() {
+                    write!(w, "(")?;
+                    inter_iter! {
+                        args.iter(),
+                        write!(w, ", ")?,
+                        |ty| => {
+                            ty.pp(w, indent)?
+                        }
+                    }
+                    write!(w, ") ")?;
+                }
                 name.pp(w, indent)?;
             }
+            Variable(id) => write!(w, "'{}", id)?,
         }
         Ok(())
     }
@@ -413,6 +433,11 @@ impl fmt::Display for HTy {
             Char => write!(f, "char")?,
             Int => write!(f, "int")?,
             Real => write!(f, "real")?,
+            String => write!(f, "string")?,
+            Int64 => write!(f, "int64")?,
+            Word64 => write!(f, "word64")?,
+            Real32 => write!(f, "real32")?,
+            Array(elem) => write!(f, "{} array", elem)?,
             Tuple(tys) => {
                 write!(f, "(")?;
                 inter_iter! {
@@ -427,7 +452,21 @@ impl fmt::Display for HTy {
             Fun(t1, t2) => {
                 write!(f, "{} -> {}", t1, t2)?;
             }
-            Datatype(name) => write!(f, "{}", name)?,
+            Datatype(name, args) => {
+                if !args.is_empty() {
+                    write!(f, "(")?;
+                    inter_iter! {
+                        args.iter(),
+                        write!(f, ", ")?,
+                        |ty| => {
+                            write!(f, "{}", ty)?
+                        }
+                    }
+                    write!(f, ") ")?;
+                }
+                write!(f, "{}", name)?
+            }
+            Variable(id) => write!(f, "'{}", id)?,
         }
         Ok(())
     }
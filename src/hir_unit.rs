@@ -0,0 +1,217 @@
+//! Units compiled separately only to HIR, and serialized.
+//!
+//! A unit is stored in an empty wasm module with two custom sections:
+//! `webml.interface`, the bindings the unit exports to the units after it,
+//! and `webml.hir`, the code of the unit lowered to HIR.
+//! It holds no wasm code. `lower_units` merges the HIR of the units and runs
+//! the whole backend on it, as monomorphization and inlining need the whole program,
+//! so only the frontend is skipped for the units which didn't change.
+
+use crate::ast::{
+    self, Context, Declaration, Type, TypeInfo, TypedCoreContext, TypedCoreDeclaration,
+};
+use crate::hir;
+use crate::parser::Fixity;
+use crate::prim::*;
+use serde::{Deserialize, Serialize};
+use std::error;
+use std::fmt;
+
+pub(crate) const WASM_HEADER: &[u8] = b"\0asm\x01\0\0\0";
+const INTERFACE_SECTION: &str = "webml.interface";
+const CODE_SECTION: &str = "webml.hir";
+
+#[derive(Debug)]
+pub enum Error {
+    NotWasm,
+    Truncated,
+    MissingSection(&'static str),
+    Decode(bincode::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::Error::*;
+        match self {
+            NotWasm => write!(f, "not a wasm module"),
+            Truncated => write!(f, "truncated wasm module"),
+            MissingSection(name) => write!(f, "custom section {} not found", name),
+            Decode(e) => write!(f, "broken HIR unit: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<bincode::Error> for Error {
+    fn from(e: bincode::Error) -> Self {
+        Error::Decode(e)
+    }
+}
+
+/// what a unit provides to the units compiled after it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Interface {
+    /// symbols of the unit and its dependencies have ids less than this
+    pub next_id: u64,
+    pub fixity: Fixity,
+    pub values: Vec<(Symbol, Type)>,
+    pub types: Vec<(Symbol, TypeInfo)>,
+    pub exceptions: Vec<(Symbol, Option<Type>)>,
+}
+
+impl Interface {
+    /// the environment made of `interfaces`, later ones shadowing earlier ones
+    pub fn merge<'i>(interfaces: impl IntoIterator<Item = &'i Interface>) -> Self {
+        let mut merged = Interface::default();
+        for interface in interfaces {
+            merged.next_id = merged.next_id.max(interface.next_id);
+            merged.fixity.extend(interface.fixity.clone());
+            merged.values.extend(interface.values.iter().cloned());
+            merged.types.extend(interface.types.iter().cloned());
+            merged
+                .exceptions
+                .extend(interface.exceptions.iter().cloned());
+        }
+        merged
+    }
+
+    /// the top level bindings of `ast`
+    pub fn exports(Context(symbol_table, ast): &TypedCoreContext) -> Self {
+        let mut interface = Interface::default();
+        interface.collect(symbol_table, &ast.0);
+        interface
+    }
+
+    fn collect(&mut self, symbol_table: &ast::SymbolTable, decls: &[TypedCoreDeclaration]) {
+        for decl in decls {
+            match decl {
+                Declaration::Datatype { name, .. } => {
+                    let info = symbol_table
+                        .get_type(name)
+                        .expect("internal error: datatype not registered");
+                    self.types.push((name.clone(), info.clone()))
+                }
+                Declaration::Val { pattern, .. } => {
+                    for (name, ty) in pattern.binds() {
+                        self.values.push((name.clone(), ty.clone()))
+                    }
+                }
+                Declaration::Exception { name, arg } => {
+                    self.exceptions.push((name.clone(), arg.clone()))
+                }
+                // only the body of local is visible from outside
+                Declaration::Local { body, .. } => self.collect(symbol_table, body),
+                Declaration::Structure { body, .. } => self.collect(symbol_table, body),
+                Declaration::D(d) => match *d {},
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HirUnit {
+    pub name: String,
+    pub interface: Interface,
+    pub code: hir::Context,
+}
+
+impl HirUnit {
+    pub fn to_wasm(&self) -> Vec<u8> {
+        let mut bytes = WASM_HEADER.to_vec();
+        let interface = bincode::serialize(&(&self.name, &self.interface))
+            .expect("internal error: failed to serialize interface");
+        let code =
+            bincode::serialize(&self.code).expect("internal error: failed to serialize code");
+        write_custom_section(&mut bytes, INTERFACE_SECTION, &interface);
+        write_custom_section(&mut bytes, CODE_SECTION, &code);
+        bytes
+    }
+
+    pub fn from_wasm(bytes: &[u8]) -> Result<Self, Error> {
+        let interface = find_custom_section(bytes, INTERFACE_SECTION)?;
+        let (name, interface) = bincode::deserialize(interface)?;
+        let code = bincode::deserialize(find_custom_section(bytes, CODE_SECTION)?)?;
+        Ok(HirUnit {
+            name,
+            interface,
+            code,
+        })
+    }
+
+    /// read only the interface, for compiling units depending on the unit
+    pub fn interface_from_wasm(bytes: &[u8]) -> Result<Interface, Error> {
+        let (_, interface): (String, Interface) =
+            bincode::deserialize(find_custom_section(bytes, INTERFACE_SECTION)?)?;
+        Ok(interface)
+    }
+}
+
+pub(crate) fn write_u32_leb128(bytes: &mut Vec<u8>, mut n: u32) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+pub(crate) fn read_u32_leb128(bytes: &[u8], pos: &mut usize) -> Result<u32, Error> {
+    let mut n = 0u32;
+    let mut shift = 0;
+    loop {
+        let byte = *bytes.get(*pos).ok_or(Error::Truncated)?;
+        *pos += 1;
+        n |= ((byte & 0x7f) as u32) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(n);
+        }
+        shift += 7;
+        if shift >= 32 {
+            return Err(Error::Truncated);
+        }
+    }
+}
+
+fn write_custom_section(bytes: &mut Vec<u8>, name: &str, content: &[u8]) {
+    let mut payload = Vec::new();
+    write_u32_leb128(&mut payload, name.len() as u32);
+    payload.extend_from_slice(name.as_bytes());
+    payload.extend_from_slice(content);
+    // custom section
+    bytes.push(0);
+    write_u32_leb128(bytes, payload.len() as u32);
+    bytes.extend(payload);
+}
+
+fn find_custom_section<'b>(bytes: &'b [u8], name: &'static str) -> Result<&'b [u8], Error> {
+    if !bytes.starts_with(WASM_HEADER) {
+        return Err(Error::NotWasm);
+    }
+    let mut pos = WASM_HEADER.len();
+    while pos < bytes.len() {
+        let id = bytes[pos];
+        pos += 1;
+        let size = read_u32_leb128(bytes, &mut pos)? as usize;
+        let end = pos + size;
+        if bytes.len() < end {
+            return Err(Error::Truncated);
+        }
+        if id == 0 {
+            let mut name_pos = pos;
+            let name_len = read_u32_leb128(bytes, &mut name_pos)? as usize;
+            let name_end = name_pos + name_len;
+            if end < name_end {
+                return Err(Error::Truncated);
+            }
+            if &bytes[name_pos..name_end] == name.as_bytes() {
+                return Ok(&bytes[name_end..end]);
+            }
+        }
+        pos = end;
+    }
+    Err(Error::MissingSection(name))
+}
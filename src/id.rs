@@ -6,7 +6,11 @@ pub struct Id(Rc<Cell<u64>>);
 
 impl Id {
     pub fn new() -> Self {
-        Id(Rc::new(Cell::new(1)))
+        Self::starting_from(1)
+    }
+
+    pub fn starting_from(id: u64) -> Self {
+        Id(Rc::new(Cell::new(id)))
     }
 
     pub fn next(&mut self) -> u64 {
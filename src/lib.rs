@@ -2,51 +2,373 @@
 pub mod util;
 pub mod ast;
 pub mod backend;
+pub mod cache;
 mod config;
 pub mod hir;
+pub mod hir_unit;
 pub mod id;
 pub mod lir;
 pub mod mir;
+pub mod mlb;
 mod parser;
 pub mod pass;
 pub mod prim;
 mod unification_pool;
+mod warning;
 
 pub use crate::ast::TypeError;
 pub use crate::config::Config;
-pub use crate::parser::parse;
+pub use crate::hir_unit::{HirUnit, Interface};
+pub use crate::parser::{parse, Fixity};
 pub use crate::pass::{Chain, Pass};
+pub use crate::warning::{Warning, Warnings};
+use std::collections::HashMap;
+use std::path::PathBuf;
 
 pub fn compile_str<'a>(input: &'a str, config: &Config) -> Result<Vec<u8>, TypeError<'a>> {
-    use crate::pass::{ConvError, PrintablePass};
-    use wasm::Dump;
+    use crate::pass::ConvError;
+    compile(ConvError::new(parse), input, config)
+}
 
-    let id = id::Id::new();
+/// compile each source of `project` to HIR and lower them as one program,
+/// reusing the units cached in `config.cache_dir` if given
+pub fn compile_project<'a>(
+    project: &'a mlb::Project,
+    config: &Config,
+) -> Result<Vec<u8>, TypeError<'a>> {
+    match &config.cache_dir {
+        Some(dir) => compile_project_cached(project, &cache::Cache::new(dir), config),
+        None => Ok(lower_units(
+            compile_project_hir(project, &[], config)?,
+            config,
+        )),
+    }
+}
 
-    let mut passes = compile_pass![
-       parse: ConvError::new(parse),
+/// compile each top level declaration of `project` separately to HIR, then lower them together.
+/// Declarations found in `cache` are not compiled again,
+/// and the program is not lowered again if none of them changed.
+pub fn compile_project_cached<'a>(
+    project: &'a mlb::Project,
+    cache: &cache::Cache,
+    config: &Config,
+) -> Result<Vec<u8>, TypeError<'a>> {
+    let mut units = Vec::new();
+    let mut keys = Vec::new();
+    compile_sources_cached(
+        &project.units,
+        &mut Interface::default(),
+        &mut HashMap::new(),
+        &mut units,
+        &mut keys,
+        cache,
+        config,
+    )?;
+    let key = cache::Key::program(&keys, config);
+    if let Some(code) = cache.get_program(key) {
+        return Ok(code);
+    }
+    let code = lower_units(units, config);
+    cache.put_program(key, &code);
+    Ok(code)
+}
+
+/// compile the top level declarations of each source of `sources`, with `compile_decls_cached`.
+/// `exports` records the bindings of each source for `mlb::Unit::Bindings`
+fn compile_sources_cached<'a>(
+    sources: &'a [mlb::Unit],
+    env: &mut Interface,
+    exports: &mut HashMap<PathBuf, Interface>,
+    units: &mut Vec<HirUnit>,
+    keys: &mut Vec<cache::Key>,
+    cache: &cache::Cache,
+    config: &Config,
+) -> Result<(), TypeError<'a>> {
+    for source in sources {
+        match source {
+            mlb::Unit::Source { path, text } => {
+                let before = env.clone();
+                let ast =
+                    parser::parse_with_fixity(&path.to_string_lossy(), text, &mut env.fixity)?;
+                if config.pretty_print_ir.contains("parse") {
+                    println!("{}", ast);
+                }
+                compile_decls_cached(ast.0, env, units, keys, cache, config)?;
+                let exported = Interface {
+                    next_id: env.next_id,
+                    fixity: env.fixity.added_since(&before.fixity),
+                    values: env.values[before.values.len()..].to_vec(),
+                    types: env.types[before.types.len()..].to_vec(),
+                    exceptions: env.exceptions[before.exceptions.len()..].to_vec(),
+                };
+                exports.insert(path.clone(), exported);
+            }
+            mlb::Unit::Local { binds, body } => {
+                let outer = env.clone();
+                compile_sources_cached(binds, env, exports, units, keys, cache, config)?;
+                let inner = env.clone();
+                compile_sources_cached(body, env, exports, units, keys, cache, config)?;
+                let fixity = env.fixity.added_since(&inner.fixity);
+                env.values.drain(outer.values.len()..inner.values.len());
+                env.types.drain(outer.types.len()..inner.types.len());
+                env.exceptions
+                    .drain(outer.exceptions.len()..inner.exceptions.len());
+                env.fixity = outer.fixity;
+                env.fixity.extend(fixity);
+            }
+            mlb::Unit::Bindings { path } => {
+                let exported = &exports[path];
+                env.fixity.extend(exported.fixity.clone());
+                env.values.extend(exported.values.iter().cloned());
+                env.types.extend(exported.types.iter().cloned());
+                env.exceptions.extend(exported.exceptions.iter().cloned());
+            }
+        }
+    }
+    Ok(())
+}
+
+// units start at multiples of this, so that a unit using a few more ids
+// doesn't shift the ids of the units after it and invalidate their cache
+const UNIT_ID_ALIGN: u64 = 1 << 16;
+
+fn compile_decls_cached<'a>(
+    decls: Vec<ast::UntypedDeclaration>,
+    env: &mut Interface,
+    units: &mut Vec<HirUnit>,
+    keys: &mut Vec<cache::Key>,
+    cache: &cache::Cache,
+    config: &Config,
+) -> Result<(), TypeError<'a>> {
+    for decl in decls {
+        match decl {
+            // split local so that its declarations are cached separately
+            ast::Declaration::Local { binds, body } => {
+                let (values, types, exceptions) =
+                    (env.values.len(), env.types.len(), env.exceptions.len());
+                compile_decls_cached(binds, env, units, keys, cache, config)?;
+                let (inner_values, inner_types, inner_exceptions) =
+                    (env.values.len(), env.types.len(), env.exceptions.len());
+                compile_decls_cached(body, env, units, keys, cache, config)?;
+                env.values.drain(values..inner_values);
+                env.types.drain(types..inner_types);
+                env.exceptions.drain(exceptions..inner_exceptions);
+            }
+            decl => {
+                env.next_id = (env.next_id / UNIT_ID_ALIGN + 1) * UNIT_ID_ALIGN;
+                let key = cache::Key::new(&decl, env, config);
+                let unit = match cache.get(key) {
+                    Some(unit) => unit,
+                    None => {
+                        let unit =
+                            compile_ast(&key.to_string(), ast::AST(vec![decl]), env, config)?;
+                        cache.put(key, &unit);
+                        unit
+                    }
+                };
+                env.next_id = unit.interface.next_id;
+                env.values.extend(unit.interface.values.iter().cloned());
+                env.types.extend(unit.interface.types.iter().cloned());
+                env.exceptions
+                    .extend(unit.interface.exceptions.iter().cloned());
+                units.push(unit);
+                keys.push(key);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// compile `input` to HIR, with the bindings of `deps` visible
+pub fn compile_unit<'a>(
+    name: &str,
+    input: &'a str,
+    deps: &[&Interface],
+    config: &Config,
+) -> Result<HirUnit, TypeError<'a>> {
+    let env = Interface::merge(deps.iter().cloned());
+    let mut fixity = env.fixity.clone();
+    let ast = parser::parse_with_fixity(name, input, &mut fixity)?;
+    if config.pretty_print_ir.contains("parse") {
+        println!("{}", ast);
+    }
+
+    let mut unit = compile_ast(name, ast, &env, config)?;
+    unit.interface.fixity = fixity;
+    Ok(unit)
+}
+
+fn compile_ast<'a>(
+    name: &str,
+    ast: ast::UntypedAst,
+    env: &Interface,
+    config: &Config,
+) -> Result<HirUnit, TypeError<'a>> {
+    use crate::pass::PrintablePass;
+
+    let id = id::Id::starting_from(env.next_id.max(1));
+
+    let mut frontend = compile_pass![
        desugar: ast::Desugar::new(id.clone()),
-       rename: ast::Rename::new(id.clone()),
+       rename: ast::Rename::with_interface(id.clone(), env),
        var_to_constructor: ast::VarToConstructor::new(id.clone()),
-       typing: ast::Typer::new(),
+       typing: ast::Typer::with_interface(env),
+    ];
+    let typed: ast::TypedCoreContext = frontend.trans(ast, config)?;
+    let mut interface = Interface::exports(&typed);
+
+    let mut lowering = compile_pass![
        case_simplify: ast::CaseSimplify::new(id.clone()),
        ast_to_hir: hir::AST2HIR::new(id.clone()),
+    ];
+    let code = lowering.trans(typed, config)?;
+
+    interface.next_id = id.clone().next();
+    Ok(HirUnit {
+        name: name.to_string(),
+        interface,
+        code,
+    })
+}
+
+/// compile each source of `project` to HIR, in order,
+/// with the bindings of `deps` visible
+pub fn compile_project_hir<'a>(
+    project: &'a mlb::Project,
+    deps: &[Interface],
+    config: &Config,
+) -> Result<Vec<HirUnit>, TypeError<'a>> {
+    let mut units = Vec::new();
+    let mut visible = deps.to_vec();
+    compile_units(
+        &project.units,
+        &mut visible,
+        &mut HashMap::new(),
+        &mut units,
+        config,
+    )?;
+    Ok(units)
+}
+
+/// `exports` records the bindings of each source for `mlb::Unit::Bindings`
+fn compile_units<'a>(
+    units: &'a [mlb::Unit],
+    visible: &mut Vec<Interface>,
+    exports: &mut HashMap<PathBuf, Interface>,
+    compiled: &mut Vec<HirUnit>,
+    config: &Config,
+) -> Result<(), TypeError<'a>> {
+    for unit in units {
+        match unit {
+            mlb::Unit::Source { path, text } => {
+                // hidden units still reserve their ids
+                let reserved = Interface {
+                    next_id: compiled
+                        .iter()
+                        .map(|u| u.interface.next_id)
+                        .chain(visible.iter().map(|i| i.next_id))
+                        .max()
+                        .unwrap_or(1),
+                    ..Interface::default()
+                };
+                let deps = visible.iter().chain(Some(&reserved)).collect::<Vec<_>>();
+                let name = path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let compiled_unit = compile_unit(&name, text, &deps, config)?;
+                visible.push(compiled_unit.interface.clone());
+                exports.insert(path.clone(), compiled_unit.interface.clone());
+                compiled.push(compiled_unit);
+            }
+            mlb::Unit::Local { binds, body } => {
+                let outer = visible.len();
+                compile_units(binds, visible, exports, compiled, config)?;
+                let inner = visible.len();
+                compile_units(body, visible, exports, compiled, config)?;
+                let exported = visible.split_off(inner);
+                visible.truncate(outer);
+                visible.extend(exported);
+            }
+            mlb::Unit::Bindings { path } => visible.push(exports[path].clone()),
+        }
+    }
+    Ok(())
+}
+
+/// lower the HIR of units, given in the order they were compiled, to a program.
+/// Their code is merged and goes through the whole backend together,
+/// as monomorphization and inlining need the whole program
+pub fn lower_units(units: Vec<HirUnit>, config: &Config) -> Vec<u8> {
+    let next_id = units.iter().map(|u| u.interface.next_id).max().unwrap_or(1);
+    let mut symbol_table = hir::SymbolTable {
+        types: Default::default(),
+    };
+    let mut vals = Vec::new();
+    for unit in units {
+        let hir::Context(table, hir::HIR(mut code)) = unit.code;
+        symbol_table.types.extend(table.types);
+        vals.append(&mut code);
+    }
+    let input = hir::Context(symbol_table, hir::HIR(vals));
+    lower(input, id::Id::starting_from(next_id), config)
+}
+
+/// the passes from HIR to wasm, shared by `compile` and `lower_units`
+fn lower(input: hir::Context, id: id::Id, config: &Config) -> Vec<u8> {
+    use crate::pass::{OptionalPass, PrintablePass};
+    use std::convert::Infallible;
+
+    let mut passes = compile_pass![
+       monomorphize: hir::Monomorphize::new(id.clone()),
        constructor_to_enum: hir::ConstructorToEnum::new(),
-       simplify: hir::Simplify::new(id.clone()),
+       simplify?: hir::Simplify::new(id.clone()),
+       inlining?: hir::Inline::new(id.clone()),
        flattening_expression: hir::FlatExpr::new(id.clone()),
        flattening_let: hir::FlatLet::new(),
        unnest_functions: hir::UnnestFunc::new(id.clone()),
        closure_conversion: hir::ForceClosure::new(),
-       hir_to_mir: mir::HIR2MIR::new(id),
-       unalias: mir::UnAlias::new(),
+       hir_to_mir: mir::HIR2MIR::new(id.clone()),
+       unalias?: mir::UnAlias::new(),
+       known_call?: mir::KnownCall::new(id.clone()),
+       uncurrying?: mir::Uncurry::new(id.clone()),
+       flattening_arguments?: mir::FlattenArgs::new(id.clone()),
+       unboxing_returns?: mir::UnboxReturns::new(id),
+       tail_call: mir::TailCall::new(),
+       constant_propagation?: mir::ConstProp::new(),
        block_arrange: mir::BlockArrange::new(),
        mir_to_lir: lir::MIR2LIR::new(),
+       peephole?: lir::Peephole::new(),
+       register_allocation: lir::RegAlloc::new(),
        backend: backend::LIR2WASM::new(),
     ];
 
-    let module: backend::Output = passes.trans(input, config)?;
+    let module: Result<backend::Output, Infallible> = passes.trans(input, config);
+    let module = match module {
+        Ok(module) => module,
+        Err(e) => match e {},
+    };
+    module.dump()
+}
 
-    let mut code = Vec::new();
-    module.0.dump(&mut code);
-    Ok(code)
+fn compile<'a, In, P>(parser: P, input: In, config: &Config) -> Result<Vec<u8>, TypeError<'a>>
+where
+    P: Pass<In, TypeError<'a>, Target = ast::UntypedAst>,
+{
+    use crate::pass::PrintablePass;
+
+    let id = id::Id::new();
+
+    let mut frontend = compile_pass![
+       parse: parser,
+       desugar: ast::Desugar::new(id.clone()),
+       rename: ast::Rename::new(id.clone()),
+       var_to_constructor: ast::VarToConstructor::new(id.clone()),
+       typing: ast::Typer::new(),
+       case_simplify: ast::CaseSimplify::new(id.clone()),
+       ast_to_hir: hir::AST2HIR::new(id.clone()),
+    ];
+
+    let hir: hir::Context = frontend.trans(input, config)?;
+    Ok(lower(hir, id, config))
 }
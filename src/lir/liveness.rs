@@ -0,0 +1,91 @@
+use super::{Block, Function};
+use std::collections::{HashMap, HashSet};
+
+/// the registers live at the boundaries of each block, by the index of `Reg`
+#[derive(Debug, Clone)]
+pub struct Liveness {
+    pub live_in: Vec<HashSet<u32>>,
+    pub live_out: Vec<HashSet<u32>>,
+}
+
+impl Function {
+    /// the indices of the blocks the control may go to from each block.
+    /// A block not ending with a terminator falls through to the next one
+    pub fn successors(&self) -> Vec<Vec<usize>> {
+        let indices = self
+            .body
+            .iter()
+            .enumerate()
+            .map(|(i, block)| (&block.name, i))
+            .collect::<HashMap<_, _>>();
+        self.body
+            .iter()
+            .enumerate()
+            .map(|(i, block)| {
+                let mut succs = block
+                    .branches()
+                    .into_iter()
+                    .map(|label| {
+                        *indices
+                            .get(label)
+                            .expect("internal error: jump to unknown block")
+                    })
+                    .collect::<Vec<_>>();
+                let falls_through = block.body.last().map(|op| !op.is_terminator());
+                if falls_through.unwrap_or(true) && i + 1 < self.body.len() {
+                    succs.push(i + 1);
+                }
+                succs.sort();
+                succs.dedup();
+                succs
+            })
+            .collect()
+    }
+
+    /// solves the liveness of the registers backward until it reaches the fixpoint
+    pub fn liveness(&self) -> Liveness {
+        let succs = self.successors();
+        let (gens, kills): (Vec<_>, Vec<_>) = self.body.iter().map(gen_kill).unzip();
+        let mut live_in = vec![HashSet::new(); self.body.len()];
+        let mut live_out = vec![HashSet::new(); self.body.len()];
+        loop {
+            let mut changed = false;
+            for i in (0..self.body.len()).rev() {
+                let out = succs[i]
+                    .iter()
+                    .flat_map(|&s| live_in[s].iter().cloned())
+                    .collect::<HashSet<u32>>();
+                let in_ = gens[i]
+                    .iter()
+                    .cloned()
+                    .chain(out.difference(&kills[i]).cloned())
+                    .collect::<HashSet<u32>>();
+                if in_ != live_in[i] || out != live_out[i] {
+                    changed = true;
+                    live_in[i] = in_;
+                    live_out[i] = out;
+                }
+            }
+            if !changed {
+                return Liveness { live_in, live_out };
+            }
+        }
+    }
+}
+
+/// the registers the block reads before writing them, and the registers it writes
+fn gen_kill(block: &Block) -> (HashSet<u32>, HashSet<u32>) {
+    let mut gen = HashSet::new();
+    let mut kill = HashSet::new();
+    for op in &block.body {
+        for reg in op.uses() {
+            if !kill.contains(&reg.1) {
+                gen.insert(reg.1);
+            }
+        }
+        for reg in op.defs() {
+            kill.insert(reg.1);
+        }
+    }
+    (gen, kill)
+}
@@ -4,13 +4,19 @@ use crate::mir;
 use crate::pass::Pass;
 use crate::prim::*;
 use log::debug;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+/// the fewest keys to branch on with a jump table when they don't start from 0
+const MIN_JUMP_TABLE_SIZE: usize = 3;
 
 pub struct MIR2LIR {}
 
 pub struct MIR2LIRPass {
     extern_types: ExternTypes,
     symbol_table: mir::SymbolTable,
+    wrapping_int: bool,
+    /// allocate the tuples and closures not escaping their functions in the frames
+    stack_allocation: bool,
 }
 
 impl MIR2LIR {
@@ -18,16 +24,22 @@ impl MIR2LIR {
         MIR2LIR {}
     }
 
-    fn generate_pass(&mut self, symbol_table: mir::SymbolTable) -> MIR2LIRPass {
-        MIR2LIRPass::new(symbol_table)
+    fn generate_pass(&mut self, symbol_table: mir::SymbolTable, config: &Config) -> MIR2LIRPass {
+        MIR2LIRPass::new(
+            symbol_table,
+            config.wrapping_int,
+            config.pass_enabled("stack_allocation"),
+        )
     }
 }
 
 impl MIR2LIRPass {
-    fn new(symbol_table: mir::SymbolTable) -> Self {
+    fn new(symbol_table: mir::SymbolTable, wrapping_int: bool, stack_allocation: bool) -> Self {
         Self {
             extern_types: HashMap::new(),
             symbol_table,
+            wrapping_int,
+            stack_allocation,
         }
     }
 
@@ -38,7 +50,12 @@ impl MIR2LIRPass {
             Char => LTy::U32,
             Int => LTy::I32,
             Float => LTy::F64,
+            Int64 => LTy::I64,
+            Word64 => LTy::U64,
+            Float32 => LTy::F32,
             Bool => LTy::I32,
+            String => LTy::Ptr,
+            Array(_) => LTy::Ptr,
             Tuple(_) => LTy::Ptr,
             //FIXME
             Union(_) => LTy::Ptr,
@@ -56,13 +73,24 @@ impl MIR2LIRPass {
         use crate::lir::Op::*;
         use crate::lir::Value::*;
         use crate::mir::Op as m;
+        let on_stack = if self.stack_allocation {
+            f.non_escaping()
+        } else {
+            HashSet::new()
+        };
         let mir::Function {
             name,
             body,
             body_ty,
+            multi_value,
         } = f;
         let nparams = body[0].params.len() as u32;
-        let ret_ty = self.ebbty_to_lty(&body_ty);
+        let ret_tys = match body_ty {
+            mir::EbbTy::Tuple(tys) if multi_value => {
+                tys.iter().map(|ty| self.ebbty_to_lty(ty)).collect()
+            }
+            ty => vec![self.ebbty_to_lty(&ty)],
+        };
         let mut regs = Vec::new();
         let mut id = 0;
         let mut blocks = Vec::new();
@@ -98,6 +126,28 @@ impl MIR2LIRPass {
                             &Literal::Char(c) => ops.push(ConstI32(reg!(var), c as u32)),
                             &Literal::Int(i) => ops.push(ConstI32(reg!(var), i as u32)),
                             &Literal::Real(f) => ops.push(ConstF64(reg!(var), f as f64)),
+                            &Literal::String(ref s) => {
+                                let reg = reg!(var);
+                                let bytes = s.as_bytes();
+                                ops.push(HeapAlloc(
+                                    reg.clone(),
+                                    I(string_alloc_size(bytes.len() as u32) as i32),
+                                    vec![LTy::I32],
+                                ));
+                                let len = new_reg(LTy::I32);
+                                ops.push(ConstI32(len.clone(), bytes.len() as u32));
+                                ops.push(StoreI32(Addr(reg.clone(), 0), len));
+                                // fill the bytes a word at a time
+                                let mut acc = 4;
+                                for chunk in bytes.chunks(4) {
+                                    let mut word = [0; 4];
+                                    word[..chunk.len()].copy_from_slice(chunk);
+                                    let word_reg = new_reg(LTy::U32);
+                                    ops.push(ConstU32(word_reg.clone(), u32::from_le_bytes(word)));
+                                    ops.push(StoreU32(Addr(reg.clone(), acc), word_reg));
+                                    acc += 4;
+                                }
+                            }
                         },
                         &m::Alias {
                             ref var,
@@ -125,64 +175,96 @@ impl MIR2LIRPass {
                             ref ty,
                             ref l,
                             ref r,
-                        } => {
-                            if ty == &mir::EbbTy::Int {
-                                ops.push(AddI32(reg!(var), reg!(l), reg!(r)));
-                            } else {
-                                assert_eq!(ty, &mir::EbbTy::Float);
-                                ops.push(AddF64(reg!(var), reg!(l), reg!(r)));
-                            }
-                        }
+                        } => match self.ebbty_to_lty(ty) {
+                            LTy::I32 if !self.wrapping_int => checked_i32(
+                                &mut ops,
+                                &mut new_reg,
+                                AddI64,
+                                reg!(var),
+                                reg!(l),
+                                reg!(r),
+                            ),
+                            LTy::I32 => ops.push(AddI32(reg!(var), reg!(l), reg!(r))),
+                            LTy::I64 => ops.push(AddI64(reg!(var), reg!(l), reg!(r))),
+                            LTy::U64 => ops.push(AddU64(reg!(var), reg!(l), reg!(r))),
+                            LTy::F32 => ops.push(AddF32(reg!(var), reg!(l), reg!(r))),
+                            LTy::F64 => ops.push(AddF64(reg!(var), reg!(l), reg!(r))),
+                            ty => panic!("unknown overloaded ty {:?} for add", ty),
+                        },
                         &m::Sub {
                             ref var,
                             ref ty,
                             ref l,
                             ref r,
-                        } => {
-                            if ty == &mir::EbbTy::Int {
-                                ops.push(SubI32(reg!(var), reg!(l), reg!(r)));
-                            } else {
-                                assert_eq!(ty, &mir::EbbTy::Float);
-                                ops.push(SubF64(reg!(var), reg!(l), reg!(r)));
-                            }
-                        }
+                        } => match self.ebbty_to_lty(ty) {
+                            LTy::I32 if !self.wrapping_int => checked_i32(
+                                &mut ops,
+                                &mut new_reg,
+                                SubI64,
+                                reg!(var),
+                                reg!(l),
+                                reg!(r),
+                            ),
+                            LTy::I32 => ops.push(SubI32(reg!(var), reg!(l), reg!(r))),
+                            LTy::I64 => ops.push(SubI64(reg!(var), reg!(l), reg!(r))),
+                            LTy::U64 => ops.push(SubU64(reg!(var), reg!(l), reg!(r))),
+                            LTy::F32 => ops.push(SubF32(reg!(var), reg!(l), reg!(r))),
+                            LTy::F64 => ops.push(SubF64(reg!(var), reg!(l), reg!(r))),
+                            ty => panic!("unknown overloaded ty {:?} for sub", ty),
+                        },
                         &m::Mul {
                             ref var,
                             ref ty,
                             ref l,
                             ref r,
-                        } => {
-                            if ty == &mir::EbbTy::Int {
-                                ops.push(MulI32(reg!(var), reg!(l), reg!(r)));
-                            } else {
-                                assert_eq!(ty, &mir::EbbTy::Float);
-                                ops.push(MulF64(reg!(var), reg!(l), reg!(r)));
-                            }
-                        }
+                        } => match self.ebbty_to_lty(ty) {
+                            LTy::I32 if !self.wrapping_int => checked_i32(
+                                &mut ops,
+                                &mut new_reg,
+                                MulI64,
+                                reg!(var),
+                                reg!(l),
+                                reg!(r),
+                            ),
+                            LTy::I32 => ops.push(MulI32(reg!(var), reg!(l), reg!(r))),
+                            LTy::I64 => ops.push(MulI64(reg!(var), reg!(l), reg!(r))),
+                            LTy::U64 => ops.push(MulU64(reg!(var), reg!(l), reg!(r))),
+                            LTy::F32 => ops.push(MulF32(reg!(var), reg!(l), reg!(r))),
+                            LTy::F64 => ops.push(MulF64(reg!(var), reg!(l), reg!(r))),
+                            ty => panic!("unknown overloaded ty {:?} for mul", ty),
+                        },
                         &m::DivInt {
                             ref var,
+                            ref ty,
                             ref l,
                             ref r,
-                            ..
-                        } => {
-                            ops.push(DivI32(reg!(var), reg!(l), reg!(r)));
-                        }
+                        } => match self.ebbty_to_lty(ty) {
+                            LTy::I32 => ops.push(DivI32(reg!(var), reg!(l), reg!(r))),
+                            LTy::I64 => ops.push(DivI64(reg!(var), reg!(l), reg!(r))),
+                            LTy::U64 => ops.push(DivU64(reg!(var), reg!(l), reg!(r))),
+                            ty => panic!("unknown overloaded ty {:?} for div", ty),
+                        },
                         &m::DivFloat {
                             ref var,
+                            ref ty,
                             ref l,
                             ref r,
-                            ..
-                        } => {
-                            ops.push(DivF64(reg!(var), reg!(l), reg!(r)));
-                        }
+                        } => match self.ebbty_to_lty(ty) {
+                            LTy::F32 => ops.push(DivF32(reg!(var), reg!(l), reg!(r))),
+                            LTy::F64 => ops.push(DivF64(reg!(var), reg!(l), reg!(r))),
+                            ty => panic!("unknown overloaded ty {:?} for divf", ty),
+                        },
                         &m::Mod {
                             ref var,
+                            ref ty,
                             ref l,
                             ref r,
-                            ..
-                        } => {
-                            ops.push(ModI32(reg!(var), reg!(l), reg!(r)));
-                        }
+                        } => match self.ebbty_to_lty(ty) {
+                            LTy::I32 => ops.push(ModI32(reg!(var), reg!(l), reg!(r))),
+                            LTy::I64 => ops.push(ModI64(reg!(var), reg!(l), reg!(r))),
+                            LTy::U64 => ops.push(ModU64(reg!(var), reg!(l), reg!(r))),
+                            ty => panic!("unknown overloaded ty {:?} for mod", ty),
+                        },
                         &m::Eq {
                             ref var,
                             ref l,
@@ -267,6 +349,170 @@ impl MIR2LIRPass {
                             (&LTy::F64, &LTy::F64) => ops.push(LeF64(reg!(var), reg!(l), reg!(r))),
                             ty => panic!("unknown overloaded ty {:?} for le", ty),
                         },
+                        &m::IntToFloat {
+                            ref var, ref sym, ..
+                        } => ops.push(ConvertF64I32(reg!(var), reg!(sym))),
+                        &m::FloatToInt {
+                            ref var,
+                            rounding,
+                            ref sym,
+                            ..
+                        } => {
+                            let round: fn(Reg, Reg) -> Op = match rounding {
+                                mir::Rounding::Floor => FloorF64,
+                                mir::Rounding::Ceil => CeilF64,
+                                mir::Rounding::Nearest => NearestF64,
+                                mir::Rounding::Trunc => TruncF64,
+                            };
+                            let rounded = new_reg(LTy::F64);
+                            ops.push(round(rounded.clone(), reg!(sym)));
+                            ops.push(TruncI32F64(reg!(var), rounded));
+                        }
+                        &m::RoundFloat {
+                            ref var,
+                            rounding,
+                            ref sym,
+                            ..
+                        } => {
+                            let round: fn(Reg, Reg) -> Op = match rounding {
+                                mir::Rounding::Floor => FloorF64,
+                                mir::Rounding::Ceil => CeilF64,
+                                mir::Rounding::Nearest => NearestF64,
+                                mir::Rounding::Trunc => TruncF64,
+                            };
+                            ops.push(round(reg!(var), reg!(sym)));
+                        }
+                        &m::Sqrt {
+                            ref var, ref sym, ..
+                        } => ops.push(SqrtF64(reg!(var), reg!(sym))),
+                        &m::Convert {
+                            ref var, ref sym, ..
+                        } => match (&symbol_table[var].0, &symbol_table[sym].0) {
+                            (&LTy::I64, &LTy::I32) => ops.push(ExtendI64I32(reg!(var), reg!(sym))),
+                            (&LTy::I32, &LTy::I64) => ops.push(WrapI32I64(reg!(var), reg!(sym))),
+                            // signedness only matters to the operations
+                            (&LTy::I64, &LTy::U64) => ops.push(MoveI64(reg!(var), reg!(sym))),
+                            (&LTy::U64, &LTy::I64) => ops.push(MoveU64(reg!(var), reg!(sym))),
+                            (&LTy::F32, &LTy::F64) => ops.push(DemoteF32F64(reg!(var), reg!(sym))),
+                            (&LTy::F64, &LTy::F32) => ops.push(PromoteF64F32(reg!(var), reg!(sym))),
+                            tys => panic!("unknown conversion {:?}", tys),
+                        },
+                        &m::StringAlloc {
+                            ref var, ref len, ..
+                        } => {
+                            // string looks like on memory:
+                            //   32    8    8
+                            // +-----+----+----+-----
+                            // | len | b0 | b1 | ...
+                            // +-----+----+----+-----
+                            // the size is rounded up to 8 as other heap objects are.
+
+                            // (len + 4 + 7) / 8 * 8
+                            let size = new_reg(LTy::I32);
+                            let c = new_reg(LTy::I32);
+                            ops.push(ConstI32(c.clone(), 4 + 7));
+                            ops.push(AddI32(size.clone(), reg!(len), c.clone()));
+                            ops.push(ConstI32(c.clone(), 8));
+                            ops.push(DivI32(size.clone(), size.clone(), c.clone()));
+                            ops.push(MulI32(size.clone(), size.clone(), c));
+
+                            let reg = reg!(var);
+                            ops.push(HeapAlloc(reg.clone(), R(size), vec![LTy::I32]));
+                            ops.push(StoreI32(Addr(reg, 0), reg!(len)));
+                        }
+                        &m::StringSize {
+                            ref var,
+                            ref string,
+                            ..
+                        } => ops.push(LoadI32(reg!(var), Addr(reg!(string), 0))),
+                        &m::StringSub {
+                            ref var,
+                            ref string,
+                            ref index,
+                            ..
+                        } => {
+                            let addr = new_reg(LTy::Ptr);
+                            ops.push(AddI32(addr.clone(), reg!(string), reg!(index)));
+                            ops.push(LoadU8(reg!(var), Addr(addr, 4)));
+                        }
+                        &m::StringUpdate {
+                            ref string,
+                            ref index,
+                            ref value,
+                            ..
+                        } => {
+                            let addr = new_reg(LTy::Ptr);
+                            ops.push(AddI32(addr.clone(), reg!(string), reg!(index)));
+                            ops.push(StoreU8(Addr(addr, 4), reg!(value)));
+                        }
+                        &m::ArrayAlloc {
+                            ref var,
+                            ref ty,
+                            ref len,
+                        } => {
+                            // array looks like on memory:
+                            //   64    64     64
+                            // +-----+------+------+-----
+                            // | len | elm0 | elm1 | ...
+                            // +-----+------+------+-----
+                            // currently all the elements are aligned to 8 as tuples.
+
+                            // len * 8 + 8
+                            let size = new_reg(LTy::I32);
+                            let c = new_reg(LTy::I32);
+                            ops.push(ConstI32(c.clone(), 8));
+                            ops.push(MulI32(size.clone(), reg!(len), c.clone()));
+                            ops.push(AddI32(size.clone(), size.clone(), c));
+
+                            let ty = match ty {
+                                mir::EbbTy::Variable(name) => {
+                                    self.symbol_table.canonical_value(name).unwrap()
+                                }
+                                ty => ty,
+                            };
+                            let elem = match ty {
+                                mir::EbbTy::Array(elem) => self.ebbty_to_lty(elem),
+                                ty => panic!("internal error: array of type {:?}", ty),
+                            };
+                            let reg = reg!(var);
+                            ops.push(HeapAlloc(reg.clone(), R(size), vec![LTy::I32, elem]));
+                            ops.push(StoreI32(Addr(reg, 0), reg!(len)));
+                        }
+                        &m::ArrayLength {
+                            ref var, ref array, ..
+                        } => ops.push(LoadI32(reg!(var), Addr(reg!(array), 0))),
+                        &m::ArraySub {
+                            ref var,
+                            ref ty,
+                            ref array,
+                            ref index,
+                        } => {
+                            check_bounds(&mut ops, &mut new_reg, reg!(array), reg!(index));
+                            if let Some(load) = load_op(&self.ebbty_to_lty(ty)) {
+                                let addr = new_reg(LTy::Ptr);
+                                let c = new_reg(LTy::I32);
+                                ops.push(ConstI32(c.clone(), 8));
+                                ops.push(MulI32(addr.clone(), reg!(index), c));
+                                ops.push(AddI32(addr.clone(), reg!(array), addr.clone()));
+                                ops.push(load(reg!(var), Addr(addr, 8)));
+                            }
+                        }
+                        &m::ArrayUpdate {
+                            ref array,
+                            ref index,
+                            ref value,
+                            ..
+                        } => {
+                            check_bounds(&mut ops, &mut new_reg, reg!(array), reg!(index));
+                            if let Some(store) = store_op(&symbol_table[value].0) {
+                                let addr = new_reg(LTy::Ptr);
+                                let c = new_reg(LTy::I32);
+                                ops.push(ConstI32(c.clone(), 8));
+                                ops.push(MulI32(addr.clone(), reg!(index), c));
+                                ops.push(AddI32(addr.clone(), reg!(array), addr.clone()));
+                                ops.push(store(Addr(addr, 8), reg!(value)));
+                            }
+                        }
                         &m::Tuple {
                             ref var,
                             ref tys,
@@ -279,7 +525,11 @@ impl MIR2LIRPass {
                             let size: u32 = tys.iter().map(|_| 8).sum();
                             // let size: u32 = tys.iter().map(|ty| ty.size()).sum();
 
-                            ops.push(HeapAlloc(reg.clone(), I(size as i32), tys.clone()));
+                            if on_stack.contains(var) {
+                                ops.push(StackAlloc(reg.clone(), size, tys.clone()));
+                            } else {
+                                ops.push(HeapAlloc(reg.clone(), I(size as i32), tys.clone()));
+                            }
 
                             let mut acc = 0;
                             for (var, ty) in tuple.iter().zip(tys) {
@@ -395,6 +645,18 @@ impl MIR2LIRPass {
                             }
                         }
 
+                        &m::GlobalGet { ref var, index, .. } => {
+                            let reg = reg!(var);
+                            if reg.0 != LTy::Unit {
+                                ops.push(LoadGlobal(reg, index))
+                            }
+                        }
+                        &m::GlobalSet { index, ref value } => {
+                            let reg = reg!(value);
+                            if reg.0 != LTy::Unit {
+                                ops.push(StoreGlobal(index, reg))
+                            }
+                        }
                         &m::Closure {
                             ref var,
                             ref fun,
@@ -406,21 +668,22 @@ impl MIR2LIRPass {
                             // +-----------------------
                             // | fptr | arg1 | ...
                             // +-----------------------
+                            // all the items are aligned to 8 as tuples so that the env is a tuple.
 
                             let reg = reg!(var);
-                            let mut size: u32 = LTy::Ptr.size();
-                            size += env
-                                .iter()
-                                .map(|&(ref ty, _)| self.ebbty_to_lty(ty).size())
-                                .sum::<u32>();
+                            let size: u32 = 8 + env.iter().map(|_| 8).sum::<u32>();
                             let mut tys = vec![LTy::FPtr];
                             for &(ref ty, _) in env.iter() {
                                 tys.push(self.ebbty_to_lty(ty));
                             }
-                            ops.push(HeapAlloc(reg.clone(), I(size as i32), tys));
+                            if on_stack.contains(var) {
+                                ops.push(StackAlloc(reg.clone(), size, tys));
+                            } else {
+                                ops.push(HeapAlloc(reg.clone(), I(size as i32), tys));
+                            }
                             // FIXME: explicitly take fun pointer
                             ops.push(StoreFnPtr(Addr(reg.clone(), 0), fun.clone()));
-                            let mut acc = LTy::FPtr.size();
+                            let mut acc = 8;
                             for &(ref ty, ref var) in env.iter() {
                                 let ty = self.ebbty_to_lty(ty);
                                 match ty {
@@ -458,16 +721,21 @@ impl MIR2LIRPass {
                         }
                         &m::ExternCall {
                             ref var,
+                            ref ty,
                             ref module,
                             ref fun,
                             ref args,
-                            ..
                         } => {
-                            let args = args.iter().map(|a| reg!(a)).collect();
-                            self.extern_types.insert(
-                                (module.to_string(), fun.to_string()),
-                                (vec![LTy::I32], LTy::Unit),
-                            );
+                            // unit arguments are not passed to the host
+                            let args: Vec<_> = args
+                                .iter()
+                                .map(|a| reg!(a))
+                                .filter(|r| r.0 != LTy::Unit)
+                                .collect();
+                            let argtys = args.iter().map(|r| r.0.clone()).collect();
+                            let retty = self.ebbty_to_lty(ty);
+                            self.extern_types
+                                .insert((module.to_string(), fun.to_string()), (argtys, retty));
                             ops.push(ExternCall(
                                 reg!(var),
                                 module.to_string(),
@@ -487,6 +755,16 @@ impl MIR2LIRPass {
                                 None => ops.push(FunCall(reg!(var), fun.clone(), args)),
                             }
                         }
+                        &m::MultiCall {
+                            ref vars,
+                            ref fun,
+                            ref args,
+                            ..
+                        } => {
+                            let vars = vars.iter().map(|v| reg!(v)).collect();
+                            let args = args.iter().map(|a| reg!(a)).collect();
+                            ops.push(MultiFunCall(vars, fun.clone(), args))
+                        }
                         &m::Branch {
                             ref cond,
                             ref clauses,
@@ -494,7 +772,6 @@ impl MIR2LIRPass {
                             ..
                         } => {
                             let mut clauses = clauses.clone();
-                            clauses.sort_by_key(|&(ref key, _, _)| *key);
                             let default_label = match default.clone() {
                                 None => None,
                                 Some((label, _)) => {
@@ -518,24 +795,59 @@ impl MIR2LIRPass {
                                 }
                             };
 
-                            if !clauses.is_empty()
-                                && clauses[0].0 == 0
-                                && clauses
+                            let cond_reg = reg!(cond);
+                            // ints may be negative
+                            let key_of = |key: u32| match cond_reg.0 {
+                                LTy::I32 => key as i32 as i64,
+                                _ => key as i64,
+                            };
+                            clauses.sort_by_key(|&(key, _, _)| key_of(key));
+                            let range = match (clauses.first(), clauses.last()) {
+                                (Some(first), Some(last)) => {
+                                    Some((key_of(first.0), key_of(last.0)))
+                                }
+                                _ => None,
+                            };
+                            // use a jump table if the keys are dense enough, filling the holes with the default
+                            let table = range.filter(|&(min, max)| {
+                                let len = clauses.len() as i64;
+                                max - min + 1 <= len * 2
+                                    && (min == 0 || MIN_JUMP_TABLE_SIZE as i64 <= len)
+                            });
+                            if let Some((min, max)) = table {
+                                let keys = clauses
                                     .iter()
-                                    .enumerate()
-                                    .all(|(n, &(ref key, _, _))| n == (*key as usize))
-                            {
-                                // use jump table
-                                ops.push(JumpTableI32(
-                                    reg!(cond),
-                                    clauses
-                                        .into_iter()
-                                        .map(|(_, label, _)| Label(label))
-                                        .collect(),
-                                    default_label,
-                                ))
+                                    .map(|(key, label, _)| (key_of(*key), Label(label.clone())))
+                                    .collect::<HashMap<_, _>>();
+                                // without the default, the match is exhaustive and the holes are never reached
+                                let hole = default_label
+                                    .clone()
+                                    .unwrap_or_else(|| Label(clauses[0].1.clone()));
+                                let labels = (min..=max)
+                                    .map(|key| {
+                                        keys.get(&key).cloned().unwrap_or_else(|| hole.clone())
+                                    })
+                                    .collect();
+                                let index = if min == 0 {
+                                    cond_reg
+                                } else {
+                                    let index = new_reg(cond_reg.0.clone());
+                                    let constant = new_reg(cond_reg.0.clone());
+                                    match cond_reg.0 {
+                                        LTy::I32 => {
+                                            ops.push(ConstI32(constant.clone(), min as u32));
+                                            ops.push(SubI32(index.clone(), cond_reg, constant));
+                                        }
+                                        _ => {
+                                            ops.push(ConstU32(constant.clone(), min as u32));
+                                            ops.push(SubU32(index.clone(), cond_reg, constant));
+                                        }
+                                    }
+                                    index
+                                };
+                                ops.push(JumpTableI32(index, labels, default_label))
                             } else {
-                                let cond = reg!(cond);
+                                let cond = cond_reg;
 
                                 match cond.0 {
                                     LTy::I32 => {
@@ -577,19 +889,24 @@ impl MIR2LIRPass {
                             ..
                         } => {
                             let params = &target_table[target];
-                            for (p, a) in params.iter().zip(args) {
-                                match p.0 {
-                                    LTy::Unit => {
-                                        // do nothing
+                            let mut args = args.iter().map(|a| reg!(a)).collect::<Vec<_>>();
+                            // an arg may be another param of the target, as in a jump to the entry.
+                            // Copy them first not to read the param after overwriting it
+                            let overlapping = args.iter().enumerate().any(|(i, a)| {
+                                params.iter().enumerate().any(|(j, p)| i != j && a.1 == p.1)
+                            });
+                            if overlapping {
+                                for a in args.iter_mut() {
+                                    let tmp = new_reg(a.0.clone());
+                                    if let Some(mov) = move_op(&a.0) {
+                                        ops.push(mov(tmp.clone(), a.clone()))
                                     }
-                                    LTy::I32 => ops.push(MoveI32(p.clone(), reg!(a))),
-                                    LTy::U32 => ops.push(MoveU32(p.clone(), reg!(a))),
-                                    LTy::I64 => ops.push(MoveI64(p.clone(), reg!(a))),
-                                    LTy::U64 => ops.push(MoveU64(p.clone(), reg!(a))),
-                                    LTy::F32 => ops.push(MoveF32(p.clone(), reg!(a))),
-                                    LTy::F64 => ops.push(MoveF64(p.clone(), reg!(a))),
-                                    LTy::Ptr => ops.push(MoveI32(p.clone(), reg!(a))),
-                                    LTy::FPtr => ops.push(MoveI32(p.clone(), reg!(a))),
+                                    *a = tmp;
+                                }
+                            }
+                            for (p, a) in params.iter().zip(args) {
+                                if let Some(mov) = move_op(&p.0) {
+                                    ops.push(mov(p.clone(), a))
                                 }
                             }
                             ops.push(Jump(Label(target.clone())))
@@ -598,6 +915,19 @@ impl MIR2LIRPass {
                             mir::EbbTy::Unit => ops.push(Ret(None)),
                             _ => ops.push(Ret(value.as_ref().map(|v| reg!(v)))),
                         },
+                        &m::MultiRet { ref values, .. } => {
+                            ops.push(MultiRet(values.iter().map(|v| reg!(v)).collect()))
+                        }
+                        &m::TailCall {
+                            ref fun, ref args, ..
+                        } => {
+                            let args = args.iter().map(|a| reg!(a)).collect();
+                            match symbol_table.get(fun) {
+                                Some(r) => ops.push(TailClosureCall(r.clone(), args)),
+                                None => ops.push(TailCall(fun.clone(), args)),
+                            }
+                        }
+                        &m::Unreachable => ops.push(Unreachable),
                     }
                 }
                 blocks.push(Block {
@@ -613,7 +943,7 @@ impl MIR2LIRPass {
             name,
             nparams,
             regs,
-            ret_ty,
+            ret_tys,
             body: blocks,
         }
     }
@@ -689,12 +1019,54 @@ impl MIR2LIRPass {
                     | &mir::Op::Le {
                         ref var, ref ty, ..
                     }
+                    | &mir::Op::IntToFloat {
+                        ref var, ref ty, ..
+                    }
+                    | &mir::Op::FloatToInt {
+                        ref var, ref ty, ..
+                    }
+                    | &mir::Op::RoundFloat {
+                        ref var, ref ty, ..
+                    }
+                    | &mir::Op::Sqrt {
+                        ref var, ref ty, ..
+                    }
+                    | &mir::Op::Convert {
+                        ref var, ref ty, ..
+                    }
+                    | &mir::Op::StringAlloc {
+                        ref var, ref ty, ..
+                    }
+                    | &mir::Op::StringSize {
+                        ref var, ref ty, ..
+                    }
+                    | &mir::Op::StringSub {
+                        ref var, ref ty, ..
+                    }
+                    | &mir::Op::StringUpdate {
+                        ref var, ref ty, ..
+                    }
+                    | &mir::Op::ArrayAlloc {
+                        ref var, ref ty, ..
+                    }
+                    | &mir::Op::ArrayLength {
+                        ref var, ref ty, ..
+                    }
+                    | &mir::Op::ArraySub {
+                        ref var, ref ty, ..
+                    }
+                    | &mir::Op::ArrayUpdate {
+                        ref var, ref ty, ..
+                    }
                     | &mir::Op::Proj {
                         ref var, ref ty, ..
                     }
                     | &mir::Op::Select {
                         ref var, ref ty, ..
                     }
+                    | &mir::Op::GlobalGet {
+                        ref var, ref ty, ..
+                    }
                     | &mir::Op::ExternCall {
                         ref var, ref ty, ..
                     }
@@ -703,6 +1075,13 @@ impl MIR2LIRPass {
                     } => {
                         intern!(self.ebbty_to_lty(ty), var);
                     }
+                    &mir::Op::MultiCall {
+                        ref vars, ref tys, ..
+                    } => {
+                        for (var, ty) in vars.iter().zip(tys) {
+                            intern!(self.ebbty_to_lty(ty), var);
+                        }
+                    }
                     &mir::Op::Tuple { ref var, .. } | &mir::Op::Closure { ref var, .. } => {
                         intern!(LTy::Ptr, var);
                     }
@@ -733,15 +1112,96 @@ impl MIR2LIRPass {
     }
 }
 
+/// the size of the heap object holding a string of `len` bytes, rounded up to 8
+fn string_alloc_size(len: u32) -> u32 {
+    (4 + len + 7) / 8 * 8
+}
+
+/// `int` arithmetic which aborts on overflow, as `Overflow` can't be caught yet.
+/// It computes in i64 and checks that the result fits in i32.
+fn checked_i32(
+    ops: &mut Vec<Op>,
+    new_reg: &mut impl FnMut(LTy) -> Reg,
+    op: fn(Reg, Reg, Reg) -> Op,
+    var: Reg,
+    l: Reg,
+    r: Reg,
+) {
+    use crate::lir::Op::*;
+    let (wide_l, wide_r, wide) = (new_reg(LTy::I64), new_reg(LTy::I64), new_reg(LTy::I64));
+    ops.push(ExtendI64I32(wide_l.clone(), l));
+    ops.push(ExtendI64I32(wide_r.clone(), r));
+    ops.push(op(wide.clone(), wide_l, wide_r));
+    ops.push(WrapI32I64(var.clone(), wide.clone()));
+    let back = new_reg(LTy::I64);
+    ops.push(ExtendI64I32(back.clone(), var));
+    let overflowed = new_reg(LTy::I32);
+    ops.push(NeqI64(overflowed.clone(), back, wide));
+    ops.push(UnreachableIfI32(overflowed));
+}
+
+/// aborts unless `index` is in the bounds of `array`, as `Subscript` can't be caught yet.
+/// Comparing them unsigned catches the negative indices too.
+fn check_bounds(ops: &mut Vec<Op>, new_reg: &mut impl FnMut(LTy) -> Reg, array: Reg, index: Reg) {
+    use crate::lir::Op::*;
+    let len = new_reg(LTy::I32);
+    ops.push(LoadI32(len.clone(), Addr(array, 0)));
+    let out_of_bounds = new_reg(LTy::I32);
+    ops.push(GeU32(out_of_bounds.clone(), index, len));
+    ops.push(UnreachableIfI32(out_of_bounds));
+}
+
+/// the instruction to move a value of `ty` between registers. `None` for unit
+fn move_op(ty: &LTy) -> Option<fn(Reg, Reg) -> Op> {
+    use crate::lir::Op::*;
+    match ty {
+        LTy::Unit => None,
+        LTy::I32 | LTy::Ptr | LTy::FPtr => Some(MoveI32),
+        LTy::U32 => Some(MoveU32),
+        LTy::I64 => Some(MoveI64),
+        LTy::U64 => Some(MoveU64),
+        LTy::F32 => Some(MoveF32),
+        LTy::F64 => Some(MoveF64),
+    }
+}
+
+/// the instruction to load a value of `ty` from the heap. `None` for unit
+fn load_op(ty: &LTy) -> Option<fn(Reg, Addr) -> Op> {
+    use crate::lir::Op::*;
+    match ty {
+        LTy::Unit => None,
+        LTy::I32 | LTy::Ptr | LTy::FPtr => Some(LoadI32),
+        LTy::U32 => Some(LoadU32),
+        LTy::I64 => Some(LoadI64),
+        LTy::U64 => Some(LoadU64),
+        LTy::F32 => Some(LoadF32),
+        LTy::F64 => Some(LoadF64),
+    }
+}
+
+/// the instruction to store a value of `ty` to the heap. `None` for unit
+fn store_op(ty: &LTy) -> Option<fn(Addr, Reg) -> Op> {
+    use crate::lir::Op::*;
+    match ty {
+        LTy::Unit => None,
+        LTy::I32 | LTy::Ptr | LTy::FPtr => Some(StoreI32),
+        LTy::U32 => Some(StoreU32),
+        LTy::I64 => Some(StoreI64),
+        LTy::U64 => Some(StoreU64),
+        LTy::F32 => Some(StoreF32),
+        LTy::F64 => Some(StoreF64),
+    }
+}
+
 impl<E> Pass<mir::Context, E> for MIR2LIR {
     type Target = Context;
 
     fn trans(
         &mut self,
         mir::Context(symbol_table, mir): mir::Context,
-        _: &Config,
+        config: &Config,
     ) -> ::std::result::Result<Self::Target, E> {
-        let mut pass = self.generate_pass(symbol_table);
+        let mut pass = self.generate_pass(symbol_table, config);
         let lir = pass.trans_mir(mir);
         let types = pass.extern_types.drain().collect();
         Ok(Context(types, lir))
@@ -1,7 +1,12 @@
+pub mod liveness;
 pub mod mir2lir;
+mod peephole;
 pub mod pp;
+mod regalloc;
 
 pub use self::mir2lir::MIR2LIR;
+pub use self::peephole::Peephole;
+pub use self::regalloc::RegAlloc;
 use crate::prim::*;
 use std::collections::HashMap;
 
@@ -74,7 +79,8 @@ pub struct Function {
     pub name: Symbol,
     pub nparams: u32,
     pub regs: Vec<LTy>,
-    pub ret_ty: LTy,
+    /// the types of the results. More than one if the function returns them with `MultiRet`
+    pub ret_tys: Vec<LTy>,
     pub body: Vec<Block>,
 }
 
@@ -120,6 +126,10 @@ pub enum Op {
     LeU32(Reg, Reg, Reg),
     StoreU32(Addr, Reg),
     LoadU32(Reg, Addr),
+    /// stores the lowest byte of the register
+    StoreU8(Addr, Reg),
+    /// zero-extends the byte at the address
+    LoadU8(Reg, Addr),
 
     ConstI64(Reg, u64),
     MoveI64(Reg, Reg),
@@ -182,17 +192,326 @@ pub enum Op {
     LeF64(Reg, Reg, Reg),
     StoreF64(Addr, Reg),
     LoadF64(Reg, Addr),
+    FloorF64(Reg, Reg),
+    CeilF64(Reg, Reg),
+    NearestF64(Reg, Reg),
+    TruncF64(Reg, Reg),
+    SqrtF64(Reg, Reg),
 
+    /// f64 <- i32
+    ConvertF64I32(Reg, Reg),
+    /// i32 <- f64, which must be integral and in range
+    TruncI32F64(Reg, Reg),
+    /// i64 <- i32, with the sign extended
+    ExtendI64I32(Reg, Reg),
+    /// i32 <- i64, dropping the upper bits
+    WrapI32I64(Reg, Reg),
+    /// f64 <- f32
+    PromoteF64F32(Reg, Reg),
+    /// f32 <- f64, rounding to the nearest
+    DemoteF32F64(Reg, Reg),
+
+    /// allocates an object of the size in bytes, which is a sequence of 8-byte slots of the types.
+    /// The last type repeats for the slots beyond them.
+    /// The garbage collector traces the slots of `LTy::Ptr`
     HeapAlloc(Reg, Value, Vec<LTy>),
     StackAlloc(Reg, u32, Vec<LTy>),
 
+    /// loads the value in the slot of the globals. Each slot is 8 bytes
+    LoadGlobal(Reg, u32),
+    StoreGlobal(u32, Reg),
+
     StoreFnPtr(Addr, Symbol),
     ExternCall(Reg, String, String, Vec<Reg>),
     FunCall(Reg, Symbol, Vec<Reg>),
+    /// calls the function returning more than one value, with the wasm multi-value
+    MultiFunCall(Vec<Reg>, Symbol, Vec<Reg>),
     ClosureCall(Reg, Reg, Vec<Reg>),
+    /// calls the function and returns its result, with `return_call`
+    TailCall(Symbol, Vec<Reg>),
+    TailClosureCall(Reg, Vec<Reg>),
     Jump(Label),
     Unreachable,
+    /// aborts if the register is not 0
+    UnreachableIfI32(Reg),
     Ret(Option<Reg>),
+    MultiRet(Vec<Reg>),
+}
+
+macro_rules! defs_of {
+    ($op: expr) => {{
+        use self::Op::*;
+        match $op {
+            ConstI32(reg, ..)
+            | ConstU32(reg, ..)
+            | LoadGlobal(reg, ..)
+            | ConstI64(reg, ..)
+            | ConstU64(reg, ..)
+            | ConstF32(reg, ..)
+            | ConstF64(reg, ..)
+            | MoveI32(reg, ..)
+            | MoveU32(reg, ..)
+            | MoveI64(reg, ..)
+            | MoveU64(reg, ..)
+            | MoveF32(reg, ..)
+            | MoveF64(reg, ..)
+            | FloorF64(reg, ..)
+            | CeilF64(reg, ..)
+            | NearestF64(reg, ..)
+            | TruncF64(reg, ..)
+            | SqrtF64(reg, ..)
+            | ConvertF64I32(reg, ..)
+            | TruncI32F64(reg, ..)
+            | ExtendI64I32(reg, ..)
+            | WrapI32I64(reg, ..)
+            | PromoteF64F32(reg, ..)
+            | DemoteF32F64(reg, ..)
+            | AddI32(reg, ..)
+            | SubI32(reg, ..)
+            | MulI32(reg, ..)
+            | DivI32(reg, ..)
+            | ModI32(reg, ..)
+            | EqI32(reg, ..)
+            | NeqI32(reg, ..)
+            | GtI32(reg, ..)
+            | GeI32(reg, ..)
+            | LtI32(reg, ..)
+            | LeI32(reg, ..)
+            | AddU32(reg, ..)
+            | SubU32(reg, ..)
+            | MulU32(reg, ..)
+            | DivU32(reg, ..)
+            | ModU32(reg, ..)
+            | EqU32(reg, ..)
+            | NeqU32(reg, ..)
+            | GtU32(reg, ..)
+            | GeU32(reg, ..)
+            | LtU32(reg, ..)
+            | LeU32(reg, ..)
+            | AddI64(reg, ..)
+            | SubI64(reg, ..)
+            | MulI64(reg, ..)
+            | DivI64(reg, ..)
+            | ModI64(reg, ..)
+            | EqI64(reg, ..)
+            | NeqI64(reg, ..)
+            | GtI64(reg, ..)
+            | GeI64(reg, ..)
+            | LtI64(reg, ..)
+            | LeI64(reg, ..)
+            | AddU64(reg, ..)
+            | SubU64(reg, ..)
+            | MulU64(reg, ..)
+            | DivU64(reg, ..)
+            | ModU64(reg, ..)
+            | EqU64(reg, ..)
+            | NeqU64(reg, ..)
+            | GtU64(reg, ..)
+            | GeU64(reg, ..)
+            | LtU64(reg, ..)
+            | LeU64(reg, ..)
+            | AddF32(reg, ..)
+            | SubF32(reg, ..)
+            | MulF32(reg, ..)
+            | DivF32(reg, ..)
+            | EqF32(reg, ..)
+            | NeqF32(reg, ..)
+            | GtF32(reg, ..)
+            | GeF32(reg, ..)
+            | LtF32(reg, ..)
+            | LeF32(reg, ..)
+            | AddF64(reg, ..)
+            | SubF64(reg, ..)
+            | MulF64(reg, ..)
+            | DivF64(reg, ..)
+            | EqF64(reg, ..)
+            | NeqF64(reg, ..)
+            | GtF64(reg, ..)
+            | GeF64(reg, ..)
+            | LtF64(reg, ..)
+            | LeF64(reg, ..)
+            | LoadI32(reg, ..)
+            | LoadU32(reg, ..)
+            | LoadU8(reg, ..)
+            | LoadI64(reg, ..)
+            | LoadU64(reg, ..)
+            | LoadF32(reg, ..)
+            | LoadF64(reg, ..)
+            | HeapAlloc(reg, ..)
+            | StackAlloc(reg, ..)
+            | ExternCall(reg, ..)
+            | FunCall(reg, ..)
+            | ClosureCall(reg, ..) => vec![reg],
+            // `regs` is `&Vec` or `&mut Vec` depending on `$op`
+            MultiFunCall(regs, ..) => IntoIterator::into_iter(regs).collect(),
+            StoreI32(..) | StoreU32(..) | StoreU8(..) | StoreI64(..) | StoreU64(..)
+            | StoreF32(..) | StoreF64(..) | JumpIfI32(..) | JumpTableI32(..) | StoreGlobal(..)
+            | StoreFnPtr(..) | TailCall(..) | TailClosureCall(..) | Jump(..) | Unreachable
+            | UnreachableIfI32(..) | Ret(..) | MultiRet(..) => vec![],
+        }
+    }};
+}
+
+macro_rules! uses_of {
+    ($op: expr) => {{
+        use self::Op::*;
+        use self::Value::*;
+        match $op {
+            ConstI32(_, ..)
+            | ConstU32(_, ..)
+            | LoadGlobal(_, ..)
+            | ConstI64(_, ..)
+            | ConstU64(_, ..)
+            | ConstF32(_, ..)
+            | ConstF64(_, ..)
+            | StackAlloc(_, ..)
+            | HeapAlloc(_, I(_), _) => vec![],
+            HeapAlloc(_, R(reg), _) => vec![reg],
+            MoveI32(_, reg)
+            | MoveU32(_, reg)
+            | MoveI64(_, reg)
+            | MoveU64(_, reg)
+            | MoveF32(_, reg)
+            | MoveF64(_, reg)
+            | FloorF64(_, reg)
+            | CeilF64(_, reg)
+            | NearestF64(_, reg)
+            | TruncF64(_, reg)
+            | SqrtF64(_, reg)
+            | ConvertF64I32(_, reg)
+            | TruncI32F64(_, reg)
+            | ExtendI64I32(_, reg)
+            | WrapI32I64(_, reg)
+            | PromoteF64F32(_, reg)
+            | DemoteF32F64(_, reg) => vec![reg],
+            AddI32(_, reg1, reg2)
+            | SubI32(_, reg1, reg2)
+            | MulI32(_, reg1, reg2)
+            | DivI32(_, reg1, reg2)
+            | ModI32(_, reg1, reg2)
+            | EqI32(_, reg1, reg2)
+            | NeqI32(_, reg1, reg2)
+            | GtI32(_, reg1, reg2)
+            | GeI32(_, reg1, reg2)
+            | LtI32(_, reg1, reg2)
+            | LeI32(_, reg1, reg2)
+            | AddU32(_, reg1, reg2)
+            | SubU32(_, reg1, reg2)
+            | MulU32(_, reg1, reg2)
+            | DivU32(_, reg1, reg2)
+            | ModU32(_, reg1, reg2)
+            | EqU32(_, reg1, reg2)
+            | NeqU32(_, reg1, reg2)
+            | GtU32(_, reg1, reg2)
+            | GeU32(_, reg1, reg2)
+            | LtU32(_, reg1, reg2)
+            | LeU32(_, reg1, reg2)
+            | AddI64(_, reg1, reg2)
+            | SubI64(_, reg1, reg2)
+            | MulI64(_, reg1, reg2)
+            | DivI64(_, reg1, reg2)
+            | ModI64(_, reg1, reg2)
+            | EqI64(_, reg1, reg2)
+            | NeqI64(_, reg1, reg2)
+            | GtI64(_, reg1, reg2)
+            | GeI64(_, reg1, reg2)
+            | LtI64(_, reg1, reg2)
+            | LeI64(_, reg1, reg2)
+            | AddU64(_, reg1, reg2)
+            | SubU64(_, reg1, reg2)
+            | MulU64(_, reg1, reg2)
+            | DivU64(_, reg1, reg2)
+            | ModU64(_, reg1, reg2)
+            | EqU64(_, reg1, reg2)
+            | NeqU64(_, reg1, reg2)
+            | GtU64(_, reg1, reg2)
+            | GeU64(_, reg1, reg2)
+            | LtU64(_, reg1, reg2)
+            | LeU64(_, reg1, reg2)
+            | AddF32(_, reg1, reg2)
+            | SubF32(_, reg1, reg2)
+            | MulF32(_, reg1, reg2)
+            | DivF32(_, reg1, reg2)
+            | EqF32(_, reg1, reg2)
+            | NeqF32(_, reg1, reg2)
+            | GtF32(_, reg1, reg2)
+            | GeF32(_, reg1, reg2)
+            | LtF32(_, reg1, reg2)
+            | LeF32(_, reg1, reg2)
+            | AddF64(_, reg1, reg2)
+            | SubF64(_, reg1, reg2)
+            | MulF64(_, reg1, reg2)
+            | DivF64(_, reg1, reg2)
+            | EqF64(_, reg1, reg2)
+            | NeqF64(_, reg1, reg2)
+            | GtF64(_, reg1, reg2)
+            | GeF64(_, reg1, reg2)
+            | LtF64(_, reg1, reg2)
+            | LeF64(_, reg1, reg2) => vec![reg1, reg2],
+            LoadI32(_, Addr(reg, _))
+            | LoadU32(_, Addr(reg, _))
+            | LoadU8(_, Addr(reg, _))
+            | LoadI64(_, Addr(reg, _))
+            | LoadU64(_, Addr(reg, _))
+            | LoadF32(_, Addr(reg, _))
+            | LoadF64(_, Addr(reg, _)) => vec![reg],
+            StoreI32(Addr(addr, _), reg)
+            | StoreU32(Addr(addr, _), reg)
+            | StoreU8(Addr(addr, _), reg)
+            | StoreI64(Addr(addr, _), reg)
+            | StoreU64(Addr(addr, _), reg)
+            | StoreF32(Addr(addr, _), reg)
+            | StoreF64(Addr(addr, _), reg) => vec![addr, reg],
+            StoreFnPtr(Addr(addr, _), _) => vec![addr],
+            JumpIfI32(reg, _) | JumpTableI32(reg, ..) | UnreachableIfI32(reg) => vec![reg],
+            StoreGlobal(_, reg) => vec![reg],
+            // `args` is `&Vec` or `&mut Vec` depending on `$op`
+            ExternCall(_, _, _, args)
+            | FunCall(_, _, args)
+            | MultiFunCall(_, _, args)
+            | TailCall(_, args) => IntoIterator::into_iter(args).collect(),
+            ClosureCall(_, fun, args) | TailClosureCall(fun, args) => {
+                std::iter::once(fun).chain(args).collect()
+            }
+            Ret(reg) => IntoIterator::into_iter(reg).collect(),
+            MultiRet(regs) => IntoIterator::into_iter(regs).collect(),
+            Jump(_) | Unreachable => vec![],
+        }
+    }};
+}
+impl Op {
+    /// the registers the op writes to
+    pub fn defs(&self) -> Vec<&Reg> {
+        defs_of!(self)
+    }
+
+    pub fn defs_mut(&mut self) -> Vec<&mut Reg> {
+        defs_of!(self)
+    }
+
+    /// the registers the op reads
+    pub fn uses(&self) -> Vec<&Reg> {
+        uses_of!(self)
+    }
+
+    pub fn uses_mut(&mut self) -> Vec<&mut Reg> {
+        uses_of!(self)
+    }
+
+    /// whether the control never reaches the op next to this
+    pub fn is_terminator(&self) -> bool {
+        use self::Op::*;
+        match *self {
+            Jump(_)
+            | JumpTableI32(_, _, Some(_))
+            | TailCall(..)
+            | TailClosureCall(..)
+            | Unreachable
+            | Ret(_)
+            | MultiRet(_) => true,
+            _ => false,
+        }
+    }
 }
 
 impl Block {
@@ -0,0 +1,117 @@
+use crate::config::Config;
+use crate::lir::*;
+use crate::pass::Pass;
+use std::collections::HashMap;
+
+/// removes the chains of `Move*`s such as `r1 <- ...; r2 <- r1; r3 <- r2`.
+/// A move from a register written and read only once in the same block
+/// is removed by letting the op writing it write to the destination of the move.
+pub struct Peephole;
+
+impl Peephole {
+    pub fn new() -> Self {
+        Peephole
+    }
+
+    fn conv_lir(&mut self, lir: LIR) -> LIR {
+        LIR(lir.0.into_iter().map(|f| self.conv_fun(f)).collect())
+    }
+
+    fn conv_fun(&mut self, mut f: Function) -> Function {
+        let mut defs = HashMap::new();
+        let mut uses = HashMap::new();
+        for op in f.body.iter().flat_map(|block| block.body.iter()) {
+            for reg in op.defs() {
+                *defs.entry(reg.1).or_insert(0) += 1;
+            }
+            for reg in op.uses() {
+                *uses.entry(reg.1).or_insert(0) += 1;
+            }
+        }
+
+        let nparams = f.nparams;
+        // the params are written by the caller
+        let written_once = |reg: &Reg| nparams <= reg.1 && defs.get(&reg.1) == Some(&1);
+        for block in f.body.iter_mut() {
+            let body = &mut block.body;
+            let mut i = 0;
+            while i < body.len() {
+                let (dst, src) = match as_move(&body[i]) {
+                    Some((dst, src)) => (dst.clone(), src.clone()),
+                    None => {
+                        i += 1;
+                        continue;
+                    }
+                };
+                if dst.1 == src.1 {
+                    body.remove(i);
+                    continue;
+                }
+                if dst.0 == src.0 && written_once(&src) && uses.get(&src.1) == Some(&1) {
+                    if let Some(j) = defining_op(&body[0..i], &dst, &src) {
+                        for reg in body[j].defs_mut() {
+                            if reg.1 == src.1 {
+                                *reg = dst.clone();
+                            }
+                        }
+                        body.remove(i);
+                        continue;
+                    }
+                }
+                i += 1;
+            }
+        }
+        f
+    }
+}
+
+fn as_move(op: &Op) -> Option<(&Reg, &Reg)> {
+    use crate::lir::Op::*;
+    match op {
+        MoveI32(dst, src)
+        | MoveU32(dst, src)
+        | MoveI64(dst, src)
+        | MoveU64(dst, src)
+        | MoveF32(dst, src)
+        | MoveF64(dst, src) => Some((dst, src)),
+        _ => None,
+    }
+}
+
+/// the index of the op writing `src`, if it can write to `dst` instead.
+/// The ops between them must not touch `dst` nor branch,
+/// which may go where `dst` holds another value
+fn defining_op(ops: &[Op], dst: &Reg, src: &Reg) -> Option<usize> {
+    for (j, op) in ops.iter().enumerate().rev() {
+        let reads_dst = op.uses().iter().any(|reg| reg.1 == dst.1);
+        let writes_dst = op.defs().iter().any(|reg| reg.1 == dst.1);
+        if op.defs().iter().any(|reg| reg.1 == src.1) {
+            // the op writing more than one may write `dst` as well
+            return if reads_dst || writes_dst {
+                None
+            } else {
+                Some(j)
+            };
+        }
+        let branches = match op {
+            Op::JumpIfI32(..) | Op::JumpTableI32(..) | Op::Jump(_) => true,
+            _ => false,
+        };
+        if reads_dst || writes_dst || branches {
+            return None;
+        }
+    }
+    None
+}
+
+impl<E> Pass<Context, E> for Peephole {
+    type Target = Context;
+
+    fn trans(
+        &mut self,
+        Context(extern_types, lir): Context,
+        _: &Config,
+    ) -> ::std::result::Result<Self::Target, E> {
+        Ok(Context(extern_types, self.conv_lir(lir)))
+    }
+}
@@ -49,7 +49,18 @@ impl PP for Function {
             }
         };
         write!(w, ") -> ")?;
-        self.ret_ty.pp(w, 0)?;
+        match self.ret_tys.as_slice() {
+            [ty] => ty.pp(w, 0)?,
+            tys => {
+                write!(w, "(")?;
+                inter_iter! {
+                    tys.iter(),
+                    write!(w, ", ")?,
+                    |ty| => ty.pp(w, 0)?
+                }
+                write!(w, ")")?;
+            }
+        }
         write!(w, " = {{\n")?;
         for op in self.body.iter() {
             write!(w, "{}", Self::nspaces(indent))?;
@@ -72,7 +83,22 @@ impl fmt::Display for Function {
                 write!(f, "r{}: {}", i, reg)?;
             }
         };
-        write!(f, ") -> {} = {{\n", self.ret_ty)?;
+        write!(f, ") -> ")?;
+        match self.ret_tys.as_slice() {
+            [ty] => write!(f, "{}", ty)?,
+            tys => {
+                write!(f, "(")?;
+                inter_iter! {
+                    tys.iter(),
+                    write!(f, ", ")?,
+                    |ty| => {
+                        write!(f, "{}", ty)?;
+                    }
+                }
+                write!(f, ")")?;
+            }
+        }
+        write!(f, " = {{\n")?;
         for op in self.body.iter() {
             write!(f, "{}{:indent$}", nspaces(indent), op, indent = indent)?;
         }
@@ -222,6 +248,7 @@ impl PP for Op {
             }
             StoreI32(addr, v)
             | StoreU32(addr, v)
+            | StoreU8(addr, v)
             | StoreI64(addr, v)
             | StoreU64(addr, v)
             | StoreF32(addr, v)
@@ -235,8 +262,19 @@ impl PP for Op {
                 write!(w, " <- ")?;
                 f.pp(w, indent)?;
             }
+            LoadGlobal(reg, index) => {
+                reg.pp(w, indent)?;
+                write!(w, ": ")?;
+                reg.0.pp(w, indent)?;
+                write!(w, " <- global[{}]", index)?;
+            }
+            StoreGlobal(index, reg) => {
+                write!(w, "global[{}] <- ", index)?;
+                reg.pp(w, indent)?;
+            }
             LoadI32(reg, addr)
             | LoadU32(reg, addr)
+            | LoadU8(reg, addr)
             | LoadI64(reg, addr)
             | LoadU64(reg, addr)
             | LoadF32(reg, addr)
@@ -247,6 +285,23 @@ impl PP for Op {
                 write!(w, " <- ")?;
                 addr.pp(w, indent)?;
             }
+            FloorF64(r1, r2)
+            | CeilF64(r1, r2)
+            | NearestF64(r1, r2)
+            | TruncF64(r1, r2)
+            | SqrtF64(r1, r2)
+            | ConvertF64I32(r1, r2)
+            | TruncI32F64(r1, r2)
+            | ExtendI64I32(r1, r2)
+            | WrapI32I64(r1, r2)
+            | PromoteF64F32(r1, r2)
+            | DemoteF32F64(r1, r2) => {
+                r1.pp(w, indent)?;
+                write!(w, ": ")?;
+                r1.0.pp(w, indent)?;
+                write!(w, " <- {} ", conversion_name(self))?;
+                r2.pp(w, indent)?;
+            }
             JumpIfI32(reg, label) => {
                 write!(w, "jump_if ")?;
                 reg.pp(w, indent)?;
@@ -478,6 +533,27 @@ impl PP for Op {
                 }
                 write!(w, ")")?;
             }
+            MultiFunCall(regs, name, args) => {
+                write!(w, "(")?;
+                inter_iter! {
+                    regs.iter(),
+                    write!(w, ", ")?,
+                    |reg| => {
+                        reg.pp(w, indent)?;
+                        write!(w, ": ")?;
+                        reg.0.pp(w, indent)?
+                    }
+                }
+                write!(w, ") <- call ")?;
+                name.pp(w, indent)?;
+                write!(w, "(")?;
+                inter_iter! {
+                    args.iter(),
+                    write!(w, ", ")?,
+                    |arg| => arg.pp(w, indent)?
+                }
+                write!(w, ")")?;
+            }
             ExternCall(reg, module, name, args) => {
                 reg.pp(w, indent)?;
                 write!(w, ": ")?;
@@ -491,6 +567,28 @@ impl PP for Op {
                 }
                 write!(w, ")")?;
             }
+            TailCall(name, args) => {
+                write!(w, "tail_call ")?;
+                name.pp(w, indent)?;
+                write!(w, "(")?;
+                inter_iter! {
+                    args.iter(),
+                    write!(w, ", ")?,
+                    |arg| => arg.pp(w, indent)?
+                }
+                write!(w, ")")?;
+            }
+            TailClosureCall(name, args) => {
+                write!(w, "tail_closure_call ")?;
+                name.pp(w, indent)?;
+                write!(w, "(")?;
+                inter_iter! {
+                    args.iter(),
+                    write!(w, ", ")?,
+                    |arg| => arg.pp(w, indent)?
+                }
+                write!(w, ")")?;
+            }
             Jump(label) => {
                 write!(w, "jump ")?;
                 label.pp(w, indent)?;
@@ -498,10 +596,23 @@ impl PP for Op {
             Unreachable => {
                 write!(w, "unreachable")?;
             }
+            UnreachableIfI32(reg) => {
+                write!(w, "unreachable_if ")?;
+                reg.pp(w, indent)?;
+            }
             Ret(reg) => {
                 write!(w, "ret ")?;
                 reg.as_ref().map(|r| r.pp(w, indent)).unwrap_or(Ok(()))?;
             }
+            MultiRet(regs) => {
+                write!(w, "ret (")?;
+                inter_iter! {
+                    regs.iter(),
+                    write!(w, ", ")?,
+                    |reg| => reg.pp(w, indent)?
+                }
+                write!(w, ")")?;
+            }
         };
         Ok(())
     }
@@ -522,17 +633,34 @@ impl fmt::Display for Op {
             | MoveF64(r1, r2) => write!(f, "{}: {} <- {}", r1, r1.0, r2)?,
             StoreI32(addr, v)
             | StoreU32(addr, v)
+            | StoreU8(addr, v)
             | StoreI64(addr, v)
             | StoreU64(addr, v)
             | StoreF32(addr, v)
             | StoreF64(addr, v) => write!(f, "{} <- {}", addr, v)?,
             StoreFnPtr(addr, fp) => write!(f, "{} <- {}", addr, fp)?,
+            LoadGlobal(reg, index) => write!(f, "{}: {} <- global[{}]", reg, reg.0, index)?,
+            StoreGlobal(index, reg) => write!(f, "global[{}] <- {}", index, reg)?,
             LoadI32(reg, addr)
             | LoadU32(reg, addr)
+            | LoadU8(reg, addr)
             | LoadI64(reg, addr)
             | LoadU64(reg, addr)
             | LoadF32(reg, addr)
             | LoadF64(reg, addr) => write!(f, "{}: {} <- {}", reg, reg.0, addr)?,
+            FloorF64(r1, r2)
+            | CeilF64(r1, r2)
+            | NearestF64(r1, r2)
+            | TruncF64(r1, r2)
+            | SqrtF64(r1, r2)
+            | ConvertF64I32(r1, r2)
+            | TruncI32F64(r1, r2)
+            | ExtendI64I32(r1, r2)
+            | WrapI32I64(r1, r2)
+            | PromoteF64F32(r1, r2)
+            | DemoteF32F64(r1, r2) => {
+                write!(f, "{}: {} <- {} {}", r1, r1.0, conversion_name(self), r2)?
+            }
             JumpIfI32(reg, label) => write!(f, "jump_if {} {}", reg, label)?,
             JumpTableI32(reg, labels, default) => {
                 write!(f, "jump_table {} ", reg)?;
@@ -643,6 +771,25 @@ impl fmt::Display for Op {
                 }
                 write!(f, ")")?;
             }
+            MultiFunCall(regs, name, args) => {
+                write!(f, "(")?;
+                inter_iter! {
+                    regs.iter(),
+                    write!(f, ", ")?,
+                    |reg| => {
+                        write!(f, "{}: {}", reg, reg.0)?;
+                    }
+                }
+                write!(f, ") <- call {}(", name)?;
+                inter_iter! {
+                    args.iter(),
+                    write!(f, ", ")?,
+                    |arg| => {
+                        write!(f, "{}", arg)?;
+                    }
+                }
+                write!(f, ")")?;
+            }
             ExternCall(reg, module, name, args) => {
                 write!(
                     f,
@@ -658,17 +805,71 @@ impl fmt::Display for Op {
                 }
                 write!(f, ")")?;
             }
+            TailCall(name, args) => {
+                write!(f, "tail_call {}(", name)?;
+                inter_iter! {
+                    args.iter(),
+                    write!(f, ", ")?,
+                    |arg| => {
+                        write!(f, "{}", arg)?;
+                    }
+                }
+                write!(f, ")")?;
+            }
+            TailClosureCall(name, args) => {
+                write!(f, "tail_closure_call {}(", name)?;
+                inter_iter! {
+                    args.iter(),
+                    write!(f, ", ")?,
+                    |arg| => {
+                        write!(f, "{}", arg)?;
+                    }
+                }
+                write!(f, ")")?;
+            }
             Jump(label) => {
                 write!(f, "jump {}", label)?;
             }
             Unreachable => {
                 write!(f, "unreachable")?;
             }
+            UnreachableIfI32(reg) => {
+                write!(f, "unreachable_if {}", reg)?;
+            }
             Ret(reg) => {
                 write!(f, "ret ")?;
                 reg.as_ref().map(|r| write!(f, "{}", r)).unwrap_or(Ok(()))?;
             }
+            MultiRet(regs) => {
+                write!(f, "ret (")?;
+                inter_iter! {
+                    regs.iter(),
+                    write!(f, ", ")?,
+                    |reg| => {
+                        write!(f, "{}", reg)?;
+                    }
+                }
+                write!(f, ")")?;
+            }
         };
         Ok(())
     }
 }
+
+fn conversion_name(op: &Op) -> &'static str {
+    use crate::lir::Op::*;
+    match op {
+        FloorF64(..) => "floor",
+        CeilF64(..) => "ceil",
+        NearestF64(..) => "nearest",
+        TruncF64(..) => "trunc",
+        SqrtF64(..) => "sqrt",
+        ConvertF64I32(..) => "convert",
+        TruncI32F64(..) => "trunc",
+        ExtendI64I32(..) => "extend",
+        WrapI32I64(..) => "wrap",
+        PromoteF64F32(..) => "promote",
+        DemoteF32F64(..) => "demote",
+        _ => unreachable!(),
+    }
+}
@@ -0,0 +1,121 @@
+use crate::config::Config;
+use crate::lir::*;
+use crate::pass::Pass;
+use std::collections::HashMap;
+
+/// assigns the registers to fewer locals by linear scan.
+/// Registers whose live intervals don't overlap share a local if they have the same `LTy`.
+/// The params keep their locals as the signature of the function is made of them.
+pub struct RegAlloc;
+
+impl RegAlloc {
+    pub fn new() -> Self {
+        RegAlloc
+    }
+
+    fn alloc_lir(&mut self, lir: LIR) -> LIR {
+        LIR(lir.0.into_iter().map(|f| self.alloc_fun(f)).collect())
+    }
+
+    fn alloc_fun(&mut self, mut f: Function) -> Function {
+        let intervals = live_intervals(&f);
+        let nparams = f.nparams;
+
+        let mut order = intervals
+            .iter()
+            .filter(|(reg, _)| nparams <= **reg)
+            .map(|(&reg, &interval)| (interval, reg))
+            .collect::<Vec<_>>();
+        order.sort();
+
+        let mut regs = f.regs[0..nparams as usize].to_vec();
+        let mut table = (0..nparams).map(|i| (i, i)).collect::<HashMap<_, _>>();
+        // (end of the interval, local)
+        let mut active = (0..nparams)
+            .map(|i| (intervals[&i].1, i))
+            .collect::<Vec<_>>();
+        let mut free: Vec<u32> = Vec::new();
+
+        for ((start, end), reg) in order {
+            // expire the intervals ending before this one
+            active.retain(|&(e, local)| {
+                if e < start {
+                    free.push(local);
+                    false
+                } else {
+                    true
+                }
+            });
+            let ty = &f.regs[reg as usize];
+            let local = match free.iter().position(|&local| &regs[local as usize] == ty) {
+                Some(i) => free.remove(i),
+                None => {
+                    regs.push(ty.clone());
+                    regs.len() as u32 - 1
+                }
+            };
+            table.insert(reg, local);
+            active.push((end, local));
+        }
+
+        for block in f.body.iter_mut() {
+            for op in block.body.iter_mut() {
+                for reg in op.defs_mut() {
+                    reg.1 = table[&reg.1];
+                }
+                for reg in op.uses_mut() {
+                    reg.1 = table[&reg.1];
+                }
+            }
+        }
+        f.regs = regs;
+        f
+    }
+}
+
+/// the interval of the positions where each register is live, by the index of `Reg`.
+/// The positions number the entries of the blocks and the ops in order
+fn live_intervals(f: &Function) -> HashMap<u32, (usize, usize)> {
+    let liveness = f.liveness();
+    let mut intervals = HashMap::new();
+    let mut extend = |reg: u32, pos: usize| {
+        let interval = intervals.entry(reg).or_insert((pos, pos));
+        interval.0 = interval.0.min(pos);
+        interval.1 = interval.1.max(pos);
+    };
+
+    // the params are written at the entry
+    for reg in 0..f.nparams {
+        extend(reg, 0);
+    }
+
+    let mut pos = 0;
+    for (i, block) in f.body.iter().enumerate() {
+        for &reg in &liveness.live_in[i] {
+            extend(reg, pos);
+        }
+        for op in &block.body {
+            pos += 1;
+            for reg in op.defs().into_iter().chain(op.uses()) {
+                extend(reg.1, pos);
+            }
+        }
+        for &reg in &liveness.live_out[i] {
+            extend(reg, pos);
+        }
+        pos += 1;
+    }
+    intervals
+}
+
+impl<E> Pass<Context, E> for RegAlloc {
+    type Target = Context;
+
+    fn trans(
+        &mut self,
+        Context(extern_types, lir): Context,
+        _: &Config,
+    ) -> ::std::result::Result<Self::Target, E> {
+        Ok(Context(extern_types, self.alloc_lir(lir)))
+    }
+}
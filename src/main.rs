@@ -1,37 +1,207 @@
-use clap::{app_from_crate, crate_authors, crate_description, crate_name, crate_version, Arg};
+use clap::{
+    app_from_crate, crate_authors, crate_description, crate_name, crate_version, AppSettings, Arg,
+    ArgMatches, SubCommand,
+};
 use std::collections::HashSet;
 use std::fs;
-use std::io::{self, prelude::*};
-use std::path::Path;
-use webml::{compile_str, Config};
+use std::path::{Path, PathBuf};
+use std::process;
+use webml::mlb::Project;
+use webml::{compile_project, compile_project_hir, lower_units, Config, HirUnit, Interface};
 
-fn read_and_append_to_string(path: impl AsRef<Path>, buf: &mut String) -> io::Result<usize> {
-    let file = fs::File::open(path)?;
-    let mut input = io::BufReader::new(file);
-    input.read_to_string(buf)
+fn exit_with(message: impl std::fmt::Display) -> ! {
+    eprintln!("{}", message);
+    process::exit(1)
+}
+
+fn report_warnings(config: &Config) {
+    for warning in config.warnings.take() {
+        eprintln!("{}", warning);
+    }
+}
+
+fn load_project(matches: &ArgMatches, with_prelude: bool) -> Project {
+    let mut project = Project::new();
+    if with_prelude {
+        project.push_source("prelude.sml", include_str!("../ml_src/prelude.sml"));
+    }
+    for filename in matches.values_of("INPUT").into_iter().flatten() {
+        if let Err(e) = project.load(filename) {
+            exit_with(e)
+        }
+    }
+    project
+}
+
+fn read_bytes(filename: &str) -> Vec<u8> {
+    fs::read(filename).unwrap_or_else(|e| exit_with(format!("{}: {}", filename, e)))
+}
+
+fn read_unit(filename: &str) -> HirUnit {
+    HirUnit::from_wasm(&read_bytes(filename))
+        .unwrap_or_else(|e| exit_with(format!("{}: {}", filename, e)))
+}
+
+fn read_interface(filename: &str) -> Interface {
+    HirUnit::interface_from_wasm(&read_bytes(filename))
+        .unwrap_or_else(|e| exit_with(format!("{}: {}", filename, e)))
+}
+
+fn compile_to_hir(matches: &ArgMatches, config: &Config) {
+    let deps = matches
+        .values_of("WITH")
+        .into_iter()
+        .flatten()
+        .map(read_interface)
+        .collect::<Vec<_>>();
+    // units given by --with are expected to include the prelude
+    let project = load_project(matches, deps.is_empty());
+    let units = compile_project_hir(&project, &deps, config).unwrap_or_else(|e| exit_with(e));
+    report_warnings(config);
+
+    let out_dir = Path::new(matches.value_of("OUT_DIR").unwrap_or("."));
+    for unit in units {
+        let path = out_dir.join(format!("{}.hir.wasm", unit.name));
+        fs::write(&path, unit.to_wasm())
+            .unwrap_or_else(|e| exit_with(format!("{}: {}", path.display(), e)));
+    }
+}
+
+fn lower(matches: &ArgMatches, config: &Config) {
+    let units = matches
+        .values_of("UNIT")
+        .into_iter()
+        .flatten()
+        .map(read_unit)
+        .collect::<Vec<_>>();
+    let code = lower_units(units, config);
+    fs::write(matches.value_of("OUTPUT").unwrap_or("out.wasm"), &code).unwrap()
 }
 
 fn main() {
     env_logger::init();
+    let print_ir = Arg::with_name("PRINT_IR")
+        .long("print-ir")
+        .help("print the output of IR")
+        .value_name("IR")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1);
+    let inline_threshold = Arg::with_name("INLINE_THRESHOLD")
+        .long("inline-threshold")
+        .help("inline the functions up to this size at their calls. 0 disables inlining")
+        .value_name("SIZE")
+        .takes_value(true);
+    let opt_level = Arg::with_name("OPT_LEVEL")
+        .short("O")
+        .help("optimization level, 0 to 3")
+        .value_name("LEVEL")
+        .takes_value(true);
+    let enable_pass = Arg::with_name("ENABLE_PASS")
+        .long("enable-pass")
+        .help("run the optimization pass regardless of the optimization level")
+        .value_name("PASS")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1);
+    let disable_pass = Arg::with_name("DISABLE_PASS")
+        .long("disable-pass")
+        .help("skip the optimization pass regardless of the optimization level")
+        .value_name("PASS")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1);
+    let wrapping_int = Arg::with_name("WRAPPING_INT")
+        .long("wrapping-int")
+        .help("let int arithmetic wrap around instead of raising Overflow");
+    let tail_call = Arg::with_name("TAIL_CALL")
+        .long("tail-call")
+        .help("compile calls in tail position to return_call of wasm");
+    let multi_value = Arg::with_name("MULTI_VALUE")
+        .long("multi-value")
+        .help("return small tuples as multiple values of wasm instead of allocating them");
+    // the options of the backend, which -c doesn't run
+    let backend_only = |arg: &Arg<'static, 'static>| arg.clone().conflicts_with("COMPILE_ONLY");
     let matches = app_from_crate!()
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .arg(print_ir.clone())
+        .arg(
+            Arg::with_name("COMPILE_ONLY")
+                .short("c")
+                .long("compile-only")
+                .help("compile each source only to HIR, <name>.hir.wasm, without lowering it to a program"),
+        )
         .arg(
-            Arg::with_name("PRINT_IR")
-                .long("print-ir")
-                .help("print the output of IR")
-                .value_name("IR")
+            Arg::with_name("WITH")
+                .long("with")
+                .help("compile against the interface of a source compiled to HIR before")
+                .value_name("UNIT")
                 .takes_value(true)
-                .multiple(true),
+                .multiple(true)
+                .number_of_values(1)
+                .requires("COMPILE_ONLY"),
         )
+        .arg(
+            Arg::with_name("OUT_DIR")
+                .long("out-dir")
+                .help("directory to put the HIR in")
+                .value_name("DIR")
+                .takes_value(true)
+                .requires("COMPILE_ONLY"),
+        )
+        .arg(
+            Arg::with_name("CACHE_DIR")
+                .long("cache-dir")
+                .help("directory to cache compiled declarations in, to skip them in later builds")
+                .value_name("DIR")
+                .takes_value(true)
+                .conflicts_with("COMPILE_ONLY"),
+        )
+        .arg(backend_only(&wrapping_int))
+        .arg(backend_only(&tail_call))
+        .arg(backend_only(&multi_value))
+        .arg(backend_only(&inline_threshold))
+        .arg(backend_only(&opt_level))
+        .arg(backend_only(&enable_pass))
+        .arg(backend_only(&disable_pass))
         .arg(
             Arg::with_name("INPUT")
-                .help("file to compile")
-                .required(true),
+                .help("source files or .mlb project files to compile, in order")
+                .required(true)
+                .multiple(true),
+        )
+        .subcommand(
+            SubCommand::with_name("lower")
+                .about("lower the HIR of sources compiled with -c to a program")
+                .arg(print_ir)
+                .arg(wrapping_int)
+                .arg(tail_call)
+                .arg(multi_value)
+                .arg(inline_threshold)
+                .arg(opt_level)
+                .arg(enable_pass)
+                .arg(disable_pass)
+                .arg(
+                    Arg::with_name("OUTPUT")
+                        .short("o")
+                        .long("output")
+                        .help("file to write the program to")
+                        .value_name("FILE")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("UNIT")
+                        .help("HIR to lower, in the order it was compiled")
+                        .required(true)
+                        .multiple(true),
+                ),
         )
         .get_matches();
 
-    let filename = matches
-        .value_of("INPUT")
-        .unwrap_or("ml_example/example1.sml");
+    let (matches, lowering) = match matches.subcommand_matches("lower") {
+        Some(matches) => (matches, true),
+        None => (&matches, false),
+    };
     let pretty_print_ir = matches
         .values_of("PRINT_IR")
         .into_iter()
@@ -39,14 +209,53 @@ fn main() {
         .map(|s| s.to_string())
         .collect::<HashSet<String>>();
 
+    let opt_level = match matches.value_of("OPT_LEVEL") {
+        None => Config::DEFAULT_OPT_LEVEL,
+        Some(level) => match level.parse() {
+            Ok(level) if level <= Config::MAX_OPT_LEVEL => level,
+            _ => exit_with(format!("invalid optimization level: {}", level)),
+        },
+    };
+    let passes = |name| {
+        matches
+            .values_of(name)
+            .into_iter()
+            .flatten()
+            .map(|pass| {
+                if !Config::is_optimization(pass) {
+                    exit_with(format!("unknown optimization pass: {}", pass))
+                }
+                pass.to_string()
+            })
+            .collect::<HashSet<String>>()
+    };
+
+    let inline_threshold = matches.value_of("INLINE_THRESHOLD").map(|size| {
+        size.parse()
+            .unwrap_or_else(|_| exit_with(format!("invalid inline threshold: {}", size)))
+    });
+
     let config = Config {
         pretty_print_ir,
-        ..Default::default()
+        cache_dir: matches.value_of("CACHE_DIR").map(PathBuf::from),
+        wrapping_int: matches.is_present("WRAPPING_INT"),
+        tail_call: matches.is_present("TAIL_CALL"),
+        multi_value: matches.is_present("MULTI_VALUE"),
+        inline_threshold,
+        opt_level,
+        enabled_passes: passes("ENABLE_PASS"),
+        disabled_passes: passes("DISABLE_PASS"),
+        ..Config::default()
     };
 
-    let prelude = include_str!("../ml_src/prelude.sml").to_string();
-    let mut input = prelude;
-    read_and_append_to_string(filename, &mut input).expect("failed to load file");
-    let code = compile_str(&input, &config).unwrap();
-    fs::write("out.wasm", &code).unwrap()
+    if lowering {
+        lower(matches, &config)
+    } else if matches.is_present("COMPILE_ONLY") {
+        compile_to_hir(matches, &config)
+    } else {
+        let project = load_project(matches, true);
+        let code = compile_project(&project, &config).unwrap();
+        report_warnings(&config);
+        fs::write("out.wasm", &code).unwrap()
+    }
 }
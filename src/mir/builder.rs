@@ -9,6 +9,7 @@ impl FunctionBuilder {
             name,
             body_ty,
             body: Vec::new(),
+            multi_value: false,
         })
     }
 
@@ -106,6 +107,135 @@ impl EBBBuilder {
         self
     }
 
+    pub fn int_to_float(&mut self, var: Symbol, ty: EbbTy, sym: Symbol) -> &mut Self {
+        self.push(Op::IntToFloat { var, ty, sym });
+        self
+    }
+
+    pub fn round_float(
+        &mut self,
+        var: Symbol,
+        ty: EbbTy,
+        rounding: Rounding,
+        sym: Symbol,
+    ) -> &mut Self {
+        self.push(Op::RoundFloat {
+            var,
+            ty,
+            rounding,
+            sym,
+        });
+        self
+    }
+
+    pub fn sqrt(&mut self, var: Symbol, ty: EbbTy, sym: Symbol) -> &mut Self {
+        self.push(Op::Sqrt { var, ty, sym });
+        self
+    }
+
+    pub fn convert(&mut self, var: Symbol, ty: EbbTy, sym: Symbol) -> &mut Self {
+        self.push(Op::Convert { var, ty, sym });
+        self
+    }
+
+    pub fn float_to_int(
+        &mut self,
+        var: Symbol,
+        ty: EbbTy,
+        rounding: Rounding,
+        sym: Symbol,
+    ) -> &mut Self {
+        self.push(Op::FloatToInt {
+            var,
+            ty,
+            rounding,
+            sym,
+        });
+        self
+    }
+
+    pub fn string_alloc(&mut self, var: Symbol, ty: EbbTy, len: Symbol) -> &mut Self {
+        self.push(Op::StringAlloc { var, ty, len });
+        self
+    }
+
+    pub fn string_size(&mut self, var: Symbol, ty: EbbTy, string: Symbol) -> &mut Self {
+        self.push(Op::StringSize { var, ty, string });
+        self
+    }
+
+    pub fn string_sub(
+        &mut self,
+        var: Symbol,
+        ty: EbbTy,
+        string: Symbol,
+        index: Symbol,
+    ) -> &mut Self {
+        self.push(Op::StringSub {
+            var,
+            ty,
+            string,
+            index,
+        });
+        self
+    }
+
+    pub fn string_update(
+        &mut self,
+        var: Symbol,
+        ty: EbbTy,
+        string: Symbol,
+        index: Symbol,
+        value: Symbol,
+    ) -> &mut Self {
+        self.push(Op::StringUpdate {
+            var,
+            ty,
+            string,
+            index,
+            value,
+        });
+        self
+    }
+
+    pub fn array_alloc(&mut self, var: Symbol, ty: EbbTy, len: Symbol) -> &mut Self {
+        self.push(Op::ArrayAlloc { var, ty, len });
+        self
+    }
+
+    pub fn array_length(&mut self, var: Symbol, ty: EbbTy, array: Symbol) -> &mut Self {
+        self.push(Op::ArrayLength { var, ty, array });
+        self
+    }
+
+    pub fn array_sub(&mut self, var: Symbol, ty: EbbTy, array: Symbol, index: Symbol) -> &mut Self {
+        self.push(Op::ArraySub {
+            var,
+            ty,
+            array,
+            index,
+        });
+        self
+    }
+
+    pub fn array_update(
+        &mut self,
+        var: Symbol,
+        ty: EbbTy,
+        array: Symbol,
+        index: Symbol,
+        value: Symbol,
+    ) -> &mut Self {
+        self.push(Op::ArrayUpdate {
+            var,
+            ty,
+            array,
+            index,
+            value,
+        });
+        self
+    }
+
     pub fn closure(
         &mut self,
         var: Symbol,
@@ -188,6 +318,16 @@ impl EBBBuilder {
         self
     }
 
+    pub fn global_get(&mut self, var: Symbol, ty: EbbTy, index: u32) -> &mut Self {
+        self.push(Op::GlobalGet { var, ty, index });
+        self
+    }
+
+    pub fn global_set(&mut self, index: u32, value: Symbol) -> &mut Self {
+        self.push(Op::GlobalSet { index, value });
+        self
+    }
+
     pub fn branch(
         mut self,
         cond: Symbol,
@@ -218,4 +358,9 @@ impl EBBBuilder {
         });
         self.0
     }
+
+    pub fn unreachable(mut self) -> EBB {
+        self.push(Op::Unreachable);
+        self.0
+    }
 }
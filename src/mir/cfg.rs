@@ -1,33 +1,23 @@
 use crate::prim::*;
-use std::collections::{HashSet, VecDeque};
 
 use super::{Function, EBB};
 use petgraph::graph::Graph;
 
 impl Function {
+    /// the control flow graph of the EBBs.
+    /// The weight of each node is the index of the EBB, which is also the index of the node
     pub fn cfg(&self) -> Graph<usize, ()> {
         let mut graph = Graph::new();
-        let mut queue = VecDeque::new();
-        let mut done = HashSet::new();
-        queue.push_back(0);
-        while !queue.is_empty() {
-            let ebb_idx = queue
-                .pop_front()
-                // this is safe because queue is non_empty
-                .expect("internal error");
-            done.insert(ebb_idx);
-            let node = graph.add_node(ebb_idx);
-            for &(next, _) in self.body[ebb_idx].next_ebbs().iter() {
+        let nodes = (0..self.body.len())
+            .map(|i| graph.add_node(i))
+            .collect::<Vec<_>>();
+        for (i, ebb) in self.body.iter().enumerate() {
+            for &(next, _) in ebb.next_ebbs().iter() {
                 let next_idx = self
                     .find_ebb(next)
                     // this is safe because jump target must be in the function
                     .expect("internal error");
-                if done.contains(&next_idx) {
-                    continue;
-                }
-                let next_node = graph.add_node(next_idx);
-                graph.add_edge(node, next_node, ());
-                queue.push_back(next_idx);
+                graph.update_edge(nodes[i], nodes[next_idx], ());
             }
         }
 
@@ -58,7 +48,7 @@ impl EBB {
                 forward,
                 ..
             } => vec![(target, forward)],
-            &Ret { .. } => vec![],
+            &Ret { .. } | &MultiRet { .. } | &TailCall { .. } | &Unreachable => vec![],
             _ => unreachable!(),
         }
     }
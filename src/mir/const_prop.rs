@@ -0,0 +1,401 @@
+use crate::config::Config;
+use crate::mir::*;
+use crate::pass::Pass;
+use crate::prim::*;
+use petgraph::graph::NodeIndex;
+use petgraph::visit::Dfs;
+use std::collections::{HashMap, HashSet};
+
+/// sparse conditional constant propagation over the SSA form of MIR,
+/// where the params of EBBs take the place of phis.
+/// The ops computing constants become `Lit`s, the branches on constants become jumps,
+/// and then the EBBs no longer reachable and the pure ops whose results are unused are removed.
+pub struct ConstProp {
+    wrapping_int: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Const {
+    Int(i64),
+    Char(u32),
+}
+
+/// the lattice of the values. Variables not evaluated yet are absent
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Value {
+    Const(Const),
+    Overdefined,
+}
+
+fn meet(old: Option<Value>, new: Value) -> Value {
+    match old {
+        None => new,
+        Some(old) if old == new => old,
+        Some(_) => Value::Overdefined,
+    }
+}
+
+struct Analysis<'a> {
+    fun: &'a Function,
+    values: HashMap<&'a Symbol, Value>,
+    executable: HashSet<usize>,
+    tuples: HashMap<&'a Symbol, &'a [Symbol]>,
+    changed: bool,
+}
+
+impl<'a> Analysis<'a> {
+    fn new(fun: &'a Function) -> Self {
+        let tuples = fun
+            .body
+            .iter()
+            .flat_map(|ebb| ebb.body.iter())
+            .filter_map(|op| match op {
+                Op::Tuple { var, tuple, .. } => Some((var, tuple.as_slice())),
+                _ => None,
+            })
+            .collect();
+        Analysis {
+            fun,
+            values: HashMap::new(),
+            executable: HashSet::new(),
+            tuples,
+            changed: false,
+        }
+    }
+
+    fn run(mut self) -> (HashMap<&'a Symbol, Value>, HashSet<usize>) {
+        let fun = self.fun;
+        // the params of the function can be anything
+        for (_, param) in fun.body[0].params.iter() {
+            self.update(param, Value::Overdefined);
+        }
+        self.executable.insert(0);
+        loop {
+            self.changed = false;
+            for (i, ebb) in fun.body.iter().enumerate() {
+                if self.executable.contains(&i) {
+                    self.visit_ebb(ebb);
+                }
+            }
+            if !self.changed {
+                return (self.values, self.executable);
+            }
+        }
+    }
+
+    fn update(&mut self, var: &'a Symbol, value: Value) {
+        let old = self.values.get(var).cloned();
+        let new = meet(old, value);
+        if old != Some(new) {
+            self.values.insert(var, new);
+            self.changed = true;
+        }
+    }
+
+    fn mark(&mut self, target: &Symbol) -> &'a EBB {
+        let fun = self.fun;
+        let idx = fun.find_ebb(target).expect("internal error");
+        if self.executable.insert(idx) {
+            self.changed = true;
+        }
+        &fun.body[idx]
+    }
+
+    fn value(&self, var: &Symbol) -> Option<Value> {
+        self.values.get(var).cloned()
+    }
+
+    fn visit_ebb(&mut self, ebb: &'a EBB) {
+        for op in ebb.body.iter() {
+            match op {
+                Op::Jump { target, args, .. } => {
+                    let target = self.mark(target);
+                    for ((_, param), arg) in target.params.iter().zip(args) {
+                        if let Some(value) = self.value(arg) {
+                            self.update(param, value)
+                        }
+                    }
+                }
+                Op::Branch {
+                    cond,
+                    clauses,
+                    default,
+                } => {
+                    let key = match self.value(cond) {
+                        None => continue,
+                        Some(Value::Const(Const::Int(i))) => Some(i as u32),
+                        Some(Value::Const(Const::Char(c))) => Some(c),
+                        Some(Value::Overdefined) => None,
+                    };
+                    let mut matched = false;
+                    for (k, label, _) in clauses.iter() {
+                        if key.map(|key| key == *k).unwrap_or(true) {
+                            self.mark(label);
+                            matched = true;
+                        }
+                    }
+                    if let Some((label, _)) = default {
+                        if key.is_none() || !matched {
+                            let target = self.mark(label);
+                            let value = self.value(cond).expect("internal error");
+                            self.update(&target.params[0].1, value);
+                        }
+                    }
+                }
+                Op::MultiCall { vars, .. } => {
+                    for var in vars {
+                        self.update(var, Value::Overdefined)
+                    }
+                }
+                op => {
+                    if let Some(var) = op.var() {
+                        if let Some(value) = self.eval(op) {
+                            self.update(var, value)
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// the value of the var `op` defines. `None` if the value of an operand is not known yet
+    fn eval(&self, op: &Op) -> Option<Value> {
+        use self::Const::*;
+        use self::Value::*;
+        let ret = match op {
+            Op::Lit {
+                value: Literal::Int(i),
+                ..
+            } => Const(Int(*i)),
+            Op::Lit {
+                value: Literal::Char(c),
+                ..
+            } => Const(Char(*c)),
+            Op::Alias { sym, .. } => self.value(sym)?,
+            Op::Add { ty, l, r, .. }
+            | Op::Sub { ty, l, r, .. }
+            | Op::Mul { ty, l, r, .. }
+            | Op::DivInt { ty, l, r, .. }
+            | Op::Mod { ty, l, r, .. } => match (self.value(l)?, self.value(r)?) {
+                (Const(Int(l)), Const(Int(r))) if *ty == EbbTy::Int => {
+                    // int is 32 bits. Leave the overflowing ones to raise `Overflow`
+                    let (l, r) = (l as i32, r as i32);
+                    let value = match op {
+                        Op::Add { .. } => l.checked_add(r),
+                        Op::Sub { .. } => l.checked_sub(r),
+                        Op::Mul { .. } => l.checked_mul(r),
+                        // where the rounding of the quotient doesn't matter
+                        Op::DivInt { .. } if 0 <= l && 0 < r => Some(l / r),
+                        Op::Mod { .. } if 0 <= l && 0 < r => Some(l % r),
+                        _ => None,
+                    };
+                    value
+                        .map(|value| Const(Int(value as i64)))
+                        .unwrap_or(Overdefined)
+                }
+                _ => Overdefined,
+            },
+            Op::Eq { l, r, .. }
+            | Op::Neq { l, r, .. }
+            | Op::Gt { l, r, .. }
+            | Op::Ge { l, r, .. }
+            | Op::Lt { l, r, .. }
+            | Op::Le { l, r, .. } => {
+                let (l, r) = match (self.value(l)?, self.value(r)?) {
+                    (Const(Int(l)), Const(Int(r))) => (l, r),
+                    (Const(Char(l)), Const(Char(r))) => (l as i64, r as i64),
+                    _ => return Some(Overdefined),
+                };
+                let value = match op {
+                    Op::Eq { .. } => l == r,
+                    Op::Neq { .. } => l != r,
+                    Op::Gt { .. } => l > r,
+                    Op::Ge { .. } => l >= r,
+                    Op::Lt { .. } => l < r,
+                    _ => l <= r,
+                };
+                Const(Int(value as i64))
+            }
+            Op::Proj { index, tuple, .. } => match self.tuples.get(tuple) {
+                Some(elems) => self.value(&elems[*index as usize])?,
+                None => Overdefined,
+            },
+            _ => Overdefined,
+        };
+        Some(ret)
+    }
+}
+
+impl ConstProp {
+    pub fn new() -> Self {
+        ConstProp {
+            wrapping_int: false,
+        }
+    }
+
+    fn conv_mir(&mut self, mir: MIR) -> MIR {
+        MIR(mir.0.into_iter().map(|f| self.conv_fun(f)).collect())
+    }
+
+    fn conv_fun(&mut self, mut fun: Function) -> Function {
+        let (values, executable) = Analysis::new(&fun).run();
+        let values = values
+            .into_iter()
+            .filter_map(|(var, value)| match value {
+                Value::Const(c) => Some((var.clone(), c)),
+                Value::Overdefined => None,
+            })
+            .collect::<HashMap<_, _>>();
+        let executable = executable
+            .into_iter()
+            .map(|i| fun.body[i].name.clone())
+            .collect::<HashSet<_>>();
+
+        for ebb in fun.body.iter_mut() {
+            if executable.contains(&ebb.name) {
+                for op in ebb.body.iter_mut() {
+                    fold(op, &values);
+                }
+            }
+        }
+        remove_unreachable_ebbs(&mut fun);
+        self.remove_dead_ops(&mut fun);
+        fun
+    }
+
+    /// removes the ops without side effects whose results are not used, until none are left
+    fn remove_dead_ops(&self, fun: &mut Function) {
+        loop {
+            let mut used = HashSet::new();
+            for op in fun.body.iter().flat_map(|ebb| ebb.body.iter()) {
+                used.extend(op.operands().into_iter().cloned());
+            }
+            let mut removed = false;
+            for ebb in fun.body.iter_mut() {
+                let len = ebb.body.len();
+                ebb.body.retain(|op| match op.var() {
+                    Some(var) => used.contains(var) || !self.is_pure(op),
+                    None => true,
+                });
+                removed |= ebb.body.len() != len;
+            }
+            if !removed {
+                return;
+            }
+        }
+    }
+
+    /// whether the op can be removed if its result is unused
+    fn is_pure(&self, op: &Op) -> bool {
+        use crate::mir::Op::*;
+        match op {
+            Add { ty, .. } | Sub { ty, .. } | Mul { ty, .. } => {
+                // may raise Overflow
+                *ty != EbbTy::Int || self.wrapping_int
+            }
+            Lit { .. }
+            | Alias { .. }
+            | DivFloat { .. }
+            | Eq { .. }
+            | Neq { .. }
+            | Gt { .. }
+            | Ge { .. }
+            | Lt { .. }
+            | Le { .. }
+            | IntToFloat { .. }
+            | RoundFloat { .. }
+            | Sqrt { .. }
+            | StringSize { .. }
+            | ArrayLength { .. }
+            | Closure { .. }
+            | Tuple { .. }
+            | Proj { .. }
+            | Union { .. }
+            | Select { .. }
+            | GlobalGet { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+/// replaces `op` with the constant it computes and the branch on a constant with a jump
+fn fold(op: &mut Op, values: &HashMap<Symbol, Const>) {
+    let folded = match op {
+        Op::Alias { var, ty, .. }
+        | Op::Add { var, ty, .. }
+        | Op::Sub { var, ty, .. }
+        | Op::Mul { var, ty, .. }
+        | Op::DivInt { var, ty, .. }
+        | Op::Mod { var, ty, .. }
+        | Op::Eq { var, ty, .. }
+        | Op::Neq { var, ty, .. }
+        | Op::Gt { var, ty, .. }
+        | Op::Ge { var, ty, .. }
+        | Op::Lt { var, ty, .. }
+        | Op::Le { var, ty, .. }
+        | Op::Proj { var, ty, .. } => values.get(var).map(|c| Op::Lit {
+            var: var.clone(),
+            ty: ty.clone(),
+            value: match *c {
+                Const::Int(i) => Literal::Int(i),
+                Const::Char(c) => Literal::Char(c),
+            },
+        }),
+        Op::Branch {
+            cond,
+            clauses,
+            default,
+        } => values.get(cond).map(|c| {
+            let key = match *c {
+                Const::Int(i) => i as u32,
+                Const::Char(c) => c,
+            };
+            match clauses.iter().find(|(k, _, _)| *k == key) {
+                Some((_, label, forward)) => Op::Jump {
+                    target: label.clone(),
+                    forward: *forward,
+                    args: vec![],
+                },
+                None => {
+                    let (label, forward) = default.clone().expect("internal error");
+                    Op::Jump {
+                        target: label,
+                        forward,
+                        args: vec![cond.clone()],
+                    }
+                }
+            }
+        }),
+        _ => None,
+    };
+    if let Some(folded) = folded {
+        *op = folded;
+    }
+}
+
+fn remove_unreachable_ebbs(fun: &mut Function) {
+    let cfg = fun.cfg();
+    let mut reachable = HashSet::new();
+    let mut dfs = Dfs::new(&cfg, NodeIndex::new(0));
+    while let Some(node) = dfs.next(&cfg) {
+        reachable.insert(cfg[node]);
+    }
+    let mut i = 0;
+    fun.body.retain(|_| {
+        i += 1;
+        reachable.contains(&(i - 1))
+    });
+}
+
+impl<E> Pass<Context, E> for ConstProp {
+    type Target = Context;
+
+    fn trans(
+        &mut self,
+        Context(symbol_table, mir): Context,
+        config: &Config,
+    ) -> ::std::result::Result<Self::Target, E> {
+        self.wrapping_int = config.wrapping_int;
+        Ok(Context(symbol_table, self.conv_mir(mir)))
+    }
+}
@@ -0,0 +1,32 @@
+use crate::prim::*;
+use std::collections::HashSet;
+
+use super::{Function, Op};
+
+impl Function {
+    /// the tuples and closures made in the function which don't outlive it.
+    /// They are only projected or called, and not passed anywhere,
+    /// so they can be allocated in the frame of the function.
+    /// Tail calls leave the frame before the callee runs, so the closures called in tail position escape
+    pub fn non_escaping(&self) -> HashSet<Symbol> {
+        let ops = || self.body.iter().flat_map(|ebb| ebb.body.iter());
+        let mut allocated = ops()
+            .filter_map(|op| match op {
+                Op::Tuple { var, .. } | Op::Closure { var, .. } => Some(var.clone()),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+        for op in ops() {
+            let escaping = match op {
+                Op::Proj { .. } => continue,
+                // the callee gets the env, which it only projects
+                Op::Call { args, .. } | Op::MultiCall { args, .. } => args.iter().collect(),
+                op => op.operands(),
+            };
+            for var in escaping {
+                allocated.remove(var);
+            }
+        }
+        allocated
+    }
+}
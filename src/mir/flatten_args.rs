@@ -0,0 +1,227 @@
+use crate::config::Config;
+use crate::id::Id;
+use crate::mir::*;
+use crate::pass::Pass;
+use crate::prim::*;
+use std::collections::{HashMap, HashSet};
+
+/// passes the elements of the tuple params as separate params
+/// so that the callers don't allocate the tuples.
+/// Only the params used by `Proj`s are flattened, and the functions of closures
+/// keep their signatures. Their closure wrappers, which take the tuples, work as the wrappers.
+pub struct FlattenArgs {
+    id: Id,
+}
+
+/// tuples larger than this are passed as they are
+const MAX_ELEMENTS: usize = 8;
+
+impl FlattenArgs {
+    pub fn new(id: Id) -> Self {
+        FlattenArgs { id }
+    }
+
+    fn gensym(&mut self, name: &str) -> Symbol {
+        Symbol(name.to_string(), self.id.next())
+    }
+
+    fn conv_mir(&mut self, mut mir: MIR) -> MIR {
+        let targets = mir
+            .0
+            .iter()
+            .flat_map(|f| f.body.iter().flat_map(|ebb| ebb.body.iter()))
+            .filter_map(|op| match op {
+                Op::Closure { fun, .. } => Some(fun.clone()),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+
+        // the element types of each param, if flattened
+        let mut layouts = HashMap::new();
+        for f in mir.0.iter_mut() {
+            if targets.contains(&f.name) {
+                continue;
+            }
+            if let Some(layout) = self.flatten_params(f) {
+                layouts.insert(f.name.clone(), layout);
+            }
+        }
+        if layouts.is_empty() {
+            return mir;
+        }
+        for f in mir.0.iter_mut() {
+            self.flatten_args(f, &layouts);
+        }
+        mir
+    }
+
+    fn flatten_params(&mut self, f: &mut Function) -> Option<Vec<Option<Vec<EbbTy>>>> {
+        let mut uses = HashMap::new();
+        let mut projs = HashMap::new();
+        for op in f.body.iter().flat_map(|ebb| ebb.body.iter()) {
+            for var in op.operands() {
+                *uses.entry(var.clone()).or_insert(0) += 1;
+            }
+            if let Op::Proj { tuple, .. } = op {
+                *projs.entry(tuple.clone()).or_insert(0) += 1;
+            }
+        }
+        let layout = f.body[0]
+            .params
+            .iter()
+            .map(|(ty, param)| match ty {
+                EbbTy::Tuple(tys)
+                    if tys.len() <= MAX_ELEMENTS && uses.get(param) == projs.get(param) =>
+                {
+                    Some(tys.clone())
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let flattened = f.body[0]
+            .params
+            .iter()
+            .zip(&layout)
+            .filter(|(_, tys)| tys.is_some())
+            .map(|((_, param), _)| param.clone())
+            .collect::<HashSet<_>>();
+        if flattened.is_empty() {
+            return None;
+        }
+
+        // the element of each flattened param. The first projection of each element becomes the param
+        let mut elements = HashMap::new();
+        for ebb in f.body.iter_mut() {
+            ebb.body.retain(|op| match op {
+                Op::Proj {
+                    var, index, tuple, ..
+                } if flattened.contains(tuple) => {
+                    let key = (tuple.clone(), *index);
+                    if elements.contains_key(&key) {
+                        true
+                    } else {
+                        elements.insert(key, var.clone());
+                        false
+                    }
+                }
+                _ => true,
+            });
+        }
+        let mut params = Vec::new();
+        let old_params = std::mem::replace(&mut f.body[0].params, Vec::new());
+        for ((ty, param), tys) in old_params.into_iter().zip(&layout) {
+            let tys = match tys {
+                Some(tys) => tys,
+                None => {
+                    params.push((ty, param));
+                    continue;
+                }
+            };
+            for (i, ty) in tys.iter().enumerate() {
+                let key = (param.clone(), i as u32);
+                let element = match elements.get(&key) {
+                    Some(var) => var.clone(),
+                    None => {
+                        let var = self.gensym("element");
+                        elements.insert(key, var.clone());
+                        var
+                    }
+                };
+                params.push((ty.clone(), element));
+            }
+        }
+        f.body[0].params = params;
+
+        // the rest projections of the same elements
+        for op in f.body.iter_mut().flat_map(|ebb| ebb.body.iter_mut()) {
+            let alias = match op {
+                Op::Proj {
+                    var,
+                    ty,
+                    index,
+                    tuple,
+                } => match elements.get(&(tuple.clone(), *index)) {
+                    Some(element) => Op::Alias {
+                        var: var.clone(),
+                        ty: ty.clone(),
+                        sym: element.clone(),
+                    },
+                    None => continue,
+                },
+                _ => continue,
+            };
+            *op = alias;
+        }
+        Some(layout)
+    }
+
+    /// passes the elements of the tuples to the functions flattened.
+    /// The tuples made in `f` are passed as their elements, and the others are projected
+    fn flatten_args(
+        &mut self,
+        f: &mut Function,
+        layouts: &HashMap<Symbol, Vec<Option<Vec<EbbTy>>>>,
+    ) {
+        let tuples = f
+            .body
+            .iter()
+            .flat_map(|ebb| ebb.body.iter())
+            .filter_map(|op| match op {
+                Op::Tuple { var, tuple, .. } => Some((var.clone(), tuple.clone())),
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+        for ebb in f.body.iter_mut() {
+            let mut body = Vec::new();
+            for mut op in ebb.body.drain(..) {
+                match &mut op {
+                    Op::Call { fun, args, .. } | Op::TailCall { fun, args, .. } => {
+                        if let Some(layout) = layouts.get(fun) {
+                            let mut new_args = Vec::new();
+                            for (arg, tys) in args.drain(..).zip(layout) {
+                                let tys = match tys {
+                                    Some(tys) => tys,
+                                    None => {
+                                        new_args.push(arg);
+                                        continue;
+                                    }
+                                };
+                                match tuples.get(&arg) {
+                                    Some(elements) => new_args.extend(elements.iter().cloned()),
+                                    None => {
+                                        for (i, ty) in tys.iter().enumerate() {
+                                            let var = self.gensym("element");
+                                            body.push(Op::Proj {
+                                                var: var.clone(),
+                                                ty: ty.clone(),
+                                                index: i as u32,
+                                                tuple: arg.clone(),
+                                            });
+                                            new_args.push(var);
+                                        }
+                                    }
+                                }
+                            }
+                            *args = new_args;
+                        }
+                    }
+                    _ => (),
+                }
+                body.push(op);
+            }
+            ebb.body = body;
+        }
+    }
+}
+
+impl<E> Pass<Context, E> for FlattenArgs {
+    type Target = Context;
+
+    fn trans(
+        &mut self,
+        Context(symbol_table, mir): Context,
+        _: &Config,
+    ) -> ::std::result::Result<Self::Target, E> {
+        Ok(Context(symbol_table, self.conv_mir(mir)))
+    }
+}
@@ -1,11 +1,12 @@
 use super::builder::*;
 use crate::config::Config;
 use crate::hir;
+use crate::hir::util::Traverse;
 use crate::id::Id;
 use crate::mir::*;
 use crate::pass::Pass;
 use crate::prim::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 pub struct HIR2MIR {
     id: Id,
@@ -25,6 +26,8 @@ struct HIR2MIRPass {
     label: u64,
     id: Id,
     closure_wrapper: HashMap<Symbol, (Symbol, EbbTy, EbbTy)>,
+    /// the toplevel values read by the functions, with their slots in the globals
+    globals: HashMap<Symbol, (u32, EbbTy)>,
     symbol_table: hir::SymbolTable,
 }
 
@@ -34,6 +37,7 @@ impl HIR2MIRPass {
             id,
             label: 0,
             closure_wrapper: HashMap::new(),
+            globals: HashMap::new(),
             symbol_table,
         }
     }
@@ -79,6 +83,11 @@ impl HIR2MIRPass {
             Char => EbbTy::Char,
             Int => EbbTy::Int,
             Real => EbbTy::Float,
+            String => EbbTy::String,
+            Int64 => EbbTy::Int64,
+            Word64 => EbbTy::Word64,
+            Real32 => EbbTy::Float32,
+            Array(elem) => EbbTy::Array(Box::new(self.trans_ty(elem))),
             Tuple(tys) => match tys.len() {
                 0 => EbbTy::Unit,
                 // TODO: treat 1-tuple as inner type
@@ -89,7 +98,11 @@ impl HIR2MIRPass {
                 param: Box::new(self.trans_ty(&*arg)),
                 ret: Box::new(self.trans_ty(&*ret)),
             },
-            Datatype(name) => EbbTy::Variable(name.clone()),
+            Datatype(name, args) => {
+                assert!(args.is_empty(), "datatypes must be monomorphized");
+                EbbTy::Variable(name.clone())
+            }
+            Variable(id) => panic!("internal error: type variable '{} is left", id),
         }
     }
 
@@ -123,14 +136,39 @@ impl HIR2MIRPass {
         wrapper_name.0.clone()
     }
 
-    fn trans_hir(&mut self, hir: hir::HIR) -> MIR {
+    /// the toplevel values are local to sml-main.
+    /// Those the other functions refer to are given the slots of the globals
+    fn find_globals(&self, hir: &mut hir::HIR) -> HashMap<Symbol, (u32, EbbTy)> {
+        let mut refs = Refs(HashSet::new());
+        for val in hir.0.iter_mut() {
+            if let hir::Expr::Fun { .. } = val.expr {
+                refs.traverse_val(val);
+            }
+        }
+        hir.0
+            .iter()
+            .filter(|val| match val.expr {
+                hir::Expr::Fun { .. } => false,
+                _ => refs.0.contains(&val.name),
+            })
+            .enumerate()
+            .map(|(i, val)| (val.name.clone(), (i as u32, self.trans_ty(&val.ty))))
+            .collect()
+    }
+
+    fn trans_hir(&mut self, mut hir: hir::HIR) -> MIR {
+        self.globals = self.find_globals(&mut hir);
         // TODO: make anonymous
         let mut mainbuilder = FunctionBuilder::new(Symbol::new("sml-main"), EbbTy::Unit);
         let mut mainebuilder = EBBBuilder::new(self.genlabel("entry"), Vec::new());
         let mut funs = Vec::new();
 
         for val in hir.0.into_iter() {
+            let name = val.name.clone();
             mainebuilder = self.trans_val(&mut funs, &mut mainbuilder, mainebuilder, val);
+            if let Some(&(index, _)) = self.globals.get(&name) {
+                mainebuilder.global_set(index, name);
+            }
         }
         for (fname, (wrapper_name, param_ty, ret_ty)) in self.closure_wrapper.clone().into_iter() {
             self.make_wrapper(
@@ -190,7 +228,7 @@ impl HIR2MIRPass {
         } = val;
         match expr {
             Fun {
-                body,
+                mut body,
                 param,
                 body_ty,
                 captures,
@@ -216,6 +254,20 @@ impl HIR2MIRPass {
                     // make pure function
                     eb_ = EBBBuilder::new(Symbol::new("entry"), vec![param]);
                 }
+                let mut refs = Refs(HashSet::new());
+                refs.traverse_expr(&mut body);
+                let mut globals = refs
+                    .0
+                    .into_iter()
+                    .filter_map(|name| {
+                        let (index, ty) = self.globals.get(&name)?.clone();
+                        Some((index, ty, name))
+                    })
+                    .collect::<Vec<_>>();
+                globals.sort_by_key(|&(index, _, _)| index);
+                for (index, ty, name) in globals {
+                    eb_.global_get(name, ty, index);
+                }
                 let mut fb = FunctionBuilder::new(name, self.trans_ty(&body_ty));
                 let ebb = self.trans_expr(&mut fb, eb_, body_ty, *body);
                 fb.add_ebb(ebb);
@@ -253,6 +305,43 @@ impl HIR2MIRPass {
                     Ge => eb.ge(name, self.trans_ty(&ty), pop!(), pop!()),
                     Lt => eb.lt(name, self.trans_ty(&ty), pop!(), pop!()),
                     Le => eb.le(name, self.trans_ty(&ty), pop!(), pop!()),
+                    Ord | Chr => eb.alias(name, self.trans_ty(&ty), pop!()),
+                    ToReal => eb.int_to_float(name, self.trans_ty(&ty), pop!()),
+                    Floor => eb.float_to_int(name, self.trans_ty(&ty), Rounding::Floor, pop!()),
+                    Ceil => eb.float_to_int(name, self.trans_ty(&ty), Rounding::Ceil, pop!()),
+                    Round => eb.float_to_int(name, self.trans_ty(&ty), Rounding::Nearest, pop!()),
+                    Trunc => eb.float_to_int(name, self.trans_ty(&ty), Rounding::Trunc, pop!()),
+                    StringSize => eb.string_size(name, self.trans_ty(&ty), pop!()),
+                    StringSub => eb.string_sub(name, self.trans_ty(&ty), pop!(), pop!()),
+                    StringAlloc => eb.string_alloc(name, self.trans_ty(&ty), pop!()),
+                    StringUpdate => {
+                        eb.string_update(name, self.trans_ty(&ty), pop!(), pop!(), pop!())
+                    }
+                    ArrayAlloc => eb.array_alloc(name, self.trans_ty(&ty), pop!()),
+                    ArrayLength => eb.array_length(name, self.trans_ty(&ty), pop!()),
+                    ArraySub => eb.array_sub(name, self.trans_ty(&ty), pop!(), pop!()),
+                    ArrayUpdate => {
+                        eb.array_update(name, self.trans_ty(&ty), pop!(), pop!(), pop!())
+                    }
+                    ArrayToVector | VectorToArray => eb.alias(name, self.trans_ty(&ty), pop!()),
+                    IntToInt64 | Int64ToInt | Int64ToWord64 | Word64ToInt64 | RealToReal32
+                    | Real32ToReal => eb.convert(name, self.trans_ty(&ty), pop!()),
+                    Sqrt => eb.sqrt(name, self.trans_ty(&ty), pop!()),
+                    RealFloor => eb.round_float(name, self.trans_ty(&ty), Rounding::Floor, pop!()),
+                    RealCeil => eb.round_float(name, self.trans_ty(&ty), Rounding::Ceil, pop!()),
+                    RealRound => {
+                        eb.round_float(name, self.trans_ty(&ty), Rounding::Nearest, pop!())
+                    }
+                    RealTrunc => eb.round_float(name, self.trans_ty(&ty), Rounding::Trunc, pop!()),
+                    Raise => {
+                        // exceptions are not catchable yet, so raising aborts the program.
+                        // The rest of the code goes to a fresh EBB with no predecessors,
+                        // which BlockArrange will drop.
+                        let ebb = eb.unreachable();
+                        fb.add_ebb(ebb);
+                        let label = self.genlabel("after_raise");
+                        return EBBBuilder::new(label, vec![(self.trans_ty(&ty), name)]);
+                    }
                 };
                 eb
             }
@@ -318,7 +407,7 @@ impl HIR2MIRPass {
                     hir::HTy::Tuple(tys) => {
                         MatchTy::Tuple(tys.into_iter().map(|ty| self.trans_ty(&ty)).collect())
                     }
-                    hir::HTy::Datatype(name) => MatchTy::Datatype(
+                    hir::HTy::Datatype(name, _) => MatchTy::Datatype(
                         self.symbol_table.types[&name]
                             .constructors
                             .iter()
@@ -546,6 +635,25 @@ impl HIR2MIRPass {
     }
 }
 
+/// the symbols an expression refers to
+struct Refs(HashSet<Symbol>);
+
+impl Traverse for Refs {
+    fn traverse_closure(
+        &mut self,
+        envs: &mut Vec<(hir::HTy, Symbol)>,
+        _param_ty: &mut hir::HTy,
+        _body_ty: &mut hir::HTy,
+        _fname: &mut Symbol,
+    ) {
+        self.0.extend(envs.iter().map(|(_, var)| var.clone()));
+    }
+
+    fn traverse_sym(&mut self, _ty: &mut hir::HTy, name: &mut Symbol) {
+        self.0.insert(name.clone());
+    }
+}
+
 fn force_symbol(e: hir::Expr) -> Symbol {
     match e {
         hir::Expr::Sym { name, .. } => name,
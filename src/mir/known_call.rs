@@ -0,0 +1,276 @@
+use super::builder::*;
+use crate::config::Config;
+use crate::id::Id;
+use crate::mir::*;
+use crate::pass::Pass;
+use crate::prim::*;
+use std::collections::{HashMap, HashSet};
+
+/// calls the closures made in the same function directly.
+/// The functions of the closures take the captured variables as params instead of the env,
+/// so the closures are made, of their wrappers, only where they escape.
+pub struct KnownCall {
+    id: Id,
+}
+
+impl KnownCall {
+    pub fn new(id: Id) -> Self {
+        KnownCall { id }
+    }
+
+    fn gensym(&mut self, name: &str) -> Symbol {
+        Symbol(name.to_string(), self.id.next())
+    }
+
+    pub fn conv_mir(&mut self, mut mir: MIR) -> MIR {
+        let mut called = HashSet::new();
+        let mut targets = HashSet::new();
+        for op in mir
+            .0
+            .iter()
+            .flat_map(|f| f.body.iter().flat_map(|ebb| ebb.body.iter()))
+        {
+            match op {
+                Op::Call { fun, .. } | Op::TailCall { fun, .. } => {
+                    called.insert(fun.clone());
+                }
+                Op::Closure { fun, .. } => {
+                    targets.insert(fun.clone());
+                }
+                _ => (),
+            }
+        }
+
+        // the functions to call with the captures instead of the closures
+        let mut directs = HashMap::new();
+        let mut wrappers = HashSet::new();
+        // the functions now taking the captures, with the types of the env
+        let mut workers = HashMap::new();
+        for f in mir.0.iter_mut() {
+            // the signature can be changed only if nothing calls it by name
+            if !targets.contains(&f.name) || called.contains(&f.name) {
+                continue;
+            }
+            if let Some(fun) = wrapped_function(f) {
+                directs.insert(f.name.clone(), fun);
+                wrappers.insert(f.name.clone());
+            } else if let Some(tys) = take_captures(f) {
+                directs.insert(f.name.clone(), f.name.clone());
+                workers.insert(f.name.clone(), tys);
+            }
+        }
+
+        for f in mir.0.iter_mut() {
+            call_directly(f, &directs);
+        }
+
+        // the closures left escape. Make them of the wrappers taking the env
+        let mut escaping = HashSet::new();
+        for op in mir
+            .0
+            .iter()
+            .flat_map(|f| f.body.iter().flat_map(|ebb| ebb.body.iter()))
+        {
+            if let Op::Closure { fun, .. } = op {
+                escaping.insert(fun.clone());
+            }
+        }
+        let mut new_wrappers = HashMap::new();
+        let mut funs = Vec::new();
+        for f in mir.0.into_iter() {
+            if escaping.contains(&f.name) {
+                if let Some(tys) = workers.get(&f.name) {
+                    let wrapper = self.make_wrapper(&f, tys.clone());
+                    new_wrappers.insert(f.name.clone(), wrapper.name.clone());
+                    funs.push(wrapper);
+                }
+            } else if wrappers.contains(&f.name) {
+                // no longer used
+                continue;
+            }
+            funs.push(f);
+        }
+        for op in funs
+            .iter_mut()
+            .flat_map(|f| f.body.iter_mut().flat_map(|ebb| ebb.body.iter_mut()))
+        {
+            if let Op::Closure { fun, .. } = op {
+                if let Some(wrapper) = new_wrappers.get(fun) {
+                    *fun = wrapper.clone();
+                }
+            }
+        }
+        MIR(funs)
+    }
+
+    /// the function taking the env, which calls `worker` with the captures in the env
+    fn make_wrapper(&mut self, worker: &Function, env_tys: Vec<EbbTy>) -> Function {
+        let name = Symbol(format!("{}_closure_wrapper", worker.name.0), self.id.next());
+        let env = self.gensym("env");
+        let mut params = vec![(EbbTy::Tuple(env_tys.clone()), env.clone())];
+        let mut args = Vec::new();
+        let mut projs = Vec::new();
+        for (i, ty) in env_tys.into_iter().enumerate() {
+            let capture = self.gensym("capture");
+            projs.push((capture.clone(), ty, i as u32));
+            args.push(capture);
+        }
+        for (ty, _) in worker.body[0].params[args.len()..].iter() {
+            let param = self.gensym("param");
+            params.push((ty.clone(), param.clone()));
+            args.push(param);
+        }
+
+        let ret_ty = worker.body_ty.clone();
+        let mut fb = FunctionBuilder::new(name, ret_ty.clone());
+        let mut eb = EBBBuilder::new(Symbol::new("entry"), params);
+        for (capture, ty, index) in projs {
+            eb.proj(capture, ty, index, env.clone());
+        }
+        let ret = self.gensym("ret");
+        eb.call(ret.clone(), ret_ty.clone(), worker.name.clone(), args);
+        fb.add_ebb(eb.ret(ret, ret_ty));
+        fb.build()
+    }
+}
+
+/// the function `f` calls with the captures in the env and the params as they are,
+/// if `f` is a closure wrapper such as the ones `HIR2MIR` and this pass make
+fn wrapped_function(f: &Function) -> Option<Symbol> {
+    if f.body.len() != 1 {
+        return None;
+    }
+    let entry = &f.body[0];
+    let (tys, env) = match entry.params.first() {
+        Some((EbbTy::Tuple(tys), env)) => (tys, env),
+        _ => return None,
+    };
+    let n = tys.len();
+    if entry.body.len() != n + 2 {
+        return None;
+    }
+    let mut forwarded = Vec::new();
+    for (i, op) in entry.body[0..n].iter().enumerate() {
+        match op {
+            Op::Proj {
+                var, index, tuple, ..
+            } if *index as usize == i && tuple == env => forwarded.push(var),
+            _ => return None,
+        }
+    }
+    forwarded.extend(entry.params[1..].iter().map(|(_, param)| param));
+    match &entry.body[n..] {
+        [Op::Call { var, fun, args, .. }, Op::Ret {
+            value: Some(value), ..
+        }] if var == value && args.iter().eq(forwarded.into_iter()) => Some(fun.clone()),
+        _ => None,
+    }
+}
+
+/// makes the function of closures take the captured variables as params
+/// instead of projecting them out of the env.
+/// Returns the types of the env if it could
+fn take_captures(f: &mut Function) -> Option<Vec<EbbTy>> {
+    let (tys, env) = match f.body[0].params.first() {
+        Some((EbbTy::Tuple(tys), env)) => (tys.clone(), env.clone()),
+        _ => return None,
+    };
+    let entry = &f.body[0];
+    if entry.body.len() < tys.len() {
+        return None;
+    }
+    let mut captures = Vec::new();
+    for (i, op) in entry.body[0..tys.len()].iter().enumerate() {
+        match op {
+            Op::Proj {
+                var,
+                ty,
+                index,
+                tuple,
+            } if *index as usize == i && tuple == &env => captures.push((ty.clone(), var.clone())),
+            _ => return None,
+        }
+    }
+    // the env must not be used other than the projections
+    let uses = f
+        .body
+        .iter()
+        .flat_map(|ebb| ebb.body.iter())
+        .flat_map(|op| op.operands())
+        .filter(|var| *var == &env)
+        .count();
+    if uses != tys.len() {
+        return None;
+    }
+
+    let entry = &mut f.body[0];
+    entry.body.drain(0..tys.len());
+    entry.params.remove(0);
+    captures.append(&mut entry.params);
+    entry.params = captures;
+    Some(tys)
+}
+
+/// replaces the calls to the closures made in `f` with the calls to their functions
+/// and removes the closures no longer used
+fn call_directly(f: &mut Function, directs: &HashMap<Symbol, Symbol>) {
+    let mut closures = HashMap::new();
+    for op in f.body.iter().flat_map(|ebb| ebb.body.iter()) {
+        if let Op::Closure { var, fun, env, .. } = op {
+            if let Some(direct) = directs.get(fun) {
+                let captures = env.iter().map(|(_, var)| var.clone()).collect::<Vec<_>>();
+                closures.insert(var.clone(), (direct.clone(), captures));
+            }
+        }
+    }
+    if closures.is_empty() {
+        return;
+    }
+
+    for op in f.body.iter_mut().flat_map(|ebb| ebb.body.iter_mut()) {
+        match op {
+            Op::Call { fun, args, .. } | Op::TailCall { fun, args, .. } => {
+                if let Some((direct, captures)) = closures.get(fun) {
+                    *fun = direct.clone();
+                    args.splice(0..0, captures.iter().cloned());
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // the closures may capture each other
+    loop {
+        let used = f
+            .body
+            .iter()
+            .flat_map(|ebb| ebb.body.iter())
+            .flat_map(|op| op.operands())
+            .cloned()
+            .collect::<HashSet<_>>();
+        let mut removed = false;
+        for ebb in f.body.iter_mut() {
+            let len = ebb.body.len();
+            ebb.body.retain(|op| match op {
+                Op::Closure { var, .. } => used.contains(var),
+                _ => true,
+            });
+            removed |= ebb.body.len() != len;
+        }
+        if !removed {
+            return;
+        }
+    }
+}
+
+impl<E> Pass<Context, E> for KnownCall {
+    type Target = Context;
+
+    fn trans(
+        &mut self,
+        Context(symbol_table, mir): Context,
+        _: &Config,
+    ) -> ::std::result::Result<Self::Target, E> {
+        Ok(Context(symbol_table, self.conv_mir(mir)))
+    }
+}
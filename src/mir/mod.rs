@@ -1,13 +1,26 @@
 mod block_arrange;
 mod builder;
 pub mod cfg;
+mod const_prop;
+mod escape;
+mod flatten_args;
 mod hir2mir;
+mod known_call;
 pub mod pp;
+mod tail_call;
 mod unalias;
+mod unbox_returns;
+mod uncurry;
 
 pub use self::block_arrange::BlockArrange;
+pub use self::const_prop::ConstProp;
+pub use self::flatten_args::FlattenArgs;
 pub use self::hir2mir::HIR2MIR;
+pub use self::known_call::KnownCall;
+pub use self::tail_call::TailCall;
 pub use self::unalias::UnAlias;
+pub use self::unbox_returns::UnboxReturns;
+pub use self::uncurry::Uncurry;
 use crate::prim::*;
 use std::collections::HashMap;
 
@@ -24,6 +37,8 @@ pub struct Function {
     // pub params_ty: Vec<EbbTy>,
     pub body: Vec<EBB>,
     pub body_ty: EbbTy,
+    /// returns the elements of the tuple `body_ty` as separate values, with `Op::MultiRet`
+    pub multi_value: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -117,6 +132,83 @@ pub enum Op {
         l: Symbol,
         r: Symbol,
     },
+    IntToFloat {
+        var: Symbol,
+        ty: EbbTy,
+        sym: Symbol,
+    },
+    FloatToInt {
+        var: Symbol,
+        ty: EbbTy,
+        rounding: Rounding,
+        sym: Symbol,
+    },
+    /// rounds `sym` to an integral float
+    RoundFloat {
+        var: Symbol,
+        ty: EbbTy,
+        rounding: Rounding,
+        sym: Symbol,
+    },
+    Sqrt {
+        var: Symbol,
+        ty: EbbTy,
+        sym: Symbol,
+    },
+    /// converts `sym` to `ty`, a number type of another width or signedness
+    Convert {
+        var: Symbol,
+        ty: EbbTy,
+        sym: Symbol,
+    },
+    StringAlloc {
+        var: Symbol,
+        ty: EbbTy,
+        len: Symbol,
+    },
+    StringSize {
+        var: Symbol,
+        ty: EbbTy,
+        string: Symbol,
+    },
+    StringSub {
+        var: Symbol,
+        ty: EbbTy,
+        string: Symbol,
+        index: Symbol,
+    },
+    StringUpdate {
+        var: Symbol,
+        ty: EbbTy,
+        string: Symbol,
+        index: Symbol,
+        value: Symbol,
+    },
+    ArrayAlloc {
+        var: Symbol,
+        ty: EbbTy,
+        len: Symbol,
+    },
+    ArrayLength {
+        var: Symbol,
+        ty: EbbTy,
+        array: Symbol,
+    },
+    /// aborts if `index` is out of the bounds of `array`, as raising `Subscript`
+    ArraySub {
+        var: Symbol,
+        ty: EbbTy,
+        array: Symbol,
+        index: Symbol,
+    },
+    /// aborts if `index` is out of the bounds of `array`, as raising `Subscript`
+    ArrayUpdate {
+        var: Symbol,
+        ty: EbbTy,
+        array: Symbol,
+        index: Symbol,
+        value: Symbol,
+    },
     Closure {
         var: Symbol,
         param_ty: EbbTy,
@@ -137,6 +229,13 @@ pub enum Op {
         fun: Symbol,
         args: Vec<Symbol>,
     },
+    /// calls the function returning the elements of its tuple as separate values
+    MultiCall {
+        vars: Vec<Symbol>,
+        tys: Vec<EbbTy>,
+        fun: Symbol,
+        args: Vec<Symbol>,
+    },
     Tuple {
         var: Symbol,
         tys: Vec<EbbTy>,
@@ -163,6 +262,17 @@ pub enum Op {
         index: u32,
         union: Symbol,
     },
+    /// reads the toplevel value stored in the slot `index` of the globals
+    GlobalGet {
+        var: Symbol,
+        ty: EbbTy,
+        index: u32,
+    },
+    /// stores the toplevel value for the other functions to read with `GlobalGet`
+    GlobalSet {
+        index: u32,
+        value: Symbol,
+    },
 
     Branch {
         cond: Symbol,
@@ -178,6 +288,28 @@ pub enum Op {
         value: Option<Symbol>,
         ty: EbbTy,
     },
+    /// returns the elements of the tuple as separate values, from the functions of `multi_value`
+    MultiRet {
+        values: Vec<Symbol>,
+        tys: Vec<EbbTy>,
+    },
+    /// calls the function and returns its result, in place of the caller
+    TailCall {
+        ty: EbbTy,
+        fun: Symbol,
+        args: Vec<Symbol>,
+    },
+    Unreachable,
+}
+
+/// how `FloatToInt` and `RoundFloat` round the fraction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    Floor,
+    Ceil,
+    /// to the nearest, ties to even
+    Nearest,
+    Trunc,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -191,7 +323,12 @@ pub enum EbbTy {
     Char,
     Int,
     Float,
+    Int64,
+    Word64,
+    Float32,
     Bool,
+    String,
+    Array(Box<EbbTy>),
     Tuple(Vec<EbbTy>),
     Union(Vec<EbbTy>),
     Cls {
@@ -212,6 +349,117 @@ impl MIR {
     }
 }
 
+impl Op {
+    /// the variable the op defines
+    pub fn var(&self) -> Option<&Symbol> {
+        use crate::mir::Op::*;
+        match self {
+            Lit { var, .. }
+            | Alias { var, .. }
+            | Add { var, .. }
+            | Sub { var, .. }
+            | Mul { var, .. }
+            | DivInt { var, .. }
+            | DivFloat { var, .. }
+            | Mod { var, .. }
+            | Eq { var, .. }
+            | Neq { var, .. }
+            | Gt { var, .. }
+            | Ge { var, .. }
+            | Lt { var, .. }
+            | Le { var, .. }
+            | IntToFloat { var, .. }
+            | FloatToInt { var, .. }
+            | RoundFloat { var, .. }
+            | Sqrt { var, .. }
+            | Convert { var, .. }
+            | StringAlloc { var, .. }
+            | StringSize { var, .. }
+            | StringSub { var, .. }
+            | StringUpdate { var, .. }
+            | ArrayAlloc { var, .. }
+            | ArrayLength { var, .. }
+            | ArraySub { var, .. }
+            | ArrayUpdate { var, .. }
+            | Closure { var, .. }
+            | ExternCall { var, .. }
+            | Call { var, .. }
+            | Tuple { var, .. }
+            | Proj { var, .. }
+            | Union { var, .. }
+            | Select { var, .. }
+            | GlobalGet { var, .. } => Some(var),
+            // defines more than one
+            MultiCall { .. } => None,
+            GlobalSet { .. }
+            | Branch { .. }
+            | Jump { .. }
+            | Ret { .. }
+            | MultiRet { .. }
+            | TailCall { .. }
+            | Unreachable => None,
+        }
+    }
+
+    /// the variables the op reads
+    pub fn operands(&self) -> Vec<&Symbol> {
+        use crate::mir::Op::*;
+        match self {
+            Lit { .. } | GlobalGet { .. } | Unreachable => vec![],
+            Alias { sym, .. }
+            | IntToFloat { sym, .. }
+            | FloatToInt { sym, .. }
+            | RoundFloat { sym, .. }
+            | Sqrt { sym, .. }
+            | Convert { sym, .. } => vec![sym],
+            Add { l, r, .. }
+            | Sub { l, r, .. }
+            | Mul { l, r, .. }
+            | DivInt { l, r, .. }
+            | DivFloat { l, r, .. }
+            | Mod { l, r, .. }
+            | Eq { l, r, .. }
+            | Neq { l, r, .. }
+            | Gt { l, r, .. }
+            | Ge { l, r, .. }
+            | Lt { l, r, .. }
+            | Le { l, r, .. } => vec![l, r],
+            StringAlloc { len, .. } | ArrayAlloc { len, .. } => vec![len],
+            StringSize { string, .. } => vec![string],
+            StringSub { string, index, .. } => vec![string, index],
+            StringUpdate {
+                string,
+                index,
+                value,
+                ..
+            } => vec![string, index, value],
+            ArrayLength { array, .. } => vec![array],
+            ArraySub { array, index, .. } => vec![array, index],
+            ArrayUpdate {
+                array,
+                index,
+                value,
+                ..
+            } => vec![array, index, value],
+            Closure { fun, env, .. } => std::iter::once(fun)
+                .chain(env.iter().map(|(_, var)| var))
+                .collect(),
+            ExternCall { args, .. } | Jump { args, .. } => args.iter().collect(),
+            Call { fun, args, .. } | MultiCall { fun, args, .. } | TailCall { fun, args, .. } => {
+                std::iter::once(fun).chain(args.iter()).collect()
+            }
+            Tuple { tuple, .. } => tuple.iter().collect(),
+            Proj { tuple, .. } => vec![tuple],
+            Union { variant, .. } => vec![variant],
+            Select { union, .. } => vec![union],
+            GlobalSet { value, .. } => vec![value],
+            Branch { cond, .. } => vec![cond],
+            Ret { value, .. } => value.iter().collect(),
+            MultiRet { values, .. } => values.iter().collect(),
+        }
+    }
+}
+
 impl SymbolTable {
     pub fn canonical_value(&self, name: &Symbol) -> Option<&EbbTy> {
         match self.table.get(name) {
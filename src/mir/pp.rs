@@ -87,6 +87,14 @@ impl PP for EbbTy {
             Char => write!(w, "char")?,
             Int => write!(w, "int")?,
             Float => write!(w, "float")?,
+            Int64 => write!(w, "int64")?,
+            Word64 => write!(w, "word64")?,
+            Float32 => write!(w, "float32")?,
+            String => write!(w, "string")?,
+            Array(elem) => {
+                elem.pp(w, indent)?;
+                write!(w, " array")?;
+            }
             Tuple(tys) => {
                 write!(w, "(")?;
                 inter_iter! {
@@ -148,6 +156,11 @@ impl fmt::Display for EbbTy {
             Char => write!(f, "char")?,
             Int => write!(f, "int")?,
             Float => write!(f, "float")?,
+            Int64 => write!(f, "int64")?,
+            Word64 => write!(f, "word64")?,
+            Float32 => write!(f, "float32")?,
+            String => write!(f, "string")?,
+            Array(elem) => write!(f, "{} array", elem)?,
             Tuple(tys) => {
                 write!(f, "(")?;
                 inter_iter! {
@@ -329,6 +342,156 @@ impl PP for Op {
             Le { var, ty, l, r } => {
                 pp_binop(w, indent, &space, "<=", var, ty, l, r)?;
             }
+            IntToFloat { var, ty, sym } => {
+                write!(w, "{}", space)?;
+                var.pp(w, indent)?;
+                write!(w, ": ")?;
+                ty.pp(w, indent)?;
+                write!(w, " := real ")?;
+                sym.pp(w, indent)?;
+            }
+            RoundFloat {
+                var,
+                ty,
+                rounding,
+                sym,
+            } => {
+                write!(w, "{}", space)?;
+                var.pp(w, indent)?;
+                write!(w, ": ")?;
+                ty.pp(w, indent)?;
+                write!(w, " := real{} ", rounding)?;
+                sym.pp(w, indent)?;
+            }
+            Sqrt { var, ty, sym } => {
+                write!(w, "{}", space)?;
+                var.pp(w, indent)?;
+                write!(w, ": ")?;
+                ty.pp(w, indent)?;
+                write!(w, " := sqrt ")?;
+                sym.pp(w, indent)?;
+            }
+            Convert { var, ty, sym } => {
+                write!(w, "{}", space)?;
+                var.pp(w, indent)?;
+                write!(w, ": ")?;
+                ty.pp(w, indent)?;
+                write!(w, " := convert ")?;
+                sym.pp(w, indent)?;
+            }
+            FloatToInt {
+                var,
+                ty,
+                rounding,
+                sym,
+            } => {
+                write!(w, "{}", space)?;
+                var.pp(w, indent)?;
+                write!(w, ": ")?;
+                ty.pp(w, indent)?;
+                write!(w, " := {} ", rounding)?;
+                sym.pp(w, indent)?;
+            }
+            StringAlloc { var, ty, len } => {
+                write!(w, "{}", space)?;
+                var.pp(w, indent)?;
+                write!(w, ": ")?;
+                ty.pp(w, indent)?;
+                write!(w, " := stralloc ")?;
+                len.pp(w, indent)?;
+            }
+            StringSize { var, ty, string } => {
+                write!(w, "{}", space)?;
+                var.pp(w, indent)?;
+                write!(w, ": ")?;
+                ty.pp(w, indent)?;
+                write!(w, " := strsize ")?;
+                string.pp(w, indent)?;
+            }
+            StringSub {
+                var,
+                ty,
+                string,
+                index,
+            } => {
+                write!(w, "{}", space)?;
+                var.pp(w, indent)?;
+                write!(w, ": ")?;
+                ty.pp(w, indent)?;
+                write!(w, " := ")?;
+                string.pp(w, indent)?;
+                write!(w, "[")?;
+                index.pp(w, indent)?;
+                write!(w, "]")?;
+            }
+            ArrayAlloc { var, ty, len } => {
+                write!(w, "{}", space)?;
+                var.pp(w, indent)?;
+                write!(w, ": ")?;
+                ty.pp(w, indent)?;
+                write!(w, " := arrayalloc ")?;
+                len.pp(w, indent)?;
+            }
+            ArrayLength { var, ty, array } => {
+                write!(w, "{}", space)?;
+                var.pp(w, indent)?;
+                write!(w, ": ")?;
+                ty.pp(w, indent)?;
+                write!(w, " := arraylength ")?;
+                array.pp(w, indent)?;
+            }
+            ArraySub {
+                var,
+                ty,
+                array,
+                index,
+            } => {
+                write!(w, "{}", space)?;
+                var.pp(w, indent)?;
+                write!(w, ": ")?;
+                ty.pp(w, indent)?;
+                write!(w, " := ")?;
+                array.pp(w, indent)?;
+                write!(w, "[")?;
+                index.pp(w, indent)?;
+                write!(w, "]")?;
+            }
+            ArrayUpdate {
+                var,
+                ty,
+                array,
+                index,
+                value,
+            } => {
+                write!(w, "{}", space)?;
+                var.pp(w, indent)?;
+                write!(w, ": ")?;
+                ty.pp(w, indent)?;
+                write!(w, " := ")?;
+                array.pp(w, indent)?;
+                write!(w, "[")?;
+                index.pp(w, indent)?;
+                write!(w, "] <- ")?;
+                value.pp(w, indent)?;
+            }
+            StringUpdate {
+                var,
+                ty,
+                string,
+                index,
+                value,
+            } => {
+                write!(w, "{}", space)?;
+                var.pp(w, indent)?;
+                write!(w, ": ")?;
+                ty.pp(w, indent)?;
+                write!(w, " := ")?;
+                string.pp(w, indent)?;
+                write!(w, "[")?;
+                index.pp(w, indent)?;
+                write!(w, "] <- ")?;
+                value.pp(w, indent)?;
+            }
             Closure {
                 var,
                 param_ty,
@@ -397,6 +560,34 @@ impl PP for Op {
                 }
                 write!(w, ")")?;
             }
+            MultiCall {
+                vars,
+                tys,
+                fun,
+                args,
+            } => {
+                write!(w, "{}(", space)?;
+                inter_iter! {
+                    vars.iter().zip(tys),
+                    write!(w, ", ")?,
+                    |(var, ty)| => {
+                        var.pp(w, indent)?;
+                        write!(w, ": ")?;
+                        ty.pp(w, indent)?
+                    }
+                }
+                write!(w, ") := ")?;
+                fun.pp(w, indent)?;
+                write!(w, "(")?;
+                inter_iter! {
+                    args.iter(),
+                    write!(w, ", ")?,
+                    |arg| => {
+                        arg.pp(w, 0)?
+                    }
+                }
+                write!(w, ")")?;
+            }
             Tuple { var, tys, tuple } => {
                 write!(w, "{}", space)?;
                 var.pp(w, indent)?;
@@ -465,6 +656,18 @@ impl PP for Op {
                 union.pp(w, indent)?;
                 write!(w, ")")?;
             }
+            GlobalGet { var, ty, index } => {
+                write!(w, "{}", space)?;
+                var.pp(w, indent)?;
+                write!(w, ": ")?;
+                ty.pp(w, indent)?;
+                write!(w, " := global_get({})", index)?;
+            }
+            GlobalSet { index, value } => {
+                write!(w, "{}global_set({}, ", space, index)?;
+                value.pp(w, indent)?;
+                write!(w, ")")?;
+            }
             Branch {
                 cond,
                 clauses,
@@ -517,6 +720,36 @@ impl PP for Op {
 
                 ty.pp(w, indent)?;
             }
+            MultiRet { values, tys } => {
+                write!(w, "{}ret (", space)?;
+                inter_iter! {
+                    values.iter().zip(tys),
+                    write!(w, ", ")?,
+                    |(value, ty)| => {
+                        value.pp(w, indent)?;
+                        write!(w, ": ")?;
+                        ty.pp(w, indent)?
+                    }
+                }
+                write!(w, ")")?;
+            }
+            TailCall { ty, fun, args } => {
+                write!(w, "{}tailcall ", space)?;
+                fun.pp(w, indent)?;
+                write!(w, "(")?;
+                inter_iter! {
+                    args.iter(),
+                    write!(w, ", ")?,
+                    |arg| => {
+                        arg.pp(w, 0)?
+                    }
+                }
+                write!(w, "): ")?;
+                ty.pp(w, indent)?;
+            }
+            Unreachable => {
+                write!(w, "{}unreachable", space)?;
+            }
         };
         Ok(())
     }
@@ -583,6 +816,85 @@ impl fmt::Display for Op {
             Le { var, ty, l, r } => {
                 display_binop(f, &space, "<=", var, ty, l, r)?;
             }
+            IntToFloat { var, ty, sym } => {
+                write!(f, "{}{}:{} := real {}", space, var, ty, sym)?;
+            }
+            RoundFloat {
+                var,
+                ty,
+                rounding,
+                sym,
+            } => {
+                write!(f, "{}{}:{} := real{} {}", space, var, ty, rounding, sym)?;
+            }
+            Sqrt { var, ty, sym } => {
+                write!(f, "{}{}:{} := sqrt {}", space, var, ty, sym)?;
+            }
+            Convert { var, ty, sym } => {
+                write!(f, "{}{}:{} := convert {}", space, var, ty, sym)?;
+            }
+            FloatToInt {
+                var,
+                ty,
+                rounding,
+                sym,
+            } => {
+                write!(f, "{}{}:{} := {} {}", space, var, ty, rounding, sym)?;
+            }
+            StringAlloc { var, ty, len } => {
+                write!(f, "{}{}:{} := stralloc {}", space, var, ty, len)?;
+            }
+            StringSize { var, ty, string } => {
+                write!(f, "{}{}:{} := strsize {}", space, var, ty, string)?;
+            }
+            StringSub {
+                var,
+                ty,
+                string,
+                index,
+            } => {
+                write!(f, "{}{}:{} := {}[{}]", space, var, ty, string, index)?;
+            }
+            ArrayAlloc { var, ty, len } => {
+                write!(f, "{}{}:{} := arrayalloc {}", space, var, ty, len)?;
+            }
+            ArrayLength { var, ty, array } => {
+                write!(f, "{}{}:{} := arraylength {}", space, var, ty, array)?;
+            }
+            ArraySub {
+                var,
+                ty,
+                array,
+                index,
+            } => {
+                write!(f, "{}{}:{} := {}[{}]", space, var, ty, array, index)?;
+            }
+            ArrayUpdate {
+                var,
+                ty,
+                array,
+                index,
+                value,
+            } => {
+                write!(
+                    f,
+                    "{}{}:{} := {}[{}] <- {}",
+                    space, var, ty, array, index, value
+                )?;
+            }
+            StringUpdate {
+                var,
+                ty,
+                string,
+                index,
+                value,
+            } => {
+                write!(
+                    f,
+                    "{}{}:{} := {}[{}] <- {}",
+                    space, var, ty, string, index, value
+                )?;
+            }
             Closure {
                 var,
                 param_ty,
@@ -638,6 +950,30 @@ impl fmt::Display for Op {
                 }
                 write!(f, ")")?;
             }
+            MultiCall {
+                vars,
+                tys,
+                fun,
+                args,
+            } => {
+                write!(f, "{}(", space)?;
+                inter_iter! {
+                    vars.iter().zip(tys),
+                    write!(f, ", ")?,
+                    |(var, ty)| => {
+                        write!(f, "{}: {}", var, ty)?;
+                    }
+                }
+                write!(f, ") := {}(", fun)?;
+                inter_iter! {
+                    args.iter(),
+                    write!(f, ", ")?,
+                    |arg| => {
+                        write!(f, "{}", arg)?;
+                    }
+                }
+                write!(f, ")")?;
+            }
             Tuple { var, tys, tuple } => {
                 write!(f, "{}{}: (", space, var)?;
                 inter_iter! {
@@ -694,6 +1030,12 @@ impl fmt::Display for Op {
                     space, var, ty, index, union
                 )?;
             }
+            GlobalGet { var, ty, index } => {
+                write!(f, "{}{}: {} := global_get({})", space, var, ty, index)?;
+            }
+            GlobalSet { index, value } => {
+                write!(f, "{}global_set({}, {})", space, index, value)?;
+            }
             Branch {
                 cond,
                 clauses,
@@ -728,7 +1070,42 @@ impl fmt::Display for Op {
                 Some(v) => write!(f, "{}ret {}: {}", space, v, ty)?,
                 None => write!(f, "{}ret: {}", space, ty)?,
             },
+            MultiRet { values, tys } => {
+                write!(f, "{}ret (", space)?;
+                inter_iter! {
+                    values.iter().zip(tys),
+                    write!(f, ", ")?,
+                    |(value, ty)| => {
+                        write!(f, "{}: {}", value, ty)?;
+                    }
+                }
+                write!(f, ")")?;
+            }
+            TailCall { ty, fun, args } => {
+                write!(f, "{}tailcall {}(", space, fun)?;
+                inter_iter! {
+                    args.iter(),
+                    write!(f, ", ")?,
+                    |arg| => {
+                        write!(f, "{}", arg)?;
+                    }
+                }
+                write!(f, "): {}", ty)?;
+            }
+            Unreachable => write!(f, "{}unreachable", space)?,
         };
         Ok(())
     }
 }
+
+impl fmt::Display for Rounding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use crate::mir::Rounding::*;
+        match self {
+            Floor => write!(f, "floor"),
+            Ceil => write!(f, "ceil"),
+            Nearest => write!(f, "round"),
+            Trunc => write!(f, "trunc"),
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use crate::config::Config;
+use crate::mir::*;
+use crate::pass::Pass;
+use crate::prim::*;
+use std::collections::HashSet;
+
+/// finds the calls in tail position.
+/// The calls to the function itself become jumps to the entry EBB
+/// and, with `Config::tail_call`, the others become `Op::TailCall`.
+pub struct TailCall {
+    general: bool,
+}
+
+impl TailCall {
+    pub fn new() -> Self {
+        TailCall { general: false }
+    }
+
+    fn conv_mir(&mut self, mir: MIR) -> MIR {
+        MIR(mir.0.into_iter().map(|f| self.conv_fun(f)).collect())
+    }
+
+    fn conv_fun(&mut self, mut fun: Function) -> Function {
+        let returning = returning_ebbs(&fun.body);
+        let entry = fun.body[0].name.clone();
+        let nparams = fun.body[0].params.len();
+        let mut rewritten = false;
+        for ebb in fun.body.iter_mut() {
+            let (fname, args) = match tail_call(ebb, &returning) {
+                Some(call) => call,
+                None => continue,
+            };
+            let op = if fname == fun.name && args.len() == nparams {
+                Op::Jump {
+                    target: entry.clone(),
+                    forward: false,
+                    args,
+                }
+            } else if self.general {
+                Op::TailCall {
+                    ty: fun.body_ty.clone(),
+                    fun: fname,
+                    args,
+                }
+            } else {
+                continue;
+            };
+            let len = ebb.body.len();
+            ebb.body.truncate(len - 2);
+            ebb.body.push(op);
+            rewritten = true;
+        }
+        if rewritten {
+            remove_unreachable_ebbs(&mut fun);
+        }
+        fun
+    }
+}
+
+/// the EBBs which return their only param as is
+pub(super) fn returning_ebbs(body: &[EBB]) -> HashSet<Symbol> {
+    let mut ret = HashSet::new();
+    loop {
+        let mut changed = false;
+        for ebb in body {
+            if ret.contains(&ebb.name) || ebb.params.len() != 1 || ebb.body.len() != 1 {
+                continue;
+            }
+            let param = &ebb.params[0].1;
+            let returns = match &ebb.body[0] {
+                Op::Ret { value, .. } => value.as_ref().map(|v| v == param).unwrap_or(false),
+                Op::Jump { target, args, .. } => {
+                    ret.contains(target) && args.len() == 1 && &args[0] == param
+                }
+                _ => false,
+            };
+            if returns {
+                ret.insert(ebb.name.clone());
+                changed = true;
+            }
+        }
+        if !changed {
+            return ret;
+        }
+    }
+}
+
+/// the callee and the args of the call the EBB ends with, if its result is returned as is
+fn tail_call(ebb: &EBB, returning: &HashSet<Symbol>) -> Option<(Symbol, Vec<Symbol>)> {
+    let len = ebb.body.len();
+    if len < 2 {
+        return None;
+    }
+    let (var, ty, fun, args) = match &ebb.body[len - 2] {
+        Op::Call { var, ty, fun, args } => (var, ty, fun, args),
+        Op::MultiCall {
+            vars, fun, args, ..
+        } => {
+            return match &ebb.body[len - 1] {
+                Op::MultiRet { values, .. } if values == vars => Some((fun.clone(), args.clone())),
+                _ => None,
+            }
+        }
+        _ => return None,
+    };
+    let returned = match &ebb.body[len - 1] {
+        Op::Ret { value: Some(v), .. } => v == var,
+        // the result of unit is not passed around
+        Op::Ret { value: None, .. } => ty == &EbbTy::Unit,
+        Op::Jump { target, args, .. } => {
+            returning.contains(target) && args.len() == 1 && &args[0] == var
+        }
+        _ => false,
+    };
+    if returned {
+        Some((fun.clone(), args.clone()))
+    } else {
+        None
+    }
+}
+
+/// removes the EBBs no longer jumped to, such as the join points of the tail calls
+pub(super) fn remove_unreachable_ebbs(fun: &mut Function) {
+    let mut reachable = HashSet::new();
+    let mut stack = vec![fun.body[0].name.clone()];
+    while let Some(name) = stack.pop() {
+        if !reachable.insert(name.clone()) {
+            continue;
+        }
+        let ebb = fun
+            .body
+            .iter()
+            .find(|ebb| ebb.name == name)
+            .expect("internal error: jump to unknown EBB");
+        stack.extend(ebb.next_ebbs().into_iter().map(|(next, _)| next.clone()));
+    }
+    fun.body.retain(|ebb| reachable.contains(&ebb.name));
+}
+
+impl<E> Pass<Context, E> for TailCall {
+    type Target = Context;
+
+    fn trans(
+        &mut self,
+        Context(symbol_table, mir): Context,
+        config: &Config,
+    ) -> ::std::result::Result<Self::Target, E> {
+        self.general = config.tail_call;
+        Ok(Context(symbol_table, self.conv_mir(mir)))
+    }
+}
@@ -112,6 +112,61 @@ impl UnAlias {
                 &mut Proj { ref mut tuple, .. } => {
                     self.resolv_alias(tuple);
                 }
+                &mut IntToFloat { ref mut sym, .. }
+                | &mut FloatToInt { ref mut sym, .. }
+                | &mut RoundFloat { ref mut sym, .. }
+                | &mut Sqrt { ref mut sym, .. }
+                | &mut Convert { ref mut sym, .. } => {
+                    self.resolv_alias(sym);
+                }
+                &mut StringAlloc { ref mut len, .. } => {
+                    self.resolv_alias(len);
+                }
+                &mut StringSize { ref mut string, .. } => {
+                    self.resolv_alias(string);
+                }
+                &mut StringSub {
+                    ref mut string,
+                    ref mut index,
+                    ..
+                } => {
+                    self.resolv_alias(string);
+                    self.resolv_alias(index);
+                }
+                &mut StringUpdate {
+                    ref mut string,
+                    ref mut index,
+                    ref mut value,
+                    ..
+                } => {
+                    self.resolv_alias(string);
+                    self.resolv_alias(index);
+                    self.resolv_alias(value);
+                }
+                &mut ArrayAlloc { ref mut len, .. } => {
+                    self.resolv_alias(len);
+                }
+                &mut ArrayLength { ref mut array, .. } => {
+                    self.resolv_alias(array);
+                }
+                &mut ArraySub {
+                    ref mut array,
+                    ref mut index,
+                    ..
+                } => {
+                    self.resolv_alias(array);
+                    self.resolv_alias(index);
+                }
+                &mut ArrayUpdate {
+                    ref mut array,
+                    ref mut index,
+                    ref mut value,
+                    ..
+                } => {
+                    self.resolv_alias(array);
+                    self.resolv_alias(index);
+                    self.resolv_alias(value);
+                }
                 &mut Union {
                     ref mut variant, ..
                 } => {
@@ -120,6 +175,9 @@ impl UnAlias {
                 &mut Select { ref mut union, .. } => {
                     self.resolv_alias(union);
                 }
+                &mut GlobalSet { ref mut value, .. } => {
+                    self.resolv_alias(value);
+                }
 
                 &mut Closure {
                     ref mut fun,
@@ -141,6 +199,16 @@ impl UnAlias {
                     ref mut fun,
                     ref mut args,
                     ..
+                }
+                | &mut MultiCall {
+                    ref mut fun,
+                    ref mut args,
+                    ..
+                }
+                | &mut TailCall {
+                    ref mut fun,
+                    ref mut args,
+                    ..
                 } => {
                     self.resolv_alias(fun);
                     for arg in args.iter_mut() {
@@ -157,7 +225,12 @@ impl UnAlias {
                         self.resolv_alias(v)
                     }
                 }
-                &mut Lit { .. } => (),
+                &mut MultiRet { ref mut values, .. } => {
+                    for value in values.iter_mut() {
+                        self.resolv_alias(value);
+                    }
+                }
+                &mut Lit { .. } | &mut GlobalGet { .. } | &mut Unreachable => (),
                 &mut Branch { ref mut cond, .. } => self.resolv_alias(cond),
             }
             body.push(op)
@@ -0,0 +1,219 @@
+use super::tail_call::{remove_unreachable_ebbs, returning_ebbs};
+use crate::config::Config;
+use crate::id::Id;
+use crate::mir::*;
+use crate::pass::Pass;
+use crate::prim::*;
+use std::collections::{HashMap, HashSet};
+
+/// returns the elements of the small tuples as separate values, with the wasm multi-value,
+/// so that the functions don't allocate the tuples they return.
+/// Does nothing unless `Config::multi_value` is set.
+/// As in `FlattenArgs`, the functions of closures keep their signatures.
+pub struct UnboxReturns {
+    id: Id,
+}
+
+/// tuples larger than this are returned as they are
+const MAX_RESULTS: usize = 4;
+
+impl UnboxReturns {
+    pub fn new(id: Id) -> Self {
+        UnboxReturns { id }
+    }
+
+    fn gensym(&mut self, name: &str) -> Symbol {
+        Symbol(name.to_string(), self.id.next())
+    }
+
+    fn conv_mir(&mut self, mut mir: MIR) -> MIR {
+        let targets = mir
+            .0
+            .iter()
+            .flat_map(|f| f.body.iter().flat_map(|ebb| ebb.body.iter()))
+            .filter_map(|op| match op {
+                Op::Closure { fun, .. } | Op::TailCall { fun, .. } => Some(fun.clone()),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+
+        // the element types of the results of each function unboxed
+        let mut layouts = HashMap::new();
+        for f in mir.0.iter_mut() {
+            if targets.contains(&f.name) {
+                continue;
+            }
+            match &f.body_ty {
+                EbbTy::Tuple(tys) if 2 <= tys.len() && tys.len() <= MAX_RESULTS => {
+                    f.multi_value = true;
+                    layouts.insert(f.name.clone(), tys.clone());
+                }
+                _ => (),
+            }
+        }
+        if layouts.is_empty() {
+            return mir;
+        }
+        // the callers first, so that the results of the calls returned as is are not boxed
+        for f in mir.0.iter_mut() {
+            self.unbox_calls(f, &layouts);
+        }
+        for f in mir.0.iter_mut() {
+            if f.multi_value {
+                self.unbox_rets(f);
+            }
+        }
+        mir
+    }
+
+    /// calls the functions unboxed with `Op::MultiCall`.
+    /// The projections of the results become aliases of the elements, and the other uses
+    /// get the tuple made of them
+    fn unbox_calls(&mut self, f: &mut Function, layouts: &HashMap<Symbol, Vec<EbbTy>>) {
+        let mut uses = HashMap::new();
+        let mut projs = HashMap::new();
+        for op in f.body.iter().flat_map(|ebb| ebb.body.iter()) {
+            for var in op.operands() {
+                *uses.entry(var.clone()).or_insert(0) += 1;
+            }
+            if let Op::Proj { tuple, .. } = op {
+                *projs.entry(tuple.clone()).or_insert(0) += 1;
+            }
+        }
+
+        // the elements of the results only projected
+        let mut elements = HashMap::new();
+        for ebb in f.body.iter_mut() {
+            let mut body = Vec::new();
+            for op in ebb.body.drain(..) {
+                let (var, fun, args) = match op {
+                    Op::Call { var, fun, args, .. } if layouts.contains_key(&fun) => {
+                        (var, fun, args)
+                    }
+                    op => {
+                        body.push(op);
+                        continue;
+                    }
+                };
+                let tys = layouts[&fun].clone();
+                let vars = tys
+                    .iter()
+                    .map(|_| self.gensym("element"))
+                    .collect::<Vec<_>>();
+                body.push(Op::MultiCall {
+                    vars: vars.clone(),
+                    tys: tys.clone(),
+                    fun,
+                    args,
+                });
+                if uses.get(&var) == projs.get(&var) {
+                    elements.insert(var, vars);
+                } else {
+                    body.push(Op::Tuple {
+                        var,
+                        tys,
+                        tuple: vars,
+                    });
+                }
+            }
+            ebb.body = body;
+        }
+
+        for op in f.body.iter_mut().flat_map(|ebb| ebb.body.iter_mut()) {
+            let alias = match op {
+                Op::Proj {
+                    var,
+                    ty,
+                    index,
+                    tuple,
+                } => match elements.get(tuple) {
+                    Some(vars) => Op::Alias {
+                        var: var.clone(),
+                        ty: ty.clone(),
+                        sym: vars[*index as usize].clone(),
+                    },
+                    None => continue,
+                },
+                _ => continue,
+            };
+            *op = alias;
+        }
+    }
+
+    /// returns the elements of the results with `Op::MultiRet`.
+    /// The tuples made in the same EBB are returned as their elements, and the others are projected.
+    /// The jumps to the EBBs returning their param return it themselves
+    fn unbox_rets(&mut self, f: &mut Function) {
+        let tys = match &f.body_ty {
+            EbbTy::Tuple(tys) => tys.clone(),
+            _ => unreachable!("internal error: unboxing the result of a non tuple"),
+        };
+        let returning = returning_ebbs(&f.body);
+        for ebb in f.body.iter_mut() {
+            let value = match ebb.body.last() {
+                Some(Op::Ret { value: Some(v), .. }) => v.clone(),
+                Some(Op::Jump { target, args, .. }) if returning.contains(target) => {
+                    args[0].clone()
+                }
+                _ => continue,
+            };
+            ebb.body.pop();
+            let tuple = ebb.body.iter().find_map(|op| match op {
+                Op::Tuple { var, tuple, .. } if *var == value => Some(tuple.clone()),
+                _ => None,
+            });
+            let values = match tuple {
+                Some(tuple) => tuple,
+                None => {
+                    let mut values = Vec::new();
+                    for (i, ty) in tys.iter().enumerate() {
+                        let var = self.gensym("element");
+                        ebb.body.push(Op::Proj {
+                            var: var.clone(),
+                            ty: ty.clone(),
+                            index: i as u32,
+                            tuple: value.clone(),
+                        });
+                        values.push(var);
+                    }
+                    values
+                }
+            };
+            ebb.body.push(Op::MultiRet {
+                values,
+                tys: tys.clone(),
+            });
+        }
+        remove_unreachable_ebbs(f);
+
+        // the tuples no longer used
+        let used = f
+            .body
+            .iter()
+            .flat_map(|ebb| ebb.body.iter())
+            .flat_map(|op| op.operands())
+            .cloned()
+            .collect::<HashSet<_>>();
+        for ebb in f.body.iter_mut() {
+            ebb.body.retain(|op| match op {
+                Op::Tuple { var, .. } => used.contains(var),
+                _ => true,
+            });
+        }
+    }
+}
+
+impl<E> Pass<Context, E> for UnboxReturns {
+    type Target = Context;
+
+    fn trans(
+        &mut self,
+        Context(symbol_table, mir): Context,
+        config: &Config,
+    ) -> ::std::result::Result<Self::Target, E> {
+        if !config.multi_value {
+            return Ok(Context(symbol_table, mir));
+        }
+        Ok(Context(symbol_table, self.conv_mir(mir)))
+    }
+}
@@ -0,0 +1,127 @@
+use crate::config::Config;
+use crate::id::Id;
+use crate::mir::*;
+use crate::pass::Pass;
+use crate::prim::*;
+use std::collections::HashMap;
+
+/// uncurries the saturated calls of the curried functions such as `fun f a b c = ...`.
+/// The calls to the functions only making closures of their params are replaced with the closures,
+/// which `KnownCall` turns into the calls to the n-ary workers taking all the params.
+/// The curried functions are kept for partial applications.
+/// As it runs `KnownCall` itself, it is enabled only along with `known_call`, by `Config::DEPENDENCIES`.
+pub struct Uncurry {
+    known_call: KnownCall,
+}
+
+/// a function returning the closure of `fun` capturing its params
+struct Maker {
+    params: Vec<Symbol>,
+    param_ty: EbbTy,
+    ret_ty: EbbTy,
+    fun: Symbol,
+    env: Vec<(EbbTy, Symbol)>,
+}
+
+impl Uncurry {
+    pub fn new(id: Id) -> Self {
+        Uncurry {
+            known_call: KnownCall::new(id),
+        }
+    }
+
+    fn conv_mir(&mut self, mut mir: MIR) -> MIR {
+        // each round uncurries one more param
+        loop {
+            let makers = mir
+                .0
+                .iter()
+                .filter_map(|f| Some((f.name.clone(), closure_maker(f)?)))
+                .collect::<HashMap<_, _>>();
+            let mut changed = false;
+            for f in mir.0.iter_mut() {
+                changed |= inline_makers(f, &makers);
+            }
+            if !changed {
+                return mir;
+            }
+            mir = self.known_call.conv_mir(mir);
+        }
+    }
+}
+
+fn closure_maker(f: &Function) -> Option<Maker> {
+    if f.body.len() != 1 {
+        return None;
+    }
+    let entry = &f.body[0];
+    let params = entry
+        .params
+        .iter()
+        .map(|(_, param)| param.clone())
+        .collect::<Vec<_>>();
+    match entry.body.as_slice() {
+        [Op::Closure {
+            var,
+            param_ty,
+            ret_ty,
+            fun,
+            env,
+        }, Op::Ret {
+            value: Some(value), ..
+        }] if var == value && env.iter().all(|(_, var)| params.contains(var)) => Some(Maker {
+            params,
+            param_ty: param_ty.clone(),
+            ret_ty: ret_ty.clone(),
+            fun: fun.clone(),
+            env: env.clone(),
+        }),
+        _ => None,
+    }
+}
+
+/// replaces the calls to the makers with the closures they make
+fn inline_makers(f: &mut Function, makers: &HashMap<Symbol, Maker>) -> bool {
+    let mut changed = false;
+    for op in f.body.iter_mut().flat_map(|ebb| ebb.body.iter_mut()) {
+        let closure = match op {
+            Op::Call { var, fun, args, .. } => match makers.get(fun) {
+                Some(maker) if maker.params.len() == args.len() => {
+                    let table = maker
+                        .params
+                        .iter()
+                        .zip(args.iter())
+                        .collect::<HashMap<_, _>>();
+                    Op::Closure {
+                        var: var.clone(),
+                        param_ty: maker.param_ty.clone(),
+                        ret_ty: maker.ret_ty.clone(),
+                        fun: maker.fun.clone(),
+                        env: maker
+                            .env
+                            .iter()
+                            .map(|(ty, var)| (ty.clone(), table[var].clone()))
+                            .collect(),
+                    }
+                }
+                _ => continue,
+            },
+            _ => continue,
+        };
+        *op = closure;
+        changed = true;
+    }
+    changed
+}
+
+impl<E> Pass<Context, E> for Uncurry {
+    type Target = Context;
+
+    fn trans(
+        &mut self,
+        Context(symbol_table, mir): Context,
+        _: &Config,
+    ) -> ::std::result::Result<Self::Target, E> {
+        Ok(Context(symbol_table, self.conv_mir(mir)))
+    }
+}
@@ -0,0 +1,543 @@
+//! ML Basis (`.mlb`) project files.
+//!
+//! A project is a sequence of source files and nested bases.
+//! Each source file is parsed on its own and its top level bindings are visible to the files after it.
+//! Supported basis declarations are:
+//!
+//! ```text
+//! basdec ::= path.sml | path.mlb
+//!          | local basdec in basdec end
+//!          | ann "annotation" in basdec end
+//!          | basis id = basexp (and id = basexp)*
+//!          | open id+
+//! basexp ::= bas basdec end | id | let basdec in basexp end
+//! ```
+//!
+//! Paths may refer to environment variables as `$(NAME)` and are relative to the `.mlb` file.
+//! Each file is elaborated once, at its first reference, and its code runs there,
+//! even inside a basis never opened. The later references and `open` only make
+//! the bindings elaborated before visible again, with `Unit::Bindings`.
+
+use log::warn;
+use std::collections::HashMap;
+use std::env;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(PathBuf, io::Error),
+    Syntax { path: PathBuf, message: String },
+    UnboundBasis { path: PathBuf, name: String },
+    UnboundVariable { path: PathBuf, name: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::Error::*;
+        match self {
+            Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            Syntax { path, message } => write!(f, "{}: syntax error: {}", path.display(), message),
+            UnboundBasis { path, name } => write!(f, "{}: unbound basis {}", path.display(), name),
+            UnboundVariable { path, name } => {
+                write!(f, "{}: unbound path variable $({})", path.display(), name)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// a source file or a scope of them, in elaboration order
+#[derive(Debug, Clone, PartialEq)]
+pub enum Unit {
+    Source {
+        path: PathBuf,
+        text: String,
+    },
+    /// bindings of `binds` are visible only to `body`
+    Local {
+        binds: Vec<Unit>,
+        body: Vec<Unit>,
+    },
+    /// the bindings of the source `path` elaborated before, visible again.
+    /// Its code doesn't run again
+    Bindings {
+        path: PathBuf,
+    },
+}
+
+/// `units` as they are visible after them, without running their code again
+fn bindings(units: &[Unit]) -> Vec<Unit> {
+    let mut visible = Vec::new();
+    for unit in units {
+        match unit {
+            Unit::Source { path, .. } | Unit::Bindings { path } => {
+                visible.push(Unit::Bindings { path: path.clone() })
+            }
+            Unit::Local { body, .. } => visible.extend(bindings(body)),
+        }
+    }
+    visible
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Project {
+    pub units: Vec<Unit>,
+    /// the bindings of the files loaded, by their canonical paths
+    loaded: HashMap<PathBuf, Vec<Unit>>,
+}
+
+impl Project {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// add a source which is not on the file system, e.g. the prelude
+    pub fn push_source(&mut self, path: impl Into<PathBuf>, text: impl Into<String>) {
+        self.units.push(Unit::Source {
+            path: path.into(),
+            text: text.into(),
+        })
+    }
+
+    /// add a `.mlb` file or a source file
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let mut elaborator = Elaborator {
+            loaded: &mut self.loaded,
+            bases: HashMap::new(),
+        };
+        let mut units = elaborator.path(path.as_ref())?;
+        self.units.append(&mut units);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Eq,
+    Semicolon,
+}
+
+fn tokenize(path: &Path, input: &str) -> Result<Vec<Token>> {
+    let syntax_error = |message: &str| Error::Syntax {
+        path: path.to_path_buf(),
+        message: message.into(),
+    };
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                if chars.peek() != Some(&'*') {
+                    return Err(syntax_error("unexpected `(`"));
+                }
+                chars.next();
+                // comments nest
+                let mut depth = 1;
+                let mut prev = ' ';
+                while depth > 0 {
+                    let c = chars
+                        .next()
+                        .ok_or_else(|| syntax_error("unclosed comment"))?;
+                    match (prev, c) {
+                        ('(', '*') => {
+                            depth += 1;
+                            prev = ' ';
+                        }
+                        ('*', ')') => {
+                            depth -= 1;
+                            prev = ' ';
+                        }
+                        _ => prev = c,
+                    }
+                }
+            }
+            '"' => {
+                chars.next();
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => s.push(c),
+                        None => return Err(syntax_error("unclosed string")),
+                    }
+                }
+                tokens.push(Token::Str(s));
+            }
+            '=' => {
+                chars.next();
+                tokens.push(Token::Eq);
+            }
+            ';' => {
+                chars.next();
+                tokens.push(Token::Semicolon);
+            }
+            _ => {
+                let mut s = String::new();
+                while let Some(&c) = chars.peek() {
+                    // `(` is allowed only in path variables, i.e. `$(NAME)`
+                    if c.is_whitespace() || c == '"' || c == '=' || c == ';' {
+                        break;
+                    }
+                    if c == '(' && !s.ends_with('$') {
+                        break;
+                    }
+                    s.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(s));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum BasDec {
+    Path(String),
+    Local(Vec<BasDec>, Vec<BasDec>),
+    Ann(Vec<String>, Vec<BasDec>),
+    Basis(Vec<(String, BasExp)>),
+    Open(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum BasExp {
+    Bas(Vec<BasDec>),
+    Id(String),
+    Let(Vec<BasDec>, Box<BasExp>),
+}
+
+static KEYWORDS: &[&str] = &[
+    "and", "ann", "bas", "basis", "end", "in", "let", "local", "open",
+];
+
+fn is_basis_id(word: &str) -> bool {
+    !KEYWORDS.contains(&word)
+        && word
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '\'')
+}
+
+struct MlbParser<'a> {
+    path: &'a Path,
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl<'a> MlbParser<'a> {
+    fn error<T>(&self, message: impl Into<String>) -> Result<T> {
+        Err(Error::Syntax {
+            path: self.path.to_path_buf(),
+            message: message.into(),
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(w)) => w == keyword,
+            _ => false,
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) -> Result<()> {
+        if self.peek_keyword(keyword) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            self.error(format!("expected `{}`", keyword))
+        }
+    }
+
+    fn id(&mut self) -> Result<String> {
+        match self.peek().cloned() {
+            Some(Token::Word(w)) if is_basis_id(&w) => {
+                self.pos += 1;
+                Ok(w)
+            }
+            _ => self.error("expected a basis identifier"),
+        }
+    }
+
+    fn decs(&mut self) -> Result<Vec<BasDec>> {
+        let mut decs = Vec::new();
+        loop {
+            match self.peek().cloned() {
+                None => break,
+                Some(Token::Semicolon) => self.pos += 1,
+                Some(Token::Word(ref w)) if w == "end" || w == "in" => break,
+                Some(_) => decs.push(self.dec()?),
+            }
+        }
+        Ok(decs)
+    }
+
+    fn dec(&mut self) -> Result<BasDec> {
+        let word = match self.peek().cloned() {
+            Some(Token::Word(w)) => w,
+            _ => return self.error("expected a basis declaration"),
+        };
+        self.pos += 1;
+        match word.as_str() {
+            "local" => {
+                let binds = self.decs()?;
+                self.keyword("in")?;
+                let body = self.decs()?;
+                self.keyword("end")?;
+                Ok(BasDec::Local(binds, body))
+            }
+            "ann" => {
+                let mut annotations = Vec::new();
+                while let Some(Token::Str(s)) = self.peek().cloned() {
+                    self.pos += 1;
+                    annotations.push(s);
+                }
+                if annotations.is_empty() {
+                    return self.error("expected an annotation");
+                }
+                self.keyword("in")?;
+                let body = self.decs()?;
+                self.keyword("end")?;
+                Ok(BasDec::Ann(annotations, body))
+            }
+            "basis" => {
+                let mut binds = Vec::new();
+                loop {
+                    let name = self.id()?;
+                    match self.peek().cloned() {
+                        Some(Token::Eq) => self.pos += 1,
+                        _ => return self.error("expected `=`"),
+                    }
+                    let exp = self.exp()?;
+                    binds.push((name, exp));
+                    if self.peek_keyword("and") {
+                        self.pos += 1;
+                    } else {
+                        break;
+                    }
+                }
+                Ok(BasDec::Basis(binds))
+            }
+            "open" => {
+                let mut names = vec![self.id()?];
+                while let Ok(name) = self.id() {
+                    names.push(name)
+                }
+                Ok(BasDec::Open(names))
+            }
+            w if KEYWORDS.contains(&w) => self.error(format!("unexpected `{}`", w)),
+            path => Ok(BasDec::Path(path.to_string())),
+        }
+    }
+
+    fn exp(&mut self) -> Result<BasExp> {
+        if self.peek_keyword("bas") {
+            self.pos += 1;
+            let decs = self.decs()?;
+            self.keyword("end")?;
+            Ok(BasExp::Bas(decs))
+        } else if self.peek_keyword("let") {
+            self.pos += 1;
+            let decs = self.decs()?;
+            self.keyword("in")?;
+            let exp = self.exp()?;
+            self.keyword("end")?;
+            Ok(BasExp::Let(decs, Box::new(exp)))
+        } else {
+            Ok(BasExp::Id(self.id()?))
+        }
+    }
+}
+
+fn parse_mlb(path: &Path, input: &str) -> Result<Vec<BasDec>> {
+    let mut parser = MlbParser {
+        path,
+        tokens: tokenize(path, input)?,
+        pos: 0,
+    };
+    let decs = parser.decs()?;
+    if parser.peek().is_some() {
+        return parser.error("unexpected token");
+    }
+    Ok(decs)
+}
+
+struct Elaborator<'a> {
+    loaded: &'a mut HashMap<PathBuf, Vec<Unit>>,
+    /// the bindings of the bases
+    bases: HashMap<String, Vec<Unit>>,
+}
+
+impl<'a> Elaborator<'a> {
+    fn path(&mut self, path: &Path) -> Result<Vec<Unit>> {
+        let key = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if let Some(visible) = self.loaded.get(&key) {
+            return Ok(visible.clone());
+        }
+        // a file including itself sees nothing of itself
+        self.loaded.insert(key.clone(), vec![]);
+        let text = fs::read_to_string(path).map_err(|e| Error::Io(path.to_path_buf(), e))?;
+        let units = if path.extension().map(|ext| ext == "mlb").unwrap_or(false) {
+            let decs = parse_mlb(path, &text)?;
+            // bases are scoped to the file they are declared in
+            let mut elaborator = Elaborator {
+                loaded: &mut *self.loaded,
+                bases: HashMap::new(),
+            };
+            elaborator.decs(path, &decs)?
+        } else {
+            vec![Unit::Source {
+                path: path.to_path_buf(),
+                text,
+            }]
+        };
+        self.loaded.insert(key, bindings(&units));
+        Ok(units)
+    }
+
+    fn decs(&mut self, mlb: &Path, decs: &[BasDec]) -> Result<Vec<Unit>> {
+        let mut units = Vec::new();
+        for dec in decs {
+            units.append(&mut self.dec(mlb, dec)?);
+        }
+        Ok(units)
+    }
+
+    fn dec(&mut self, mlb: &Path, dec: &BasDec) -> Result<Vec<Unit>> {
+        match dec {
+            BasDec::Path(path) => {
+                let path = expand_path(mlb, path)?;
+                self.path(&path)
+            }
+            BasDec::Local(binds, body) => {
+                let outer = self.bases.clone();
+                let binds = self.decs(mlb, binds)?;
+                let inner = self.bases.clone();
+                let body = self.decs(mlb, body)?;
+                let exported = self
+                    .bases
+                    .iter()
+                    .filter(|(name, units)| inner.get(*name) != Some(*units))
+                    .map(|(name, units)| (name.clone(), units.clone()))
+                    .collect::<Vec<_>>();
+                self.bases = outer;
+                self.bases.extend(exported);
+                Ok(vec![Unit::Local { binds, body }])
+            }
+            BasDec::Ann(annotations, body) => {
+                for annotation in annotations {
+                    warn!(
+                        "{}: ignoring unsupported annotation \"{}\"",
+                        mlb.display(),
+                        annotation
+                    );
+                }
+                self.decs(mlb, body)
+            }
+            // the code of the bases runs here, and `open` makes their bindings visible
+            BasDec::Basis(binds) => {
+                let mut units = Vec::new();
+                let mut bound = Vec::new();
+                for (name, exp) in binds {
+                    let mut code = self.exp(mlb, exp)?;
+                    bound.push((name.clone(), bindings(&code)));
+                    units.append(&mut code);
+                }
+                self.bases.extend(bound);
+                if units.is_empty() {
+                    return Ok(vec![]);
+                }
+                Ok(vec![Unit::Local {
+                    binds: units,
+                    body: vec![],
+                }])
+            }
+            BasDec::Open(names) => {
+                let mut units = Vec::new();
+                for name in names {
+                    match self.bases.get(name) {
+                        Some(basis) => units.extend(basis.iter().cloned()),
+                        None => {
+                            return Err(Error::UnboundBasis {
+                                path: mlb.to_path_buf(),
+                                name: name.clone(),
+                            })
+                        }
+                    }
+                }
+                Ok(units)
+            }
+        }
+    }
+
+    /// the units of `exp`. Those of the bases it refers to by name are only their bindings
+    fn exp(&mut self, mlb: &Path, exp: &BasExp) -> Result<Vec<Unit>> {
+        match exp {
+            BasExp::Bas(decs) => {
+                let outer = self.bases.clone();
+                let units = self.decs(mlb, decs)?;
+                self.bases = outer;
+                Ok(units)
+            }
+            BasExp::Id(name) => self
+                .bases
+                .get(name)
+                .cloned()
+                .ok_or_else(|| Error::UnboundBasis {
+                    path: mlb.to_path_buf(),
+                    name: name.clone(),
+                }),
+            BasExp::Let(decs, exp) => {
+                let outer = self.bases.clone();
+                let binds = self.decs(mlb, decs)?;
+                let body = self.exp(mlb, exp)?;
+                self.bases = outer;
+                Ok(vec![Unit::Local { binds, body }])
+            }
+        }
+    }
+}
+
+/// expand `$(NAME)`s and resolve `path` relative to `mlb`
+fn expand_path(mlb: &Path, path: &str) -> Result<PathBuf> {
+    let mut expanded = String::new();
+    let mut rest = path;
+    while let Some(start) = rest.find("$(") {
+        expanded.push_str(&rest[..start]);
+        let end = match rest[start..].find(')') {
+            Some(end) => start + end,
+            None => {
+                return Err(Error::Syntax {
+                    path: mlb.to_path_buf(),
+                    message: format!("unclosed path variable in {}", path),
+                })
+            }
+        };
+        let name = &rest[start + 2..end];
+        let value = env::var(name).map_err(|_| Error::UnboundVariable {
+            path: mlb.to_path_buf(),
+            name: name.to_string(),
+        })?;
+        expanded.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+    let dir = mlb.parent().unwrap_or_else(|| Path::new(""));
+    Ok(dir.join(expanded))
+}
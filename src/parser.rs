@@ -8,60 +8,160 @@ use nom::multi::{many0, many1, separated_list, separated_nonempty_list};
 use nom::number::complete::recognize_float;
 use nom::sequence::{preceded, terminated, tuple};
 use nom::IResult;
+use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::HashMap;
+use std::rc::Rc;
 
 static KEYWORDS: &[&str] = &[
-    "val", "fun", "fn", "let", "in", "end", "if", "then", "else", "case", "of", "_", "datatype",
-    "op", "=>", "infix", "infixr",
+    "val",
+    "fun",
+    "fn",
+    "let",
+    "in",
+    "end",
+    "if",
+    "then",
+    "else",
+    "case",
+    "of",
+    "_",
+    "datatype",
+    "op",
+    "=>",
+    "infix",
+    "infixr",
+    "local",
+    "structure",
+    "struct",
+    "exception",
+    "raise",
 ];
 
 static RESERVED: &[&str] = &["|", "=", "#"];
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Assoc {
+    Left,
+    Right,
+}
+
+/// infix operators in effect, carried over between separately parsed sources
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Fixity(HashMap<Symbol, (u8, Assoc)>);
+
+impl Fixity {
+    /// operators declared in `self` but not in `base`
+    pub fn added_since(&self, base: &Fixity) -> Fixity {
+        Fixity(
+            self.0
+                .iter()
+                .filter(|(name, fixity)| base.0.get(name) != Some(fixity))
+                .map(|(name, fixity)| (name.clone(), *fixity))
+                .collect(),
+        )
+    }
+
+    pub fn extend(&mut self, other: Fixity) {
+        self.0.extend(other.0)
+    }
+}
+
 struct Parser {
-    infixes: RefCell<Vec<BTreeMap<u8, Vec<Symbol>>>>,
+    infixes: RefCell<Vec<HashMap<Symbol, (u8, Assoc)>>>,
+    // type variables of the datatype being parsed. `'a` refers to `Type::Variable(index)`
+    tyvars: RefCell<Vec<String>>,
+    source: Rc<str>,
+    /// the offsets where the lines of the input start
+    line_starts: Vec<usize>,
+    /// the length of the input. The parsers get its suffixes
+    len: usize,
+}
+
+/// applies the type constructor `tycon`, which may be a builtin one
+fn apply_tycon(tycon: Symbol, mut args: Vec<Type>) -> Type {
+    match (tycon.0.as_str(), args.len()) {
+        ("array", 1) => Type::Array(Box::new(args.remove(0))),
+        ("vector", 1) => Type::Vector(Box::new(args.remove(0))),
+        _ => Type::Datatype(tycon, args),
+    }
 }
 
 impl Parser {
-    fn new() -> Self {
+    fn new(input: &str) -> Self {
+        Self::with_fixity(Fixity::default(), "", input)
+    }
+
+    fn with_fixity(fixity: Fixity, source: &str, input: &str) -> Self {
+        let line_starts = Some(0)
+            .into_iter()
+            .chain(input.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
         Self {
-            infixes: RefCell::new(vec![BTreeMap::new()]),
+            infixes: RefCell::new(vec![fixity.0]),
+            tyvars: RefCell::new(Vec::new()),
+            source: source.into(),
+            line_starts,
+            len: input.len(),
+        }
+    }
+
+    /// the position where `i`, a suffix of the input, starts
+    fn position(&self, i: &str) -> Position {
+        let offset = self.len - i.len();
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        };
+        Position {
+            source: self.source.clone(),
+            line: line + 1,
+            column: offset - self.line_starts[line] + 1,
+        }
+    }
+
+    /// `f` returning also the position where it starts
+    fn with_position<'a, O>(
+        &'a self,
+        f: impl Fn(&str) -> IResult<&str, O> + 'a,
+    ) -> impl Fn(&str) -> IResult<&str, (Position, O)> + 'a {
+        move |i| {
+            let position = self.position(i);
+            let (i, o) = f(i)?;
+            Ok((i, (position, o)))
         }
     }
 
     fn with_scope<R>(&self, f: impl FnOnce() -> R) -> R {
-        self.infixes.borrow_mut().push(BTreeMap::default());
+        self.infixes.borrow_mut().push(HashMap::default());
         let r = f();
         self.infixes.borrow_mut().pop();
         r
     }
 
-    // TODO: support let scopes
-    fn new_infix_op(&self, priority: Option<u8>, mut names: Vec<Symbol>) {
+    fn new_infix_op(&self, priority: Option<u8>, assoc: Assoc, names: Vec<Symbol>) {
         let priority = priority.unwrap_or(0);
         let mut infixes = self.infixes.borrow_mut();
         let len = infixes.len();
-        infixes[len - 1]
-            .entry(priority)
-            .or_insert(Vec::new())
-            .append(&mut names)
+        for name in names {
+            infixes[len - 1].insert(name, (priority, assoc));
+        }
     }
 
-    fn get_table(&self) -> BTreeMap<u8, Vec<Symbol>> {
+    fn get_fixity(&self, name: &Symbol) -> Option<(u8, Assoc)> {
         self.infixes
             .borrow()
             .iter()
-            .fold(HashMap::new(), |mut acc, map| {
-                for (&priority, names) in map {
-                    for name in names {
-                        acc.insert(name, priority);
-                    }
-                }
-                acc
-            })
-            .into_iter()
-            .fold(BTreeMap::new(), |mut acc, (name, priority)| {
-                acc.entry(priority).or_insert(Vec::new()).push(name.clone());
+            .rev()
+            .find_map(|table| table.get(name).cloned())
+    }
+
+    fn get_table(&self) -> HashMap<Symbol, (u8, Assoc)> {
+        self.infixes
+            .borrow()
+            .iter()
+            .fold(HashMap::new(), |mut acc, table| {
+                acc.extend(table.iter().map(|(name, fixity)| (name.clone(), *fixity)));
                 acc
             })
     }
@@ -83,6 +183,10 @@ impl Parser {
                 self.decl_val(),
                 self.decl_fun(),
                 self.decl_infix(),
+                self.decl_infixr(),
+                self.decl_local(),
+                self.decl_structure(),
+                self.decl_exception(),
             ))(i)
         }
     }
@@ -91,15 +195,57 @@ impl Parser {
         move |i| {
             let (i, _) = tag("datatype")(i)?;
             let (i, _) = self.space1()(i)?;
+            let (i, tyvars) = opt(terminated(self.tyvarseq(), self.space0()))(i)?;
             let (i, name) = self.symbol()(i)?;
             let (i, _) = self.space0()(i)?;
             let (i, _) = tag("=")(i)?;
             let (i, _) = self.space0()(i)?;
-            let (i, constructors) = separated_nonempty_list(
+            let tyvars = tyvars.unwrap_or_default();
+            let params = (0..tyvars.len() as u64).collect();
+            *self.tyvars.borrow_mut() = tyvars;
+            let constructors = separated_nonempty_list(
                 tuple((self.space0(), tag("|"), self.space0())),
                 self.constructor_def(),
-            )(i)?;
-            Ok((i, Declaration::Datatype { name, constructors }))
+            )(i);
+            self.tyvars.borrow_mut().clear();
+            let (i, constructors) = constructors?;
+            Ok((
+                i,
+                Declaration::Datatype {
+                    name,
+                    params,
+                    constructors,
+                },
+            ))
+        }
+    }
+
+    /// `'a` or `('a, 'b, ...)`
+    fn tyvarseq(&self) -> impl Fn(&str) -> IResult<&str, Vec<String>> + '_ {
+        move |i| {
+            alt((
+                map(self.tyvar(), |tyvar| vec![tyvar]),
+                map(
+                    tuple((
+                        tag("("),
+                        self.space0(),
+                        separated_nonempty_list(
+                            tuple((self.space0(), tag(","), self.space0())),
+                            self.tyvar(),
+                        ),
+                        self.space0(),
+                        tag(")"),
+                    )),
+                    |(_, _, tyvars, _, _)| tyvars,
+                ),
+            ))(i)
+        }
+    }
+
+    fn tyvar(&self) -> impl Fn(&str) -> IResult<&str, String> + '_ {
+        move |i| {
+            let (i, name) = recognize(tuple((tag("'"), self.symbol_alphanumeric())))(i)?;
+            Ok((i, name.to_string()))
         }
     }
 
@@ -129,7 +275,7 @@ impl Parser {
             let (i, _) = self.space1()(i)?;
             let (i, cs) = separated_nonempty_list(
                 tuple((self.space0(), tag("|"), self.space0())),
-                map(
+                self.with_position(map(
                     tuple((
                         self.decl_funbind(),
                         self.space0(),
@@ -138,18 +284,27 @@ impl Parser {
                         self.expr(),
                     )),
                     |((name, params), _, _, _, e)| (name, params, e),
-                ),
+                )),
             )(i)?;
             let mut cs = cs.into_iter();
-            let (name, params, expr) = cs.next().expect("nonempty list empty");
+            let (position, (name, params, expr)) = cs.next().expect("nonempty list empty");
             let mut clauses = vec![(params, expr)];
-            for (new_name, params, expr) in cs {
+            let mut positions = vec![position];
+            for (position, (new_name, params, expr)) in cs {
                 if name != new_name {
                     return Err(nom::Err::Error((i, nom::error::ErrorKind::Tag)));
                 }
-                clauses.push((params, expr))
+                clauses.push((params, expr));
+                positions.push(position);
             }
-            Ok((i, Declaration::D(DerivedDeclaration::Fun { name, clauses })))
+            Ok((
+                i,
+                Declaration::D(DerivedDeclaration::Fun {
+                    name,
+                    clauses,
+                    positions,
+                }),
+            ))
         }
     }
 
@@ -189,7 +344,7 @@ impl Parser {
                 s.parse()
                     .expect("internal error: falied to parse digits as integer")
             });
-            self.new_infix_op(priority, names.clone());
+            self.new_infix_op(priority, Assoc::Left, names.clone());
             Ok((
                 i,
                 Declaration::D(DerivedDeclaration::Infix { priority, names }),
@@ -197,6 +352,67 @@ impl Parser {
         }
     }
 
+    fn decl_infixr(&self) -> impl Fn(&str) -> IResult<&str, UntypedDeclaration> + '_ {
+        move |i| {
+            let (i, _) = tag("infixr")(i)?;
+            let (i, _) = self.space1()(i)?;
+            let (i, priority) = opt(digit1)(i)?;
+            let (i, _) = self.space1()(i)?;
+            let (i, names) = separated_nonempty_list(self.space1(), self.symbol_eq())(i)?;
+            let priority = priority.map(|s| {
+                s.parse()
+                    .expect("internal error: falied to parse digits as integer")
+            });
+            self.new_infix_op(priority, Assoc::Right, names.clone());
+            Ok((
+                i,
+                Declaration::D(DerivedDeclaration::Infixr { priority, names }),
+            ))
+        }
+    }
+
+    fn decl_local(&self) -> impl Fn(&str) -> IResult<&str, UntypedDeclaration> + '_ {
+        move |i| {
+            let (i, _) = tag("local")(i)?;
+            let (i, _) = self.space1()(i)?;
+            let (i, binds) = separated_list(self.space1(), self.decl())(i)?;
+            let (i, _) = self.space1()(i)?;
+            let (i, _) = tag("in")(i)?;
+            let (i, _) = self.space1()(i)?;
+            let (i, body) = separated_list(self.space1(), self.decl())(i)?;
+            let (i, _) = self.space1()(i)?;
+            let (i, _) = tag("end")(i)?;
+            Ok((i, Declaration::Local { binds, body }))
+        }
+    }
+
+    fn decl_structure(&self) -> impl Fn(&str) -> IResult<&str, UntypedDeclaration> + '_ {
+        move |i| {
+            let (i, _) = tag("structure")(i)?;
+            let (i, _) = self.space1()(i)?;
+            let (i, name) = self.symbol_alphanumeric()(i)?;
+            let (i, _) = self.space0()(i)?;
+            let (i, _) = tag("=")(i)?;
+            let (i, _) = self.space0()(i)?;
+            let (i, _) = tag("struct")(i)?;
+            let (i, _) = self.space1()(i)?;
+            // infix declarations in the structure are not visible outside
+            let (i, body) = self.with_scope(|| separated_list(self.space1(), self.decl())(i))?;
+            let (i, _) = self.space0()(i)?;
+            let (i, _) = tag("end")(i)?;
+            Ok((i, Declaration::Structure { name, body }))
+        }
+    }
+
+    fn decl_exception(&self) -> impl Fn(&str) -> IResult<&str, UntypedDeclaration> + '_ {
+        move |i| {
+            let (i, _) = tag("exception")(i)?;
+            let (i, _) = self.space1()(i)?;
+            let (i, (name, arg)) = self.constructor_def()(i)?;
+            Ok((i, Declaration::Exception { name, arg }))
+        }
+    }
+
     fn expr(&self) -> impl Fn(&str) -> IResult<&str, UntypedExpr> + '_ {
         move |i| {
             alt((
@@ -204,6 +420,7 @@ impl Parser {
                 self.expr_fun(),
                 self.expr_if(),
                 self.expr_case(),
+                self.expr_raise(),
                 self.expr_infix_and_app(),
             ))(i)
         }
@@ -215,9 +432,12 @@ impl Parser {
                 self.expr1_tuple(),
                 self.expr1_unit(),
                 self.expr1_paren(),
+                self.expr1_list(),
+                self.expr1_vector(),
                 self.expr1_float(),
                 self.expr1_int(),
                 self.expr1_char(),
+                self.expr1_string(),
                 self.expr1_bool(),
                 self.expr1_sym(),
                 self.expr1_builtincall(),
@@ -311,7 +531,7 @@ impl Parser {
             let (i, _) = self.space1()(i)?;
             let (i, clauses) = separated_nonempty_list(
                 tuple((self.space0(), tag("|"), self.space0())),
-                map(
+                self.with_position(map(
                     tuple((
                         self.pattern(),
                         self.space0(),
@@ -320,8 +540,9 @@ impl Parser {
                         self.expr(),
                     )),
                     |(pat, _, _, _, expr)| (pat, expr),
-                ),
+                )),
             )(i)?;
+            let (positions, clauses) = clauses.into_iter().unzip();
             Ok((
                 i,
                 Expr {
@@ -329,6 +550,25 @@ impl Parser {
                     inner: ExprKind::Case {
                         cond: cond.boxed(),
                         clauses,
+                        positions,
+                    },
+                },
+            ))
+        }
+    }
+
+    fn expr_raise(&self) -> impl Fn(&str) -> IResult<&str, UntypedExpr> + '_ {
+        move |i| {
+            let (i, _) = tag("raise")(i)?;
+            let (i, _) = self.space1()(i)?;
+            let (i, exn) = self.expr()(i)?;
+            Ok((
+                i,
+                Expr {
+                    ty: Empty {},
+                    inner: ExprKind::BuiltinCall {
+                        fun: BIF::Raise,
+                        args: vec![exn],
                     },
                 },
             ))
@@ -339,26 +579,28 @@ impl Parser {
     fn expr_infix_and_app(&self) -> impl Fn(&str) -> IResult<&str, UntypedExpr> + '_ {
         move |i| {
             // TODO: support 1+1
-            let (i, mixed) = many1(map(tuple((self.space0(), self.expr1())), |(_, e)| e))(i)?;
-            #[derive(Debug)]
-            enum Mixed {
-                E(UntypedExpr),
-                Fix(u8, Symbol),
-            }
+            let (i, mixed) = many1(map(
+                tuple((
+                    self.space0(),
+                    alt((
+                        map(self.expr1_op(), |e| (true, e)),
+                        map(self.expr1(), |e| (false, e)),
+                    )),
+                )),
+                |(_, e)| e,
+            ))(i)?;
             use Mixed::*;
-            // find infixes
+            // find infixes. `op` prefixed ones are not.
             let mixed = mixed
                 .into_iter()
-                .map(|mut e| match e.inner {
-                    ExprKind::Symbol { name } => {
-                        for (f, table) in self.get_table() {
-                            if table.contains(&name) {
-                                return Fix(f, name);
-                            }
+                .map(|(is_op, mut e)| match e.inner {
+                    ExprKind::Symbol { name } if !is_op => match self.get_fixity(&name) {
+                        Some((priority, assoc)) => Fix(priority, assoc, name),
+                        None => {
+                            e.inner = ExprKind::Symbol { name };
+                            E(e)
                         }
-                        e.inner = ExprKind::Symbol { name };
-                        E(e)
-                    }
+                    },
                     inner => {
                         e.inner = inner;
                         E(e)
@@ -381,41 +623,102 @@ impl Parser {
             });
 
             // reduce infixes
-            fn reduce_infixl_n(n: u8, mixed: Vec<Mixed>) -> Vec<Mixed> {
-                use Mixed::*;
-                map_window3(mixed, |m1, m2, m3| match (m1, m2, m3) {
-                    (E(l), Fix(fixty, op), E(r)) if fixty == n => (
-                        E(Expr {
+            let e = reduce_infixes(rest, |op, l, r| Expr {
+                ty: Empty {},
+                inner: ExprKind::App {
+                    fun: Expr {
+                        ty: Empty {},
+                        inner: ExprKind::Symbol { name: op },
+                    }
+                    .boxed(),
+                    arg: Expr {
+                        ty: Empty {},
+                        inner: ExprKind::Tuple { tuple: vec![l, r] },
+                    }
+                    .boxed(),
+                },
+            })
+            .ok_or_else(|| nom::Err::Error((i, nom::error::ErrorKind::SeparatedList)))?;
+            Ok((i, e))
+        }
+    }
+
+    /// `op sym`, which refers to `sym` even if it is an infix operator
+    fn expr1_op(&self) -> impl Fn(&str) -> IResult<&str, UntypedExpr> + '_ {
+        move |i| {
+            let (i, _) = tag("op")(i)?;
+            let (i, name) = alt((
+                preceded(self.space1(), self.symbol_alphanumeric()),
+                preceded(self.space0(), self.symbol_symbolic_eq()),
+            ))(i)?;
+            Ok((
+                i,
+                Expr {
+                    ty: Empty {},
+                    inner: ExprKind::Symbol { name },
+                },
+            ))
+        }
+    }
+
+    /// `[e1, e2, ...]`, the elements of list and vector literals
+    fn elems(&self) -> impl Fn(&str) -> IResult<&str, Vec<UntypedExpr>> + '_ {
+        move |i| {
+            let (i, _) = tag("[")(i)?;
+            let (i, _) = self.space0()(i)?;
+            let (i, es) =
+                separated_list(tuple((self.space0(), tag(","), self.space0())), self.expr())(i)?;
+            let (i, _) = self.space0()(i)?;
+            let (i, _) = tag("]")(i)?;
+            Ok((i, es))
+        }
+    }
+
+    /// `[e1, e2, ...]`, which is `e1 :: e2 :: ... :: nil`
+    fn expr1_list(&self) -> impl Fn(&str) -> IResult<&str, UntypedExpr> + '_ {
+        move |i| {
+            let (i, es) = self.elems()(i)?;
+            let nil = Expr {
+                ty: Empty {},
+                inner: ExprKind::Constructor {
+                    name: Symbol::new("nil"),
+                    arg: None,
+                },
+            };
+            let list = es.into_iter().rev().fold(nil, |tail, head| Expr {
+                ty: Empty {},
+                inner: ExprKind::Constructor {
+                    name: Symbol::new("::"),
+                    arg: Some(
+                        Expr {
                             ty: Empty {},
-                            inner: ExprKind::App {
-                                fun: Expr {
-                                    ty: Empty {},
-                                    inner: ExprKind::Symbol { name: op },
-                                }
-                                .boxed(),
-                                arg: Expr {
-                                    ty: Empty {},
-                                    inner: ExprKind::Tuple { tuple: vec![l, r] },
-                                }
-                                .boxed(),
+                            inner: ExprKind::Tuple {
+                                tuple: vec![head, tail],
                             },
-                        }),
-                        None,
+                        }
+                        .boxed(),
                     ),
-                    (m1, m2, m3) => (m1, Some((m2, m3))),
-                })
-            }
-            let mut rest = (1u8..=9)
-                .rev()
-                .fold(rest, |rest, n| reduce_infixl_n(n, rest));
-            assert_eq!(rest.len(), 1);
-            let e = match rest.remove(0) {
-                E(e) => e,
-                Fix(..) => unreachable!("infix alone"),
-            };
-            Ok((i, e))
+                },
+            });
+            Ok((i, list))
+        }
+    }
+
+    /// `#[e1, ..., en]`
+    fn expr1_vector(&self) -> impl Fn(&str) -> IResult<&str, UntypedExpr> + '_ {
+        move |i| {
+            let (i, _) = tag("#")(i)?;
+            let (i, elems) = self.elems()(i)?;
+            Ok((
+                i,
+                Expr {
+                    ty: Empty {},
+                    inner: ExprKind::D(DerivedExprKind::Vector { elems }),
+                },
+            ))
         }
     }
+
     fn expr1_sym(&self) -> impl Fn(&str) -> IResult<&str, UntypedExpr> + '_ {
         move |i| {
             // = is allowed to be used in expression exceptionally
@@ -470,16 +773,54 @@ impl Parser {
         }
     }
 
+    fn expr1_string(&self) -> impl Fn(&str) -> IResult<&str, UntypedExpr> + '_ {
+        move |i| {
+            let (i, s) = self.string_literal()(i)?;
+            let s = s
+                .into_iter()
+                .map(|c| std::char::from_u32(c).unwrap_or(std::char::REPLACEMENT_CHARACTER))
+                .collect();
+            Ok((
+                i,
+                Expr {
+                    ty: Empty {},
+                    inner: ExprKind::Literal {
+                        value: Literal::String(s),
+                    },
+                },
+            ))
+        }
+    }
+
     fn string_literal(&self) -> impl Fn(&str) -> IResult<&str, Vec<u32>> + '_ {
         move |i| {
             let (i, _) = tag("\"")(i)?;
             let mut s = vec![];
             let mut chars = i.chars();
             while let Some(c) = chars.next() {
-                if c == '"' {
-                    break;
+                match c {
+                    '"' => break,
+                    '\\' => {
+                        let escaped = match chars.next() {
+                            Some('n') => Some('\n' as u32),
+                            Some('t') => Some('\t' as u32),
+                            Some('\\') => Some('\\' as u32),
+                            Some('"') => Some('"' as u32),
+                            Some(d) if d.is_ascii_digit() => {
+                                // \ddd: exactly three decimal digits
+                                let digits: String =
+                                    std::iter::once(d).chain(chars.by_ref().take(2)).collect();
+                                digits.parse().ok().filter(|_| digits.len() == 3)
+                            }
+                            _ => None,
+                        };
+                        match escaped {
+                            Some(c) => s.push(c),
+                            None => return Err(nom::Err::Error((i, nom::error::ErrorKind::Char))),
+                        }
+                    }
+                    c => s.push(c as u32),
                 }
-                s.push(c as u32)
             }
             let i = chars.as_str();
             Ok((i, s))
@@ -576,6 +917,35 @@ impl Parser {
                 "ge" => Ok(BIF::Ge),
                 "lt" => Ok(BIF::Lt),
                 "le" => Ok(BIF::Le),
+                "raise" => Ok(BIF::Raise),
+                "ord" => Ok(BIF::Ord),
+                "chr" => Ok(BIF::Chr),
+                "real" => Ok(BIF::ToReal),
+                "floor" => Ok(BIF::Floor),
+                "ceil" => Ok(BIF::Ceil),
+                "round" => Ok(BIF::Round),
+                "trunc" => Ok(BIF::Trunc),
+                "strsize" => Ok(BIF::StringSize),
+                "strsub" => Ok(BIF::StringSub),
+                "stralloc" => Ok(BIF::StringAlloc),
+                "strupdate" => Ok(BIF::StringUpdate),
+                "arrayalloc" => Ok(BIF::ArrayAlloc),
+                "arraylength" => Ok(BIF::ArrayLength),
+                "arraysub" => Ok(BIF::ArraySub),
+                "arrayupdate" => Ok(BIF::ArrayUpdate),
+                "arraytovector" => Ok(BIF::ArrayToVector),
+                "vectortoarray" => Ok(BIF::VectorToArray),
+                "inttoint64" => Ok(BIF::IntToInt64),
+                "int64toint" => Ok(BIF::Int64ToInt),
+                "int64toword64" => Ok(BIF::Int64ToWord64),
+                "word64toint64" => Ok(BIF::Word64ToInt64),
+                "realtoreal32" => Ok(BIF::RealToReal32),
+                "real32toreal" => Ok(BIF::Real32ToReal),
+                "sqrt" => Ok(BIF::Sqrt),
+                "realfloor" => Ok(BIF::RealFloor),
+                "realceil" => Ok(BIF::RealCeil),
+                "realround" => Ok(BIF::RealRound),
+                "realtrunc" => Ok(BIF::RealTrunc),
                 _ => Err(nom::Err::Error(nom::error::ErrorKind::Tag)),
             })(i)?;
             let (i, _) = tag("\"")(i)?;
@@ -663,7 +1033,52 @@ impl Parser {
     }
 
     fn typename2(&self) -> impl Fn(&str) -> IResult<&str, Type> + '_ {
-        move |i| alt((self.typename2_paren(), self.typename2_datatype()))(i)
+        move |i| {
+            let (i, ty) = alt((
+                self.typename2_tyvar(),
+                self.typename2_args(),
+                self.typename2_paren(),
+                self.typename2_datatype(),
+            ))(i)?;
+            // type constructors applied postfix, like `int list list`
+            let (i, tycons) = many0(preceded(self.space1(), self.symbol_alphanumeric()))(i)?;
+            let ty = tycons
+                .into_iter()
+                .fold(ty, |ty, tycon| apply_tycon(tycon, vec![ty]));
+            Ok((i, ty))
+        }
+    }
+
+    fn typename2_tyvar(&self) -> impl Fn(&str) -> IResult<&str, Type> + '_ {
+        move |i| {
+            let (i, tyvar) = map_res(self.tyvar(), |tyvar| {
+                self.tyvars
+                    .borrow()
+                    .iter()
+                    .position(|t| t == &tyvar)
+                    .ok_or(nom::error::ErrorKind::Verify)
+            })(i)?;
+            Ok((i, Type::Variable(tyvar as u64)))
+        }
+    }
+
+    /// `(ty1, ty2, ...) tycon`
+    fn typename2_args(&self) -> impl Fn(&str) -> IResult<&str, Type> + '_ {
+        move |i| {
+            let (i, _) = tag("(")(i)?;
+            let (i, _) = self.space0()(i)?;
+            let sep = || tuple((self.space0(), tag(","), self.space0()));
+            let (i, tys) = many1(map(tuple((self.typename(), sep())), |(ty, _)| ty))(i)?;
+            let (i, ty) = self.typename()(i)?;
+            let (i, _) = self.space0()(i)?;
+            let (i, _) = tag(")")(i)?;
+            let (i, _) = self.space0()(i)?;
+            let (i, tycon) = self.symbol_alphanumeric()(i)?;
+
+            let mut tys = tys;
+            tys.push(ty);
+            Ok((i, apply_tycon(tycon, tys)))
+        }
     }
 
     fn typename0_fun(&self) -> impl Fn(&str) -> IResult<&str, Type> + '_ {
@@ -707,7 +1122,12 @@ impl Parser {
                 "unit" => Type::Tuple(vec![]),
                 "real" => Type::Real,
                 "int" => Type::Int,
-                _ => Type::Datatype(name),
+                "char" => Type::Char,
+                "string" => Type::String,
+                "Int64.int" => Type::Int64,
+                "Word64.word" => Type::Word64,
+                "Real32.real" => Type::Real32,
+                _ => Type::Datatype(name, vec![]),
             })(i)
         }
     }
@@ -717,7 +1137,13 @@ impl Parser {
     }
 
     fn symbol(&self) -> impl Fn(&str) -> IResult<&str, Symbol> + '_ {
-        move |i| alt((self.symbol_alphanumeric(), self.symbol_symbolic()))(i)
+        move |i| {
+            alt((
+                self.symbol_alphanumeric(),
+                self.symbol_qualified_symbolic(),
+                self.symbol_symbolic(),
+            ))(i)
+        }
     }
 
     fn op_symbol_eq(&self) -> impl Fn(&str) -> IResult<&str, Symbol> + '_ {
@@ -738,14 +1164,34 @@ impl Parser {
         }
     }
 
+    /// identifiers, possibly qualified with structure names like `List.map`
     fn symbol_alphanumeric(&self) -> impl Fn(&str) -> IResult<&str, Symbol> + '_ {
         move |i| {
-            // FIXME: collect syntax is [a-zA-Z'_][a-zA-Z'_0-9]*
-            let (i, sym) = verify(alphanumeric1, |s: &str| !KEYWORDS.contains(&s))(i)?;
+            fn ident(i: &str) -> IResult<&str, &str> {
+                recognize(tuple((
+                    nom::character::complete::alpha1,
+                    many0(alt((alphanumeric1, tag("_"), tag("'")))),
+                )))(i)
+            }
+            let long_ident = recognize(tuple((many0(tuple((ident, tag(".")))), ident)));
+            let (i, sym) = verify(long_ident, |s: &str| !KEYWORDS.contains(&s))(i)?;
             Ok((i, Symbol::new(sym.to_string())))
         }
     }
 
+    /// symbolic identifiers qualified with structure names like `IntInf.+`
+    fn symbol_qualified_symbolic(&self) -> impl Fn(&str) -> IResult<&str, Symbol> + '_ {
+        move |i| {
+            let ident = recognize(tuple((
+                nom::character::complete::alpha1,
+                many0(alt((alphanumeric1, tag("_"), tag("'")))),
+            )));
+            let (i, qualifier) = recognize(many1(tuple((ident, tag(".")))))(i)?;
+            let (i, sym) = self.symbol_symbolic()(i)?;
+            Ok((i, Symbol::new(format!("{}{}", qualifier, sym.0))))
+        }
+    }
+
     fn symbol_symbolic_eq(&self) -> impl Fn(&str) -> IResult<&str, Symbol> + '_ {
         move |i| alt((self.symbol_symbolic(), value(Symbol::new("="), tag("="))))(i)
     }
@@ -753,7 +1199,7 @@ impl Parser {
     fn symbol_symbolic(&self) -> impl Fn(&str) -> IResult<&str, Symbol> + '_ {
         move |i| {
             let symbolic1 = recognize(many1(nom::character::complete::one_of(
-                "!%&$#+-/:<=>?@\\~'^|*",
+                "!%&$#+-/:<=>?@\\~`^|*",
             )));
 
             let (i, sym) = verify(symbolic1, |s: &str| {
@@ -812,8 +1258,88 @@ impl Parser {
         }
     }
 
+    // treat constructor applications and infix constructors, i.e. sequences of patterns
     fn pattern(&self) -> impl Fn(&str) -> IResult<&str, UntypedPattern> + '_ {
-        move |i| alt((self.pattern_constructor(), self.pattern_atmic()))(i)
+        move |i| {
+            let (i, mixed) = many1(map(
+                tuple((
+                    self.space0(),
+                    alt((
+                        map(self.pattern_op(), |p| (true, p)),
+                        map(self.pattern_atmic(), |p| (false, p)),
+                    )),
+                )),
+                |(_, p)| p,
+            ))(i)?;
+            use Mixed::*;
+            // find infixes. `op` prefixed ones are not.
+            let mixed = mixed
+                .into_iter()
+                .map(|(is_op, mut p)| match p.inner {
+                    PatternKind::Variable { name } if !is_op => match self.get_fixity(&name) {
+                        Some((priority, assoc)) => Fix(priority, assoc, name),
+                        None => {
+                            p.inner = PatternKind::Variable { name };
+                            E(p)
+                        }
+                    },
+                    inner => {
+                        p.inner = inner;
+                        E(p)
+                    }
+                })
+                .collect::<Vec<_>>();
+            // reduce constructor applications
+            let rest = map_window2(mixed, |m1, m2| match (m1, m2) {
+                (
+                    E(Pattern {
+                        inner: PatternKind::Variable { name },
+                        ..
+                    }),
+                    E(arg),
+                ) => (
+                    E(Pattern {
+                        ty: Empty {},
+                        inner: PatternKind::Constructor {
+                            name,
+                            arg: Some(Box::new(arg)),
+                        },
+                    }),
+                    None,
+                ),
+                (m1, m2) => (m1, Some(m2)),
+            });
+            // reduce infixes
+            let p = reduce_infixes(rest, |op, l, r| Pattern {
+                ty: Empty {},
+                inner: PatternKind::Constructor {
+                    name: op,
+                    arg: Some(Box::new(Pattern {
+                        ty: Empty {},
+                        inner: PatternKind::Tuple { tuple: vec![l, r] },
+                    })),
+                },
+            })
+            .ok_or_else(|| nom::Err::Error((i, nom::error::ErrorKind::SeparatedList)))?;
+            Ok((i, p))
+        }
+    }
+
+    fn pattern_op(&self) -> impl Fn(&str) -> IResult<&str, UntypedPattern> + '_ {
+        move |i| {
+            let (i, _) = tag("op")(i)?;
+            let (i, name) = alt((
+                preceded(self.space1(), self.symbol_alphanumeric()),
+                preceded(self.space0(), self.symbol_symbolic()),
+            ))(i)?;
+            Ok((
+                i,
+                Pattern {
+                    ty: Empty {},
+                    inner: PatternKind::Variable { name },
+                },
+            ))
+        }
     }
 
     fn pattern_atmic(&self) -> impl Fn(&str) -> IResult<&str, UntypedPattern> + '_ {
@@ -827,6 +1353,7 @@ impl Parser {
                 self.pattern_wildcard(),
                 self.pattern_unit(),
                 self.pattern_paren(),
+                self.pattern_list(),
             ))(i)
         }
     }
@@ -913,24 +1440,37 @@ impl Parser {
         }
     }
 
-    // require constructor to have arg for now.
-    // constructor withouth arg is parsed as variable and
-    //  will be converted in later phases
-    fn pattern_constructor(&self) -> impl Fn(&str) -> IResult<&str, UntypedPattern> + '_ {
+    /// `[p1, p2, ...]`, which is `p1 :: p2 :: ... :: nil`
+    fn pattern_list(&self) -> impl Fn(&str) -> IResult<&str, UntypedPattern> + '_ {
         move |i| {
-            let (i, name) = self.symbol()(i)?;
+            let (i, _) = tag("[")(i)?;
             let (i, _) = self.space0()(i)?;
-            let (i, arg) = self.pattern_atmic()(i)?;
-            Ok((
-                i,
-                Pattern {
-                    ty: Empty {},
-                    inner: PatternKind::Constructor {
-                        name,
-                        arg: Some(Box::new(arg)),
-                    },
+            let (i, ps) = separated_list(
+                tuple((self.space0(), tag(","), self.space0())),
+                self.pattern(),
+            )(i)?;
+            let (i, _) = self.space0()(i)?;
+            let (i, _) = tag("]")(i)?;
+            let nil = Pattern {
+                ty: Empty {},
+                inner: PatternKind::Constructor {
+                    name: Symbol::new("nil"),
+                    arg: None,
                 },
-            ))
+            };
+            let list = ps.into_iter().rev().fold(nil, |tail, head| Pattern {
+                ty: Empty {},
+                inner: PatternKind::Constructor {
+                    name: Symbol::new("::"),
+                    arg: Some(Box::new(Pattern {
+                        ty: Empty {},
+                        inner: PatternKind::Tuple {
+                            tuple: vec![head, tail],
+                        },
+                    })),
+                },
+            });
+            Ok((i, list))
         }
     }
 
@@ -968,6 +1508,43 @@ impl Parser {
     }
 }
 
+#[derive(Debug)]
+enum Mixed<E> {
+    E(E),
+    Fix(u8, Assoc, Symbol),
+}
+
+/// reduce the infix operators in `mixed` from the highest priority.
+/// Returns `None` if operators are left without operands.
+fn reduce_infixes<E>(mixed: Vec<Mixed<E>>, mut apply: impl FnMut(Symbol, E, E) -> E) -> Option<E> {
+    use Mixed::*;
+    let mut rest = mixed;
+    for n in (0u8..=9).rev() {
+        rest = map_window3(rest, |m1, m2, m3| match (m1, m2, m3) {
+            (E(l), Fix(priority, Assoc::Left, op), E(r)) if priority == n => {
+                (E(apply(op, l, r)), None)
+            }
+            (m1, m2, m3) => (m1, Some((m2, m3))),
+        });
+        // right associative operators are reduced from the right
+        rest.reverse();
+        rest = map_window3(rest, |m1, m2, m3| match (m1, m2, m3) {
+            (E(r), Fix(priority, Assoc::Right, op), E(l)) if priority == n => {
+                (E(apply(op, l, r)), None)
+            }
+            (m1, m2, m3) => (m1, Some((m2, m3))),
+        });
+        rest.reverse();
+    }
+    if rest.len() != 1 {
+        return None;
+    }
+    match rest.remove(0) {
+        E(e) => Some(e),
+        Fix(..) => None,
+    }
+}
+
 fn map_window2<I>(
     iter: I,
     mut f: impl FnMut(I::Item, I::Item) -> (I::Item, Option<I::Item>),
@@ -1047,7 +1624,7 @@ where
 #[test]
 fn test_expr_infix_and_app() {
     let input = "true";
-    let ret = Parser::new().expr_infix_and_app()(input).unwrap();
+    let ret = Parser::new(input).expr_infix_and_app()(input).unwrap();
     assert_eq!(
         ret,
         (
@@ -1066,7 +1643,7 @@ fn test_expr_infix_and_app() {
 #[test]
 fn test_expr_infix_and_app2() {
     let input = "f arg";
-    let ret = Parser::new().expr_infix_and_app()(input).unwrap();
+    let ret = Parser::new(input).expr_infix_and_app()(input).unwrap();
     assert_eq!(
         ret,
         (
@@ -1097,7 +1674,20 @@ fn test_expr_infix_and_app2() {
 pub fn parse(
     input: &str,
 ) -> ::std::result::Result<UntypedAst, nom::Err<(&str, nom::error::ErrorKind)>> {
-    let parser = Parser::new();
+    let parser = Parser::new(input);
+    let (_, iresult) = all_consuming(parser.top())(input)?;
+    Ok(iresult)
+}
+
+/// parse `input`, named `source` in the positions, with the operators of `fixity` in effect,
+/// and add the operators `input` declares to `fixity`
+pub fn parse_with_fixity<'a>(
+    source: &str,
+    input: &'a str,
+    fixity: &mut Fixity,
+) -> ::std::result::Result<UntypedAst, nom::Err<(&'a str, nom::error::ErrorKind)>> {
+    let parser = Parser::with_fixity(fixity.clone(), source, input);
     let (_, iresult) = all_consuming(parser.top())(input)?;
+    *fixity = Fixity(parser.get_table());
     Ok(iresult)
 }
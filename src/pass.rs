@@ -88,6 +88,24 @@ where
     }
 }
 
+/// runs the optimization `self.1` only if `Config` enables it
+pub struct OptionalPass<T>(pub T, pub &'static str);
+
+impl<T, In, Err> Pass<In, Err> for OptionalPass<T>
+where
+    T: Pass<In, Err, Target = In>,
+{
+    type Target = In;
+
+    fn trans(&mut self, i: In, config: &Config) -> Result<Self::Target, Err> {
+        if !config.pass_enabled(self.1) {
+            info!("pass skipped: {}", self.1);
+            return Ok(i);
+        }
+        self.0.trans(i, config)
+    }
+}
+
 pub struct Chain<F, FO, S, SO> {
     pub fst: F,
     pub snd: S,
@@ -151,13 +169,17 @@ where
 
 #[macro_export]
 macro_rules! compile_pass {
-    ($($labels: ident : $passes: expr,)*) => {
-        compile_pass!($($labels: $passes),*)
+    // `label?: pass` is an optimization, which `Config` may skip
+    ($label: ident ?: $pass: expr $(,)?) => {
+        PrintablePass(OptionalPass($pass, stringify!($label)), stringify!($label))
     };
-    ($label: ident : $pass: expr, $($labels: ident : $passes: expr),*) => {
-        Chain::new(PrintablePass($pass, stringify!($label)), compile_pass!($($labels: $passes),*))
-    };
-    ($label: ident : $pass: expr) => {
+    ($label: ident : $pass: expr $(,)?) => {
         PrintablePass($pass, stringify!($label))
     };
+    ($label: ident ?: $pass: expr, $($rest: tt)+) => {
+        Chain::new(compile_pass!($label?: $pass), compile_pass!($($rest)+))
+    };
+    ($label: ident : $pass: expr, $($rest: tt)+) => {
+        Chain::new(compile_pass!($label: $pass), compile_pass!($($rest)+))
+    };
 }
@@ -1,8 +1,9 @@
 use crate::util::PP;
+use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::io;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Symbol(pub String, pub u64);
 
 impl Symbol {
@@ -24,11 +25,12 @@ impl fmt::Display for Symbol {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Literal {
     Int(i64),
     Real(f64),
     Char(u32),
+    String(String),
 }
 
 impl PP for Literal {
@@ -44,6 +46,9 @@ impl PP for Literal {
             Char(c) => {
                 write!(w, r##"#"{}""##, c)?;
             }
+            String(s) => {
+                write!(w, "{:?}", s)?;
+            }
         }
         Ok(())
     }
@@ -62,12 +67,15 @@ impl fmt::Display for Literal {
             Char(c) => {
                 write!(f, r##"#"{}""##, c)?;
             }
+            String(s) => {
+                write!(f, "{:?}", s)?;
+            }
         }
         Ok(())
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum BIF {
     Add,
     Sub,
@@ -81,6 +89,57 @@ pub enum BIF {
     Ge,
     Lt,
     Le,
+    Raise,
+    /// char -> int
+    Ord,
+    /// int -> char, without range checks
+    Chr,
+    /// int -> real
+    ToReal,
+    /// real -> int
+    Floor,
+    Ceil,
+    Round,
+    Trunc,
+    /// string -> int
+    StringSize,
+    /// string * int -> char, without bounds checks
+    StringSub,
+    /// int -> string, allocates a string of the given size with unspecified contents
+    StringAlloc,
+    /// string * int * char -> unit, without bounds checks
+    StringUpdate,
+    /// int -> 'a array, allocates an array of the given length with unspecified contents
+    ArrayAlloc,
+    /// 'a array -> int
+    ArrayLength,
+    /// 'a array * int -> 'a, without bounds checks
+    ArraySub,
+    /// 'a array * int * 'a -> unit, without bounds checks
+    ArrayUpdate,
+    /// 'a array -> 'a vector, without copying
+    ArrayToVector,
+    /// 'a vector -> 'a array, without copying
+    VectorToArray,
+    /// int -> Int64.int
+    IntToInt64,
+    /// Int64.int -> int, dropping the upper bits
+    Int64ToInt,
+    /// Int64.int -> Word64.word, keeping the bits
+    Int64ToWord64,
+    /// Word64.word -> Int64.int, keeping the bits
+    Word64ToInt64,
+    /// real -> Real32.real, rounding to the nearest
+    RealToReal32,
+    /// Real32.real -> real
+    Real32ToReal,
+    /// real -> real
+    Sqrt,
+    /// real -> real, rounding to an integral value
+    RealFloor,
+    RealCeil,
+    RealRound,
+    RealTrunc,
 }
 
 impl PP for BIF {
@@ -123,6 +182,93 @@ impl PP for BIF {
             Le => {
                 write!(w, "le")?;
             }
+            Raise => {
+                write!(w, "raise")?;
+            }
+            Ord => {
+                write!(w, "ord")?;
+            }
+            Chr => {
+                write!(w, "chr")?;
+            }
+            ToReal => {
+                write!(w, "real")?;
+            }
+            Floor => {
+                write!(w, "floor")?;
+            }
+            Ceil => {
+                write!(w, "ceil")?;
+            }
+            Round => {
+                write!(w, "round")?;
+            }
+            Trunc => {
+                write!(w, "trunc")?;
+            }
+            StringSize => {
+                write!(w, "strsize")?;
+            }
+            StringSub => {
+                write!(w, "strsub")?;
+            }
+            StringAlloc => {
+                write!(w, "stralloc")?;
+            }
+            StringUpdate => {
+                write!(w, "strupdate")?;
+            }
+            ArrayAlloc => {
+                write!(w, "arrayalloc")?;
+            }
+            ArrayLength => {
+                write!(w, "arraylength")?;
+            }
+            ArraySub => {
+                write!(w, "arraysub")?;
+            }
+            ArrayUpdate => {
+                write!(w, "arrayupdate")?;
+            }
+            ArrayToVector => {
+                write!(w, "arraytovector")?;
+            }
+            VectorToArray => {
+                write!(w, "vectortoarray")?;
+            }
+            IntToInt64 => {
+                write!(w, "inttoint64")?;
+            }
+            Int64ToInt => {
+                write!(w, "int64toint")?;
+            }
+            Int64ToWord64 => {
+                write!(w, "int64toword64")?;
+            }
+            Word64ToInt64 => {
+                write!(w, "word64toint64")?;
+            }
+            RealToReal32 => {
+                write!(w, "realtoreal32")?;
+            }
+            Real32ToReal => {
+                write!(w, "real32toreal")?;
+            }
+            Sqrt => {
+                write!(w, "sqrt")?;
+            }
+            RealFloor => {
+                write!(w, "realfloor")?;
+            }
+            RealCeil => {
+                write!(w, "realceil")?;
+            }
+            RealRound => {
+                write!(w, "realround")?;
+            }
+            RealTrunc => {
+                write!(w, "realtrunc")?;
+            }
         }
         Ok(())
     }
@@ -168,6 +314,93 @@ impl fmt::Display for BIF {
             Le => {
                 write!(f, "le")?;
             }
+            Raise => {
+                write!(f, "raise")?;
+            }
+            Ord => {
+                write!(f, "ord")?;
+            }
+            Chr => {
+                write!(f, "chr")?;
+            }
+            ToReal => {
+                write!(f, "real")?;
+            }
+            Floor => {
+                write!(f, "floor")?;
+            }
+            Ceil => {
+                write!(f, "ceil")?;
+            }
+            Round => {
+                write!(f, "round")?;
+            }
+            Trunc => {
+                write!(f, "trunc")?;
+            }
+            StringSize => {
+                write!(f, "strsize")?;
+            }
+            StringSub => {
+                write!(f, "strsub")?;
+            }
+            StringAlloc => {
+                write!(f, "stralloc")?;
+            }
+            StringUpdate => {
+                write!(f, "strupdate")?;
+            }
+            ArrayAlloc => {
+                write!(f, "arrayalloc")?;
+            }
+            ArrayLength => {
+                write!(f, "arraylength")?;
+            }
+            ArraySub => {
+                write!(f, "arraysub")?;
+            }
+            ArrayUpdate => {
+                write!(f, "arrayupdate")?;
+            }
+            ArrayToVector => {
+                write!(f, "arraytovector")?;
+            }
+            VectorToArray => {
+                write!(f, "vectortoarray")?;
+            }
+            IntToInt64 => {
+                write!(f, "inttoint64")?;
+            }
+            Int64ToInt => {
+                write!(f, "int64toint")?;
+            }
+            Int64ToWord64 => {
+                write!(f, "int64toword64")?;
+            }
+            Word64ToInt64 => {
+                write!(f, "word64toint64")?;
+            }
+            RealToReal32 => {
+                write!(f, "realtoreal32")?;
+            }
+            Real32ToReal => {
+                write!(f, "real32toreal")?;
+            }
+            Sqrt => {
+                write!(f, "sqrt")?;
+            }
+            RealFloor => {
+                write!(f, "realfloor")?;
+            }
+            RealCeil => {
+                write!(f, "realceil")?;
+            }
+            RealRound => {
+                write!(f, "realround")?;
+            }
+            RealTrunc => {
+                write!(f, "realtrunc")?;
+            }
         }
         Ok(())
     }
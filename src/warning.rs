@@ -0,0 +1,39 @@
+use crate::ast::Position;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+/// a problem in the program which doesn't stop compiling it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    /// `None` for the code the compiler made
+    pub position: Option<Position>,
+    pub message: String,
+}
+
+/// the warnings the passes found, for the caller to report. The clones share them
+#[derive(Debug, Clone, Default)]
+pub struct Warnings(Rc<RefCell<Vec<Warning>>>);
+
+impl Warnings {
+    pub fn push(&self, position: Option<Position>, message: impl Into<String>) {
+        self.0.borrow_mut().push(Warning {
+            position,
+            message: message.into(),
+        })
+    }
+
+    /// removes the warnings found so far and returns them
+    pub fn take(&self) -> Vec<Warning> {
+        self.0.borrow_mut().drain(..).collect()
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(position) = &self.position {
+            write!(f, "{}: ", position)?;
+        }
+        write!(f, "warning: {}", self.message)
+    }
+}
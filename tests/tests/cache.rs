@@ -0,0 +1,115 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use webml::cache::Cache;
+use webml::mlb::Project;
+use webml::{compile_project_cached, Config};
+
+fn cache_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("webml-test-cache-{}", name));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+fn project(main: &str) -> Project {
+    let mut project = Project::new();
+    project.push_source("prelude.sml", include_str!("../../ml_src/prelude.sml"));
+    project.push_source("main.sml", main);
+    project
+}
+
+fn build_with(project: &Project, dir: &PathBuf, config: &Config) -> Cache {
+    let cache = Cache::new(dir);
+    if let Err(e) = compile_project_cached(project, &cache, config) {
+        panic!("failed to compile: {}", e)
+    }
+    cache
+}
+
+fn build(project: &Project, dir: &PathBuf) -> (usize, usize) {
+    let cache = build_with(project, dir, &Config::default());
+    (cache.hits(), cache.misses())
+}
+
+#[test]
+fn cache_reuses_unchanged_declarations() {
+    let dir = cache_dir("unchanged");
+    let original = project("val x = 1\nfun f y = y + x\nval z = f 2\nval _ = print z");
+    let (hits, misses) = build(&original, &dir);
+    assert_eq!(hits, 0);
+    assert!(misses > 0);
+
+    let (hits, rebuilt) = build(&original, &dir);
+    assert_eq!((hits, rebuilt), (misses, 0));
+
+    let changed = project("val x = 1\nfun f y = y + x + 1\nval z = f 2\nval _ = print z");
+    let (hits, rebuilt) = build(&changed, &dir);
+    assert_eq!((hits, rebuilt), (misses - 1, 1));
+}
+
+#[test]
+fn cache_reuses_moved_declarations() {
+    let dir = cache_dir("moved");
+    let source = "fun f 0 = 1\n  | f n = case n of 1 => 2 | _ => n\nval _ = print (f 3)";
+    let (_, misses) = build(&project(source), &dir);
+
+    let moved = project(&format!("(* a line above *)\n{}", source));
+    let (hits, rebuilt) = build(&moved, &dir);
+    assert_eq!((hits, rebuilt), (misses, 0));
+}
+
+#[test]
+fn cache_recompiles_dependents_of_changed_interface() {
+    let dir = cache_dir("interface");
+    let (_, misses) = build(&project("val x = 1\nval y = x"), &dir);
+    let (hits, rebuilt) = build(&project("val x = 1.0\nval y = x"), &dir);
+    assert_eq!((hits, rebuilt), (misses - 2, 2));
+}
+
+#[test]
+fn cache_reuses_unchanged_program() {
+    let dir = cache_dir("program");
+    let original = project("val x = 1\nval _ = print x");
+    let config = Config::default();
+    assert_eq!(build_with(&original, &dir, &config).program_hits(), 0);
+    assert_eq!(build_with(&original, &dir, &config).program_hits(), 1);
+
+    let changed = project("val x = 2\nval _ = print x");
+    assert_eq!(build_with(&changed, &dir, &config).program_hits(), 0);
+}
+
+#[test]
+fn cache_keys_by_options() {
+    let dir = cache_dir("options");
+    let original = project("val x = 1\nval _ = print x");
+    let (_, misses) = build(&original, &dir);
+
+    let unoptimized = Config {
+        opt_level: 0,
+        ..Config::default()
+    };
+    let cache = build_with(&original, &dir, &unoptimized);
+    assert_eq!((cache.hits(), cache.program_hits()), (0, 0));
+
+    // the same passes run as by default
+    let mut enabled_passes = HashSet::new();
+    enabled_passes.insert("inlining".to_string());
+    let same = Config {
+        enabled_passes,
+        ..Config::default()
+    };
+    let cache = build_with(&original, &dir, &same);
+    assert_eq!((cache.hits(), cache.program_hits()), (misses, 1));
+}
+
+#[test]
+fn cache_ignores_broken_entries() {
+    let dir = cache_dir("broken");
+    let original = project("val x = 1");
+    build(&original, &dir);
+    for entry in fs::read_dir(&dir).unwrap() {
+        fs::write(entry.unwrap().path(), b"broken").unwrap();
+    }
+    let (hits, _) = build(&original, &dir);
+    assert_eq!(hits, 0);
+}
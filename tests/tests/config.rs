@@ -0,0 +1,51 @@
+use std::collections::HashSet;
+use webml::Config;
+
+#[test]
+fn inline_threshold_follows_opt_level() {
+    let config = Config::default();
+    assert_eq!(
+        config.effective_inline_threshold(),
+        Config::DEFAULT_INLINE_THRESHOLD
+    );
+
+    let config = Config {
+        opt_level: 3,
+        ..Config::default()
+    };
+    assert_eq!(
+        config.effective_inline_threshold(),
+        Config::AGGRESSIVE_INLINE_THRESHOLD
+    );
+}
+
+#[test]
+fn inline_threshold_overrides_opt_level() {
+    let config = Config {
+        opt_level: 3,
+        inline_threshold: Some(5),
+        ..Config::default()
+    };
+    assert_eq!(config.effective_inline_threshold(), 5);
+}
+
+#[test]
+fn uncurrying_requires_known_call() {
+    let mut disabled_passes = HashSet::new();
+    disabled_passes.insert("known_call".to_string());
+    let config = Config {
+        disabled_passes,
+        ..Config::default()
+    };
+    assert!(!config.pass_enabled("uncurrying"));
+
+    let mut enabled_passes = HashSet::new();
+    enabled_passes.insert("uncurrying".to_string());
+    let config = Config {
+        opt_level: 0,
+        enabled_passes,
+        ..Config::default()
+    };
+    assert!(!config.pass_enabled("uncurrying"));
+    assert!(Config::default().pass_enabled("uncurrying"));
+}
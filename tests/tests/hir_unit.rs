@@ -0,0 +1,51 @@
+use webml::mlb::Project;
+use webml::{compile_project_hir, compile_unit, lower_units, Config, HirUnit};
+
+#[test]
+fn hir_unit_roundtrip() {
+    let config = Config::default();
+    let unit = compile_unit("unit", "val x = 1", &[], &config).unwrap();
+    let bytes = unit.to_wasm();
+    assert!(bytes.starts_with(b"\0asm"));
+
+    let read = HirUnit::from_wasm(&bytes).unwrap();
+    assert_eq!(read.name, "unit");
+    assert_eq!(read.interface, unit.interface);
+    assert_eq!(
+        HirUnit::interface_from_wasm(&bytes).unwrap(),
+        unit.interface
+    );
+}
+
+#[test]
+fn hir_unit_not_wasm() {
+    assert!(HirUnit::from_wasm(b"val x = 1").is_err());
+}
+
+#[test]
+fn hir_unit_compile_against_interface() {
+    let config = Config::default();
+    let lib = compile_unit("lib", "fun double x = (x, x)", &[], &config).unwrap();
+    let main = compile_unit("main", "val y = double 2", &[&lib.interface], &config).unwrap();
+    assert!(compile_unit("main", "val y = triple 2", &[&lib.interface], &config).is_err());
+    lower_units(vec![lib, main], &config);
+}
+
+#[test]
+fn hir_unit_lower_project() {
+    let mut project = Project::new();
+    project.push_source("prelude.sml", include_str!("../../ml_src/prelude.sml"));
+    project
+        .load("tests/tests/mlb/project.mlb")
+        .expect("failed to load project");
+    let config = Config::default();
+    let units = match compile_project_hir(&project, &[], &config) {
+        Ok(units) => units,
+        Err(e) => panic!("failed to compile project.mlb: {}", e),
+    };
+    let units = units
+        .iter()
+        .map(|unit| HirUnit::from_wasm(&unit.to_wasm()).unwrap())
+        .collect();
+    lower_units(units, &config);
+}
@@ -0,0 +1,64 @@
+use webml::mlb::{Project, Unit};
+use webml::{compile_project, Config};
+
+fn load(path: &str) -> Project {
+    let mut project = Project::new();
+    project.push_source("prelude.sml", include_str!("../../ml_src/prelude.sml"));
+    project.load(path).expect("failed to load project");
+    project
+}
+
+#[test]
+fn mlb_compile_pass() {
+    let project = load("tests/tests/mlb/project.mlb");
+    if let Err(e) = compile_project(&project, &Config::default()) {
+        panic!("failed to compile project.mlb: {}", e)
+    }
+}
+
+#[test]
+fn mlb_local_hides_bindings() {
+    let project = load("tests/tests/mlb/hidden.mlb");
+    assert!(compile_project(&project, &Config::default()).is_err())
+}
+
+/// the number of times the code of the source `name` runs
+fn runs_of(units: &[Unit], name: &str) -> usize {
+    units
+        .iter()
+        .map(|unit| match unit {
+            Unit::Source { path, .. } => path.ends_with(name) as usize,
+            Unit::Local { binds, body } => runs_of(binds, name) + runs_of(body, name),
+            Unit::Bindings { .. } => 0,
+        })
+        .sum()
+}
+
+fn compile_once(path: &str) {
+    let project = load(path);
+    assert_eq!(runs_of(&project.units, "helper.sml"), 1);
+    if let Err(e) = compile_project(&project, &Config::default()) {
+        panic!("failed to compile {}: {}", path, e)
+    }
+}
+
+#[test]
+fn mlb_open_twice() {
+    compile_once("tests/tests/mlb/twice.mlb");
+}
+
+#[test]
+fn mlb_reference_after_local() {
+    compile_once("tests/tests/mlb/reexport.mlb");
+}
+
+#[test]
+fn mlb_reference_after_unopened_basis() {
+    compile_once("tests/tests/mlb/unopened.mlb");
+}
+
+#[test]
+fn mlb_missing_file() {
+    let mut project = Project::new();
+    assert!(project.load("tests/tests/mlb/no_such_file.mlb").is_err());
+}
@@ -1,2 +1,7 @@
+pub mod cache;
 pub mod compile;
+pub mod config;
+pub mod hir_unit;
+pub mod mlb;
 pub mod parser;
+pub mod warning;
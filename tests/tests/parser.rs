@@ -1,10 +1,19 @@
 use webml::ast::{
     Declaration, DerivedDeclaration, DerivedExprKind, Empty, Expr, ExprKind, Pattern, PatternKind,
-    Type, AST,
+    Position, Type, AST,
 };
 use webml::parse;
 use webml::prim::*;
 
+/// the position of `column` in the one-line inputs
+fn pos(column: usize) -> Position {
+    Position {
+        source: "".into(),
+        line: 1,
+        column,
+    }
+}
+
 #[test]
 fn parse_char() {
     let input = r##"val x = #"a""##;
@@ -29,6 +38,83 @@ fn parse_char() {
     )
 }
 
+#[test]
+fn parse_string() {
+    let input = r#"val x = "a\tb\"\065\\""#;
+    let ast = parse(input).unwrap();
+    assert_eq!(
+        ast,
+        AST(vec![Declaration::Val {
+            rec: false,
+            pattern: Pattern {
+                ty: Empty {},
+                inner: PatternKind::Variable {
+                    name: Symbol::new("x"),
+                }
+            },
+            expr: Expr {
+                ty: Empty {},
+                inner: ExprKind::Literal {
+                    value: Literal::String("a\tb\"A\\".to_string()),
+                }
+            },
+        },])
+    )
+}
+
+#[test]
+fn parse_vector() {
+    let input = r#"val x = #[1]"#;
+    let ast = parse(input).unwrap();
+    assert_eq!(
+        ast,
+        AST(vec![Declaration::Val {
+            rec: false,
+            pattern: Pattern {
+                ty: Empty {},
+                inner: PatternKind::Variable {
+                    name: Symbol::new("x"),
+                }
+            },
+            expr: Expr {
+                ty: Empty {},
+                inner: ExprKind::D(DerivedExprKind::Vector {
+                    elems: vec![Expr {
+                        ty: Empty {},
+                        inner: ExprKind::Literal {
+                            value: Literal::Int(1),
+                        }
+                    }],
+                }),
+            },
+        },])
+    )
+}
+
+#[test]
+fn parse_qualified_symbolic() {
+    let input = r#"val x = IntInf.~"#;
+    let ast = parse(input).unwrap();
+    assert_eq!(
+        ast,
+        AST(vec![Declaration::Val {
+            rec: false,
+            pattern: Pattern {
+                ty: Empty {},
+                inner: PatternKind::Variable {
+                    name: Symbol::new("x"),
+                }
+            },
+            expr: Expr {
+                ty: Empty {},
+                inner: ExprKind::Symbol {
+                    name: Symbol::new("IntInf.~"),
+                }
+            },
+        },])
+    )
+}
+
 #[test]
 fn parse_int() {
     let input = r#"val x = 1"#;
@@ -633,6 +719,7 @@ fn parse_datatype_single() {
         ast,
         AST(vec![Declaration::Datatype {
             name: Symbol::new("hoge"),
+            params: vec![],
             constructors: vec![(Symbol::new("Hoge"), None)]
         },])
     )
@@ -646,6 +733,7 @@ fn parse_datatype_multi() {
         ast,
         AST(vec![Declaration::Datatype {
             name: Symbol::new("hoge"),
+            params: vec![],
             constructors: vec![
                 (Symbol::new("Hoge"), None),
                 (Symbol::new("Fuga"), None),
@@ -663,6 +751,7 @@ fn parse_datatype_arg1() {
         ast,
         AST(vec![Declaration::Datatype {
             name: Symbol::new("hoge"),
+            params: vec![],
             constructors: vec![
                 (Symbol::new("Hoge"), Some(Type::Int)),
                 (Symbol::new("Fuga"), Some(Type::Real))
@@ -679,13 +768,14 @@ fn parse_datatype_arg2() {
         ast,
         AST(vec![Declaration::Datatype {
             name: Symbol::new("hoge"),
+            params: vec![],
             constructors: vec![
                 (Symbol::new("Hoge"), Some(Type::Int)),
                 (Symbol::new("Fuga"), Some(Type::Real)),
                 (
                     Symbol::new("Piyo"),
                     Some(Type::Fun(
-                        Box::new(Type::Datatype(Symbol::new("bool"))),
+                        Box::new(Type::Datatype(Symbol::new("bool"), vec![])),
                         Box::new(Type::Fun(
                             Box::new(Type::Tuple(vec![])),
                             Box::new(Type::Int)
@@ -705,6 +795,7 @@ fn parse_datatype_tuple() {
         ast,
         AST(vec![Declaration::Datatype {
             name: Symbol::new("hoge"),
+            params: vec![],
             constructors: vec![(
                 Symbol::new("Hoge"),
                 Some(Type::Tuple(vec![Type::Int, Type::Real]))
@@ -722,13 +813,14 @@ fn parse_datatype_arg3() {
         ast,
         AST(vec![Declaration::Datatype {
             name: Symbol::new("hoge"),
+            params: vec![],
             constructors: vec![
                 (Symbol::new("Hoge"), Some(Type::Int)),
                 (Symbol::new("Fuga"), Some(Type::Real)),
                 (
                     Symbol::new("Piyo"),
                     Some(Type::Fun(
-                        Box::new(Type::Datatype(Symbol::new("bool"))),
+                        Box::new(Type::Datatype(Symbol::new("bool"), vec![])),
                         Box::new(Type::Tuple(vec![
                             Type::Fun(Box::new(Type::Real), Box::new(Type::Int)),
                             Type::Real
@@ -748,12 +840,13 @@ fn parse_datatype_primlike() {
         ast,
         AST(vec![Declaration::Datatype {
             name: Symbol::new("intlist"),
+            params: vec![],
             constructors: vec![
                 (
                     Symbol::new("Cons"),
                     Some(Type::Tuple(vec![
                         Type::Int,
-                        Type::Datatype(Symbol::new("intlist"))
+                        Type::Datatype(Symbol::new("intlist"), vec![])
                     ]))
                 ),
                 (Symbol::new("Nil"), None)
@@ -783,7 +876,8 @@ fn parse_fun_unary() {
                         name: Symbol::new("x"),
                     }
                 }
-            )]
+            )],
+            positions: vec![pos(5)]
         }),])
     )
 }
@@ -817,7 +911,8 @@ fn parse_fun_binary() {
                         name: Symbol::new("x"),
                     }
                 }
-            )]
+            )],
+            positions: vec![pos(5)]
         }),])
     )
 }
@@ -856,7 +951,8 @@ fn parse_fun_pattern() {
                         name: Symbol::new("x"),
                     }
                 }
-            )]
+            )],
+            positions: vec![pos(5)]
         }),])
     )
 }
@@ -895,7 +991,8 @@ fn parse_fun_op() {
                         name: Symbol::new("x"),
                     }
                 }
-            )]
+            )],
+            positions: vec![pos(5)]
         }),])
     )
 }
@@ -949,7 +1046,8 @@ fn parse_fun_multiclause() {
                         }
                     }
                 )
-            ]
+            ],
+            positions: vec![pos(5), pos(21)]
         }),])
     )
 }
@@ -1067,6 +1165,7 @@ fn parse_case_bool() {
                             },
                         ),
                     ],
+                    positions: vec![pos(22), pos(38)],
                 }
             },
         },])
@@ -1135,6 +1234,7 @@ fn parse_case_constructor() {
                             },
                         ),
                     ],
+                    positions: vec![pos(22), pos(40)],
                 }
             },
         },])
@@ -1199,6 +1299,7 @@ fn parse_case_var() {
                             },
                         ),
                     ],
+                    positions: vec![pos(22), pos(38)],
                 }
             },
         },])
@@ -1261,6 +1362,7 @@ fn parse_case_wildcard() {
                             },
                         ),
                     ],
+                    positions: vec![pos(22), pos(38)],
                 }
             },
         },])
@@ -1329,6 +1431,7 @@ fn parse_case_int() {
                             },
                         ),
                     ],
+                    positions: vec![pos(19), pos(28), pos(37)],
                 }
             },
         },])
@@ -1411,6 +1514,7 @@ fn parse_case_tuple() {
                             }
                         },
                     ),],
+                    positions: vec![pos(27)],
                 }
             },
         },])
@@ -1602,7 +1706,8 @@ fn parse_funarg_pattern() {
                         }
                     }
                 )
-            ]
+            ],
+            positions: vec![pos(5), pos(36), pos(65), pos(94)]
         })])
     )
 }
@@ -1631,6 +1736,7 @@ fn parse_multistatement_val_datatype() {
             },
             Declaration::Datatype {
                 name: Symbol::new("order"),
+                params: vec![],
                 constructors: vec![
                     (Symbol::new("GREATER"), None),
                     (Symbol::new("EQUAL"), None),
@@ -1647,3 +1753,214 @@ fn pares_comment() {
     let ast = parse(input).unwrap();
     assert_eq!(ast, AST(vec![]));
 }
+
+#[test]
+fn parse_local() {
+    let input = r#"local val x = 1 in val y = x end"#;
+    let ast = parse(input).unwrap();
+    assert_eq!(
+        ast,
+        AST(vec![Declaration::Local {
+            binds: vec![Declaration::Val {
+                rec: false,
+                pattern: Pattern {
+                    ty: Empty {},
+                    inner: PatternKind::Variable {
+                        name: Symbol::new("x")
+                    }
+                },
+                expr: Expr {
+                    ty: Empty {},
+                    inner: ExprKind::Literal {
+                        value: Literal::Int(1)
+                    }
+                }
+            }],
+            body: vec![Declaration::Val {
+                rec: false,
+                pattern: Pattern {
+                    ty: Empty {},
+                    inner: PatternKind::Variable {
+                        name: Symbol::new("y")
+                    }
+                },
+                expr: Expr {
+                    ty: Empty {},
+                    inner: ExprKind::Symbol {
+                        name: Symbol::new("x")
+                    }
+                }
+            }],
+        }])
+    )
+}
+
+#[test]
+fn parse_datatype_tyvars() {
+    let input = r#"datatype ('a, 'b) pair = Pair of 'a * 'b"#;
+    let ast = parse(input).unwrap();
+    assert_eq!(
+        ast,
+        AST(vec![Declaration::Datatype {
+            name: Symbol::new("pair"),
+            params: vec![0, 1],
+            constructors: vec![(
+                Symbol::new("Pair"),
+                Some(Type::Tuple(vec![Type::Variable(0), Type::Variable(1)]))
+            )]
+        }])
+    )
+}
+
+#[test]
+fn parse_type_application() {
+    let input = r#"datatype t = T of int list list"#;
+    let ast = parse(input).unwrap();
+    assert_eq!(
+        ast,
+        AST(vec![Declaration::Datatype {
+            name: Symbol::new("t"),
+            params: vec![],
+            constructors: vec![(
+                Symbol::new("T"),
+                Some(Type::Datatype(
+                    Symbol::new("list"),
+                    vec![Type::Datatype(Symbol::new("list"), vec![Type::Int])]
+                ))
+            )]
+        }])
+    )
+}
+
+#[test]
+fn parse_list() {
+    let input = r#"val x = [1, 2]"#;
+    let ast = parse(input).unwrap();
+    let cons = |head, tail| Expr {
+        ty: Empty {},
+        inner: ExprKind::Constructor {
+            name: Symbol::new("::"),
+            arg: Some(
+                Expr {
+                    ty: Empty {},
+                    inner: ExprKind::Tuple {
+                        tuple: vec![head, tail],
+                    },
+                }
+                .boxed(),
+            ),
+        },
+    };
+    let int = |value| Expr {
+        ty: Empty {},
+        inner: ExprKind::Literal {
+            value: Literal::Int(value),
+        },
+    };
+    let nil = Expr {
+        ty: Empty {},
+        inner: ExprKind::Constructor {
+            name: Symbol::new("nil"),
+            arg: None,
+        },
+    };
+    assert_eq!(
+        ast,
+        AST(vec![Declaration::Val {
+            rec: false,
+            pattern: Pattern {
+                ty: Empty {},
+                inner: PatternKind::Variable {
+                    name: Symbol::new("x")
+                }
+            },
+            expr: cons(int(1), cons(int(2), nil)),
+        }])
+    )
+}
+
+#[test]
+fn parse_infixr() {
+    let input = r#"infixr 5 :: val x = a :: b :: c"#;
+    let ast = parse(input).unwrap();
+    let sym = |name| Expr {
+        ty: Empty {},
+        inner: ExprKind::Symbol {
+            name: Symbol::new(name),
+        },
+    };
+    let cons = |l, r| Expr {
+        ty: Empty {},
+        inner: ExprKind::App {
+            fun: sym("::").boxed(),
+            arg: Expr {
+                ty: Empty {},
+                inner: ExprKind::Tuple { tuple: vec![l, r] },
+            }
+            .boxed(),
+        },
+    };
+    assert_eq!(
+        ast,
+        AST(vec![
+            Declaration::D(DerivedDeclaration::Infixr {
+                priority: Some(5),
+                names: vec![Symbol::new("::")],
+            }),
+            Declaration::Val {
+                rec: false,
+                pattern: Pattern {
+                    ty: Empty {},
+                    inner: PatternKind::Variable {
+                        name: Symbol::new("x")
+                    }
+                },
+                expr: cons(sym("a"), cons(sym("b"), sym("c"))),
+            },
+        ])
+    )
+}
+
+#[test]
+fn parse_structure() {
+    let input = r#"structure S = struct val x = 1 end val y = S.x"#;
+    let ast = parse(input).unwrap();
+    assert_eq!(
+        ast,
+        AST(vec![
+            Declaration::Structure {
+                name: Symbol::new("S"),
+                body: vec![Declaration::Val {
+                    rec: false,
+                    pattern: Pattern {
+                        ty: Empty {},
+                        inner: PatternKind::Variable {
+                            name: Symbol::new("x")
+                        }
+                    },
+                    expr: Expr {
+                        ty: Empty {},
+                        inner: ExprKind::Literal {
+                            value: Literal::Int(1)
+                        }
+                    }
+                }],
+            },
+            Declaration::Val {
+                rec: false,
+                pattern: Pattern {
+                    ty: Empty {},
+                    inner: PatternKind::Variable {
+                        name: Symbol::new("y")
+                    }
+                },
+                expr: Expr {
+                    ty: Empty {},
+                    inner: ExprKind::Symbol {
+                        name: Symbol::new("S.x")
+                    }
+                }
+            },
+        ])
+    )
+}
@@ -0,0 +1,58 @@
+use webml::{compile_unit, Config};
+
+fn warnings(input: &str) -> Vec<String> {
+    let config = Config::default();
+    compile_unit("main.sml", input, &[], &config).unwrap();
+    config
+        .warnings
+        .take()
+        .iter()
+        .map(ToString::to_string)
+        .collect()
+}
+
+#[test]
+fn redundant_fun_clause_is_warned() {
+    let input = "fun f 0 = 1\n  | f 0 = 2\n  | f _ = 3";
+    assert_eq!(
+        warnings(input),
+        vec!["main.sml:2:5: warning: redundant match: the clause 2 is never matched"]
+    );
+}
+
+#[test]
+fn redundant_case_clause_is_warned() {
+    let input = "datatype t = A | B\nval f = fn x => case x of A => 1 | B => 2 | _ => 3";
+    assert_eq!(
+        warnings(input),
+        vec!["main.sml:2:45: warning: redundant match: the clause 3 is never matched"]
+    );
+}
+
+#[test]
+fn clause_after_true_and_false_is_warned() {
+    let input = "val f = fn b => case b of true => 1 | false => 2 | _ => 3";
+    assert_eq!(
+        warnings(input),
+        vec!["main.sml:1:52: warning: redundant match: the clause 3 is never matched"]
+    );
+}
+
+#[test]
+fn needed_clauses_are_not_warned() {
+    let input = "val f = fn b => case b of true => 1 | _ => 2\nfun g 0 = 1 | g _ = 2";
+    assert!(warnings(input).is_empty());
+}
+
+#[test]
+fn prelude_has_no_warnings() {
+    let config = Config::default();
+    compile_unit(
+        "prelude.sml",
+        include_str!("../../ml_src/prelude.sml"),
+        &[],
+        &config,
+    )
+    .unwrap();
+    assert_eq!(config.warnings.take(), vec![]);
+}
@@ -1,4 +1,9 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, prelude::*};
 use std::path::Path;
+use std::rc::Rc;
+use std::time::Instant;
 use wasmtime::*;
 
 fn add_ffi_module(linker: &mut Linker) {
@@ -6,7 +11,17 @@ fn add_ffi_module(linker: &mut Linker) {
         .func("js-ffi", "print", |x: i32| println!("{}", x))
         .expect("failed to add ffi functions");
 }
-fn add_rt_module(linker: &mut Linker) {
+/// the clock `webml-rt` measures the collections with
+fn add_clock_module(linker: &mut Linker) {
+    let start = Instant::now();
+    linker
+        .func("clock", "now", move || -> f64 {
+            start.elapsed().as_secs_f64() * 1000.0
+        })
+        .expect("failed to add clock functions");
+}
+
+fn add_rt_module(linker: &mut Linker, config: &GcConfig) -> Instance {
     let module_data =
         include_bytes!("../../webml-rt/target/wasm32-unknown-unknown/release/webml_rt.wasm");
     let module =
@@ -14,26 +29,202 @@ fn add_rt_module(linker: &mut Linker) {
     let instance = linker
         .instantiate(&module)
         .expect("failed to instanciate webml_rt");
+    if let Some(limit) = config.heap_limit {
+        let set_heap_limit = rt_func(&instance, "gc_set_heap_limit")
+            .get1::<i32, ()>()
+            .unwrap();
+        set_heap_limit(limit as i32).expect("webml-rt trapped");
+    }
+    if let Some(percent) = config.growth_factor {
+        let set_growth_factor = rt_func(&instance, "gc_set_growth_factor")
+            .get1::<i32, ()>()
+            .unwrap();
+        set_growth_factor(percent as i32).expect("webml-rt trapped");
+    }
     linker
         .instance("webml-rt", &instance)
         .expect("failed to import webml-rt");
+    instance
+}
+
+fn rt_func(rt: &Instance, name: &str) -> Func {
+    rt.get_export(name)
+        .and_then(|e| e.into_func())
+        .unwrap_or_else(|| panic!("webml-rt doesn't export {}", name))
+}
+
+/// host side of `TextIO`. Strings are passed as pointers to `[len: i32][bytes]`.
+fn add_io_module(linker: &mut Linker, memory: Memory) {
+    // the line read by `readline` and waiting for `takeline`
+    let line = Rc::new(RefCell::new(Vec::new()));
+
+    let mem = memory.clone();
+    linker
+        .func("webml-io", "write", move |fd: i32, s: i32| {
+            let bytes = unsafe { string_bytes(&mem, s) };
+            let ret = match fd {
+                2 => io::stderr().write_all(bytes),
+                _ => io::stdout().write_all(bytes),
+            };
+            ret.expect("failed to write");
+        })
+        .expect("failed to add io functions");
+    linker
+        .func("webml-io", "flush", |fd: i32| {
+            let ret = match fd {
+                2 => io::stderr().flush(),
+                _ => io::stdout().flush(),
+            };
+            ret.expect("failed to flush");
+        })
+        .expect("failed to add io functions");
+    let buf = line.clone();
+    linker
+        .func("webml-io", "readline", move || -> i32 {
+            // show prompts before blocking
+            io::stdout().flush().expect("failed to flush");
+            let mut buf = buf.borrow_mut();
+            buf.clear();
+            match io::stdin().lock().read_until(b'\n', &mut *buf) {
+                Ok(0) => -1,
+                Ok(n) => n as i32,
+                Err(e) => panic!("failed to read: {}", e),
+            }
+        })
+        .expect("failed to add io functions");
+    linker
+        .func("webml-io", "takeline", move |s: i32| {
+            let buf = line.borrow();
+            unsafe { string_bytes_mut(&memory, s) }.copy_from_slice(&buf);
+        })
+        .expect("failed to add io functions");
+}
+
+/// host side of `Math`, for the functions wasm doesn't have instructions for
+fn add_math_module(linker: &mut Linker) {
+    let unary: &[(&str, fn(f64) -> f64)] = &[
+        ("sin", f64::sin),
+        ("cos", f64::cos),
+        ("tan", f64::tan),
+        ("asin", f64::asin),
+        ("acos", f64::acos),
+        ("atan", f64::atan),
+        ("exp", f64::exp),
+        ("ln", f64::ln),
+        ("log10", f64::log10),
+        ("sinh", f64::sinh),
+        ("cosh", f64::cosh),
+        ("tanh", f64::tanh),
+    ];
+    for &(name, f) in unary {
+        linker
+            .func("math", name, move |x: f64| f(x))
+            .expect("failed to add math functions");
+    }
+    let binary: &[(&str, fn(f64, f64) -> f64)] = &[("atan2", f64::atan2), ("pow", f64::powf)];
+    for &(name, f) in binary {
+        linker
+            .func("math", name, move |x: f64, y: f64| f(x, y))
+            .expect("failed to add math functions");
+    }
+}
+
+unsafe fn string_bytes(memory: &Memory, s: i32) -> &[u8] {
+    let data = memory.data_unchecked();
+    let s = s as usize;
+    let mut len = [0; 4];
+    len.copy_from_slice(&data[s..s + 4]);
+    let len = i32::from_le_bytes(len) as usize;
+    &data[s + 4..s + 4 + len]
+}
+
+unsafe fn string_bytes_mut(memory: &Memory, s: i32) -> &mut [u8] {
+    let len = string_bytes(memory, s).len();
+    let s = s as usize;
+    &mut memory.data_unchecked_mut()[s + 4..s + 4 + len]
+}
+
+/// the tuning of the garbage collector, given to `webml-rt` before running programs
+#[derive(Debug, Clone, Default)]
+pub struct GcConfig {
+    /// the limit of the heap in bytes
+    pub heap_limit: Option<u32>,
+    /// the heap may grow to this percentage of the live objects before the next collection
+    pub growth_factor: Option<u32>,
+}
+
+/// the counters of the garbage collector
+#[derive(Debug, Clone)]
+pub struct GcStats {
+    pub bytes_allocated: u64,
+    pub live_bytes: u32,
+    pub heap_size: u32,
+    pub collections: u32,
+    /// in milliseconds
+    pub pause_time: f64,
+}
+
+impl fmt::Display for GcStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "bytes allocated: {}", self.bytes_allocated)?;
+        writeln!(f, "live bytes:      {}", self.live_bytes)?;
+        writeln!(f, "heap size:       {}", self.heap_size)?;
+        writeln!(f, "collections:     {}", self.collections)?;
+        write!(f, "pause time:      {:.3}ms", self.pause_time)
+    }
 }
 
 pub fn linker() -> Linker {
+    linker_with(&GcConfig::default()).0
+}
+
+/// the linker and the instance of `webml-rt` in it
+pub fn linker_with(config: &GcConfig) -> (Linker, Instance) {
     let store = Store::default();
     let mut linker = Linker::new(&store);
     add_ffi_module(&mut linker);
-    add_rt_module(&mut linker);
-    linker
+    add_clock_module(&mut linker);
+    let rt = add_rt_module(&mut linker, config);
+    let memory = rt
+        .get_export("memory")
+        .and_then(|e| e.into_memory())
+        .expect("webml-rt doesn't export memory");
+    add_io_module(&mut linker, memory);
+    add_math_module(&mut linker);
+    (linker, rt)
 }
 
 pub struct WebmlInterp {
     linker: Linker,
+    rt: Instance,
 }
 
 impl WebmlInterp {
     pub fn new() -> Self {
-        Self { linker: linker() }
+        Self::with_gc_config(&GcConfig::default())
+    }
+
+    pub fn with_gc_config(config: &GcConfig) -> Self {
+        let (linker, rt) = linker_with(config);
+        Self { linker, rt }
+    }
+
+    pub fn gc_stats(&self) -> GcStats {
+        let get_u32 = |name: &str| {
+            let get = rt_func(&self.rt, name).get0::<i32>().unwrap();
+            get().expect("webml-rt trapped") as u32
+        };
+        let bytes_allocated = rt_func(&self.rt, "gc_bytes_allocated")
+            .get0::<i64>()
+            .unwrap();
+        let pause_time = rt_func(&self.rt, "gc_pause_time").get0::<f64>().unwrap();
+        GcStats {
+            bytes_allocated: bytes_allocated().expect("webml-rt trapped") as u64,
+            live_bytes: get_u32("gc_live_bytes"),
+            heap_size: get_u32("gc_heap_size"),
+            collections: get_u32("gc_collections"),
+            pause_time: pause_time().expect("webml-rt trapped"),
+        }
     }
 
     fn is_wasm(prog: &[u8]) -> bool {
@@ -1,8 +1,31 @@
 use std::env::args;
-use webml_interp::WebmlInterp;
+use webml_interp::{GcConfig, WebmlInterp};
+
+const USAGE: &str = "Usage: [--gc-stats] [--heap-limit BYTES] [--gc-growth-factor PERCENT] FILE";
 
 fn main() {
-    let path = args().nth(1).expect("Usage: FILE");
-    let mut interp = WebmlInterp::new();
-    interp.run_file(path)
+    let mut gc_stats = false;
+    let mut config = GcConfig::default();
+    let mut path = None;
+    let mut args = args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--gc-stats" => gc_stats = true,
+            "--heap-limit" => {
+                let limit = args.next().and_then(|n| n.parse().ok()).expect(USAGE);
+                config.heap_limit = Some(limit);
+            }
+            "--gc-growth-factor" => {
+                let percent = args.next().and_then(|n| n.parse().ok()).expect(USAGE);
+                config.growth_factor = Some(percent);
+            }
+            _ => path = Some(arg),
+        }
+    }
+    let path = path.expect(USAGE);
+    let mut interp = WebmlInterp::with_gc_config(&config);
+    interp.run_file(path);
+    if gc_stats {
+        eprintln!("{}", interp.gc_stats());
+    }
 }
@@ -0,0 +1,317 @@
+//! A non-moving mark-sweep collector.
+//!
+//! # Roots
+//!
+//! The compiled code keeps its pointers in the shadow stack:
+//! each function with pointer registers pushes a frame with `enter_frame` on entry,
+//! stores the registers to its slots before anything that may allocate,
+//! and pops the frame with `leave_frame` before it returns.
+//!
+//! ```text
+//!   32     32       32     32
+//! +------+--------+------+-------+-------+-----
+//! | prev | nslots | seg  | slot0 | slot1 | ...
+//! +------+--------+------+-------+-------+-----
+//! ```
+//!
+//! Frames are laid out contiguously in segments, which are chained when a frame doesn't fit.
+//!
+//! The toplevel values the functions read are kept in the globals, the bottom frame,
+//! which `init_globals` pushes before the program starts. They may be numbers, which are
+//! marked conservatively; a number looking like a pointer only keeps the object alive.
+//!
+//! # Descriptors
+//!
+//! The `desc` in the object header is derived from the `LTy`s of `lir::Op::HeapAlloc`
+//! by the backend. The payload is a sequence of 8-byte slots and a pointer is held in the lower
+//! 32 bits of a slot.
+//!
+//! - If `EXTENDED` is set, the rest is the number of the slots `n`
+//!   and a bitmap of `n` bits follows the slots, 32 bits a word.
+//! - Otherwise bit `i` tells whether slot `i` is a pointer for `i < 30`,
+//!   and bit 30 does for all the rest slots.
+use crate::*;
+
+/// the number of the slots whose bits are in the descriptor
+const DESC_SLOTS: usize = 30;
+pub const EXTENDED: u32 = 1 << 31;
+const SEGMENT_SIZE: usize = WASM_PAGE_SIZE;
+const MARK_STACK_SIZE: usize = 1024;
+
+#[link(wasm_import_module = "clock")]
+extern "C" {
+    /// the current time in milliseconds, for the statistics
+    fn now() -> f64;
+}
+
+#[repr(C)]
+struct Frame {
+    prev: *mut Frame,
+    nslots: usize,
+    segment: *mut Segment,
+}
+
+#[repr(C)]
+struct Segment {
+    next: *mut Segment,
+    end: usize,
+}
+
+static mut TOP: *mut Frame = 0 as *mut _;
+static mut SEGMENT: *mut Segment = 0 as *mut _;
+/// where the next frame goes in `SEGMENT`
+static mut SP: usize = 0;
+/// the slots of the globals
+static mut GLOBALS: *mut u32 = 0 as *mut _;
+
+static mut MARK_STACK: [*mut Header; MARK_STACK_SIZE] = [0 as *mut _; MARK_STACK_SIZE];
+static mut MARK_SP: usize = 0;
+/// some objects are marked but not pushed to `MARK_STACK` for lack of room
+static mut OVERFLOWED: bool = false;
+
+/// the heap may grow to `GROWTH_FACTOR`% of the live bytes before the next collection
+static mut GROWTH_FACTOR: usize = 200;
+static mut COLLECTIONS: u32 = 0;
+/// the live bytes after the last collection
+static mut LIVE: usize = 0;
+/// the total time spent in collections, in milliseconds
+static mut PAUSE_TIME: f64 = 0.0;
+
+/// a segment which has room for `size` bytes of frames.
+/// Segments are not GC pages, so they are not in `PAGE_TABLE`
+unsafe fn new_segment(size: usize) -> *mut Segment {
+    let size = size + mem::size_of::<Segment>();
+    let npages = (size.max(SEGMENT_SIZE) + WASM_PAGE_SIZE - 1) / WASM_PAGE_SIZE;
+    let ret = memory_grow(MEMORY, npages);
+    if ret == usize::max_value() {
+        unreachable()
+    }
+    let segment = (ret * WASM_PAGE_SIZE) as *mut Segment;
+    (*segment).end = segment as usize + npages * WASM_PAGE_SIZE;
+    segment
+}
+
+unsafe fn segment_start(segment: *mut Segment) -> usize {
+    segment as usize + mem::size_of::<Segment>()
+}
+
+pub unsafe fn init() {
+    SEGMENT = new_segment(0);
+    SP = segment_start(SEGMENT);
+}
+
+/// pushes a frame with `nslots` zeroed slots and returns the pointer to the slots
+#[no_mangle]
+pub unsafe extern "C" fn enter_frame(nslots: usize) -> *mut u32 {
+    let size = mem::size_of::<Frame>() + 4 * nslots;
+    if (*SEGMENT).end < SP + size {
+        let next = (*SEGMENT).next;
+        // reuse the segment left by a deeper recursion if it fits
+        if next.is_null() || (*next).end < segment_start(next) + size {
+            // a too small one is leaked. It only happens with a frame larger than a segment
+            let segment = new_segment(size);
+            (*SEGMENT).next = segment;
+        }
+        SEGMENT = (*SEGMENT).next;
+        SP = segment_start(SEGMENT);
+    }
+    let frame = SP as *mut Frame;
+    (*frame).prev = TOP;
+    (*frame).nslots = nslots;
+    (*frame).segment = SEGMENT;
+    TOP = frame;
+    SP += size;
+    let slots = frame.offset(1) as *mut u32;
+    ptr::write_bytes(slots, 0, nslots);
+    slots
+}
+
+/// pushes the globals with `nslots` slots, which are never popped
+#[no_mangle]
+pub unsafe extern "C" fn init_globals(nslots: usize) {
+    GLOBALS = enter_frame(nslots);
+}
+
+/// the slots of the globals
+#[no_mangle]
+pub unsafe extern "C" fn globals() -> *mut u32 {
+    GLOBALS
+}
+
+/// pops the frame `enter_frame` returned `slots` for
+#[no_mangle]
+pub unsafe extern "C" fn leave_frame(slots: *mut u32) {
+    let frame = (slots as *mut Frame).offset(-1);
+    TOP = (*frame).prev;
+    SEGMENT = (*frame).segment;
+    SP = frame as usize;
+}
+
+unsafe fn mark(value: u32) {
+    if let Some(header) = object_of(value as usize) {
+        if (*header).size & MARK == 0 {
+            (*header).size |= MARK;
+            if MARK_SP < MARK_STACK_SIZE {
+                MARK_STACK[MARK_SP] = header;
+                MARK_SP += 1;
+            } else {
+                OVERFLOWED = true;
+            }
+        }
+    }
+}
+
+/// marks the objects `header` points to
+unsafe fn scan(header: *mut Header) {
+    let slots = payload(header) as *const u64;
+    let desc = (*header).desc;
+    if desc & EXTENDED != 0 {
+        let nslots = (desc & !EXTENDED) as usize;
+        let bitmap = slots.offset(nslots as isize) as *const u32;
+        for i in 0..nslots {
+            if *bitmap.offset((i / 32) as isize) & (1 << (i % 32)) != 0 {
+                mark(*slots.offset(i as isize) as u32);
+            }
+        }
+    } else if desc != 0 {
+        for i in 0..payload_size(header) / 8 {
+            if desc & (1 << i.min(DESC_SLOTS)) != 0 {
+                mark(*slots.offset(i as isize) as u32);
+            }
+        }
+    }
+}
+
+unsafe fn drain() {
+    while 0 < MARK_SP {
+        MARK_SP -= 1;
+        scan(MARK_STACK[MARK_SP]);
+    }
+}
+
+unsafe fn mark_roots() {
+    let mut frame = TOP;
+    while !frame.is_null() {
+        let slots = frame.offset(1) as *const u32;
+        for i in 0..(*frame).nslots {
+            mark(*slots.offset(i as isize));
+            drain();
+        }
+        frame = (*frame).prev;
+    }
+}
+
+/// scans all the marked objects again to find the objects dropped from the mark stack
+unsafe fn rescan() {
+    OVERFLOWED = false;
+    let mut page = GC;
+    while !page.is_null() {
+        let data = (*page).data as usize;
+        let mut at = data;
+        while at < data + (*page).top {
+            let header = at as *mut Header;
+            if (*header).size & MARK != 0 {
+                scan(header);
+                drain();
+            }
+            at += HEADER_SIZE + payload_size(header);
+        }
+        page = (*page).next;
+    }
+    let mut page = LARGE;
+    while !page.is_null() {
+        let header = (*page).data as *mut Header;
+        if (*header).size & MARK != 0 {
+            scan(header);
+            drain();
+        }
+        page = (*page).next;
+    }
+}
+
+/// frees the unmarked objects, coalescing adjacent ones, and returns the live bytes
+unsafe fn sweep() -> usize {
+    FREE_LIST = ptr::null_mut();
+    let mut live = 0;
+    let mut page = GC;
+    while !page.is_null() {
+        let data = (*page).data as usize;
+        let top = data + (*page).top;
+        let mut at = data;
+        // the first chunk of the current free run
+        let mut run: Option<*mut Header> = None;
+        while at < top {
+            let header = at as *mut Header;
+            let size = HEADER_SIZE + payload_size(header);
+            if (*header).size & MARK != 0 {
+                (*header).size &= !MARK;
+                live += size;
+                if let Some(run) = run.take() {
+                    free_chunk(run, at - payload(run) as usize);
+                }
+            } else if run.is_none() {
+                (*header).desc = 0;
+                run = Some(header);
+            } else {
+                clear_start(page, header);
+            }
+            at += size;
+        }
+        if let Some(run) = run {
+            free_chunk(run, top - payload(run) as usize);
+        }
+        page = (*page).next;
+    }
+    // large pages are kept for later large objects
+    let mut page = LARGE;
+    while !page.is_null() {
+        let header = (*page).data as *mut Header;
+        if (*header).size & MARK != 0 {
+            (*header).size &= !MARK;
+            live += HEADER_SIZE + payload_size(header);
+        } else {
+            (*header).size |= FREE;
+        }
+        page = (*page).next;
+    }
+    live
+}
+
+pub unsafe fn collect() {
+    let start = now();
+    mark_roots();
+    while OVERFLOWED {
+        rescan();
+    }
+    let live = sweep();
+    USED = live;
+    LIVE = live;
+    ALLOCATED = 0;
+    let growth = live as u64 * GROWTH_FACTOR.saturating_sub(100) as u64 / 100;
+    THRESHOLD = INITIAL_THRESHOLD.max(growth.min(usize::max_value() as u64) as usize);
+    COLLECTIONS += 1;
+    PAUSE_TIME += now() - start;
+}
+
+/// sets the growth factor in percent. Meant to be called before running the program
+#[no_mangle]
+pub unsafe extern "C" fn gc_set_growth_factor(percent: usize) {
+    GROWTH_FACTOR = percent;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gc_collections() -> u32 {
+    COLLECTIONS
+}
+
+/// the live bytes after the last collection
+#[no_mangle]
+pub unsafe extern "C" fn gc_live_bytes() -> usize {
+    LIVE
+}
+
+/// the total time spent in collections, in milliseconds
+#[no_mangle]
+pub unsafe extern "C" fn gc_pause_time() -> f64 {
+    PAUSE_TIME
+}
@@ -0,0 +1,305 @@
+//! Arbitrary-precision integers for `IntInf`.
+//!
+//! An `IntInf.int` shares the layout with strings so that the compiler can treat it as one:
+//!
+//! ```text
+//!   32    32     32      32
+//! +-----+------+-------+-------+-----
+//! | len | sign | limb0 | limb1 | ...
+//! +-----+------+-------+-------+-----
+//! ```
+//!
+//! `len` is the size in bytes following it, `sign` is 1 for negative numbers
+//! and the limbs hold the magnitude in little endian.
+//! Values are normalized, i.e. the most significant limb is not 0 and 0 is not negative.
+//! Values are immutable once returned to the program.
+use crate::alloc;
+use crate::gc::{enter_frame, leave_frame};
+use core::arch::wasm32::unreachable;
+use core::cmp::Ordering;
+use core::slice;
+
+type Limb = u32;
+type DoubleLimb = u64;
+const LIMB_BITS: usize = 32;
+
+/// allocates a zeroed value which can hold `n` limbs
+unsafe fn new(n: usize) -> *mut u8 {
+    let len = 4 + 4 * n;
+    let ptr = alloc((len + 4 + 7) / 8 * 8, 0);
+    *(ptr as *mut i32) = len as i32;
+    *(ptr.offset(4) as *mut u32) = 0;
+    for l in limbs_mut(ptr) {
+        *l = 0;
+    }
+    ptr
+}
+
+unsafe fn len(ptr: *const u8) -> usize {
+    (*(ptr as *const i32) as usize - 4) / 4
+}
+
+unsafe fn is_negative(ptr: *const u8) -> bool {
+    *(ptr.offset(4) as *const u32) != 0
+}
+
+unsafe fn limbs<'a>(ptr: *const u8) -> &'a [Limb] {
+    slice::from_raw_parts(ptr.offset(8) as *const Limb, len(ptr))
+}
+
+unsafe fn limbs_mut<'a>(ptr: *mut u8) -> &'a mut [Limb] {
+    slice::from_raw_parts_mut(ptr.offset(8) as *mut Limb, len(ptr))
+}
+
+/// drops the leading zeros and sets the sign
+unsafe fn finish(ptr: *mut u8, negative: bool) -> *mut u8 {
+    let n = normalized_len(limbs(ptr));
+    *(ptr as *mut i32) = (4 + 4 * n) as i32;
+    *(ptr.offset(4) as *mut u32) = (negative && n != 0) as u32;
+    ptr
+}
+
+fn normalized_len(a: &[Limb]) -> usize {
+    a.iter().rposition(|&l| l != 0).map(|i| i + 1).unwrap_or(0)
+}
+
+fn cmp_mag(a: &[Limb], b: &[Limb]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+/// `out = a + b`, where `out` is longer than both
+fn add_mag(a: &[Limb], b: &[Limb], out: &mut [Limb]) {
+    let mut carry = 0;
+    for (i, o) in out.iter_mut().enumerate() {
+        let x = *a.get(i).unwrap_or(&0) as DoubleLimb;
+        let y = *b.get(i).unwrap_or(&0) as DoubleLimb;
+        let s = x + y + carry;
+        *o = s as Limb;
+        carry = s >> LIMB_BITS;
+    }
+}
+
+/// `out = a - b`, where `a >= b`
+fn sub_mag(a: &[Limb], b: &[Limb], out: &mut [Limb]) {
+    let mut borrow = 0;
+    for (i, o) in out.iter_mut().enumerate() {
+        let x = *a.get(i).unwrap_or(&0) as DoubleLimb;
+        let y = *b.get(i).unwrap_or(&0) as DoubleLimb + borrow;
+        if x >= y {
+            *o = (x - y) as Limb;
+            borrow = 0;
+        } else {
+            *o = ((1 << LIMB_BITS) + x - y) as Limb;
+            borrow = 1;
+        }
+    }
+}
+
+/// `out = a * b`, where `out` is zeroed and as long as `a` and `b` together
+fn mul_mag(a: &[Limb], b: &[Limb], out: &mut [Limb]) {
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0;
+        for (j, &y) in b.iter().enumerate() {
+            let t = x as DoubleLimb * y as DoubleLimb + out[i + j] as DoubleLimb + carry;
+            out[i + j] = t as Limb;
+            carry = t >> LIMB_BITS;
+        }
+        out[i + b.len()] = carry as Limb;
+    }
+}
+
+/// divides `a` by `b` bit by bit, leaving the quotient in `q` and the remainder in `r`.
+/// `q` is as long as `a`, `r` is one limb longer than `b`, and both are zeroed.
+fn divmod_mag(a: &[Limb], b: &[Limb], q: &mut [Limb], r: &mut [Limb]) {
+    for bit in (0..a.len() * LIMB_BITS).rev() {
+        // r = r << 1 | a[bit]
+        let mut carry = (a[bit / LIMB_BITS] >> (bit % LIMB_BITS)) & 1;
+        for l in r.iter_mut() {
+            let next = *l >> (LIMB_BITS - 1);
+            *l = *l << 1 | carry;
+            carry = next;
+        }
+        if cmp_mag(&r[..normalized_len(r)], b) != Ordering::Less {
+            let r_len = r.len();
+            let mut borrow = 0;
+            for i in 0..r_len {
+                let x = r[i] as DoubleLimb;
+                let y = *b.get(i).unwrap_or(&0) as DoubleLimb + borrow;
+                if x >= y {
+                    r[i] = (x - y) as Limb;
+                    borrow = 0;
+                } else {
+                    r[i] = ((1 << LIMB_BITS) + x - y) as Limb;
+                    borrow = 1;
+                }
+            }
+            q[bit / LIMB_BITS] |= 1 << (bit % LIMB_BITS);
+        }
+    }
+}
+
+/// divides the magnitude in place by a single limb and returns the remainder
+fn div_limb(a: &mut [Limb], d: Limb) -> Limb {
+    let mut rem: DoubleLimb = 0;
+    for l in a.iter_mut().rev() {
+        let cur = rem << LIMB_BITS | *l as DoubleLimb;
+        *l = (cur / d as DoubleLimb) as Limb;
+        rem = cur % d as DoubleLimb;
+    }
+    rem as Limb
+}
+
+unsafe fn copy(a: *const u8, negative: bool) -> *mut u8 {
+    let ret = new(len(a));
+    limbs_mut(ret).copy_from_slice(limbs(a));
+    finish(ret, negative)
+}
+
+/// `a + b` if `negate_b` is false, otherwise `a - b`
+unsafe fn add_signed(a: *const u8, b: *const u8, negate_b: bool) -> *mut u8 {
+    let (x, y) = (limbs(a), limbs(b));
+    let (sa, sb) = (is_negative(a), is_negative(b) != negate_b);
+    if sa == sb {
+        let ret = new(x.len().max(y.len()) + 1);
+        add_mag(x, y, limbs_mut(ret));
+        return finish(ret, sa);
+    }
+    match cmp_mag(x, y) {
+        Ordering::Less => {
+            let ret = new(y.len());
+            sub_mag(y, x, limbs_mut(ret));
+            finish(ret, sb)
+        }
+        _ => {
+            let ret = new(x.len());
+            sub_mag(x, y, limbs_mut(ret));
+            finish(ret, sa)
+        }
+    }
+}
+
+/// truncating division. Returns the quotient and the remainder
+unsafe fn quot_rem(a: *const u8, b: *const u8) -> (*mut u8, *mut u8) {
+    let (x, y) = (limbs(a), limbs(b));
+    if y.is_empty() {
+        // Div
+        unreachable()
+    }
+    // keep `q` alive while allocating `r`
+    let roots = enter_frame(1);
+    let q = new(x.len());
+    *roots = q as u32;
+    let r = new(y.len() + 1);
+    leave_frame(roots);
+    divmod_mag(x, y, limbs_mut(q), limbs_mut(r));
+    let (sa, sb) = (is_negative(a), is_negative(b));
+    (finish(q, sa != sb), finish(r, sa))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn intinf_from_int(i: i32) -> *mut u8 {
+    let ret = new(1);
+    limbs_mut(ret)[0] = (i as i64).abs() as Limb;
+    finish(ret, i < 0)
+}
+
+/// traps if the value doesn't fit in an `int`
+#[no_mangle]
+pub unsafe extern "C" fn intinf_to_int(a: *const u8) -> i32 {
+    let (x, negative) = (limbs(a), is_negative(a));
+    let mag = match x.len() {
+        0 => 0,
+        1 => x[0] as i64,
+        // Overflow
+        _ => unreachable(),
+    };
+    let i = if negative { -mag } else { mag };
+    if i < i32::min_value() as i64 || (i32::max_value() as i64) < i {
+        // Overflow
+        unreachable()
+    }
+    i as i32
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn intinf_add(a: *const u8, b: *const u8) -> *mut u8 {
+    add_signed(a, b, false)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn intinf_sub(a: *const u8, b: *const u8) -> *mut u8 {
+    add_signed(a, b, true)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn intinf_mul(a: *const u8, b: *const u8) -> *mut u8 {
+    let (x, y) = (limbs(a), limbs(b));
+    let ret = new(x.len() + y.len());
+    mul_mag(x, y, limbs_mut(ret));
+    finish(ret, is_negative(a) != is_negative(b))
+}
+
+/// the quotient rounded toward zero. Traps if `b` is 0
+#[no_mangle]
+pub unsafe extern "C" fn intinf_quot(a: *const u8, b: *const u8) -> *mut u8 {
+    quot_rem(a, b).0
+}
+
+/// the remainder with the sign of `a`. Traps if `b` is 0
+#[no_mangle]
+pub unsafe extern "C" fn intinf_rem(a: *const u8, b: *const u8) -> *mut u8 {
+    quot_rem(a, b).1
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn intinf_neg(a: *const u8) -> *mut u8 {
+    copy(a, !is_negative(a))
+}
+
+/// -1, 0 or 1 as `a` is less than, equal to or greater than `b`
+#[no_mangle]
+pub unsafe extern "C" fn intinf_compare(a: *const u8, b: *const u8) -> i32 {
+    let ord = match (is_negative(a), is_negative(b)) {
+        (false, false) => cmp_mag(limbs(a), limbs(b)),
+        (true, true) => cmp_mag(limbs(b), limbs(a)),
+        (false, true) => Ordering::Greater,
+        (true, false) => Ordering::Less,
+    };
+    ord as i32
+}
+
+/// the decimal notation with `~` for negative numbers, as a string
+#[no_mangle]
+pub unsafe extern "C" fn intinf_to_string(a: *const u8) -> *mut u8 {
+    // each limb takes up to 10 digits, plus the sign
+    let cap = len(a) * 10 + 2;
+    let ret = alloc((cap + 4 + 7) / 8 * 8, 0);
+    let buf = slice::from_raw_parts_mut(ret.offset(4), cap);
+
+    // keep `ret` alive while allocating `work`
+    let roots = enter_frame(1);
+    *roots = ret as u32;
+    let work = copy(a, false);
+    leave_frame(roots);
+    let mut n = len(work);
+    // fill the digits from the end
+    let mut at = cap;
+    loop {
+        let digit = div_limb(&mut limbs_mut(work)[..n], 10);
+        at -= 1;
+        buf[at] = b'0' + digit as u8;
+        n = normalized_len(&limbs(work)[..n]);
+        if n == 0 {
+            break;
+        }
+    }
+    if is_negative(a) {
+        at -= 1;
+        buf[at] = b'~';
+    }
+    buf.copy_within(at.., 0);
+    *(ret as *mut i32) = (cap - at) as i32;
+    ret
+}
@@ -1,67 +1,388 @@
 #![no_std]
 #![cfg(target_arch = "wasm32")]
-use core::arch::wasm32::{memory_grow, memory_size};
+use core::arch::wasm32::{memory_grow, memory_size, unreachable};
 use core::mem;
 use core::panic::PanicInfo;
+use core::ptr;
 
-#[repr(C)]
+mod gc;
+mod intinf;
+
+/// a GC page is a wasm page holding small objects.
+/// Objects larger than it are allocated in large pages, which span contiguous wasm pages
+/// and hold a single object, leaving `starts` unused.
+///
+/// The page and the header are aligned to 8 so that the payloads are, for 64-bit loads and stores
+#[repr(C, align(8))]
 struct Page {
     next: *mut Page,
     size: usize,
     top: usize,
     data: *mut u8,
+    /// a bit per 8 bytes of `data`, set where an object header starts
+    starts: [u32; START_WORDS],
+}
+
+/// every object is preceded by a header:
+///
+/// ```text
+///   32     32     64
+/// +------+------+---------
+/// | size | desc | payload ...
+/// +------+------+---------
+/// ```
+///
+/// `size` is the size of the payload, which is a multiple of 8, with the `MARK` and `FREE` flags.
+/// `desc` tells the collector which slots of the payload hold pointers. See `gc.rs`.
+/// Pointers given to the program point to the payload.
+#[repr(C, align(8))]
+struct Header {
+    size: u32,
+    desc: u32,
 }
 
 const MEMORY: u32 = 0;
 const WASM_PAGE_SIZE: usize = 64 * 1024;
 // GC page size including meta data
 const GC_PAGE_SIZE: usize = 1 * WASM_PAGE_SIZE;
+const START_WORDS: usize = GC_PAGE_SIZE / 8 / 32;
+const HEADER_SIZE: usize = mem::size_of::<Header>();
+/// the object is reachable. Only set during collection
+const MARK: u32 = 1 << 31;
+/// the object is a free chunk, whose first slot links to the next one
+const FREE: u32 = 1 << 30;
+const SIZE_MASK: u32 = FREE - 1;
+/// collect garbage when this many bytes are allocated since the last collection,
+/// or the live bytes after it if more
+const INITIAL_THRESHOLD: usize = 4 * GC_PAGE_SIZE;
+
 static mut GC: *mut Page = 0 as *mut _;
 static mut HEAD: *mut Page = 0 as *mut _;
+static mut LARGE: *mut Page = 0 as *mut _;
+static mut FREE_LIST: *mut Header = 0 as *mut _;
+static mut ALLOCATED: usize = 0;
+static mut THRESHOLD: usize = INITIAL_THRESHOLD;
+/// the bytes taken by the objects including their headers
+static mut USED: usize = 0;
+/// the bytes ever allocated including the headers
+static mut BYTES_ALLOCATED: u64 = 0;
+/// the bytes of the pages for the objects
+static mut HEAP_SIZE: usize = 0;
+/// `HEAP_SIZE` doesn't grow beyond this
+static mut HEAP_LIMIT: usize = usize::max_value();
+/// a bit per wasm page, set if the page is a GC page
+static mut PAGE_TABLE: [u32; 65536 / 32] = [0; 65536 / 32];
+/// a bit per wasm page, set if the page is a part of a large page
+static mut LARGE_TABLE: [u32; 65536 / 32] = [0; 65536 / 32];
 
-unsafe fn new_page() -> *mut Page {
-    let ret = memory_grow(MEMORY, 1);
-    // if we failed to allocate a page then panic
+unsafe fn set_page_bit(table: &mut [u32], index: usize) {
+    table[index / 32] |= 1 << (index % 32);
+}
+
+unsafe fn page_bit(table: &[u32], index: usize) -> bool {
+    table[index / 32] & (1 << (index % 32)) != 0
+}
+
+/// a page of `npages` wasm pages, or traps if the memory is exhausted
+unsafe fn grow_page(npages: usize) -> *mut Page {
+    let ret = memory_grow(MEMORY, npages);
     if ret == usize::max_value() {
-        // TODO: collect garbage
-        panic!("memory exhausted")
-    } else {
-        let page = (ret * WASM_PAGE_SIZE) as *mut u8 as *mut Page;
-        // next, top: relying wasm's page is 0 initialized
-        (*page).size = GC_PAGE_SIZE - mem::size_of::<Page>();
-        (*page).data = (page as *mut u8).offset(mem::size_of::<Page>() as isize);
-        page
+        unreachable()
     }
+    HEAP_SIZE += npages * WASM_PAGE_SIZE;
+    let page = (ret * WASM_PAGE_SIZE) as *mut u8 as *mut Page;
+    // next, top, starts: relying wasm's page is 0 initialized
+    (*page).size = npages * WASM_PAGE_SIZE - mem::size_of::<Page>();
+    (*page).data = (page as *mut u8).offset(mem::size_of::<Page>() as isize);
+    page
+}
+
+unsafe fn new_page() -> *mut Page {
+    let page = grow_page(GC_PAGE_SIZE / WASM_PAGE_SIZE);
+    set_page_bit(&mut PAGE_TABLE, page as usize / WASM_PAGE_SIZE);
+    page
 }
 
 unsafe fn add_new_page() {
+    // hand the rest of the current page to the free list
+    let rest = (*HEAD).size - (*HEAD).top;
+    if HEADER_SIZE + 8 <= rest {
+        let header = (*HEAD).data.offset((*HEAD).top as isize) as *mut Header;
+        set_start(HEAD, header);
+        (*header).desc = 0;
+        free_chunk(header, rest - HEADER_SIZE);
+        (*HEAD).top = (*HEAD).size;
+    }
     let page = new_page();
     (*HEAD).next = page;
     HEAD = page;
 }
 
+unsafe fn page_of(header: *const Header) -> *mut Page {
+    (header as usize / GC_PAGE_SIZE * GC_PAGE_SIZE) as *mut Page
+}
+
+unsafe fn set_start(page: *mut Page, header: *const Header) {
+    let i = (header as usize - (*page).data as usize) / 8;
+    (*page).starts[i / 32] |= 1 << (i % 32);
+}
+
+unsafe fn clear_start(page: *mut Page, header: *const Header) {
+    let i = (header as usize - (*page).data as usize) / 8;
+    (*page).starts[i / 32] &= !(1 << (i % 32));
+}
+
+unsafe fn payload(header: *mut Header) -> *mut u8 {
+    (header as *mut u8).offset(HEADER_SIZE as isize)
+}
+
+unsafe fn payload_size(header: *const Header) -> usize {
+    ((*header).size & SIZE_MASK) as usize
+}
+
+/// turns `header` into a free chunk with `size` bytes of payload and pushes it to the free list
+unsafe fn free_chunk(header: *mut Header, size: usize) {
+    (*header).size = FREE | size as u32;
+    *(payload(header) as *mut *mut Header) = FREE_LIST;
+    FREE_LIST = header;
+}
+
+/// finds the object `ptr` points into, if any.
+/// Any value is accepted: the slots the program marks as pointers may hold other values.
+unsafe fn object_of(ptr: usize) -> Option<*mut Header> {
+    if ptr % 4 != 0 {
+        return None;
+    }
+    let index = ptr / WASM_PAGE_SIZE;
+    if page_bit(&LARGE_TABLE, index) {
+        return large_object_of(ptr);
+    }
+    if !page_bit(&PAGE_TABLE, index) {
+        return None;
+    }
+    let page = (index * WASM_PAGE_SIZE) as *mut Page;
+    let data = (*page).data as usize;
+    if ptr < data + HEADER_SIZE || data + (*page).top <= ptr {
+        return None;
+    }
+    // the nearest header below the payload `ptr` points into
+    let mut i = (ptr - HEADER_SIZE - data) / 8;
+    loop {
+        let word = i / 32;
+        let mask = if i % 32 == 31 {
+            !0
+        } else {
+            (1 << (i % 32 + 1)) - 1
+        };
+        let bits = (*page).starts[word] & mask;
+        if bits != 0 {
+            i = word * 32 + 31 - bits.leading_zeros() as usize;
+            break;
+        }
+        if word == 0 {
+            return None;
+        }
+        i = word * 32 - 1;
+    }
+    let header = (data + i * 8) as *mut Header;
+    if (*header).size & FREE != 0 || payload(header) as usize + payload_size(header) <= ptr {
+        return None;
+    }
+    Some(header)
+}
+
+unsafe fn large_object_of(ptr: usize) -> Option<*mut Header> {
+    let mut page = LARGE;
+    while !page.is_null() {
+        let header = (*page).data as *mut Header;
+        let payload = payload(header) as usize;
+        if payload <= ptr && ptr < payload + payload_size(header) {
+            if (*header).size & FREE != 0 {
+                return None;
+            }
+            return Some(header);
+        }
+        page = (*page).next;
+    }
+    None
+}
+
+/// whether an object of `size` bytes needs a large page
+fn is_large(size: usize) -> bool {
+    GC_PAGE_SIZE - mem::size_of::<Page>() < HEADER_SIZE + size
+}
+
+/// the number of the wasm pages to add for an object of `size` bytes
+fn npages_for(size: usize) -> usize {
+    if is_large(size) {
+        (mem::size_of::<Page>() + HEADER_SIZE + size + WASM_PAGE_SIZE - 1) / WASM_PAGE_SIZE
+    } else {
+        GC_PAGE_SIZE / WASM_PAGE_SIZE
+    }
+}
+
+/// reuses a free large page for an object of `size` bytes if any fits
+unsafe fn reuse_large(size: usize) -> Option<*mut Header> {
+    let total = HEADER_SIZE + size;
+    let mut page = LARGE;
+    while !page.is_null() {
+        let header = (*page).data as *mut Header;
+        if (*header).size & FREE != 0 && total <= (*page).size {
+            (*page).top = total;
+            return Some(header);
+        }
+        page = (*page).next;
+    }
+    None
+}
+
+/// an object of `size` bytes in a new large page
+unsafe fn new_large(size: usize) -> *mut Header {
+    let npages = npages_for(size);
+    let page = grow_page(npages);
+    let index = page as usize / WASM_PAGE_SIZE;
+    for i in index..index + npages {
+        set_page_bit(&mut LARGE_TABLE, i);
+    }
+    (*page).top = HEADER_SIZE + size;
+    (*page).next = LARGE;
+    LARGE = page;
+    (*page).data as *mut Header
+}
+
+/// takes a chunk of `size` bytes from the free list, splitting it if large enough
+unsafe fn take_free(size: usize) -> Option<*mut Header> {
+    let mut link: *mut *mut Header = &mut FREE_LIST;
+    while !(*link).is_null() {
+        let chunk = *link;
+        let next = *(payload(chunk) as *mut *mut Header);
+        let chunk_size = payload_size(chunk);
+        if chunk_size == size {
+            *link = next;
+            return Some(chunk);
+        }
+        if size + HEADER_SIZE + 8 <= chunk_size {
+            let rest = payload(chunk).offset(size as isize) as *mut Header;
+            set_start(page_of(chunk), rest);
+            (*rest).desc = 0;
+            (*rest).size = FREE | (chunk_size - size - HEADER_SIZE) as u32;
+            *(payload(rest) as *mut *mut Header) = next;
+            *link = rest;
+            return Some(chunk);
+        }
+        link = payload(chunk) as *mut *mut Header;
+    }
+    None
+}
+
+/// takes `HEADER_SIZE + size` bytes from the top of the current page
+unsafe fn bump(size: usize) -> Option<*mut Header> {
+    let total = HEADER_SIZE + size;
+    if (*HEAD).size < (*HEAD).top + total {
+        return None;
+    }
+    let header = (*HEAD).data.offset((*HEAD).top as isize) as *mut Header;
+    (*HEAD).top += total;
+    set_start(HEAD, header);
+    Some(header)
+}
+
+/// takes room for an object of `size` bytes from the pages already there
+unsafe fn find_room(size: usize) -> Option<*mut Header> {
+    if is_large(size) {
+        return reuse_large(size);
+    }
+    match take_free(size) {
+        Some(header) => Some(header),
+        None => bump(size),
+    }
+}
+
+/// adds pages to make room for an object of `size` bytes
+unsafe fn grow(size: usize) -> *mut Header {
+    if is_large(size) {
+        return new_large(size);
+    }
+    add_new_page();
+    match bump(size) {
+        Some(header) => header,
+        None => unreachable(),
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn init() {
     let page_ptr = new_page();
     GC = page_ptr;
     HEAD = GC;
+    gc::init();
 }
 
+/// allocates a zeroed object of `size` bytes, whose pointers are described by `desc`
 #[no_mangle]
-pub unsafe extern "C" fn alloc(size: usize) -> *mut u8 {
-    if (*HEAD).size <= (*HEAD).top + size {
-        add_new_page();
+pub unsafe extern "C" fn alloc(size: usize, desc: u32) -> *mut u8 {
+    // free chunks need a slot to link
+    let size = ((size + 7) / 8 * 8).max(8);
+    if SIZE_MASK as usize <= size {
+        unreachable()
+    }
+    if THRESHOLD <= ALLOCATED {
+        gc::collect();
     }
-    let ret = (*HEAD).data.offset((*HEAD).top as isize);
-    (*GC).top += size;
+    let header = match find_room(size) {
+        Some(header) => header,
+        None if HEAP_LIMIT < HEAP_SIZE + npages_for(size) * WASM_PAGE_SIZE => {
+            // collect garbage before giving up
+            gc::collect();
+            match find_room(size) {
+                Some(header) => header,
+                None if HEAP_LIMIT < HEAP_SIZE + npages_for(size) * WASM_PAGE_SIZE => unreachable(),
+                None => grow(size),
+            }
+        }
+        None => grow(size),
+    };
+    ALLOCATED += HEADER_SIZE + size;
+    USED += HEADER_SIZE + size;
+    BYTES_ALLOCATED += (HEADER_SIZE + size) as u64;
+    (*header).size = size as u32;
+    (*header).desc = desc;
+    let ret = payload(header);
+    ptr::write_bytes(ret, 0, size);
     ret
 }
 
+/// the bytes taken by the objects, including their headers and the garbage not collected yet
 #[no_mangle]
 pub unsafe extern "C" fn memory_used() -> usize {
+    USED
+}
+
+/// the size of the linear memory in bytes
+#[no_mangle]
+pub unsafe extern "C" fn memory_reserved() -> usize {
     WASM_PAGE_SIZE * memory_size(MEMORY)
 }
 
+/// the bytes ever allocated, including the headers
+#[no_mangle]
+pub unsafe extern "C" fn gc_bytes_allocated() -> u64 {
+    BYTES_ALLOCATED
+}
+
+/// the bytes of the pages for the objects
+#[no_mangle]
+pub unsafe extern "C" fn gc_heap_size() -> usize {
+    HEAP_SIZE
+}
+
+/// limits the pages for the objects to `limit` bytes. Allocations beyond it trap.
+/// Meant to be called before running the program
+#[no_mangle]
+pub unsafe extern "C" fn gc_set_heap_limit(limit: usize) {
+    HEAP_LIMIT = limit;
+}
+
 #[panic_handler]
 fn panic(_: &PanicInfo) -> ! {
     // currently no way to handle panic
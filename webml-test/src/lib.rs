@@ -1,9 +1,17 @@
 use std::cell::RefCell;
 use std::io::prelude::*;
+use std::io::Cursor;
 use wasmtime::*;
+use webml::Config;
+
+#[cfg(test)]
+mod rt;
 
 thread_local! {
     static OUTPUT: RefCell<Vec<u8>> = RefCell::new(Vec::new());
+    static INPUT: RefCell<Cursor<Vec<u8>>> = RefCell::new(Cursor::new(Vec::new()));
+    // the line read by `readline` and waiting for `takeline`
+    static LINE: RefCell<Vec<u8>> = RefCell::new(Vec::new());
 }
 
 fn add_ffi_module(linker: &mut Linker) {
@@ -15,24 +23,128 @@ fn add_ffi_module(linker: &mut Linker) {
         })
         .expect("failed to add ffi functions");
 }
-fn add_rt_module(linker: &mut Linker) {
-    let module_data =
-        include_bytes!("../../webml-rt/target/wasm32-unknown-unknown/release/webml_rt.wasm");
+/// a clock for `webml-rt` which always returns 0, to keep the tests deterministic
+fn add_clock_module(linker: &mut Linker) {
+    linker
+        .func("clock", "now", || -> f64 { 0.0 })
+        .expect("failed to add clock functions");
+}
+
+const RT_MODULE: &[u8] =
+    include_bytes!("../../webml-rt/target/wasm32-unknown-unknown/release/webml_rt.wasm");
+
+fn add_rt_module(linker: &mut Linker) -> Memory {
     let module =
-        Module::from_binary(linker.store(), module_data).expect("failed to compile webml_rt");
+        Module::from_binary(linker.store(), RT_MODULE).expect("failed to compile webml_rt");
     let instance = linker
         .instantiate(&module)
         .expect("failed to instanciate webml_rt");
     linker
         .instance("webml-rt", &instance)
         .expect("failed to import webml-rt");
+    instance
+        .get_export("memory")
+        .and_then(|e| e.into_memory())
+        .expect("webml-rt doesn't export memory")
+}
+
+/// `TextIO` writing to `OUTPUT` and reading from `INPUT`.
+/// Standard error is not captured.
+fn add_io_module(linker: &mut Linker, memory: Memory) {
+    let mem = memory.clone();
+    linker
+        .func("webml-io", "write", move |fd: i32, s: i32| {
+            let bytes = unsafe { string_bytes(&mem, s) };
+            let ret = match fd {
+                2 => std::io::stderr().write_all(bytes),
+                _ => OUTPUT.with(|out| out.borrow_mut().write_all(bytes)),
+            };
+            ret.expect("failed to write");
+        })
+        .expect("failed to add io functions");
+    linker
+        .func("webml-io", "flush", |_: i32| ())
+        .expect("failed to add io functions");
+    linker
+        .func("webml-io", "readline", || -> i32 {
+            LINE.with(|line| {
+                let mut line = line.borrow_mut();
+                line.clear();
+                let n = INPUT
+                    .with(|input| input.borrow_mut().read_until(b'\n', &mut *line))
+                    .expect("failed to read");
+                if n == 0 {
+                    -1
+                } else {
+                    n as i32
+                }
+            })
+        })
+        .expect("failed to add io functions");
+    linker
+        .func("webml-io", "takeline", move |s: i32| {
+            LINE.with(|line| {
+                unsafe { string_bytes_mut(&memory, s) }.copy_from_slice(&line.borrow());
+            })
+        })
+        .expect("failed to add io functions");
+}
+
+/// host side of `Math`, for the functions wasm doesn't have instructions for
+fn add_math_module(linker: &mut Linker) {
+    let unary: &[(&str, fn(f64) -> f64)] = &[
+        ("sin", f64::sin),
+        ("cos", f64::cos),
+        ("tan", f64::tan),
+        ("asin", f64::asin),
+        ("acos", f64::acos),
+        ("atan", f64::atan),
+        ("exp", f64::exp),
+        ("ln", f64::ln),
+        ("log10", f64::log10),
+        ("sinh", f64::sinh),
+        ("cosh", f64::cosh),
+        ("tanh", f64::tanh),
+    ];
+    for &(name, f) in unary {
+        linker
+            .func("math", name, move |x: f64| f(x))
+            .expect("failed to add math functions");
+    }
+    let binary: &[(&str, fn(f64, f64) -> f64)] = &[("atan2", f64::atan2), ("pow", f64::powf)];
+    for &(name, f) in binary {
+        linker
+            .func("math", name, move |x: f64, y: f64| f(x, y))
+            .expect("failed to add math functions");
+    }
+}
+
+unsafe fn string_bytes(memory: &Memory, s: i32) -> &[u8] {
+    let data = memory.data_unchecked();
+    let s = s as usize;
+    let mut len = [0; 4];
+    len.copy_from_slice(&data[s..s + 4]);
+    let len = i32::from_le_bytes(len) as usize;
+    &data[s + 4..s + 4 + len]
+}
+
+unsafe fn string_bytes_mut(memory: &Memory, s: i32) -> &mut [u8] {
+    let len = string_bytes(memory, s).len();
+    let s = s as usize;
+    &mut memory.data_unchecked_mut()[s + 4..s + 4 + len]
 }
 
 pub fn test_runtime() -> Linker {
-    let store = Store::default();
+    runtime_in(Store::default())
+}
+
+fn runtime_in(store: Store) -> Linker {
     let mut linker = Linker::new(&store);
     add_ffi_module(&mut linker);
-    add_rt_module(&mut linker);
+    add_clock_module(&mut linker);
+    let memory = add_rt_module(&mut linker);
+    add_io_module(&mut linker, memory);
+    add_math_module(&mut linker);
     linker
 }
 
@@ -47,6 +159,15 @@ impl TestRuntime {
         }
     }
 
+    /// the runtime supporting the wasm multi-value proposal, for `Config::multi_value`
+    pub fn with_multi_value() -> Self {
+        let mut config = wasmtime::Config::new();
+        config.wasm_multi_value(true);
+        TestRuntime {
+            linker: runtime_in(Store::new(&Engine::new(&config))),
+        }
+    }
+
     pub fn output(&self) -> Vec<u8> {
         OUTPUT.with(|o| Vec::clone(&*o.borrow()))
     }
@@ -63,13 +184,42 @@ impl TestRuntime {
         self.run(module_data);
         assert_eq!(self.output(), output.as_bytes());
     }
+
+    /// runs the module expecting it to stop at `raise`, after printing `output`.
+    /// Exceptions can't be handled yet, so `raise` traps with `unreachable`,
+    /// which tells it from the traps of the wasm instructions
+    pub fn test_raise(&mut self, module_data: &[u8], output: &str) {
+        let module = Module::from_binary(self.linker.store(), module_data)
+            .expect("failed to compile module");
+        let error = match self.linker.instantiate(&module) {
+            Ok(_) => panic!("the module didn't raise"),
+            Err(e) => e,
+        };
+        let trap = error
+            .downcast_ref::<Trap>()
+            .unwrap_or_else(|| panic!("failed with other than a trap: {}", error));
+        assert!(
+            trap.to_string().contains("unreachable"),
+            "trapped other than by raise: {}",
+            trap
+        );
+        assert_eq!(self.output(), output.as_bytes());
+    }
+
+    pub fn set_input(&mut self, input: &str) {
+        INPUT.with(|i| *i.borrow_mut() = Cursor::new(input.as_bytes().to_vec()));
+    }
 }
 
 pub fn compile(input: &str) -> Vec<u8> {
-    use webml::{compile_str, Config};
+    compile_with(input, &Config::default())
+}
+
+pub fn compile_with(input: &str, config: &Config) -> Vec<u8> {
+    use webml::compile_str;
     let mut prelude = include_str!("../../ml_src/prelude.sml").to_string();
     prelude.push_str(input);
-    compile_str(&prelude, &Config::default()).expect("failed to compile")
+    compile_str(&prelude, config).expect("failed to compile")
 }
 
 #[test]
@@ -80,6 +230,14 @@ fn test_add_and_print() {
     tester.test_output(&module, "3\n");
 }
 
+#[test]
+fn test_array() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/array.sml"));
+
+    tester.test_output(&module, "5\n10\n0\n50\n0\n1\n4\n9\n-6\n5\n0\n");
+}
+
 #[test]
 fn test_big_expression() {
     let mut tester = TestRuntime::new();
@@ -96,6 +254,14 @@ fn test_binary_operators() {
     tester.test_output(&module, "");
 }
 
+#[test]
+fn test_bool() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/bool.sml"));
+
+    tester.test_output(&module, "0\n1\n");
+}
+
 #[test]
 fn test_boolean_case() {
     let mut tester = TestRuntime::new();
@@ -120,6 +286,14 @@ fn test_char() {
     tester.test_output(&module, "0\n1\n");
 }
 
+#[test]
+fn test_char_conversion() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/char_conversion.sml"));
+
+    tester.test_output(&module, "97\n65\n98\n66\n98\n49\n1\n0\n1\n0\n");
+}
+
 #[test]
 fn test_closures() {
     let mut tester = TestRuntime::new();
@@ -128,6 +302,14 @@ fn test_closures() {
     tester.test_output(&module, "3\n");
 }
 
+#[test]
+fn test_const_prop() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/const_prop.sml"));
+
+    tester.test_output(&module, "100\n2\n5\n1\n");
+}
+
 #[test]
 fn test_datatype() {
     let mut tester = TestRuntime::new();
@@ -158,6 +340,30 @@ fn test_fn() {
 
     tester.test_output(&module, "");
 }
+#[test]
+fn test_from_string() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/from_string.sml"));
+
+    tester.test_output(&module, "123\n-45\n6\n0\n-2147483648\n2\n-15\n5\n0\n");
+}
+
+#[test]
+fn test_gc() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/gc.sml"));
+
+    tester.test_output(&module, "3000\n1498500000\n161700\n1\n");
+}
+
+#[test]
+fn test_globals() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/globals.sml"));
+
+    tester.test_output(&module, "16\n20\n4\n23\n");
+}
+
 #[test]
 fn test_if() {
     let mut tester = TestRuntime::new();
@@ -172,6 +378,26 @@ fn test_infix() {
 
     tester.test_output(&module, "");
 }
+
+#[test]
+fn test_inline() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/inline.sml"));
+
+    tester.test_output(&module, "2\n3\n10\n55\n");
+}
+
+#[test]
+fn test_inline_disabled() {
+    let mut tester = TestRuntime::new();
+    let config = Config {
+        inline_threshold: Some(0),
+        ..Config::default()
+    };
+    let module = compile_with(include_str!("../../ml_example/inline.sml"), &config);
+
+    tester.test_output(&module, "2\n3\n10\n55\n");
+}
 #[test]
 fn test_int_list() {
     let mut tester = TestRuntime::new();
@@ -179,6 +405,55 @@ fn test_int_list() {
 
     tester.test_output(&module, "1\n2\n3\n");
 }
+#[test]
+fn test_int() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/int.sml"));
+
+    tester.test_output(&module, "3\n2\n5\n-1\n0\n0\n1\n2147483647\n");
+}
+
+#[test]
+fn test_int_inf() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/int_inf.sml"));
+
+    tester.test_output(
+        &module,
+        "15241578750190521\n0\n15511210043330985984000000\n1267650600228229401496703205376\n~14285714285714285715\n5\n~14285714285714285714\n-2\n1\n-2147483648\n",
+    );
+}
+
+#[test]
+fn test_int64() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/int64.sml"));
+
+    tester.test_output(
+        &module,
+        "9223372036854775807\n~9223372036854775808\n15000000000000\n2142857142857\n1\n~9223372036854775808\n1\n~1234567890123\nFFFFFFFFFFFFFFFF\n1\nFFFFFFFFFFFFFFF\n-1\nFF\n0.2\n0\n4\n",
+    );
+}
+
+#[test]
+fn test_match_compile() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/match_compile.sml"));
+
+    tester.test_output(&module, "8\n5\n0\n40\n0\n6\n");
+}
+
+#[test]
+fn test_math() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/math.sml"));
+
+    tester.test_output(
+        &module,
+        "1.414214\n2.0\n3.0\n2.0\n~2.0\n1.0\n1.0\n3.141593\n2.718282\n1.0\n1024.0\n3.0\n",
+    );
+}
+
 #[test]
 fn test_integer_case() {
     let mut tester = TestRuntime::new();
@@ -186,6 +461,214 @@ fn test_integer_case() {
 
     tester.test_output(&module, "1\n1\n2\n3\n5\n8\n");
 }
+#[test]
+fn test_known_call() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/known_call.sml"));
+
+    tester.test_output(&module, "50\n6\n9\n");
+}
+
+#[test]
+fn test_list_all() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/list_all.sml"));
+
+    tester.test_output(&module, "1\n0\n1\n");
+}
+
+#[test]
+fn test_list_app() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/list_app.sml"));
+
+    tester.test_output(&module, "1\n2\n3\n");
+}
+
+#[test]
+fn test_list_append() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/list_append.sml"));
+
+    tester.test_output(&module, "1\n2\n3\n4\n5\n");
+}
+
+#[test]
+fn test_list_collate() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/list_collate.sml"));
+
+    tester.test_output(&module, "1\n0\n2\n");
+}
+
+#[test]
+fn test_list_concat() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/list_concat.sml"));
+
+    tester.test_output(&module, "1\n2\n3\n4\n5\n");
+}
+
+#[test]
+fn test_list_drop() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/list_drop.sml"));
+
+    tester.test_output(&module, "3\n4\n0\n");
+}
+
+#[test]
+fn test_list_exists() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/list_exists.sml"));
+
+    tester.test_output(&module, "1\n0\n0\n");
+}
+
+#[test]
+fn test_list_filter() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/list_filter.sml"));
+
+    tester.test_output(&module, "2\n4\n6\n");
+}
+
+#[test]
+fn test_list_find() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/list_find.sml"));
+
+    tester.test_output(&module, "3\n0\n");
+}
+
+#[test]
+fn test_list_foldl() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/list_foldl.sml"));
+
+    tester.test_output(&module, "123\n");
+}
+
+#[test]
+fn test_list_foldr() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/list_foldr.sml"));
+
+    tester.test_output(&module, "321\n");
+}
+
+#[test]
+fn test_list_get_item() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/list_getItem.sml"));
+
+    tester.test_output(&module, "2\n0\n");
+}
+
+#[test]
+fn test_list_hd() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/list_hd.sml"));
+
+    tester.test_output(&module, "1\n4\n");
+}
+
+#[test]
+fn test_list_last() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/list_last.sml"));
+
+    tester.test_output(&module, "3\n");
+}
+
+#[test]
+fn test_list_length() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/list_length.sml"));
+
+    tester.test_output(&module, "3\n0\n2\n");
+}
+
+#[test]
+fn test_list_map() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/list_map.sml"));
+
+    tester.test_output(&module, "1\n4\n9\n3\n7\n");
+}
+
+#[test]
+fn test_list_map_partial() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/list_mapPartial.sml"));
+
+    tester.test_output(&module, "20\n40\n");
+}
+
+#[test]
+fn test_list_nth() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/list_nth.sml"));
+
+    tester.test_output(&module, "1\n3\n");
+}
+
+#[test]
+fn test_list_null() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/list_null.sml"));
+
+    tester.test_output(&module, "1\n0\n");
+}
+
+#[test]
+fn test_list_partition() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/list_partition.sml"));
+
+    tester.test_output(&module, "2\n4\n1\n3\n5\n");
+}
+
+#[test]
+fn test_list_rev() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/list_rev.sml"));
+
+    tester.test_output(&module, "3\n2\n1\n");
+}
+
+#[test]
+fn test_list_rev_append() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/list_revAppend.sml"));
+
+    tester.test_output(&module, "2\n1\n3\n4\n");
+}
+
+#[test]
+fn test_list_tabulate() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/list_tabulate.sml"));
+
+    tester.test_output(&module, "0\n2\n4\n6\n");
+}
+
+#[test]
+fn test_list_take() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/list_take.sml"));
+
+    tester.test_output(&module, "1\n2\n0\n");
+}
+
+#[test]
+fn test_list_tl() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/list_tl.sml"));
+
+    tester.test_output(&module, "2\n3\n");
+}
+
 #[test]
 fn test_multi_clause_fun() {
     let mut tester = TestRuntime::new();
@@ -216,6 +699,38 @@ fn test_non_alphanumeric_identifier() {
 
     tester.test_output(&module, "");
 }
+#[test]
+fn test_opt_level_0() {
+    let mut tester = TestRuntime::new();
+    let config = Config {
+        opt_level: 0,
+        ..Config::default()
+    };
+    let module = compile_with(include_str!("../../ml_example/known_call.sml"), &config);
+
+    tester.test_output(&module, "50\n6\n9\n");
+}
+
+#[test]
+fn test_opt_level_3() {
+    let mut tester = TestRuntime::new();
+    let config = Config {
+        opt_level: 3,
+        ..Config::default()
+    };
+    let module = compile_with(include_str!("../../ml_example/inline.sml"), &config);
+
+    tester.test_output(&module, "2\n3\n10\n55\n");
+}
+
+#[test]
+fn test_option() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/option.sml"));
+
+    tester.test_output(&module, "1\n2\n3\n1\n0\n5\n6\n7\n8\n9\n10\n");
+}
+
 #[test]
 fn test_overloaded_add() {
     let mut tester = TestRuntime::new();
@@ -223,6 +738,14 @@ fn test_overloaded_add() {
 
     tester.test_output(&module, "");
 }
+#[test]
+fn test_overflow() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/overflow.sml"));
+
+    tester.test_raise(&module, "-2147483648\n2147483647\n");
+}
+
 #[test]
 fn test_pattern_in_funarg() {
     let mut tester = TestRuntime::new();
@@ -237,6 +760,33 @@ fn test_prelude() {
 
     tester.test_output(&module, "100000\n");
 }
+#[test]
+fn test_real() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/real.sml"));
+
+    tester.test_output(
+        &module,
+        "2\n3\n2\n4\n-2\n-3\n1\n-1\n2\n2\n0\n2147483647\n-2147483648\n",
+    );
+}
+
+#[test]
+fn test_real_nan() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/real_nan.sml"));
+
+    tester.test_raise(&module, "1\n");
+}
+
+#[test]
+fn test_real_overflow() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/real_overflow.sml"));
+
+    tester.test_raise(&module, "2147483647\n");
+}
+
 #[test]
 fn test_random_expressions() {
     let mut tester = TestRuntime::new();
@@ -244,6 +794,89 @@ fn test_random_expressions() {
 
     tester.test_output(&module, "");
 }
+
+#[test]
+fn test_register_allocation() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/register_allocation.sml"));
+
+    tester.test_output(&module, "85\n110\n");
+}
+
+#[test]
+fn test_stack_alloc() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/stack_alloc.sml"));
+
+    tester.test_output(&module, "2000\n15\n");
+}
+
+#[test]
+fn test_string() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/string.sml"));
+
+    tester.test_output(
+        &module,
+        "12\n111\n1\n1\n1\n1\n1\n12\n1\n1\n1\n1\n0\n1\n1\n3\n2\n5\n1\n1\n",
+    );
+}
+
+#[test]
+fn test_substring() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/substring.sml"));
+
+    tester.test_output(&module, "11\n101\n1\n1\n1\n1\n1\n1\n2\n1\n1\n1\n114\n0\n");
+}
+
+#[test]
+fn test_tail_call() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/tail_call.sml"));
+
+    tester.test_output(&module, "1000000\n450000\n");
+}
+
+#[test]
+fn test_text_io() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/text_io.sml"));
+
+    tester.set_input("foo\nbar");
+    tester.test_output(&module, "hello\nab\nFOO\nBAR2\n");
+}
+
+#[test]
+fn test_to_string() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/to_string.sml"));
+
+    tester.test_output(&module, "1\n1\n1\n1\n1\n1\n1\n1\n1\n1\n1\n1\n1\n");
+}
+
+#[test]
+fn test_tuple_args() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/tuple_args.sml"));
+
+    tester.test_output(&module, "6\n5050\n4\n");
+}
+
+#[test]
+fn test_multi_value() {
+    let mut tester = TestRuntime::with_multi_value();
+    let config = Config {
+        multi_value: true,
+        // keep the calls
+        inline_threshold: Some(0),
+        ..Config::default()
+    };
+    let module = compile_with(include_str!("../../ml_example/multi_value.sml"), &config);
+
+    tester.test_output(&module, "3\n2\n55\n89\n42\n1\n2\n");
+}
+
 #[test]
 fn test_tuple_pattern() {
     let mut tester = TestRuntime::new();
@@ -251,6 +884,26 @@ fn test_tuple_pattern() {
 
     tester.test_output(&module, "2\n");
 }
+#[test]
+fn test_uncurry() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/uncurry.sml"));
+
+    tester.test_output(&module, "31\n31\n55\n");
+}
+
+#[test]
+fn test_uncurry_without_known_call() {
+    let mut tester = TestRuntime::new();
+    let config = Config {
+        disabled_passes: vec!["known_call".to_string()].into_iter().collect(),
+        ..Config::default()
+    };
+    let module = compile_with(include_str!("../../ml_example/uncurry.sml"), &config);
+
+    tester.test_output(&module, "31\n31\n55\n");
+}
+
 #[test]
 fn test_variable_scope() {
     let mut tester = TestRuntime::new();
@@ -258,3 +911,31 @@ fn test_variable_scope() {
 
     tester.test_output(&module, "3\n");
 }
+
+#[test]
+fn test_vector() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/vector.sml"));
+
+    tester.test_output(&module, "3\n3\n10\n20\n30\n6\n98\n7\n2\n");
+}
+
+#[test]
+fn test_vector_literal() {
+    let mut tester = TestRuntime::new();
+    let module = compile(include_str!("../../ml_example/vector_literal.sml"));
+
+    tester.test_output(&module, "3\n5\n");
+}
+
+#[test]
+fn test_wrapping_int() {
+    let mut tester = TestRuntime::new();
+    let config = Config {
+        wrapping_int: true,
+        ..Config::default()
+    };
+    let module = compile_with(include_str!("../../ml_example/overflow.sml"), &config);
+
+    tester.test_output(&module, "-2147483648\n2147483647\n-2147483648\n");
+}
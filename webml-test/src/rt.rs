@@ -0,0 +1,250 @@
+//! Tests of the allocator and the collector, calling the exports of `webml-rt` directly.
+use super::{add_clock_module, RT_MODULE};
+use wasmtime::*;
+
+struct Rt {
+    instance: Instance,
+    memory: Memory,
+}
+
+impl Rt {
+    fn new() -> Self {
+        let store = Store::default();
+        let mut linker = Linker::new(&store);
+        add_clock_module(&mut linker);
+        let module = Module::from_binary(&store, RT_MODULE).expect("failed to compile webml_rt");
+        let instance = linker
+            .instantiate(&module)
+            .expect("failed to instanciate webml_rt");
+        let memory = instance
+            .get_export("memory")
+            .and_then(|e| e.into_memory())
+            .expect("webml-rt doesn't export memory");
+        let rt = Rt { instance, memory };
+        rt.func("init").get0::<()>().unwrap()().expect("init trapped");
+        rt
+    }
+
+    fn func(&self, name: &str) -> Func {
+        self.instance
+            .get_export(name)
+            .and_then(|e| e.into_func())
+            .unwrap_or_else(|| panic!("webml-rt doesn't export {}", name))
+    }
+
+    fn alloc(&self, size: u32, desc: u32) -> u32 {
+        let alloc = self.func("alloc").get2::<i32, i32, i32>().unwrap();
+        alloc(size as i32, desc as i32).expect("alloc trapped") as u32
+    }
+
+    fn enter_frame(&self, nslots: u32) -> u32 {
+        let enter_frame = self.func("enter_frame").get1::<i32, i32>().unwrap();
+        enter_frame(nslots as i32).expect("enter_frame trapped") as u32
+    }
+
+    fn try_alloc(&self, size: u32, desc: u32) -> Result<u32, Trap> {
+        let alloc = self.func("alloc").get2::<i32, i32, i32>().unwrap();
+        alloc(size as i32, desc as i32).map(|ptr| ptr as u32)
+    }
+
+    fn get(&self, name: &str) -> u32 {
+        let get = self.func(name).get0::<i32>().unwrap();
+        get().expect("webml-rt trapped") as u32
+    }
+
+    fn set(&self, name: &str, value: u32) {
+        let set = self.func(name).get1::<i32, ()>().unwrap();
+        set(value as i32).expect("webml-rt trapped")
+    }
+
+    fn memory_used(&self) -> u32 {
+        let memory_used = self.func("memory_used").get0::<i32>().unwrap();
+        memory_used().expect("memory_used trapped") as u32
+    }
+
+    fn memory_reserved(&self) -> u32 {
+        let memory_reserved = self.func("memory_reserved").get0::<i32>().unwrap();
+        memory_reserved().expect("memory_reserved trapped") as u32
+    }
+
+    fn load(&self, addr: u32) -> u32 {
+        let data = unsafe { self.memory.data_unchecked() };
+        let mut word = [0; 4];
+        word.copy_from_slice(&data[addr as usize..addr as usize + 4]);
+        u32::from_le_bytes(word)
+    }
+
+    fn store(&self, addr: u32, value: u32) {
+        let data = unsafe { self.memory.data_unchecked_mut() };
+        data[addr as usize..addr as usize + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// a cell of an int and a pointer to the next cell
+    fn cons(&self, value: u32, next: u32) -> u32 {
+        let cell = self.alloc(16, 0b10);
+        self.store(cell, value);
+        self.store(cell + 8, next);
+        cell
+    }
+}
+
+#[test]
+fn test_alloc_aligned_and_zeroed() {
+    let rt = Rt::new();
+    let mut objects = Vec::new();
+    for size in 1..200 {
+        let ptr = rt.alloc(size, 0);
+        assert_eq!(ptr % 8, 0);
+        let data = unsafe { rt.memory.data_unchecked_mut() };
+        let object = &mut data[ptr as usize..(ptr + size) as usize];
+        assert!(object.iter().all(|b| *b == 0));
+        for b in object {
+            *b = 0xff;
+        }
+        objects.push((ptr, size));
+    }
+    objects.sort();
+    for pair in objects.windows(2) {
+        assert!(pair[0].0 + pair[0].1 <= pair[1].0);
+    }
+}
+
+#[test]
+fn test_alloc_across_pages() {
+    let rt = Rt::new();
+    // 4 pages, not enough to start a collection
+    let objects = (0..8000).map(|_| rt.alloc(24, 0)).collect::<Vec<_>>();
+    for (i, ptr) in objects.iter().enumerate() {
+        rt.store(*ptr, i as u32);
+        rt.store(*ptr + 20, i as u32);
+    }
+    for (i, ptr) in objects.iter().enumerate() {
+        assert_eq!(rt.load(*ptr), i as u32);
+        assert_eq!(rt.load(*ptr + 20), i as u32);
+    }
+}
+
+#[test]
+fn test_alloc_large_object() {
+    let rt = Rt::new();
+    let reserved = rt.memory_reserved();
+    let large = rt.alloc(300_000, 0);
+    assert_eq!(large % 8, 0);
+    assert!(reserved + 300_000 <= rt.memory_reserved());
+    let small = rt.alloc(8, 0);
+    assert!(small + 8 <= large || large + 300_000 <= small);
+    rt.store(large, 1);
+    rt.store(large + 300_000 - 4, 2);
+    rt.store(small, 3);
+    assert_eq!(rt.load(large), 1);
+    assert_eq!(rt.load(large + 300_000 - 4), 2);
+}
+
+#[test]
+fn test_memory_used() {
+    let rt = Rt::new();
+    assert_eq!(rt.memory_used(), 0);
+    // an 8-byte header and the size rounded up to 8
+    rt.alloc(1, 0);
+    assert_eq!(rt.memory_used(), 16);
+    rt.alloc(16, 0);
+    assert_eq!(rt.memory_used(), 16 + 24);
+    rt.alloc(100_000, 0);
+    assert_eq!(rt.memory_used(), 16 + 24 + 100_008);
+}
+
+#[test]
+fn test_collect_garbage() {
+    let rt = Rt::new();
+    let roots = rt.enter_frame(1);
+    let mut list = 0;
+    for i in 0..1000 {
+        list = rt.cons(i, list);
+        rt.store(roots, list);
+    }
+    for _ in 0..100_000 {
+        rt.alloc(64, 0);
+    }
+    for i in (0..1000).rev() {
+        assert_eq!(rt.load(list), i);
+        list = rt.load(list + 8);
+    }
+    assert_eq!(list, 0);
+    assert!(rt.memory_reserved() < 4 * 1024 * 1024);
+    assert!(rt.memory_used() < 1024 * 1024);
+}
+
+#[test]
+fn test_collect_large_garbage() {
+    let rt = Rt::new();
+    for _ in 0..100 {
+        rt.alloc(1024 * 1024, 0);
+    }
+    assert!(rt.memory_reserved() < 8 * 1024 * 1024);
+}
+
+#[test]
+fn test_gc_stats() {
+    let rt = Rt::new();
+    assert_eq!(rt.get("gc_collections"), 0);
+    let roots = rt.enter_frame(1);
+    let mut list = 0;
+    for i in 0..1000 {
+        list = rt.cons(i, list);
+        rt.store(roots, list);
+    }
+    for _ in 0..10_000 {
+        rt.alloc(120, 0);
+    }
+    let bytes_allocated = rt.func("gc_bytes_allocated").get0::<i64>().unwrap();
+    assert_eq!(
+        bytes_allocated().unwrap(),
+        1000 * (8 + 16) + 10_000 * (8 + 120)
+    );
+    assert!(0 < rt.get("gc_collections"));
+    // only the list survives the collections
+    assert_eq!(rt.get("gc_live_bytes"), 1000 * (8 + 16));
+    assert!(rt.get("gc_heap_size") < 1024 * 1024);
+}
+
+#[test]
+fn test_growth_factor() {
+    let collections = |percent| {
+        let rt = Rt::new();
+        rt.set("gc_set_growth_factor", percent);
+        let roots = rt.enter_frame(1);
+        let mut list = 0;
+        for i in 0..100_000 {
+            list = rt.cons(i, list);
+            rt.store(roots, list);
+        }
+        rt.get("gc_collections")
+    };
+    assert!(collections(400) < collections(150));
+}
+
+#[test]
+fn test_heap_limit() {
+    let rt = Rt::new();
+    rt.set("gc_set_heap_limit", 1024 * 1024);
+    // the garbage is collected to stay in the limit
+    for _ in 0..100_000 {
+        rt.alloc(64, 0);
+    }
+    assert!(rt.get("gc_heap_size") <= 1024 * 1024);
+    assert!(rt.try_alloc(2 * 1024 * 1024, 0).is_err());
+
+    let roots = rt.enter_frame(1);
+    let mut list = 0;
+    let trapped = (0..100_000).any(|i| match rt.try_alloc(16, 0b10) {
+        Ok(cell) => {
+            rt.store(cell, i);
+            rt.store(cell + 8, list);
+            list = cell;
+            rt.store(roots, list);
+            false
+        }
+        Err(_) => true,
+    });
+    assert!(trapped);
+}